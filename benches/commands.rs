@@ -0,0 +1,39 @@
+//! Rendering commands to the strings sent to sway.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ksway::{cmd, command, criteria, CommandSeq};
+
+mod common;
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    group.bench_function("raw", |b| {
+        b.iter(|| command::raw(black_box("focus")).to_string())
+    });
+    group.bench_function("criteria", |b| {
+        b.iter(|| {
+            let id = black_box(10_001u64);
+            cmd!([con_id=id app_id="foot"] "focus").to_string()
+        })
+    });
+    group.bench_function("mark", |b| {
+        b.iter(|| {
+            command::mark(black_box("a mark"))
+                .with_criteria(vec![criteria::con_id(black_box(10_001))])
+                .to_string()
+        })
+    });
+    group.bench_function("seq", |b| {
+        b.iter(|| {
+            CommandSeq::new()
+                .for_each(
+                    0..common::WINDOWS as u64,
+                    |id| cmd!([con_id=id] "move scratchpad"),
+                )
+                .to_string()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
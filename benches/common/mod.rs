@@ -0,0 +1,118 @@
+// Shared between the benchmarks, so not every item is used by each of them.
+#![allow(dead_code)]
+
+use ksway::core::Header;
+use serde_json::{json, Value as JsonValue};
+
+/// The number of windows in `tree()`, about what a heavy session has.
+pub const WINDOWS: usize = 500;
+
+const OUTPUTS: usize = 2;
+const WORKSPACES_PER_OUTPUT: usize = 5;
+/// Windows are grouped in splits of this many, to give the tree some depth.
+const SPLIT: usize = 5;
+
+fn rect(x: i64, y: i64) -> JsonValue {
+    json!({"x": x, "y": y, "width": 640, "height": 480})
+}
+
+/// A window with the fields sway sends, titled `window {id}` with app_id `app-{id % 50}`. The
+/// last window is focused.
+fn window(id: u64, focused: bool) -> JsonValue {
+    json!({
+        "id": id,
+        "type": "con",
+        "name": format!("window {}", id),
+        "layout": "none",
+        "orientation": "none",
+        "percent": 1.0 / SPLIT as f64,
+        "border": "pixel",
+        "current_border_width": 2,
+        "rect": rect(id as i64, 0),
+        "window_rect": rect(0, 0),
+        "deco_rect": rect(0, 0),
+        "geometry": rect(0, 0),
+        "urgent": false,
+        "focused": focused,
+        "focus": [],
+        "marks": [format!("mark-{}", id)],
+        "fullscreen_mode": 0,
+        "sticky": false,
+        "app_id": format!("app-{}", id % 50),
+        "pid": 1000 + id,
+        "visible": true,
+        "shell": "xdg_shell",
+        "inhibit_idle": false,
+        "idle_inhibitors": {"user": "none", "application": "none"},
+        "nodes": [],
+        "floating_nodes": [],
+    })
+}
+
+fn container(id: u64, node_type: &str, name: &str, nodes: Vec<JsonValue>) -> JsonValue {
+    json!({
+        "id": id,
+        "type": node_type,
+        "name": name,
+        "layout": "splith",
+        "orientation": "horizontal",
+        "rect": rect(0, 0),
+        "urgent": false,
+        "focused": false,
+        "focus": nodes.iter().map(|node| node["id"].clone()).collect::<Vec<_>>(),
+        "nodes": nodes,
+        "floating_nodes": [],
+    })
+}
+
+/// A synthetic GET_TREE reply with `WINDOWS` windows, spread over two outputs with five
+/// workspaces each, in splits of five windows.
+pub fn tree() -> JsonValue {
+    let per_workspace = WINDOWS / (OUTPUTS * WORKSPACES_PER_OUTPUT);
+    let mut next_id = 1;
+    let mut id = || {
+        next_id += 1;
+        next_id
+    };
+    let mut window_id = 0;
+    let outputs = (0..OUTPUTS)
+        .map(|output| {
+            let workspaces = (0..WORKSPACES_PER_OUTPUT)
+                .map(|workspace| {
+                    let splits = (0..per_workspace / SPLIT)
+                        .map(|_| {
+                            let windows = (0..SPLIT)
+                                .map(|_| {
+                                    window_id += 1;
+                                    window(10_000 + window_id, window_id == WINDOWS as u64)
+                                })
+                                .collect();
+                            container(id(), "con", "", windows)
+                        })
+                        .collect();
+                    let name = (output * WORKSPACES_PER_OUTPUT + workspace + 1).to_string();
+                    container(id(), "workspace", &name, splits)
+                })
+                .collect();
+            container(id(), "output", &format!("DP-{}", output + 1), workspaces)
+        })
+        .collect();
+    container(1, "root", "root", outputs)
+}
+
+/// A window `title` event for a window of `tree()`.
+pub fn window_event(id: u64) -> Vec<u8> {
+    serde_json::to_vec(&json!({"change": "title", "container": window(id, false)})).unwrap()
+}
+
+/// The i3-ipc frame sway sends for a payload.
+pub fn frame(payload_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    Header {
+        payload_len: payload.len() as u32,
+        payload_type,
+    }
+    .write_to(&mut out);
+    out.extend_from_slice(payload);
+    out
+}
@@ -0,0 +1,79 @@
+//! Splitting a stream of event frames into messages, and parsing the events.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ksway::core::{Connection, Decoder};
+use ksway::events::{SwayEvent, WindowChange};
+use ksway::{IpcEvent, JsonValue};
+
+mod common;
+
+/// How many events the stream has, e.g. a burst of title changes.
+const EVENTS: usize = 1000;
+
+fn demux(c: &mut Criterion) {
+    let code = IpcEvent::Window.code().raw();
+    let stream: Vec<u8> = (0..EVENTS as u64)
+        .flat_map(|id| common::frame(code, &common::window_event(10_000 + id)))
+        .collect();
+    let mut group = c.benchmark_group("demux");
+    group.throughput(Throughput::Elements(EVENTS as u64));
+    group.bench_function("decode", |b| {
+        b.iter(|| {
+            let mut decoder = Decoder::new();
+            decoder.feed(&stream);
+            let mut count = 0;
+            while decoder.decode().unwrap().is_some() {
+                count += 1;
+            }
+            count
+        })
+    });
+    group.bench_function("decode_into", |b| {
+        let mut payload = Vec::new();
+        b.iter(|| {
+            let mut decoder = Decoder::new();
+            decoder.feed(&stream);
+            let mut count = 0;
+            while decoder.decode_into(&mut payload).unwrap().is_some() {
+                count += 1;
+            }
+            count
+        })
+    });
+    // As `Client` gets them, in reads of up to 64 KiB.
+    group.bench_function("connection", |b| {
+        let mut payload = Vec::new();
+        b.iter(|| {
+            let mut connection = Connection::new();
+            let mut count = 0;
+            for chunk in stream.chunks(64 * 1024) {
+                connection.receive(chunk);
+                while connection.poll_into(&mut payload).unwrap().is_some() {
+                    count += 1;
+                }
+            }
+            count
+        })
+    });
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let payload = common::window_event(10_001);
+    let mut group = c.benchmark_group("parse_event");
+    group.bench_function("json", |b| {
+        b.iter(|| serde_json::from_slice::<JsonValue>(&payload).unwrap())
+    });
+    group.bench_function("change", |b| {
+        b.iter(|| {
+            let json: JsonValue = serde_json::from_slice(&payload).unwrap();
+            WindowChange::from_payload(&json)
+        })
+    });
+    group.bench_function("typed", |b| {
+        b.iter(|| SwayEvent::parse(IpcEvent::Window, &payload).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, demux, parse);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+//! Parsing a GET_TREE reply: into a `JsonValue`, into the typed `reply::Node`, walking it with
+//! the streaming `tree::visit()`, and into a `tree::Tree` with parent links.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ksway::{reply, tree, JsonValue};
+
+mod common;
+
+fn get_tree(c: &mut Criterion) {
+    let payload = serde_json::to_vec(&common::tree()).unwrap();
+    let mut group = c.benchmark_group("get_tree");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    group.bench_function("raw", |b| {
+        b.iter(|| serde_json::from_slice::<JsonValue>(&payload).unwrap())
+    });
+    group.bench_function("typed", |b| {
+        b.iter(|| serde_json::from_slice::<reply::Node>(&payload).unwrap())
+    });
+    group.bench_function("visit", |b| {
+        b.iter(|| {
+            let mut windows = 0;
+            tree::visit(&payload, |node| {
+                if node.is_leaf() && node.node_type == "con" {
+                    windows += 1;
+                }
+                tree::Visit::Continue
+            })
+            .unwrap();
+            windows
+        })
+    });
+    group.bench_function("tree", |b| b.iter(|| tree::Tree::parse(&payload).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, get_tree);
+criterion_main!(benches);
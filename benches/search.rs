@@ -0,0 +1,52 @@
+//! Searching a parsed GET_TREE reply for windows.
+use criterion::{criterion_group, criterion_main, Criterion};
+use ksway::criteria::{self, Matcher};
+use ksway::{reply, tree, SwayJsonExt};
+
+mod common;
+
+fn search(c: &mut Criterion) {
+    let json = common::tree();
+    let payload = serde_json::to_vec(&json).unwrap();
+    let typed: reply::Node = serde_json::from_slice(&payload).unwrap();
+    let linked = tree::Tree::parse(&payload).unwrap();
+    // The last window, so every search goes through the whole tree.
+    let last = 10_000 + common::WINDOWS as u64;
+    let mark = format!("mark-{}", last);
+    assert_eq!(linked.find_focused().map(|node| node.id), Some(last));
+
+    let mut group = c.benchmark_group("search");
+    group.bench_function("json_focused", |b| {
+        b.iter(|| tree::nodes(&json).find(|node| node.node.is_focused()))
+    });
+    group.bench_function("typed_focused", |b| b.iter(|| typed.find_focused()));
+    group.bench_function("tree_focused", |b| b.iter(|| linked.find_focused()));
+    group.bench_function("json_by_id", |b| {
+        b.iter(|| tree::windows(&json).find(|window| window.id() == Some(last)))
+    });
+    group.bench_function("typed_by_mark", |b| {
+        b.iter(|| typed.iter().find(|node| node.marks.contains(&mark)))
+    });
+    group.bench_function("tree_by_app", |b| {
+        b.iter(|| {
+            linked
+                .windows()
+                .filter(|window| window.node().app_id.as_deref() == Some("app-0"))
+                .count()
+        })
+    });
+    // Compiling the criteria and finding the focused window are part of every search.
+    group.bench_function("matcher", |b| {
+        b.iter(|| {
+            let criteria = [criteria::app_id::<&str, _>("^app-1"), criteria::tiling()];
+            let matcher = Matcher::new(&criteria).unwrap().focused_in(&json);
+            tree::nodes(&json)
+                .filter(|node| matcher.matches(node))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+use std::io::{self, BufReader};
+
+use anyhow::Result;
+use ksway::{
+    bar::{read_click_events, BarState, BarWriter},
+    Client, IpcEvent,
+};
+
+/// A status command for swaybar showing the workspaces, the binding mode and the focused window
+/// title:
+///
+///     bar {
+///         status_command ksway-bar
+///     }
+fn main() -> Result<()> {
+    let mut client = Client::connect()?;
+    let mut bar = BarState::new(&mut client)?;
+    let mut writer = BarWriter::new(io::stdout(), true);
+    let clicks = read_click_events(BufReader::new(io::stdin()));
+
+    let rx = client.subscribe(vec![IpcEvent::Workspace, IpcEvent::Mode, IpcEvent::Window])?;
+    writer.write(&bar.blocks())?;
+    loop {
+        let mut changed = false;
+        while let Ok((payload_type, payload)) = rx.try_recv() {
+            let payload = serde_json::from_slice(&payload)?;
+            changed |= bar.handle_event(&mut client, payload_type, &payload)?;
+        }
+        while let Ok(click) = clicks.try_recv() {
+            bar.handle_click(&mut client, &click)?;
+        }
+        if changed {
+            writer.write(&bar.blocks())?;
+        }
+        client.poll()?;
+    }
+}
@@ -0,0 +1,12 @@
+use anyhow::Result;
+use ksway::{dsl::Action, Client};
+
+/// Run a single `ksway::dsl` action, e.g. from a sway binding:
+///
+///     bindsym $mod+Tab exec ksway-do focus-next workspace=__focused__
+fn main() -> Result<()> {
+    let action = Action::from_words(std::env::args().skip(1))?;
+    let mut client = Client::connect()?;
+    action.execute(&mut client)?;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use anyhow::{bail, Result};
+
+/// Run a Lua script with the `sway` module from `ksway::lua`, passing it the remaining
+/// arguments as `arg`:
+///
+///     ksway-lua focus-last.lua
+///
+/// or with `#!/usr/bin/env ksway-lua` as the first line of an executable script.
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let script = match args.next() {
+        Some(script) => script,
+        None => bail!("usage: ksway-lua <script> [args...]"),
+    };
+    ksway::lua::run_file(script, &args.collect::<Vec<_>>())?;
+    Ok(())
+}
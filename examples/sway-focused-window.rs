@@ -1,9 +1,7 @@
 use anyhow::*;
-use ksway::{Client, JsonValue, SwayClientJson};
+use ksway::{json::path::Path, Client, JsonValue, SwayClientJson};
 use structopt::StructOpt;
 
-mod utils;
-
 #[derive(StructOpt)]
 struct Opt {
     #[structopt(short)]
@@ -18,12 +16,11 @@ fn main() -> Result<()> {
     let value = client
         .focused_window()?
         .ok_or_else(|| anyhow!("Failed to find focused window"))?;
-    let mut target = utils::extract_path(&value, &opt.parts);
-    if target == &JsonValue::Null {
-        if let Some(default) = opt.default.as_ref() {
-            target = default;
-        }
-    }
+    let target = Path::new(&opt.parts)
+        .get(&value)
+        .filter(|target| !target.is_null())
+        .or(opt.default.as_ref())
+        .unwrap_or(&JsonValue::Null);
     serde_json::to_writer(&mut std::io::stdout().lock(), target)?;
     Ok(())
 }
@@ -1,13 +1,9 @@
 use anyhow::*;
-use ksway::{cmd, criteria::focused, Client, SwayClient, SwayClientJson};
+use ksway::{cmd, criteria::focused, Client, SwayClient, SwayClientJson, SwayJsonExt};
 use log::*;
 use parse_display::*;
 use structopt::StructOpt;
 
-mod utils;
-
-use utils::*;
-
 #[derive(Display, FromStr, Debug, Copy, Clone)]
 enum ResolutionPart {
     #[display("{0}/{1}")]
@@ -57,8 +53,8 @@ fn main() -> Result<()> {
         let data = client
             .focused_workspace()?
             .ok_or_else(|| anyhow!("Couldn't find focused workspace"))?;
-        let (_, _, w, h) = get_rect(&data).unwrap();
-        (w as f32, h as f32)
+        let rect = data.rect().unwrap();
+        (rect.width as f32, rect.height as f32)
     };
     let (w, h) = match opt.resolution {
         Resolution::Both(a, b) => (a.pixels(ws_dim.0), b.pixels(ws_dim.1)),
@@ -0,0 +1,30 @@
+// Shared between the examples, so not every item is used by each of them.
+#![allow(dead_code)]
+
+use serde_json::Value as JsonValue;
+
+pub fn preorder<T, F: FnMut(&JsonValue) -> Option<T>>(
+    value: &JsonValue,
+    visitor: &mut F,
+) -> Option<T> {
+    match visitor(value) {
+        None => (),
+        value => return value,
+    }
+    if let Some(obj) = value.as_object() {
+        for (_k, v) in obj.iter() {
+            match preorder(v, visitor) {
+                None => (),
+                value => return value,
+            }
+        }
+    } else if let Some(arr) = value.as_array() {
+        for v in arr.iter() {
+            match preorder(v, visitor) {
+                None => (),
+                value => return value,
+            }
+        }
+    }
+    None
+}
@@ -2,14 +2,11 @@ use log::*;
 use redis::{Client as RedisClient, Commands, Connection};
 
 use anyhow::{anyhow, Result};
-use ksway::{cmd, Client, IpcEvent, JsonValue, SwayClient, SwayClientJson};
-
-mod utils;
-
-use utils::*;
+use ksway::geometry::Alignment;
+use ksway::{cmd, Client, IpcEvent, JsonValue, SwayClient, SwayClientJson, SwayJsonExt};
 
 fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonValue) -> Result<()> {
-    use AlignmentVerbs::*;
+    use Alignment::*;
 
     // Get focused workspace
     let focused_workspace = client
@@ -18,13 +15,17 @@ fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonVal
 
     debug!("workspace: {}", focused_workspace);
 
-    // Get focused window topleft coords
-    let (fx, fy, fw, fh) = get_rect(&container)?;
+    // Get focused window rectangle
+    let focused = container
+        .rect()
+        .ok_or_else(|| anyhow!("Couldn't find focused window's rect"))?;
     // Get workspace rectangle
-    let (w_x, w_y, w_w, w_h) = get_rect(&focused_workspace)?;
+    let workspace = focused_workspace
+        .rect()
+        .ok_or_else(|| anyhow!("Couldn't find workspace's rect"))?;
 
     // These are the possible positions to send the floating container
-    const VERBS: &[&[AlignmentVerbs]] = &[
+    const VERBS: &[&[Alignment]] = &[
         &[Top, Left],
         &[Top, Right],
         &[Bottom, Left],
@@ -37,26 +38,6 @@ fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonVal
         // &[CenterY, Bottom],
     ];
 
-    // let (w_x, w_y) = (0, 0);
-    // let outputs = payload_to_json(client.ipc(ipc_command::get_outputs())?)?;
-    // let focused_output = outputs
-    //     .members()
-    //     .find(|c| c["focused"].as_bool() == Some(true))
-    //     .ok_or_else(|| anyhow!("Failed to find focused output"))?;
-    // let (w_w, w_h) = {
-    //     let mode = &focused_output["current_mode"];
-    //     (
-    //         mode["width"]
-    //             .as_i32()
-    //             .ok_or_else(|| anyhow!("Invalid mode"))?,
-    //         mode["height"]
-    //             .as_i32()
-    //             .ok_or_else(|| anyhow!("Invalid mode"))?,
-    //     )
-    // };
-
-    let (cx, cy) = ((fx + fw) / 2, (fy + fh) / 2);
-
     for value in focused_workspace["floating_nodes"]
         .as_array()
         .unwrap()
@@ -67,7 +48,9 @@ fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonVal
         {
             continue;
         }
-        let (r_x, r_y, r_w, r_h) = get_rect(&value)?;
+        let rect = value
+            .rect()
+            .ok_or_else(|| anyhow!("Couldn't find window's rect"))?;
         let window_id = value["id"].as_u64().unwrap();
         debug!("sploosh/window/id = {}", window_id);
         // Use redis to check if something is splooshy
@@ -81,29 +64,18 @@ fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonVal
         // Find the furthest place to send this to based on the verbs.
         // This overlaps windows currently.
         // I should investigate layout algorithms such as cassowary instead.
-        let (mx, my) = VERBS
+        let target = VERBS
             .iter()
             // Possible positions
             .map(|verbs| {
-                let (x, y) = calculate_coords(w_x, w_y, w_w, w_h, r_w, r_h, r_x, r_y, verbs);
-                let clamped = (
-                    x.max(w_x).min(w_x + w_w - r_w),
-                    y.max(w_y).min(w_y + w_h - r_h),
-                );
-                debug!(
-                    "verb test: {:?} -> {:?} -> {:?}",
-                    (w_x, w_y, w_w, w_h, r_w, r_h, r_x, r_y, verbs),
-                    (x, y),
-                    clamped
-                );
+                let clamped = rect.align(workspace, verbs).clamp(&workspace);
+                debug!("verb test: {:?} -> {:?}", verbs, clamped);
                 clamped
             })
-            .max_by_key(|(x, y)| {
-                // Distance from topleft of focused window
-                let result = (cx - x).pow(2) + (cy - y).pow(2);
-                (result as f32).sqrt() as i32
-            })
+            // Distance from the focused window
+            .max_by_key(|target| target.center_distance(&focused) as i64)
             .unwrap();
+        let (mx, my) = (target.x, target.y);
         debug!("sploosh/window/(mx,my) = ({}, {})", mx, my);
         // Move the floating window.
         if let Err(err) =
@@ -115,28 +87,15 @@ fn sploosh(client: &mut Client, redis_conn: &mut Connection, container: &JsonVal
     Ok(())
 }
 
-struct Config {
-    max_focused_windows: usize,
-}
-
 fn main() -> Result<()> {
     env_logger::init();
 
-    let config = Config {
-        max_focused_windows: 100,
-    };
-
     let mut client = Client::connect()?;
     let redis_client = RedisClient::open("redis://127.0.0.1")?;
     let mut redis_conn = redis_client.get_connection()?;
 
     info!("{}", client.socket_path().display());
 
-    // NOTE:
-    //  Clean up the previous FOCUSED_WINDOWS_KEY and ignore errors.
-    //    - ashkan, Wed 02 Sep 2020 02:20:30 PM JST
-    let _ = redis_conn.del::<_, ()>(FOCUSED_WINDOWS_KEY).ok();
-
     let rx = client.subscribe(vec![IpcEvent::Window, IpcEvent::Tick])?;
     let mut last_focused = None;
     loop {
@@ -153,19 +112,6 @@ fn main() -> Result<()> {
                                 && container["type"].as_str() != Some("floating_con")
                             {
                                 last_focused = Some(container.clone());
-                                // TODO provide a connection to execute actions without
-                                // using redis as a middle man.
-                                (|| -> Option<()> {
-                                    let id = container["id"].as_u64()?;
-                                    redis_conn.lpush(FOCUSED_WINDOWS_KEY, id).ok()?;
-                                    redis_conn
-                                        .ltrim(
-                                            FOCUSED_WINDOWS_KEY,
-                                            0,
-                                            config.max_focused_windows as isize,
-                                        )
-                                        .ok()
-                                })();
                                 true
                             } else {
                                 false
@@ -187,7 +133,7 @@ fn main() -> Result<()> {
             if should_sploosh {
                 debug!("tick/sploosh");
                 if let Some(ref container) = last_focused {
-                    match sploosh(&mut client, &mut redis_conn, &container) {
+                    match sploosh(&mut client, &mut redis_conn, container) {
                         Ok(res) => info!("sploosh() = {:?}", res),
                         Err(err) => error!("sploosh() = {:?}", err),
                     }
@@ -0,0 +1,93 @@
+//! Running a command on every window of an application at once.
+//!
+//! ```no_run
+//! use ksway::{app::{for_app, Scope}, cmd};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! // Close every mpv window on the focused workspace.
+//! for_app(&mut client, "mpv", cmd!("kill"), Scope::Workspace)?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use crate::criteria::con_id;
+use crate::tree::{nodes, windows};
+use crate::{Command, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// Which windows `for_app()` considers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Every window, including those in the scratchpad.
+    All,
+    /// Windows on the focused workspace.
+    Workspace,
+    /// Windows on the output of the focused workspace.
+    Output,
+}
+
+/// The con_ids of the windows of `app_id` in `scope`, see `tree::app_identifier()`.
+fn app_windows(tree: &JsonValue, app_id: &str, scope: Scope) -> Vec<u64> {
+    let focused = nodes(tree).find(|node| node.node.is_focused());
+    let (workspace, output) = match focused {
+        Some(node) => (node.workspace, node.output),
+        None if scope == Scope::All => (None, None),
+        None => return Vec::new(),
+    };
+    windows(tree)
+        .filter(|window| match scope {
+            Scope::All => true,
+            Scope::Workspace => window.workspace == workspace,
+            Scope::Output => window.output == output,
+        })
+        .filter(|window| window.app_identifier() == Some(app_id))
+        .filter_map(|window| window.id())
+        .collect()
+}
+
+/// Run `command` on every window of `app_id` in `scope`, as one batch with a `con_id` criteria
+/// per window. Returns the con_ids of the windows, and doesn't send anything if there are none.
+pub fn for_app<C: SwayClientJson>(
+    client: &mut C,
+    app_id: &str,
+    command: Command,
+    scope: Scope,
+) -> Result<Vec<u64>> {
+    let tree = client.get_tree_json()?;
+    let ids = app_windows(&tree, app_id, scope);
+    if !ids.is_empty() {
+        let commands: Vec<String> = ids
+            .iter()
+            .map(|&id| command.clone().with_criteria(vec![con_id(id)]).to_string())
+            .collect();
+        client.run(commands.join("; "))?;
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn windows_in_scope() {
+        let tree = json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "output", "name": "DP-1", "nodes": [
+                {"id": 3, "type": "workspace", "name": "1", "nodes": [
+                    {"id": 10, "type": "con", "app_id": "mpv", "focused": true},
+                    {"id": 11, "type": "con", "app_id": "foot"},
+                ]},
+                {"id": 4, "type": "workspace", "name": "2", "nodes": [
+                    {"id": 12, "type": "con", "app_id": "mpv"},
+                ]},
+            ]},
+            {"id": 5, "type": "output", "name": "HDMI-1", "nodes": [
+                {"id": 6, "type": "workspace", "name": "3", "floating_nodes": [
+                    {"id": 13, "type": "floating_con", "app_id": "mpv"},
+                ]},
+            ]},
+        ]});
+        assert_eq!(app_windows(&tree, "mpv", Scope::All), vec![10, 12, 13]);
+        assert_eq!(app_windows(&tree, "mpv", Scope::Output), vec![10, 12]);
+        assert_eq!(app_windows(&tree, "mpv", Scope::Workspace), vec![10]);
+        assert!(app_windows(&tree, "kitty", Scope::All).is_empty());
+    }
+}
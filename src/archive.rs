@@ -0,0 +1,368 @@
+//! Move windows which haven't been focused in a while to an "archive" workspace, and bring them
+//! back to where they came from later.
+//!
+//! `Archiver` only knows about focus through the events you feed it, so it's meant to live inside
+//! of a daemon which is already subscribed to `IpcEvent::Window`:
+//! ```no_run
+//! use ksway::{archive::{ArchiveConfig, Archiver}, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut archiver = Archiver::new(ArchiveConfig::default());
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Window])?;
+//! loop {
+//!     while let Ok((payload_type, payload)) = rx.try_recv() {
+//!         archiver.handle_event(payload_type, &serde_json::from_slice(&payload)?);
+//!     }
+//!     archiver.archive_idle(&mut client)?;
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::WindowChange;
+use crate::json::windows_by_workspace;
+use crate::persist::Persistent;
+use crate::{cmd, command, IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// Workspaces which are never archived from. `__i3_scratch` is where the scratchpad lives.
+const SKIPPED_WORKSPACES: &[&str] = &["__i3_scratch"];
+
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    /// How long a window has to go without focus before it gets archived.
+    pub idle: Duration,
+    /// The workspace that idle windows are moved to.
+    pub workspace: String,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(60 * 60),
+            workspace: "archive".into(),
+        }
+    }
+}
+
+/// Everything the `Archiver` keeps track of. This can be saved, e.g. with `persist::JsonFile`,
+/// and handed back to `Archiver::with_state()` when the daemon restarts, since container ids
+/// survive as long as sway does.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ArchiveState {
+    /// The last time each container was focused, keyed by con_id.
+    pub last_focus: HashMap<u64, SystemTime>,
+    /// The workspace each archived container was taken from, keyed by con_id.
+    pub archived: HashMap<u64, String>,
+}
+
+impl Persistent for ArchiveState {
+    const KIND: &'static str = "archive";
+    const VERSION: u32 = 1;
+}
+
+pub struct Archiver {
+    config: ArchiveConfig,
+    state: ArchiveState,
+    clock: Box<dyn Fn() -> SystemTime + Send>,
+}
+
+impl fmt::Debug for Archiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Archiver")
+            .field("config", &self.config)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Archiver {
+    pub fn new(config: ArchiveConfig) -> Self {
+        Self::with_state(config, ArchiveState::default())
+    }
+
+    /// Resume from previously saved state.
+    pub fn with_state(config: ArchiveConfig, state: ArchiveState) -> Self {
+        Self {
+            config,
+            state,
+            clock: Box::new(SystemTime::now),
+        }
+    }
+
+    /// Tell the time with `clock` instead of `SystemTime::now()`, e.g. to test a daemon without
+    /// waiting for windows to go idle.
+    pub fn clock<F: Fn() -> SystemTime + Send + 'static>(mut self, clock: F) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn config(&self) -> &ArchiveConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> &ArchiveState {
+        &self.state
+    }
+
+    /// Feed a subscription event to the archiver. Only window `focus` and `close` events are of
+    /// interest, everything else is ignored.
+    pub fn handle_event(&mut self, event: IpcEvent, payload: &JsonValue) {
+        if event == IpcEvent::Window {
+            let id = match payload["container"].con_id() {
+                Some(id) => id,
+                None => return,
+            };
+            match WindowChange::from_payload(payload) {
+                Some(WindowChange::Focus) => self.touch(id, (self.clock)()),
+                Some(WindowChange::Close) => self.forget(id),
+                _ => (),
+            }
+        }
+    }
+
+    /// Record that a container was focused at `at`.
+    pub fn touch(&mut self, con_id: u64, at: SystemTime) {
+        self.state.last_focus.insert(con_id, at);
+    }
+
+    /// Stop tracking a container, e.g. because it was closed.
+    pub fn forget(&mut self, con_id: u64) {
+        self.state.last_focus.remove(&con_id);
+        self.state.archived.remove(&con_id);
+    }
+
+    /// Decide which of `windows` (as `(con_id, workspace)` pairs) have been idle for too long.
+    ///
+    /// Windows we have never seen focused start counting from `now`, which gives everything that
+    /// existed before the archiver started a full idle period of grace. Tracking for containers
+    /// which no longer exist is dropped.
+    fn idle_windows(&mut self, windows: &[(u64, String)], now: SystemTime) -> Vec<(u64, String)> {
+        let state = &mut self.state;
+        state
+            .last_focus
+            .retain(|id, _| windows.iter().any(|(window, _)| window == id));
+        state
+            .archived
+            .retain(|id, _| windows.iter().any(|(window, _)| window == id));
+
+        let mut idle = Vec::new();
+        for (id, workspace) in windows {
+            let last_focus = *state.last_focus.entry(*id).or_insert(now);
+            if workspace == &self.config.workspace
+                || SKIPPED_WORKSPACES.contains(&workspace.as_str())
+            {
+                continue;
+            }
+            // A clock going backwards counts as not idle.
+            let elapsed = now.duration_since(last_focus).unwrap_or_default();
+            if elapsed >= self.config.idle {
+                idle.push((*id, workspace.clone()));
+            }
+        }
+        idle
+    }
+
+    /// Move every window which hasn't been focused for `config.idle` to the archive workspace.
+    /// All of the moves are sent as a single command. Returns the con_ids which were archived.
+    pub fn archive_idle<C: SwayClientJson>(&mut self, client: &mut C) -> Result<Vec<u64>> {
+        let windows = windows_by_workspace(&client.get_tree_json()?);
+        let now = (self.clock)();
+        let idle = self.idle_windows(&windows, now);
+        if idle.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let archive = &self.config.workspace;
+        let batch: Vec<String> = idle
+            .iter()
+            .map(|(id, _)| {
+                cmd!([con_id=*id] "move container to workspace {}", command::quote(archive))
+                    .to_string()
+            })
+            .collect();
+        client.run(batch.join("; "))?;
+
+        let mut archived = Vec::with_capacity(idle.len());
+        for (id, workspace) in idle {
+            self.state.archived.insert(id, workspace);
+            archived.push(id);
+        }
+        Ok(archived)
+    }
+
+    /// Move an archived container back to the workspace it was archived from. Returns `false` if
+    /// the container isn't archived. If the move fails, the container stays archived.
+    pub fn restore<C: SwayClientJson>(&mut self, client: &mut C, con_id: u64) -> Result<bool> {
+        let workspace = match self.state.archived.get(&con_id) {
+            Some(workspace) => workspace,
+            None => return Ok(false),
+        };
+        client.run_checked(
+            cmd!([con_id=con_id] "move container to workspace {}", command::quote(workspace)),
+        )?;
+        self.state.archived.remove(&con_id);
+        // Don't immediately archive it again.
+        self.touch(con_id, (self.clock)());
+        Ok(true)
+    }
+
+    /// Restore every archived container in a single command. Returns the con_ids which were
+    /// restored. If any of the moves fail, every container stays archived, so that calling this
+    /// again retries all of them.
+    pub fn restore_all<C: SwayClientJson>(&mut self, client: &mut C) -> Result<Vec<u64>> {
+        if self.state.archived.is_empty() {
+            return Ok(Vec::new());
+        }
+        let batch: Vec<String> = self
+            .state
+            .archived
+            .iter()
+            .map(|(id, workspace)| {
+                cmd!([con_id=*id] "move container to workspace {}", command::quote(workspace))
+                    .to_string()
+            })
+            .collect();
+        client.run_checked(batch.join("; "))?;
+
+        let now = (self.clock)();
+        let restored: Vec<u64> = self.state.archived.drain().map(|(id, _)| id).collect();
+        for &id in &restored {
+            self.touch(id, now);
+        }
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_windows() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut archiver = Archiver::new(ArchiveConfig {
+            idle: Duration::from_secs(60),
+            workspace: "archive".into(),
+        });
+        let windows = vec![
+            (1, "1".to_string()),
+            (2, "1".to_string()),
+            (3, "archive".to_string()),
+            (4, "__i3_scratch".to_string()),
+        ];
+        archiver.touch(5, start);
+
+        // Unseen windows get a grace period and the closed window is dropped.
+        assert!(archiver.idle_windows(&windows, start).is_empty());
+        assert!(!archiver.state().last_focus.contains_key(&5));
+
+        archiver.touch(2, start + Duration::from_secs(30));
+        assert_eq!(
+            archiver.idle_windows(&windows, start + Duration::from_secs(60)),
+            vec![(1, "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn archive_and_restore() {
+        use crate::testing::MockServer;
+        use crate::{ipc_command, Client};
+        use std::sync::{Arc, Mutex};
+
+        let server = MockServer::start().unwrap();
+        let tree = serde_json::json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "output", "name": "DP-1", "nodes": [
+                {"id": 3, "type": "workspace", "name": "2: mail", "nodes": [
+                    {"id": 4, "type": "con"},
+                    {"id": 5, "type": "con"},
+                ]},
+            ]},
+        ]});
+        server.reply(&ipc_command::get_tree(), tree.to_string());
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let now = Arc::new(Mutex::new(SystemTime::UNIX_EPOCH));
+        let advance = |secs: u64| *now.lock().unwrap() += Duration::from_secs(secs);
+        let mut archiver = Archiver::new(ArchiveConfig {
+            idle: Duration::from_secs(60),
+            workspace: "old stuff".into(),
+        })
+        .clock({
+            let now = Arc::clone(&now);
+            move || *now.lock().unwrap()
+        });
+
+        assert!(archiver.archive_idle(&mut client).unwrap().is_empty());
+        advance(30);
+        let focus = serde_json::json!({"change": "focus", "container": {"id": 5}});
+        archiver.handle_event(IpcEvent::Window, &focus);
+        advance(30);
+        assert_eq!(archiver.archive_idle(&mut client).unwrap(), vec![4]);
+        assert!(archiver.restore(&mut client, 4).unwrap());
+        advance(29);
+        assert!(archiver.archive_idle(&mut client).unwrap().is_empty());
+        server.assert_run_commands(&[
+            r#"[con_id="4"] move container to workspace "old stuff""#,
+            r#"[con_id="4"] move container to workspace "2: mail""#,
+        ]);
+    }
+
+    #[test]
+    fn failed_restore_keeps_records() {
+        use crate::testing::MockServer;
+        use crate::{ipc_command, Client};
+
+        let server = MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let mut archiver = Archiver::with_state(
+            ArchiveConfig::default(),
+            ArchiveState {
+                archived: vec![(4, "1".to_string()), (5, "2".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..ArchiveState::default()
+            },
+        );
+
+        let rejected = r#"[{"success": false, "error": "No matching node."}]"#;
+        server.reply(&ipc_command::run(""), rejected);
+        server.reply(&ipc_command::run(""), rejected);
+        server.reply(
+            &ipc_command::run(""),
+            r#"[{"success": true}, {"success": true}]"#,
+        );
+        assert!(archiver.restore(&mut client, 4).is_err());
+        assert!(archiver.restore_all(&mut client).is_err());
+        assert_eq!(archiver.state().archived.len(), 2);
+
+        let mut restored = archiver.restore_all(&mut client).unwrap();
+        restored.sort();
+        assert_eq!(restored, vec![4, 5]);
+        assert!(archiver.state().archived.is_empty());
+    }
+
+    #[test]
+    fn windows_by_workspace() {
+        let tree = serde_json::json!({
+            "id": 1, "type": "root", "nodes": [{
+                "id": 2, "type": "output", "name": "DP-1", "nodes": [{
+                    "id": 3, "type": "workspace", "name": "1",
+                    "nodes": [
+                        {"id": 4, "type": "con", "nodes": []},
+                        {"id": 5, "type": "con", "nodes": [{"id": 6, "type": "con", "nodes": []}]},
+                    ],
+                    "floating_nodes": [{"id": 7, "type": "floating_con", "nodes": []}],
+                }],
+            }],
+        });
+        assert_eq!(
+            super::windows_by_workspace(&tree),
+            vec![(4, "1".into()), (6, "1".into()), (7, "1".into())]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! An async client on top of tokio, enabled with the `tokio` feature.
+//!
+//! Like `ksway::Client`, a connection is either used for commands or for events. Subscribing
+//! consumes the client and turns the connection into an `EventStream`, so use a second client for
+//! commands:
+//! ```no_run
+//! # async fn example() -> ksway::Result<()> {
+//! use ksway::{async_client::Client, IpcEvent};
+//!
+//! let mut client = Client::connect().await?;
+//! let mut events = Client::connect().await?.subscribe(vec![IpcEvent::Window]).await?;
+//! while let Some(event) = events.next().await {
+//!     let (event, payload) = event?;
+//!     client.run("nop").await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::future::poll_fn;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
+use tokio::net::UnixStream;
+
+use crate::core::{self, Decoder, Message, MessageKind, Skipped, Step};
+use crate::{guess_sway_socket_path, ipc_command, Error, EventMask, IpcCommand, IpcEvent, Result};
+
+/// Read from `socket` into `decoder` until it has a message, or `None` at the end of the stream.
+/// Without a warnings channel, of what the decoder skips only oversized replies are reported, as
+/// `Error::OversizedReply`, since a command may be waiting for them.
+fn poll_message(
+    socket: &mut UnixStream,
+    decoder: &mut Decoder,
+    cx: &mut Context<'_>,
+) -> Poll<Result<Option<Message>>> {
+    let mut buffer = [0u8; 4096];
+    let mut payload = Vec::new();
+    loop {
+        match decoder.step(&mut payload) {
+            Step::Frame(payload_type) => {
+                return Poll::Ready(Ok(Some(Message::from_frame(payload_type, payload))))
+            }
+            Step::Skipped(Skipped::Oversized { len, payload_type })
+                if !MessageKind::is_event(payload_type) =>
+            {
+                return Poll::Ready(Err(Error::OversizedReply {
+                    len,
+                    max: decoder.max_payload(),
+                }))
+            }
+            Step::Skipped(_) => continue,
+            Step::Incomplete => {}
+        }
+        let mut read = ReadBuf::new(&mut buffer);
+        match Pin::new(&mut *socket).poll_read(cx, &mut read) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+            Poll::Ready(Ok(())) if read.filled().is_empty() => {
+                return Poll::Ready(if decoder.buffered() == 0 {
+                    Ok(None)
+                } else {
+                    Err(Error::Disconnected)
+                })
+            }
+            Poll::Ready(Ok(())) => decoder.feed(read.filled()),
+        }
+    }
+}
+
+pub struct Client {
+    socket: UnixStream,
+    socket_path: PathBuf,
+    decoder: Decoder,
+}
+
+impl Client {
+    /// Connect to a specific socket.
+    pub async fn connect_to_path<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let socket = UnixStream::connect(&path).await?;
+        Ok(Self {
+            socket,
+            socket_path: path,
+            decoder: Decoder::new(),
+        })
+    }
+
+    /// Like `ksway::Client::connect()`, see `guess_sway_socket_path()`.
+    pub async fn connect() -> Result<Self> {
+        Self::connect_to_path(guess_sway_socket_path()?).await
+    }
+
+    /// The socket path that we are currently connected to.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// See `ksway::Client::set_max_payload()`.
+    pub fn set_max_payload(&mut self, max: u32) {
+        self.decoder.set_max_payload(max);
+    }
+
+    /// Send an ipc command and wait for its reply, see `ksway::Client::ipc()`.
+    pub async fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
+        let expected = command.code() as u32;
+        self.socket.write_all(&core::encode(&command)).await?;
+        loop {
+            let message =
+                poll_fn(|cx| poll_message(&mut self.socket, &mut self.decoder, cx)).await?;
+            match message {
+                Some(Message::Reply { code, payload }) if code != expected => {
+                    return Err(Error::UnexpectedReply {
+                        expected,
+                        received: code,
+                        reply: payload,
+                    })
+                }
+                Some(Message::Reply { payload, .. }) => return Ok(payload),
+                // Not subscribed, so there shouldn't be any.
+                Some(Message::Event { .. }) => continue,
+                None => return Err(Error::Disconnected),
+            }
+        }
+    }
+
+    /// Alias for `client.ipc(ipc_command::run(...))`.
+    pub async fn run<T: ToString>(&mut self, command: T) -> Result<Vec<u8>> {
+        self.ipc(ipc_command::run(command.to_string())).await
+    }
+
+    /// Subscribe to events, turning this connection into a stream of them.
+    pub async fn subscribe<E: Into<EventMask>>(mut self, event_types: E) -> Result<EventStream> {
+        self.ipc(ipc_command::subscribe(event_types)).await?;
+        Ok(EventStream {
+            socket: self.socket,
+            decoder: self.decoder,
+        })
+    }
+}
+
+/// The events of a subscribed connection. It ends when sway closes the connection.
+pub struct EventStream {
+    socket: UnixStream,
+    decoder: Decoder,
+}
+
+impl EventStream {
+    /// The next event, for use without `futures::StreamExt`.
+    pub async fn next(&mut self) -> Option<Result<(IpcEvent, Vec<u8>)>> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<(IpcEvent, Vec<u8>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            return match poll_message(&mut this.socket, &mut this.decoder, cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+                Poll::Ready(Ok(None)) => Poll::Ready(None),
+                Poll::Ready(Ok(Some(Message::Event { code, payload }))) => {
+                    match code.event_type() {
+                        Some(event) => Poll::Ready(Some(Ok((event, payload)))),
+                        // Events we don't know the type of are dropped, like `ksway::Client` does.
+                        None => continue,
+                    }
+                }
+                // The reply to a subscribe which was sent on this connection.
+                Poll::Ready(Ok(Some(Message::Reply { .. }))) => continue,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn commands_and_events() {
+        let server = MockServer::start().unwrap();
+        server.reply(&ipc_command::get_tree(), "{}");
+        block_on(async {
+            let mut client = Client::connect_to_path(server.path()).await.unwrap();
+            assert_eq!(client.run("nop").await.unwrap(), br#"[{"success":true}]"#);
+            assert_eq!(client.ipc(ipc_command::get_tree()).await.unwrap(), b"{}");
+
+            let mut events = Client::connect_to_path(server.path())
+                .await
+                .unwrap()
+                .subscribe(vec![IpcEvent::Window])
+                .await
+                .unwrap();
+            server
+                .send_event(crate::EventCode::from_raw(0x8000_00ff).unwrap(), b"{}")
+                .unwrap();
+            server
+                .send_event(IpcEvent::Window, br#"{"change":"focus"}"#)
+                .unwrap();
+            // The unknown event is skipped.
+            let (event, payload) = events.next().await.unwrap().unwrap();
+            assert_eq!(event, IpcEvent::Window);
+            assert_eq!(payload, br#"{"change":"focus"}"#);
+
+            server.disconnect();
+            assert!(events.next().await.is_none());
+            match client.run("nop").await {
+                Err(Error::Disconnected) | Err(Error::Io(_)) => {}
+                other => panic!("{:?}", other),
+            }
+        });
+        server.assert_run_commands(&["nop"]);
+    }
+}
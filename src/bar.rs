@@ -0,0 +1,431 @@
+//! Writing status lines for swaybar with the i3bar protocol.
+//!
+//! The protocol is a header followed by an endless JSON array of status lines, each of which is
+//! an array of `Block`s. With click events enabled, swaybar writes an endless JSON array of
+//! `ClickEvent`s to our stdin, one per line.
+//!
+//! `BarState` turns workspace, mode and window events into blocks, so a status generator only
+//! writes a new line when something actually changed instead of polling. With
+//! `BarState::with_format()`, workspaces are shown like `workspace::workspace_display_name()`
+//! renders them, so a bar and a workspace renamer using the same options agree:
+//! ```no_run
+//! use ksway::{bar::{BarState, BarWriter}, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut bar = BarState::new(&mut client)?;
+//! let mut writer = BarWriter::new(std::io::stdout(), true);
+//! let clicks = ksway::bar::read_click_events(std::io::BufReader::new(std::io::stdin()));
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Workspace, IpcEvent::Mode, IpcEvent::Window])?;
+//! writer.write(&bar.blocks())?;
+//! loop {
+//!     let mut changed = false;
+//!     while let Ok((payload_type, payload)) = rx.try_recv() {
+//!         let payload = serde_json::from_slice(&payload)?;
+//!         changed |= bar.handle_event(&mut client, payload_type, &payload)?;
+//!     }
+//!     while let Ok(click) = clicks.try_recv() {
+//!         bar.handle_click(&mut client, &click)?;
+//!     }
+//!     if changed {
+//!         writer.write(&bar.blocks())?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::thread;
+
+use crossbeam_channel as chan;
+use serde::{Deserialize, Serialize};
+
+use crate::events::WindowChange;
+use crate::tree::{self, NodeKind};
+use crate::workspace::{workspace_display_name, FormatOptions};
+use crate::{command, IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// One block of a status line. Only `full_text` is required, see `man swaybar-protocol` for the
+/// meaning of the rest.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Block {
+    pub full_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<String>,
+    /// Identifies the block in click events, along with `instance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub urgent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<bool>,
+}
+
+impl Block {
+    pub fn new<S: Into<String>>(full_text: S) -> Self {
+        Self {
+            full_text: full_text.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn named<S: Into<String>>(mut self, name: S, instance: Option<String>) -> Self {
+        self.name = Some(name.into());
+        self.instance = instance;
+        self
+    }
+
+    pub fn color<S: Into<String>>(mut self, color: S) -> Self {
+        self.color = Some(color.into());
+        self
+    }
+}
+
+/// A click on a block, as written by swaybar to stdin.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClickEvent {
+    pub name: Option<String>,
+    pub instance: Option<String>,
+    pub button: u32,
+    #[serde(default)]
+    pub x: i64,
+    #[serde(default)]
+    pub y: i64,
+}
+
+/// Parse one line of swaybar's click event stream. The opening `[` and the commas between
+/// events are skipped, and give `None`.
+pub fn parse_click_event(line: &str) -> Result<Option<ClickEvent>> {
+    let line = line.trim().trim_start_matches(['[', ',']);
+    if line.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(line)?))
+}
+
+/// Read click events from `reader`, usually stdin, on a background thread. Lines which can't be
+/// parsed are skipped. The channel disconnects when `reader` is exhausted.
+pub fn read_click_events<R: BufRead + Send + 'static>(reader: R) -> chan::Receiver<ClickEvent> {
+    let (tx, rx) = chan::unbounded();
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Ok(Some(event)) = parse_click_event(&line) {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Writes the i3bar protocol, starting with the header on the first status line.
+pub struct BarWriter<W: Write> {
+    out: W,
+    click_events: bool,
+    started: bool,
+}
+
+impl<W: Write> BarWriter<W> {
+    /// Set `click_events` to have swaybar send `ClickEvent`s to stdin.
+    pub fn new(out: W, click_events: bool) -> Self {
+        Self {
+            out,
+            click_events,
+            started: false,
+        }
+    }
+
+    /// Write a status line, replacing the previous one.
+    pub fn write(&mut self, blocks: &[Block]) -> Result<()> {
+        if !self.started {
+            writeln!(
+                self.out,
+                "{{\"version\":1,\"click_events\":{}}}\n[",
+                self.click_events
+            )?;
+            self.started = true;
+        }
+        serde_json::to_writer(&mut self.out, blocks)?;
+        self.out.write_all(b",\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct WorkspaceState {
+    name: String,
+    /// What the block shows, the name unless formatted.
+    text: String,
+    focused: bool,
+    visible: bool,
+    urgent: bool,
+}
+
+/// The workspaces, binding mode and focused window title, kept up to date from events.
+#[derive(Debug, Default)]
+pub struct BarState {
+    format: Option<FormatOptions>,
+    workspaces: Vec<WorkspaceState>,
+    mode: Option<String>,
+    title: Option<String>,
+}
+
+impl BarState {
+    /// Start from the current workspaces and focused window.
+    pub fn new<C: SwayClientJson>(client: &mut C) -> Result<Self> {
+        Self::start(client, None)
+    }
+
+    /// Like `new()`, but show workspaces with `workspace_display_name()`. Their windows are then
+    /// tracked too, so window events which can change the icons cost a GET_TREE.
+    pub fn with_format<C: SwayClientJson>(client: &mut C, format: FormatOptions) -> Result<Self> {
+        Self::start(client, Some(format))
+    }
+
+    fn start<C: SwayClientJson>(client: &mut C, format: Option<FormatOptions>) -> Result<Self> {
+        let mut state = Self {
+            format,
+            ..Self::default()
+        };
+        state.refresh_workspaces(client)?;
+        state.title = client
+            .focused_window()?
+            .and_then(|window| window["name"].as_str().map(ToOwned::to_owned));
+        Ok(state)
+    }
+
+    fn refresh_workspaces<C: SwayClientJson>(&mut self, client: &mut C) -> Result<()> {
+        let workspaces = client.get_workspaces_json()?;
+        let texts: HashMap<String, String> = match &self.format {
+            Some(format) => {
+                let tree = client.get_tree_json()?;
+                tree::nodes(&tree)
+                    .filter(|node| node.kind == NodeKind::Workspace)
+                    .filter_map(|ws| {
+                        let name = ws.node.name()?.to_owned();
+                        Some((name, workspace_display_name(ws.node, format)))
+                    })
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+        self.workspaces = workspaces
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|ws| {
+                let name = ws["name"].as_str()?;
+                Some(WorkspaceState {
+                    name: name.to_owned(),
+                    text: texts.get(name).map_or(name, String::as_str).to_owned(),
+                    focused: ws["focused"].as_bool().unwrap_or(false),
+                    visible: ws["visible"].as_bool().unwrap_or(false),
+                    urgent: ws["urgent"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Apply a mode or window event, returning whether anything changed. Workspace events only
+    /// say that something changed, so they are handled by `handle_event()`.
+    fn apply(&mut self, event: IpcEvent, payload: &JsonValue) -> bool {
+        match event {
+            IpcEvent::Mode => {
+                let mode = payload["change"]
+                    .as_str()
+                    .filter(|mode| *mode != "default")
+                    .map(ToOwned::to_owned);
+                let changed = mode != self.mode;
+                self.mode = mode;
+                changed
+            }
+            IpcEvent::Window => {
+                let container = &payload["container"];
+                let title = container.name().map(ToOwned::to_owned);
+                let changed = match WindowChange::from_payload(payload) {
+                    Some(WindowChange::Focus) => title != self.title,
+                    Some(WindowChange::Title) if container.is_focused() => title != self.title,
+                    _ => false,
+                };
+                if changed {
+                    self.title = title;
+                }
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    /// Feed a subscription event, returning whether the blocks changed.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<bool> {
+        let icons_changed = self.format.is_some()
+            && event == IpcEvent::Window
+            && matches!(
+                WindowChange::from_payload(payload),
+                Some(WindowChange::New | WindowChange::Close | WindowChange::Move)
+            );
+        if event == IpcEvent::Workspace || icons_changed {
+            let changed = self.apply(event, payload);
+            let previous = std::mem::take(&mut self.workspaces);
+            self.refresh_workspaces(client)?;
+            return Ok(changed || previous != self.workspaces);
+        }
+        Ok(self.apply(event, payload))
+    }
+
+    /// Switch to a workspace when its block is clicked with the left button.
+    pub fn handle_click<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        click: &ClickEvent,
+    ) -> Result<()> {
+        if let (Some("workspace"), Some(name), 1) =
+            (click.name.as_deref(), &click.instance, click.button)
+        {
+            client.run(format!("workspace {}", command::quote(name)))?;
+        }
+        Ok(())
+    }
+
+    /// The workspaces, followed by the binding mode if it isn't the default one, and the title of
+    /// the focused window.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = self
+            .workspaces
+            .iter()
+            .map(|ws| {
+                let block = Block {
+                    urgent: ws.urgent,
+                    ..Block::new(ws.text.clone()).named("workspace", Some(ws.name.clone()))
+                };
+                match (ws.focused, ws.visible) {
+                    (true, _) => block.color("#ffffff"),
+                    (false, true) => block.color("#aaaaaa"),
+                    (false, false) => block.color("#666666"),
+                }
+            })
+            .collect();
+        if let Some(mode) = &self.mode {
+            blocks.push(
+                Block::new(mode.clone())
+                    .named("mode", None)
+                    .color("#ff8800"),
+            );
+        }
+        if let Some(title) = &self.title {
+            blocks.push(Block::new(title.clone()).named("title", None));
+        }
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn protocol_output() {
+        let mut writer = BarWriter::new(Vec::new(), true);
+        writer
+            .write(&[Block::new("1").named("workspace", Some("1".into()))])
+            .unwrap();
+        writer.write(&[]).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "{\"version\":1,\"click_events\":true}\n[\n\
+             [{\"full_text\":\"1\",\"name\":\"workspace\",\"instance\":\"1\"}],\n\
+             [],\n"
+        );
+
+        assert_eq!(parse_click_event("[").unwrap(), None);
+        assert_eq!(
+            parse_click_event(",{\"name\":\"workspace\",\"instance\":\"2\",\"button\":1}").unwrap(),
+            Some(ClickEvent {
+                name: Some("workspace".into()),
+                instance: Some("2".into()),
+                button: 1,
+                x: 0,
+                y: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn mode_and_title() {
+        let mut state = BarState::default();
+        assert!(state.apply(IpcEvent::Mode, &json!({"change": "resize"})));
+        assert!(!state.apply(IpcEvent::Mode, &json!({"change": "resize"})));
+        assert!(state.apply(
+            IpcEvent::Window,
+            &json!({"change": "focus", "container": {"name": "vim"}})
+        ));
+        assert!(!state.apply(
+            IpcEvent::Window,
+            &json!({"change": "title", "container": {"name": "other", "focused": false}})
+        ));
+        let texts: Vec<_> = state.blocks().into_iter().map(|b| b.full_text).collect();
+        assert_eq!(texts, vec!["resize", "vim"]);
+        assert!(state.apply(IpcEvent::Mode, &json!({"change": "default"})));
+        assert_eq!(state.blocks().len(), 1);
+    }
+
+    #[test]
+    fn formatted_workspaces() {
+        use crate::testing::MockServer;
+        use crate::{ipc_command, Client};
+
+        let server = MockServer::start().unwrap();
+        let workspaces = json!([{"name": "1:web", "focused": true}, {"name": "2"}]).to_string();
+        let tree = |app_id: &str| {
+            json!({"id": 1, "type": "root", "nodes": [
+                {"id": 2, "type": "workspace", "name": "1:web", "nodes": [
+                    {"id": 4, "type": "con", "app_id": app_id},
+                ]},
+                {"id": 3, "type": "workspace", "name": "2"},
+            ]})
+            .to_string()
+        };
+        server.reply(&ipc_command::get_workspaces(), workspaces);
+        server.reply(&ipc_command::get_tree(), tree("firefox"));
+        server.reply(&ipc_command::get_tree(), tree("foot"));
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let format = FormatOptions::new().icon("firefox", "F").icon("foot", "T");
+        let mut state = BarState::with_format(&mut client, format).unwrap();
+        let texts = |state: &BarState| -> Vec<_> {
+            state.blocks().into_iter().map(|b| b.full_text).collect()
+        };
+        assert_eq!(texts(&state), vec!["1: F", "2"]);
+        assert_eq!(state.blocks()[0].instance.as_deref(), Some("1:web"));
+        let new = json!({"change": "new", "container": {"id": 4}});
+        assert!(state
+            .handle_event(&mut client, IpcEvent::Window, &new)
+            .unwrap());
+        assert_eq!(texts(&state), vec!["1: T", "2"]);
+    }
+}
@@ -0,0 +1,146 @@
+//! `ksway`, a swaymsg with extras: extracting a path from replies, filtering the tree with
+//! criteria and streaming events as JSON lines. Built with the `cli` feature:
+//!
+//!     cargo install ksway --features cli
+//!     ksway run workspace 2
+//!     ksway get-tree --match app_id=foot --match floating --path 0/id
+//!     ksway get-tree --where 'type==con && rect/width>500'
+//!     ksway focused-window --path rect/width
+//!     ksway subscribe --json-lines window workspace
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use ksway::criteria::{Criteria, Matcher};
+use ksway::json::path::Path;
+use ksway::query::Query;
+use ksway::{tree, Client, Command, IpcEvent, JsonValue, SwayClientJson};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "ksway", about = "Talk to sway over its IPC socket.")]
+struct Opt {
+    /// The socket to connect to, instead of guessing it.
+    #[structopt(short, long, global = true, parse(from_os_str))]
+    socket: Option<PathBuf>,
+    /// Only print the value at this path of each reply or event, e.g. `rect/width` or `0/name`.
+    /// Missing values are `null`.
+    #[structopt(short, long, global = true)]
+    path: Option<Path>,
+    /// Pretty print JSON, except for `subscribe --json-lines`.
+    #[structopt(long, global = true)]
+    pretty: bool,
+    #[structopt(subcommand)]
+    command: Subcommand,
+}
+
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Run a command, with the arguments joined like swaymsg does. Fails if sway reports an
+    /// error.
+    Run {
+        #[structopt(required = true)]
+        command: Vec<String>,
+    },
+    /// Print the tree, or with criteria or a query, the containers matching all of them.
+    GetTree {
+        /// A criteria as between the brackets of a sway command, e.g. `app_id=foot`.
+        #[structopt(short, long = "match")]
+        criteria: Vec<Criteria>,
+        /// A query over the containers' JSON, e.g. `type==con && rect/width>500`, see
+        /// `ksway::query`.
+        #[structopt(short, long = "where")]
+        query: Option<Query>,
+    },
+    /// Print the workspaces.
+    GetWorkspaces,
+    /// Print events as they arrive, until sway exits.
+    Subscribe {
+        /// Print one `{"event": ..., "payload": ...}` object per line, instead of the payloads.
+        #[structopt(long)]
+        json_lines: bool,
+        /// The events to subscribe to, e.g. `window` or `workspace`.
+        #[structopt(required = true, parse(try_from_str = parse_event))]
+        events: Vec<IpcEvent>,
+    },
+    /// Print the focused window, or `null` if there is none.
+    FocusedWindow,
+}
+
+fn parse_event(name: &str) -> Result<IpcEvent> {
+    serde_json::from_value(JsonValue::String(name.to_owned()))
+        .with_context(|| format!("unknown event: {}", name))
+}
+
+fn extract(value: JsonValue, path: Option<&Path>) -> JsonValue {
+    match path {
+        Some(path) => path.take(value).unwrap_or(JsonValue::Null),
+        None => value,
+    }
+}
+
+fn print(out: &mut impl Write, value: &JsonValue, pretty: bool) -> Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(&mut *out, value)?;
+    } else {
+        serde_json::to_writer(&mut *out, value)?;
+    }
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let mut client = match &opt.socket {
+        Some(socket) => Client::connect_to_path(socket),
+        None => Client::connect(),
+    }
+    .context("couldn't connect to sway")?;
+    let path = opt.path.as_ref();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let reply = match opt.command {
+        Subcommand::Run { command } => {
+            serde_json::to_value(client.run_checked(Command::from_args(&command))?)?
+        }
+        Subcommand::GetTree { criteria, query } if criteria.is_empty() && query.is_none() => {
+            client.get_tree_json()?
+        }
+        Subcommand::GetTree { criteria, query } => {
+            let tree = client.get_tree_json()?;
+            let matcher = Matcher::new(&criteria)?.focused_in(&tree);
+            tree::nodes(&tree)
+                .filter(|node| matcher.matches(node))
+                .filter(|node| {
+                    query
+                        .as_ref()
+                        .map_or(true, |query| query.matches(node.node))
+                })
+                .map(|node| node.node.clone())
+                .collect()
+        }
+        Subcommand::GetWorkspaces => client.get_workspaces_json()?,
+        Subcommand::FocusedWindow => client.focused_window()?.unwrap_or_default(),
+        Subcommand::Subscribe { json_lines, events } => {
+            let rx = client.subscribe(events)?;
+            loop {
+                while let Ok((event, payload)) = rx.try_recv() {
+                    let payload = extract(serde_json::from_slice(&payload)?, path);
+                    if json_lines {
+                        let line = serde_json::json!({ "event": event, "payload": payload });
+                        print(&mut out, &line, false)?;
+                    } else {
+                        print(&mut out, &payload, opt.pretty)?;
+                    }
+                }
+                match client.poll() {
+                    Err(ksway::Error::Disconnected) => return Ok(()),
+                    polled => polled?,
+                }
+            }
+        }
+    };
+    print(&mut out, &extract(reply, path), opt.pretty)
+}
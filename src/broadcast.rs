@@ -0,0 +1,151 @@
+//! Fanning events out to components which can start listening at any time.
+//!
+//! A component created late, e.g. a bar widget for an output that was just plugged in, would
+//! otherwise need to query the current workspaces and outputs itself before it can make sense of
+//! events. `StateBroadcaster` instead starts every new subscriber off with events describing the
+//! current state, then streams the live events it is fed:
+//! ```no_run
+//! use ksway::{broadcast::StateBroadcaster, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut broadcaster = StateBroadcaster::new();
+//! let rx = client.subscribe(vec![IpcEvent::Workspace, IpcEvent::Output])?;
+//! let widget = broadcaster.subscribe(&mut client)?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         broadcaster.handle_event(event, &serde_json::from_slice(&payload)?);
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use crossbeam_channel as chan;
+use serde_json::json;
+
+use crate::{IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// Hands every event it is fed to all of its subscribers, after replaying the current state to
+/// each new one:
+///
+/// - an `Output` event per output, with `change` set to `init` and the GET_OUTPUTS entry as
+///   `output`, which sway's own output events don't have;
+/// - a `Workspace` event per workspace with `change` set to `init` and the GET_WORKSPACES entry
+///   as `current`, just like sway's own when a workspace is created;
+/// - a `Workspace` event with `change` set to `focus` for the focused workspace.
+///
+/// Events which were already received from sway but not yet fed when a subscriber attaches are
+/// still delivered to it, so they can repeat what the replayed state already says.
+#[derive(Debug, Default)]
+pub struct StateBroadcaster {
+    subscribers: Vec<chan::Sender<(IpcEvent, JsonValue)>>,
+}
+
+/// The events describing the current outputs and workspaces, in the order they are replayed.
+fn initial_events(outputs: &[JsonValue], workspaces: &[JsonValue]) -> Vec<(IpcEvent, JsonValue)> {
+    let mut events: Vec<_> = outputs
+        .iter()
+        .map(|output| {
+            (
+                IpcEvent::Output,
+                json!({"change": "init", "output": output}),
+            )
+        })
+        .collect();
+    events.extend(workspaces.iter().map(|workspace| {
+        (
+            IpcEvent::Workspace,
+            json!({"change": "init", "current": workspace, "old": null}),
+        )
+    }));
+    if let Some(focused) = workspaces.iter().find(|workspace| workspace.is_focused()) {
+        events.push((
+            IpcEvent::Workspace,
+            json!({"change": "focus", "current": focused, "old": null}),
+        ));
+    }
+    events
+}
+
+impl StateBroadcaster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a subscriber, whose channel starts with the replayed state, queried with `client`.
+    pub fn subscribe<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+    ) -> Result<chan::Receiver<(IpcEvent, JsonValue)>> {
+        let outputs = list(client.get_outputs_json()?);
+        let workspaces = list(client.get_workspaces_json()?);
+        let (tx, rx) = chan::unbounded();
+        for event in initial_events(&outputs, &workspaces) {
+            // The receiver is still alive.
+            let _ = tx.send(event);
+        }
+        self.subscribers.push(tx);
+        Ok(rx)
+    }
+
+    /// The number of subscribers whose receiver was still alive at the last event.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// Feed a live event, which is passed on to every subscriber. Subscribers whose receiver was
+    /// dropped are forgotten.
+    pub fn handle_event(&mut self, event: IpcEvent, payload: &JsonValue) {
+        self.subscribers
+            .retain(|tx| tx.send((event, payload.clone())).is_ok());
+    }
+}
+
+fn list(value: JsonValue) -> Vec<JsonValue> {
+    match value {
+        JsonValue::Array(values) => values,
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::{ipc_command, Client};
+
+    #[test]
+    fn replay_then_stream() {
+        let server = MockServer::start().unwrap();
+        server.reply(&ipc_command::get_outputs(), r#"[{"name": "DP-1"}]"#);
+        server.reply(
+            &ipc_command::get_workspaces(),
+            r#"[{"id": 3, "name": "1", "focused": false}, {"id": 4, "name": "2", "focused": true}]"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let mut broadcaster = StateBroadcaster::new();
+
+        let early = broadcaster.subscribe(&mut client).unwrap();
+        assert_eq!(early.len(), 4);
+        broadcaster.handle_event(IpcEvent::Mode, &json!({"change": "resize"}));
+        let late = broadcaster.subscribe(&mut client).unwrap();
+        let replayed: Vec<_> = late
+            .try_iter()
+            .map(|(event, payload)| (event, payload["change"].clone()))
+            .collect();
+        assert_eq!(
+            replayed,
+            vec![
+                (IpcEvent::Output, json!("init")),
+                (IpcEvent::Workspace, json!("init")),
+                (IpcEvent::Workspace, json!("init")),
+                (IpcEvent::Workspace, json!("focus")),
+            ]
+        );
+        assert_eq!(early.try_iter().last().unwrap().0, IpcEvent::Mode);
+
+        drop(early);
+        broadcaster.handle_event(IpcEvent::Output, &json!({"change": "unspecified"}));
+        assert_eq!(broadcaster.subscriber_count(), 1);
+        assert_eq!(late.try_recv().unwrap().0, IpcEvent::Output);
+    }
+}
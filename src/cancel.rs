@@ -0,0 +1,97 @@
+//! Stopping long-running helpers from another thread.
+//!
+//! Helpers which loop until something happens, e.g. iterating over `Client::events()` or
+//! waiting for windows to close in `process::terminate_cancellable()`, check a `CancelToken`
+//! between iterations and stop with `Error::Cancelled` once it is cancelled. The same token can
+//! be handed to any number of them, to stop everything at once:
+//! ```no_run
+//! use ksway::{CancelToken, IpcEvent};
+//!
+//! let token = CancelToken::new();
+//! let events = ksway::Client::connect()?
+//!     .events(vec![IpcEvent::Window])?
+//!     .cancel_on(token.clone())?;
+//! let watcher = std::thread::spawn(move || events.count());
+//! token.cancel();
+//! watcher.join().unwrap();
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+/// How often blocking loops wake up to check their token.
+pub(crate) const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A flag shared between clones, which is set once and stays set.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop everything holding a clone of this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Error::Cancelled` once cancelled, for use with `?` in loops.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleep for `duration`, but wake up every `CHECK_INTERVAL` to stop early with
+    /// `Error::Cancelled`.
+    pub(crate) fn sleep(&self, duration: Duration) -> Result<()> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(remaining.min(CHECK_INTERVAL));
+        }
+    }
+}
+
+/// Clones of the same token are equal.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_between_clones() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(matches!(clone.check(), Err(Error::Cancelled)));
+        assert!(matches!(
+            clone.sleep(Duration::from_secs(60)),
+            Err(Error::Cancelled)
+        ));
+        assert_eq!(token, clone);
+        assert_ne!(token, CancelToken::new());
+    }
+}
@@ -0,0 +1,216 @@
+//! Window metadata for driving external screenshot tools, e.g. `grim` for the thumbnails of a
+//! window switcher.
+//!
+//! `WindowTracker` keeps a snapshot of every window's geometry, output and visibility, and
+//! reports what changed whenever it is refreshed. Those changes are exactly the windows which
+//! need to be (re)captured or dropped:
+//! ```no_run
+//! use ksway::{capture::{WindowChange, WindowTracker}, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut tracker = WindowTracker::new();
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Window, IpcEvent::Workspace])?;
+//! for change in tracker.refresh(&mut client)? {
+//!     println!("{:?}", change);
+//! }
+//! loop {
+//!     while let Ok((payload_type, payload)) = rx.try_recv() {
+//!         let payload = serde_json::from_slice(&payload)?;
+//!         if WindowTracker::is_relevant(payload_type, &payload) {
+//!             for change in tracker.refresh(&mut client)? {
+//!                 if let WindowChange::Added(window) | WindowChange::Changed(window) = change {
+//!                     if window.visible {
+//!                         println!("grim -g '{}' {}.png", window.grim_geometry(), window.con_id);
+//!                     }
+//!                 }
+//!             }
+//!         }
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events;
+use crate::json::{windows, WindowRef};
+use crate::tree::app_identifier;
+use crate::{IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// A window's position and size in the global coordinate space, in pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+/// Everything a capture tool needs to know about a window.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowMetadata {
+    pub con_id: u64,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub geometry: Geometry,
+    pub output: String,
+    pub workspace: String,
+    /// Whether the window is currently shown on screen and can be captured.
+    pub visible: bool,
+    pub focused: bool,
+}
+
+impl WindowMetadata {
+    fn from_window(window: &WindowRef) -> Option<Self> {
+        let node = window.node;
+        let rect = node.rect()?;
+        Some(Self {
+            con_id: node.con_id()?,
+            app_id: app_identifier(node).map(ToOwned::to_owned),
+            title: node.name().map(ToOwned::to_owned),
+            geometry: Geometry {
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+            },
+            output: window.output.to_owned(),
+            workspace: window.workspace.to_owned(),
+            visible: node["visible"].as_bool().unwrap_or(false),
+            focused: node.is_focused(),
+        })
+    }
+
+    /// The geometry in the `X,Y WxH` format that `grim -g` and `slurp` use.
+    pub fn grim_geometry(&self) -> String {
+        let g = &self.geometry;
+        format!("{},{} {}x{}", g.x, g.y, g.width, g.height)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum WindowChange {
+    /// A window which wasn't in the previous snapshot.
+    Added(WindowMetadata),
+    /// A window whose metadata differs from the previous snapshot.
+    Changed(WindowMetadata),
+    /// The con_id of a window which is gone.
+    Removed(u64),
+}
+
+/// Compute the changes between two snapshots, ordered by con_id with removals first.
+fn diff(
+    old: &BTreeMap<u64, WindowMetadata>,
+    new: &BTreeMap<u64, WindowMetadata>,
+) -> Vec<WindowChange> {
+    let removed = old
+        .keys()
+        .filter(|id| !new.contains_key(id))
+        .map(|id| WindowChange::Removed(*id));
+    let updated = new
+        .values()
+        .filter_map(|window| match old.get(&window.con_id) {
+            None => Some(WindowChange::Added(window.clone())),
+            Some(previous) if previous != window => Some(WindowChange::Changed(window.clone())),
+            Some(_) => None,
+        });
+    removed.chain(updated).collect()
+}
+
+#[derive(Debug, Default)]
+pub struct WindowTracker {
+    windows: BTreeMap<u64, WindowMetadata>,
+}
+
+impl WindowTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current snapshot, ordered by con_id.
+    pub fn windows(&self) -> impl Iterator<Item = &WindowMetadata> {
+        self.windows.values()
+    }
+
+    pub fn get(&self, con_id: u64) -> Option<&WindowMetadata> {
+        self.windows.get(&con_id)
+    }
+
+    /// Whether an event can change window metadata, i.e. if it's worth calling `refresh()`.
+    /// Workspace events matter because switching workspaces changes which windows are visible.
+    pub fn is_relevant(event: IpcEvent, payload: &JsonValue) -> bool {
+        match event {
+            IpcEvent::Window => {
+                events::WindowChange::from_payload(payload) != Some(events::WindowChange::Mark)
+            }
+            IpcEvent::Workspace => true,
+            _ => false,
+        }
+    }
+
+    /// Take a new snapshot from GET_TREE and return how it differs from the previous one. The
+    /// first refresh reports every window as added.
+    pub fn refresh<C: SwayClientJson>(&mut self, client: &mut C) -> Result<Vec<WindowChange>> {
+        let tree = client.get_tree_json()?;
+        let windows: BTreeMap<u64, WindowMetadata> = windows(&tree)
+            .iter()
+            .filter_map(WindowMetadata::from_window)
+            .map(|window| (window.con_id, window))
+            .collect();
+        let changes = diff(&self.windows, &windows);
+        self.windows = windows;
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(con_id: u64, x: i64, visible: bool) -> WindowMetadata {
+        WindowMetadata {
+            con_id,
+            app_id: Some("foot".into()),
+            title: None,
+            geometry: Geometry {
+                x,
+                y: 0,
+                width: 100,
+                height: 50,
+            },
+            output: "DP-1".into(),
+            workspace: "1".into(),
+            visible,
+            focused: false,
+        }
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let snapshot = |windows: Vec<WindowMetadata>| -> BTreeMap<u64, WindowMetadata> {
+            windows.into_iter().map(|w| (w.con_id, w)).collect()
+        };
+        let old = snapshot(vec![
+            window(1, 0, true),
+            window(2, 0, true),
+            window(3, 0, true),
+        ]);
+        let new = snapshot(vec![
+            window(2, 0, true),
+            window(3, 10, false),
+            window(4, 0, true),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                WindowChange::Removed(1),
+                WindowChange::Changed(window(3, 10, false)),
+                WindowChange::Added(window(4, 0, true)),
+            ]
+        );
+        assert_eq!(window(1, -5, true).grim_geometry(), "-5,0 100x50");
+    }
+}
@@ -0,0 +1,244 @@
+//! Snapshots of the compositor state, for checking what a sequence of commands did.
+//!
+//! ```no_run
+//! use ksway::{checkpoint::{Change, Checkpoint}, SwayClient};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let before = Checkpoint::capture(&mut client)?;
+//! client.run("workspace 9; exec foot")?;
+//! # std::thread::sleep(std::time::Duration::from_millis(500));
+//! let after = Checkpoint::capture(&mut client)?;
+//! for change in before.diff(&after) {
+//!     if let Change::WindowAdded { id, workspace } = change {
+//!         assert_eq!(workspace.as_deref(), Some("9"), "window {} opened elsewhere", id);
+//!     }
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashMap;
+
+use crate::reply::{Output, Workspace};
+use crate::tree::{nodes, windows};
+use crate::{JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// A difference between two checkpoints, see `Checkpoint::diff()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    WorkspaceAdded(String),
+    WorkspaceRemoved(String),
+    FocusedWorkspace {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    OutputAdded(String),
+    OutputRemoved(String),
+    /// Anything in the output's GET_OUTPUTS entry changed, e.g. its mode or current workspace.
+    OutputChanged(String),
+    WindowAdded {
+        id: u64,
+        workspace: Option<String>,
+    },
+    WindowRemoved {
+        id: u64,
+        workspace: Option<String>,
+    },
+    WindowMoved {
+        id: u64,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// The focused node, which isn't necessarily a window, e.g. for an empty workspace.
+    FocusedNode {
+        from: Option<u64>,
+        to: Option<u64>,
+    },
+}
+
+/// The tree, workspaces and outputs at one point in time.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub tree: JsonValue,
+    pub workspaces: Vec<Workspace>,
+    pub outputs: Vec<Output>,
+}
+
+/// The con_id of every window along with its workspace, in tree order.
+fn window_workspaces(tree: &JsonValue) -> Vec<(u64, Option<String>)> {
+    windows(tree)
+        .filter_map(|node| Some((node.id()?, node.workspace.map(ToOwned::to_owned))))
+        .collect()
+}
+
+fn focused_node(tree: &JsonValue) -> Option<u64> {
+    nodes(tree)
+        .find(|node| node.node.is_focused())
+        .and_then(|node| node.id())
+}
+
+fn focused_workspace(workspaces: &[Workspace]) -> Option<String> {
+    workspaces
+        .iter()
+        .find(|ws| ws.focused)
+        .map(|ws| ws.name.clone())
+}
+
+impl Checkpoint {
+    pub fn capture<C: SwayClientJson>(client: &mut C) -> Result<Self> {
+        Ok(Self {
+            tree: client.get_tree_json()?,
+            workspaces: client.get_workspaces_typed()?,
+            outputs: client.get_outputs_typed()?,
+        })
+    }
+
+    /// What changed going from `self` to `other`. Removals are listed in the order of `self`,
+    /// and everything else in the order of `other`.
+    pub fn diff(&self, other: &Checkpoint) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        let names: Vec<&str> = self.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        let other_names: Vec<&str> = other.workspaces.iter().map(|ws| ws.name.as_str()).collect();
+        for name in &names {
+            if !other_names.contains(name) {
+                changes.push(Change::WorkspaceRemoved(name.to_string()));
+            }
+        }
+        for name in &other_names {
+            if !names.contains(name) {
+                changes.push(Change::WorkspaceAdded(name.to_string()));
+            }
+        }
+        let (from, to) = (
+            focused_workspace(&self.workspaces),
+            focused_workspace(&other.workspaces),
+        );
+        if from != to {
+            changes.push(Change::FocusedWorkspace { from, to });
+        }
+
+        let outputs: HashMap<&str, &Output> = self
+            .outputs
+            .iter()
+            .map(|output| (output.name.as_str(), output))
+            .collect();
+        for output in &self.outputs {
+            if !other.outputs.iter().any(|o| o.name == output.name) {
+                changes.push(Change::OutputRemoved(output.name.clone()));
+            }
+        }
+        for output in &other.outputs {
+            match outputs.get(output.name.as_str()) {
+                None => changes.push(Change::OutputAdded(output.name.clone())),
+                Some(previous) if *previous != output => {
+                    changes.push(Change::OutputChanged(output.name.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let windows = window_workspaces(&self.tree);
+        let other_windows = window_workspaces(&other.tree);
+        let by_id: HashMap<u64, &Option<String>> =
+            windows.iter().map(|(id, ws)| (*id, ws)).collect();
+        let other_by_id: HashMap<u64, &Option<String>> =
+            other_windows.iter().map(|(id, ws)| (*id, ws)).collect();
+        for (id, workspace) in &windows {
+            if !other_by_id.contains_key(id) {
+                changes.push(Change::WindowRemoved {
+                    id: *id,
+                    workspace: workspace.clone(),
+                });
+            }
+        }
+        for (id, workspace) in &other_windows {
+            match by_id.get(id) {
+                None => changes.push(Change::WindowAdded {
+                    id: *id,
+                    workspace: workspace.clone(),
+                }),
+                Some(previous) if *previous != workspace => changes.push(Change::WindowMoved {
+                    id: *id,
+                    from: (*previous).clone(),
+                    to: workspace.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        let (from, to) = (focused_node(&self.tree), focused_node(&other.tree));
+        if from != to {
+            changes.push(Change::FocusedNode { from, to });
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workspace(name: &str, focused: bool) -> Workspace {
+        serde_json::from_value(json!({
+            "id": 0, "num": -1, "name": name, "visible": focused, "focused": focused,
+            "urgent": false, "output": "DP-1",
+            "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+        }))
+        .unwrap()
+    }
+
+    fn tree(workspaces: JsonValue) -> JsonValue {
+        json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "output", "name": "DP-1", "nodes": workspaces},
+        ]})
+    }
+
+    #[test]
+    fn diff_checkpoints() {
+        let before = Checkpoint {
+            tree: tree(json!([
+                {"id": 3, "type": "workspace", "name": "1", "nodes": [
+                    {"id": 10, "type": "con", "focused": true},
+                    {"id": 11, "type": "con"},
+                ]},
+            ])),
+            workspaces: vec![workspace("1", true)],
+            outputs: Vec::new(),
+        };
+        let after = Checkpoint {
+            tree: tree(json!([
+                {"id": 3, "type": "workspace", "name": "1", "nodes": [{"id": 10, "type": "con"}]},
+                {"id": 4, "type": "workspace", "name": "2", "nodes": [
+                    {"id": 11, "type": "con"},
+                    {"id": 12, "type": "con", "focused": true},
+                ]},
+            ])),
+            workspaces: vec![workspace("1", false), workspace("2", true)],
+            outputs: Vec::new(),
+        };
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                Change::WorkspaceAdded("2".into()),
+                Change::FocusedWorkspace {
+                    from: Some("1".into()),
+                    to: Some("2".into())
+                },
+                Change::WindowMoved {
+                    id: 11,
+                    from: Some("1".into()),
+                    to: Some("2".into())
+                },
+                Change::WindowAdded {
+                    id: 12,
+                    workspace: Some("2".into())
+                },
+                Change::FocusedNode {
+                    from: Some(10),
+                    to: Some(12)
+                },
+            ]
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+}
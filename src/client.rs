@@ -1,23 +1,309 @@
-use std::io::Read;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use byteorder::{NativeEndian, ReadBytesExt};
 use crossbeam_channel as chan;
-use num_traits::FromPrimitive;
 
+use crate::cancel::{self, CancelToken};
+use crate::core::{self, Connection, MessageKind, Skipped};
+use crate::events::{SwayEvent, TickEvent};
+use crate::focus_history::FocusHistory;
 use crate::ipc_command;
-use crate::{guess_sway_socket_path, Error, IpcCommand, IpcEvent, Result};
+use crate::reply::CommandOutcome;
+use crate::tree::{RawNode, Visit};
+use crate::version::{Capability, SwayVersion};
+use crate::{
+    cmd, guess_sway_socket_path, tree, Command, CommandSeq, Error, EventCode, EventMask, HasIpc,
+    IpcCommand, IpcCommandCode, IpcEvent, Result, SwayClient, SwayClientJson, SwayJsonExt,
+};
+
+/// The least a read asks for, so that bursts of events and small replies take one read.
+const READ_CHUNK: usize = 64 * 1024;
+/// The most a read asks for, however large the frame being received claims to be.
+const MAX_READ: usize = 16 * 1024 * 1024;
+
+/// Something unexpected that sway sent, which the client worked around instead of failing. See
+/// `Client::warnings()`.
+#[derive(derive_more::Display, Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// An event this version doesn't know the type of, which was dropped.
+    #[display(fmt = "dropped an event with unknown type 0x{:x}", "_0.raw()")]
+    UnknownEvent(EventCode),
+    /// A reply arrived while no command was waiting for one, and was dropped.
+    #[display(fmt = "dropped a reply of type {} which nothing was waiting for", "_0")]
+    UnsolicitedReply(u32),
+    /// The reply to a command whose `ipc()` call had already failed, e.g. on a read timeout,
+    /// which was dropped.
+    #[display(fmt = "dropped a late reply of type {}", "_0")]
+    LateReply(u32),
+    /// The connection was lost and a new one made, see `ReconnectPolicy`. Events from in
+    /// between are lost.
+    #[display(fmt = "reconnected after {} attempt(s)", "_0")]
+    Reconnected(usize),
+    /// A frame whose payload was longer than the limit, see `Client::set_max_payload()`, which
+    /// was dropped without reading it into memory.
+    #[display(
+        fmt = "dropped a frame of type 0x{:x} with a payload of {} bytes",
+        payload_type,
+        len
+    )]
+    OversizedPayload { len: u32, payload_type: u32 },
+    /// The stream was out of sync, and `skipped` bytes were dropped up to the next frame.
+    #[display(fmt = "dropped {} bytes to get back in sync", skipped)]
+    Resync { skipped: usize },
+}
+
+impl From<Skipped> for Warning {
+    fn from(skipped: Skipped) -> Self {
+        match skipped {
+            Skipped::Oversized { len, payload_type } => {
+                Warning::OversizedPayload { len, payload_type }
+            }
+            Skipped::Resync { skipped } => Warning::Resync { skipped },
+        }
+    }
+}
+
+/// What `Client::poll_timeout()` and `Client::poll_until()` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollOutcome {
+    /// At least one message was received and routed, events to the subscription channel.
+    Event,
+    /// Nothing complete arrived in time.
+    TimedOut,
+    /// The connection was lost and the reconnect policy made a new one. Events from in between
+    /// are lost, and the socket is a new one.
+    Reconnected,
+}
+
+/// How a client made with `Client::connect_with()` reconnects when its connection is lost, e.g.
+/// because sway restarted. Attempts are spaced out with an exponential backoff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    max_attempts: Option<usize>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    cancel: Option<CancelToken>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: None,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            cancel: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Retry forever, waiting from 100ms up to 5s between attempts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up after `attempts` failed attempts, returning the last error.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Wait `initial` before the first attempt, doubling after every failed attempt up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Give up with `Error::Cancelled` once `token` is cancelled, within a fraction of a second
+    /// even while waiting between attempts.
+    pub fn cancel_on(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// How long a `Client` waits on its socket, see `Client::set_timeouts()`. `None` waits forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeouts {
+    read: Option<Duration>,
+    write: Option<Duration>,
+    request: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            read: Some(Duration::from_secs(1)),
+            write: None,
+            request: None,
+        }
+    }
+}
+
+impl Timeouts {
+    /// A one second read timeout, and no write timeout or request deadline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a single read waits, which is also how long `poll()` waits for events.
+    pub fn read(mut self, timeout: Option<Duration>) -> Self {
+        self.read = timeout;
+        self
+    }
+
+    /// How long a single write waits, e.g. while sway isn't reading its socket.
+    pub fn write(mut self, timeout: Option<Duration>) -> Self {
+        self.write = timeout;
+        self
+    }
+
+    /// How long `ipc()` waits for a reply in total, across as many reads as it takes. Without
+    /// one, `ipc()` gives up after a single read timeout.
+    pub fn request(mut self, timeout: Option<Duration>) -> Self {
+        self.request = timeout;
+        self
+    }
+}
+
+/// `Error::Timeout` for the IO errors of a socket timeout, which are WouldBlock or TimedOut
+/// depending on the platform.
+fn map_timeout(err: Error) -> Error {
+    match err {
+        Error::Io(ref io)
+            if matches!(
+                io.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Error::Timeout
+        }
+        err => err,
+    }
+}
+
+/// Whether `err` means the connection is gone, as opposed to e.g. a read timeout.
+fn is_disconnect(err: &Error) -> bool {
+    match err {
+        Error::Disconnected => true,
+        Error::Io(err) => matches!(
+            err.kind(),
+            io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+        ),
+        _ => false,
+    }
+}
+
+/// Whether connecting failed because sway isn't up yet, or has just gone down.
+fn is_not_up(err: &Error) -> bool {
+    match err {
+        Error::SockPathNotFound => true,
+        Error::Io(err) => matches!(
+            err.kind(),
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+        ),
+        _ => false,
+    }
+}
+
+/// Call `connect` until it succeeds, or fails for another reason than sway not being up, or
+/// `deadline` passes, with the backoff of the default `ReconnectPolicy`.
+fn retry_connect<F>(deadline: Instant, mut connect: F) -> Result<Client>
+where
+    F: FnMut() -> Result<Client>,
+{
+    let policy = ReconnectPolicy::default();
+    let mut backoff = policy.initial_backoff;
+    loop {
+        let err = match connect() {
+            Err(err) if is_not_up(&err) => err,
+            result => return result,
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(err);
+        }
+        thread::sleep(backoff.min(remaining));
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
+
+/// The payload of the tick event which a raw subscription gets after the client reconnected,
+/// with `first` set, see `Client::connect_with()`.
+pub const RECONNECTED_TICK: &str = "ksway-reconnected";
+
+/// A reply along with its type code, which is the code of the command it answers unless the
+/// stream is out of sync. See `Client::ipc_with_code()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub code: u32,
+    pub payload: Vec<u8>,
+}
+
+/// What `Client::route_next()` did with a message.
+enum Routed {
+    /// No complete message is buffered.
+    Empty,
+    /// An event or a reply which nobody is waiting for.
+    Handled,
+    /// The reply to the command that `ipc()` is waiting for, with this code. Its payload is in
+    /// `Client::payload`.
+    Reply(u32),
+}
+
+/// Where subscription events go. Only the sending end is kept, so that sending fails rather
+/// than piling events up once the receiver was dropped.
+#[derive(Clone)]
+enum Subscription {
+    Raw(chan::Sender<(IpcEvent, Vec<u8>)>),
+    Typed(chan::Sender<Result<SwayEvent>>),
+}
 
 pub struct Client {
     socket: UnixStream,
     socket_path: PathBuf,
-    subscription_events: Option<chan::Sender<(IpcEvent, Vec<u8>)>>,
+    /// Buffers partially read frames and keeps track of the commands waiting for a reply.
+    connection: Connection,
+    /// Reused by every read, see `receive()`.
+    read_buffer: Vec<u8>,
+    /// The payload of the message being routed, reused for every message, see `route_next()`.
+    payload: Vec<u8>,
+    subscription_events: Option<Subscription>,
+    /// What we subscribed to, to subscribe again after reconnecting.
+    subscribed: EventMask,
+    warnings: Option<chan::Sender<Warning>>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    /// Whether the socket path was guessed, and is guessed again when reconnecting.
+    guessed_path: bool,
+    timeouts: Timeouts,
+    /// See `set_nonblocking()`.
+    nonblocking: bool,
+    /// Whether tick events were only subscribed to by `sync()`, and aren't passed on.
+    sync_ticks: bool,
+    /// The payload of the tick `sync()` is waiting for.
+    sync_token: Option<String>,
+    /// How many times `sync()` was called, to make its ticks unique.
+    syncs: u64,
+    /// See `server_version()`.
+    version: Option<SwayVersion>,
+    /// See `track_focus()`.
+    focus_history: Option<FocusHistory>,
+    /// Whether window events were only subscribed to by `track_focus()`, and aren't passed on.
+    focus_windows: bool,
 }
 
-type RawResponse = (u32, Vec<u8>);
-
 impl Client {
     /// The socket path that we are currently connected to.
     pub fn socket_path(&self) -> &Path {
@@ -28,61 +314,442 @@ impl Client {
     pub fn connect_to_path<P: Into<PathBuf>>(path: P) -> Result<Self> {
         let path = path.into();
         let socket = UnixStream::connect(&path)?;
-        // socket.set_nonblocking(true)?;
-        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
-        Ok(Self {
+        let mut client = Self {
             socket,
             socket_path: path,
+            connection: Connection::new(),
+            read_buffer: Vec::new(),
+            payload: Vec::new(),
             subscription_events: None,
-        })
+            subscribed: EventMask::empty(),
+            warnings: None,
+            reconnect_policy: None,
+            guessed_path: false,
+            timeouts: Timeouts::default(),
+            nonblocking: false,
+            sync_ticks: false,
+            sync_token: None,
+            syncs: 0,
+            version: None,
+            focus_history: None,
+            focus_windows: false,
+        };
+        client.set_timeouts(Timeouts::default())?;
+        Ok(client)
+    }
+
+    /// Change how long this client waits on its socket, replacing the default one second read
+    /// timeout. When a timeout runs out, `ipc()` fails with `Error::Timeout`, while `poll()`
+    /// just returns. The timeouts are kept when reconnecting.
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use ksway::{SwayClient, Timeouts};
+    ///
+    /// let mut client = ksway::Client::connect()?;
+    /// client.set_timeouts(
+    ///     Timeouts::new()
+    ///         .read(Some(Duration::from_millis(100)))
+    ///         .request(Some(Duration::from_secs(5))),
+    /// )?;
+    /// client.run("reload")?;
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) -> Result<()> {
+        self.socket.set_read_timeout(timeouts.read)?;
+        self.socket.set_write_timeout(timeouts.write)?;
+        self.timeouts = timeouts;
+        Ok(())
+    }
+
+    /// Drop frames whose payload is longer than `max` bytes, instead of those longer than
+    /// `core::DEFAULT_MAX_PAYLOAD`, with a `Warning::OversizedPayload`. A command whose reply is
+    /// dropped fails with `Error::OversizedReply`. The limit is kept when reconnecting.
+    pub fn set_max_payload(&mut self, max: u32) {
+        self.connection.set_max_payload(max);
+    }
+
+    /// The version of the sway we are connected to. It is only requested the first time, and
+    /// again after reconnecting, since sway may have been upgraded in between.
+    pub fn server_version(&mut self) -> Result<SwayVersion> {
+        if let Some(version) = self.version {
+            return Ok(version);
+        }
+        let version = SwayVersion::from(&self.get_version_typed()?);
+        self.version = Some(version);
+        Ok(version)
+    }
+
+    /// Whether the sway we are connected to supports `capability`, see `server_version()`.
+    pub fn supports(&mut self, capability: Capability) -> Result<bool> {
+        Ok(self.server_version()?.supports(capability))
+    }
+
+    /// Like `connect()`, but when the connection is lost later on, reconnect according to
+    /// `policy` instead of failing, see `set_reconnect_policy()`.
+    pub fn connect_with(policy: ReconnectPolicy) -> Result<Self> {
+        let mut client = Self::connect()?;
+        client.set_reconnect_policy(Some(policy));
+        Ok(client)
+    }
+
+    /// When the connection is lost, reconnect according to `policy` instead of failing, or stop
+    /// doing so with `None`. Subscriptions are renewed, with a `SwayEvent::Reconnected` on
+    /// typed subscriptions, a tick event with `first` set and `RECONNECTED_TICK` as its payload
+    /// on raw ones, and a `Warning::Reconnected` on the `warnings()` channel.
+    ///
+    /// `ipc()` sends a query which was interrupted again on the new connection. A RUN_COMMAND
+    /// or SEND_TICK may or may not have been received by sway, so it isn't sent again, and fails
+    /// with `Error::Disconnected` after reconnecting.
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = policy;
     }
 
     /// Guess which socket to connect to using `ksway::guess_sway_socket_path()`.
     /// This first checks for SWAYSOCK environment variable, or tries to find an appropriate
     /// socket when run outside of a graphical environment. See `guess_sway_socket_path()` for more.
     pub fn connect() -> Result<Self> {
-        Self::connect_to_path(guess_sway_socket_path()?)
+        let mut client = Self::connect_to_path(guess_sway_socket_path()?)?;
+        client.guessed_path = true;
+        Ok(client)
+    }
+
+    /// Like `connect()`, but while sway isn't up yet, keep guessing the socket path and connecting
+    /// until `timeout` runs out, waiting from 100ms up to 5s between attempts. Meant for daemons
+    /// started in parallel with sway, e.g. as systemd user services. Once the timeout runs out,
+    /// the last error is returned. Errors other than a missing socket or a refused connection
+    /// are returned right away.
+    pub fn connect_wait(timeout: Duration) -> Result<Self> {
+        retry_connect(Instant::now() + timeout, Self::connect)
+    }
+
+    /// Guess the socket path again with `guess_sway_socket_path()`, e.g. after sway was
+    /// restarted, since the socket name contains sway's pid. This doesn't reconnect, see
+    /// `reconnect()` for that. Returns whether the path changed.
+    pub fn refresh_socket_path(&mut self) -> Result<bool> {
+        let path = guess_sway_socket_path()?;
+        let changed = path != self.socket_path;
+        self.socket_path = path;
+        Ok(changed)
+    }
+
+    /// Open a new connection, replacing the current one, to a refreshed socket path if this
+    /// client guessed it, or else to the same path. If this client was subscribed it subscribes
+    /// to the same events again, and they keep arriving on the same channel.
+    pub fn reconnect(&mut self) -> Result<()> {
+        if self.guessed_path {
+            self.refresh_socket_path()?;
+        }
+        let mut client = Self::connect_to_path(self.socket_path.clone())?;
+        client.guessed_path = self.guessed_path;
+        client.warnings = self.warnings.clone();
+        client.set_timeouts(self.timeouts.clone())?;
+        client.set_nonblocking(self.nonblocking)?;
+        client.set_max_payload(self.connection.max_payload());
+        // Also without a channel, for `track_focus()` and `sync()`.
+        if !self.subscribed.is_empty() {
+            client.ipc(ipc_command::subscribe(self.subscribed))?;
+            client.subscribed = self.subscribed;
+            client.sync_ticks = self.sync_ticks;
+            client.focus_windows = self.focus_windows;
+        }
+        client.subscription_events = self.subscription_events.clone();
+        if let Some(mut history) = self.focus_history.clone() {
+            // Windows may have closed while disconnected.
+            history.prune(&client.get_tree_json()?);
+            client.focus_history = Some(history);
+        }
+        // Only now, so that a failure above goes back to whoever is retrying.
+        client.reconnect_policy = self.reconnect_policy.clone();
+        *self = client;
+        Ok(())
     }
 
-    /// Call this to check for new subscription events from the socket.
+    /// Reconnect according to the policy, after `err` lost the connection. Without a policy,
+    /// or once it gives up, the error is returned.
+    fn recover(&mut self, err: Error) -> Result<()> {
+        let policy = match &self.reconnect_policy {
+            Some(policy) if is_disconnect(&err) => policy.clone(),
+            _ => return Err(err),
+        };
+        let cancel = policy.cancel.clone().unwrap_or_default();
+        let mut backoff = policy.initial_backoff;
+        let mut attempts = 0;
+        loop {
+            cancel.sleep(backoff)?;
+            attempts += 1;
+            match self.reconnect() {
+                Ok(()) => break,
+                Err(err) if policy.max_attempts.is_some_and(|max| attempts >= max) => {
+                    return Err(err)
+                }
+                Err(_) => backoff = (backoff * 2).min(policy.max_backoff),
+            }
+        }
+        self.warn(Warning::Reconnected(attempts));
+        let sent = match &self.subscription_events {
+            Some(Subscription::Raw(tx)) => {
+                let tick = serde_json::json!({"first": true, "payload": RECONNECTED_TICK});
+                tx.send((IpcEvent::Tick, tick.to_string().into_bytes()))
+                    .is_ok()
+            }
+            Some(Subscription::Typed(tx)) => tx.send(Ok(SwayEvent::Reconnected)).is_ok(),
+            None => true,
+        };
+        if !sent {
+            return Err(Error::SubscriptionError);
+        }
+        Ok(())
+    }
+
+    /// Report anomalies in what sway sends on the returned channel, which are otherwise handled
+    /// silently, e.g. events of an unknown type. Calling this again replaces the previous
+    /// channel.
+    pub fn warnings(&mut self) -> chan::Receiver<Warning> {
+        let (tx, rx) = chan::unbounded();
+        self.warnings = Some(tx);
+        rx
+    }
+
+    fn warn(&self, warning: Warning) {
+        if let Some(tx) = &self.warnings {
+            // Nobody listening anymore is fine.
+            let _ = tx.send(warning);
+        }
+    }
+
+    /// Call this to check for new subscription events from the socket. This waits for up to the
+    /// read timeout, a second by default, if nothing has been received yet. See
+    /// `poll_timeout()` to choose how long to wait, and tell whether anything arrived.
     pub fn poll(&mut self) -> Result<()> {
-        let (payload_type, payload) = match self.read_response() {
-            Ok(value) => value,
-            // EAGAIN/EWOULDBLOCK means there's no data right now, but this isn't
-            // an error for us in this scenario since we are checking with a timeout.
-            Err(Error::Io(ref err)) if err.raw_os_error() == Some(11) => return Ok(()),
-            err => err?,
-        };
-        if payload_type & IpcEvent::Workspace as u32 > 0 {
-            if let Some(ref tx) = self.subscription_events {
-                tx.send((IpcEvent::from_u32(payload_type).unwrap(), payload))
-                    .map_err(|_| Error::SubscriptionError)?;
+        match self.poll_once() {
+            Err(err) => self.recover(err),
+            ok => ok,
+        }
+    }
+
+    fn poll_once(&mut self) -> Result<()> {
+        let mut handled = false;
+        loop {
+            match self.route_next(false)? {
+                Routed::Empty if handled => return Ok(()),
+                Routed::Empty => {}
+                Routed::Handled | Routed::Reply(_) => {
+                    handled = true;
+                    continue;
+                }
             }
+            match self.receive().map_err(map_timeout) {
+                Ok(()) => handled = true,
+                // There's no data right now, either within the read timeout or at all on a
+                // non-blocking socket, which isn't an error when polling.
+                Err(Error::Timeout) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Like `poll()`, but waits up to `timeout` instead of the read timeout, and tells whether
+    /// anything arrived. Read errors are still errors, rather than looking like a timeout.
+    pub fn poll_timeout(&mut self, timeout: Duration) -> Result<PollOutcome> {
+        self.poll_until(Instant::now() + timeout)
+    }
+
+    /// Like `poll_timeout()`, but waits until `deadline`. A deadline which already passed only
+    /// routes what was already received.
+    pub fn poll_until(&mut self, deadline: Instant) -> Result<PollOutcome> {
+        match self.poll_once_until(deadline) {
+            Err(err) => self.recover(err).map(|()| PollOutcome::Reconnected),
+            ok => ok,
+        }
+    }
+
+    fn poll_once_until(&mut self, deadline: Instant) -> Result<PollOutcome> {
+        let mut routed = false;
+        loop {
+            match self.route_next(false)? {
+                Routed::Empty if routed => return Ok(PollOutcome::Event),
+                Routed::Empty => {}
+                Routed::Handled | Routed::Reply(_) => {
+                    routed = true;
+                    continue;
+                }
+            }
+            match self.receive_before(Some(deadline)).map_err(map_timeout) {
+                Ok(()) => {}
+                Err(Error::Timeout) => return Ok(PollOutcome::TimedOut),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Like `poll()`, but never waits: everything which can be read without blocking is read
+    /// and routed. Meant for event loops (mio, calloop, ...) which watch the socket through
+    /// `AsFd`/`AsRawFd` and only call this once it is readable. Returns how many messages were
+    /// routed, or `None` if not a single complete one could be read. A frame which was only
+    /// partially read stays buffered until the rest arrives.
+    ///
+    /// If the connection was lost and the reconnect policy made a new one, this also returns
+    /// `None`, since nothing was routed: the reconnect is reported on the subscription channel
+    /// and as `Warning::Reconnected`. The socket is then a new one, which the event loop has to
+    /// watch instead.
+    pub fn try_poll(&mut self) -> Result<Option<usize>> {
+        let drained = if self.nonblocking {
+            self.drain()
         } else {
-            // TODO figure out
-            unreachable!();
-            // return Ok(payload);
+            self.socket.set_nonblocking(true)?;
+            let drained = self.drain();
+            self.socket.set_nonblocking(false)?;
+            drained
+        };
+        match drained {
+            Err(err) => self.recover(err).map(|()| None),
+            Ok(0) => Ok(None),
+            Ok(routed) => Ok(Some(routed)),
+        }
+    }
+
+    fn drain(&mut self) -> Result<usize> {
+        loop {
+            match self.receive() {
+                Ok(()) => {}
+                Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
         }
+        let mut routed = 0;
+        while !matches!(self.route_next(false)?, Routed::Empty) {
+            routed += 1;
+        }
+        Ok(routed)
+    }
+
+    /// Put the socket in non-blocking mode, e.g. to drive the client from an event loop with
+    /// `try_poll()`. `poll()` and `poll_timeout()` then return right away when nothing can be
+    /// read. Commands still wait for their reply: the socket is blocking for the duration of
+    /// `ipc()`.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.socket.set_nonblocking(nonblocking)?;
+        self.nonblocking = nonblocking;
         Ok(())
     }
 
-    fn read_response(&mut self) -> Result<RawResponse> {
-        let mut buffer = *b"i3-ipc";
-        self.socket.read_exact(&mut buffer).map_err(Error::Io)?;
-        debug_assert_eq!(b"i3-ipc", &buffer);
-        let payload_length = self.socket.read_u32::<NativeEndian>().map_err(Error::Io)?;
-        let payload_type = self.socket.read_u32::<NativeEndian>().map_err(Error::Io)?;
-        let mut buffer = vec![0u8; payload_length as usize];
-        self.socket.read_exact(&mut buffer).map_err(Error::Io)?;
-        let payload = (payload_type, buffer);
-        Ok(payload)
+    /// Read whatever is available from the socket, waiting up to the read timeout. A frame
+    /// which was only partially read stays buffered, so giving up on a timeout doesn't lose it.
+    ///
+    /// Reads ask for the rest of the frame being received, so that a large reply like GET_TREE
+    /// usually takes two reads, one for the first chunk and one for whatever is left.
+    fn receive(&mut self) -> Result<()> {
+        let wanted = self.connection.missing().clamp(READ_CHUNK, MAX_READ);
+        if self.read_buffer.len() < wanted {
+            self.read_buffer.resize(wanted, 0);
+        }
+        let n = self.socket.read(&mut self.read_buffer[..wanted])?;
+        if n == 0 {
+            return Err(Error::Disconnected);
+        }
+        self.connection.receive(&self.read_buffer[..n]);
+        Ok(())
     }
 
-    fn send_command(&mut self, command: IpcCommand) -> Result<()> {
-        command.write(&mut self.socket).map_err(Error::Io)?;
+    /// Route the next complete message which was received, decoding it into `self.payload`.
+    /// Events go to the subscription channel. Replies are matched up with commands in order,
+    /// and only the reply to the last command is returned if `waiting`, i.e. when called from
+    /// `ipc()`. Replies to earlier commands, whose `ipc()` call already failed, are dropped with
+    /// a warning.
+    fn route_next(&mut self, waiting: bool) -> Result<Routed> {
+        let expected = self.connection.next_reply_code();
+        let last = self.connection.pending_replies() == 1;
+        let polled = self.connection.poll_into(&mut self.payload);
+        for skipped in self.connection.take_skipped() {
+            self.warn(skipped.into());
+        }
+        let polled = match polled {
+            // The reply to the command being waited for, which `ipc_with_code()` returns anyway.
+            Err(Error::UnexpectedReply {
+                received, reply, ..
+            }) if waiting && last => {
+                self.payload = reply;
+                return Ok(Routed::Reply(received));
+            }
+            // Reported above, and only an error for the command waiting for it.
+            Err(Error::OversizedReply { .. }) if !(waiting && last) => return Ok(Routed::Handled),
+            polled => polled?,
+        };
+        match polled {
+            None => Ok(Routed::Empty),
+            Some(payload_type) => match (EventCode::from_raw(payload_type), expected) {
+                (Some(code), _) => {
+                    let payload = std::mem::take(&mut self.payload);
+                    let dispatched = self.dispatch_event(code, &payload);
+                    self.payload = payload;
+                    match dispatched {
+                        // Reported by polling, a command isn't affected.
+                        Err(Error::SubscriptionError) if waiting => Ok(Routed::Handled),
+                        dispatched => dispatched.map(|()| Routed::Handled),
+                    }
+                }
+                (None, None) => {
+                    self.warn(Warning::UnsolicitedReply(payload_type));
+                    Ok(Routed::Handled)
+                }
+                (None, Some(_)) if !(waiting && last) => {
+                    self.warn(Warning::LateReply(payload_type));
+                    Ok(Routed::Handled)
+                }
+                (None, Some(_)) => Ok(Routed::Reply(payload_type)),
+            },
+        }
+    }
+
+    /// Forward an event frame to the subscription channel. Events we don't know the type of are
+    /// dropped, with a warning. Fails with `Error::SubscriptionError` if the receiver was
+    /// dropped, and the event with it.
+    fn dispatch_event(&mut self, code: EventCode, payload: &[u8]) -> Result<()> {
+        let event = match code.event_type() {
+            Some(event) => event,
+            None => {
+                self.warn(Warning::UnknownEvent(code));
+                return Ok(());
+            }
+        };
+        if event == IpcEvent::Window {
+            if let Some(history) = &mut self.focus_history {
+                if let Ok(payload) = serde_json::from_slice(payload) {
+                    history.handle_event(event, &payload);
+                }
+                if self.focus_windows {
+                    return Ok(());
+                }
+            }
+        }
+        if event == IpcEvent::Tick && (self.sync_token.is_some() || self.sync_ticks) {
+            let tick = TickEvent::parse(payload).ok();
+            if tick.is_some_and(|tick| Some(&tick.payload) == self.sync_token.as_ref()) {
+                self.sync_token = None;
+                return Ok(());
+            }
+            if self.sync_ticks {
+                return Ok(());
+            }
+        }
+        let sent = match &self.subscription_events {
+            Some(Subscription::Raw(tx)) => tx.send((event, payload.to_vec())).is_ok(),
+            Some(Subscription::Typed(tx)) => tx.send(SwayEvent::parse(event, payload)).is_ok(),
+            None => true,
+        };
+        if !sent {
+            return Err(Error::SubscriptionError);
+        }
         Ok(())
     }
 
+    fn send_command(&mut self, command: IpcCommand) -> Result<()> {
+        self.connection.send(&command);
+        self.socket
+            .write_all(&self.connection.take_outgoing())
+            .map_err(|err| map_timeout(Error::Io(err)))
+    }
+
     /// Send an ipc command. Used with the IpcCommand enum or constructed from the convenience
     /// methods under ksway::ipc_command::*
     /// An alias for `client.ipc(ipc_command::run(...))` is provided at `client.run(...)`
@@ -90,38 +757,290 @@ impl Client {
     /// The result is immediately read, aka this is a synchronous call.
     /// The raw bytes are returned in order to avoid dependency on any particular json
     /// implementation.
+    ///
+    /// A reply whose type isn't that of the command means the stream is out of sync, which is
+    /// `Error::UnexpectedReply`.
     pub fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
-        let code = command.code() as u32;
+        let mut payload = Vec::new();
+        self.ipc_into(command, &mut payload)?;
+        Ok(payload)
+    }
+
+    /// Like `ipc()`, but the reply replaces the contents of `payload` instead of being returned
+    /// in a new `Vec`. The client keeps the allocation `payload` had for decoding later frames,
+    /// so a caller reusing the same buffer for its queries doesn't allocate once both buffers
+    /// have grown to the size of the largest reply.
+    pub fn ipc_into(&mut self, command: IpcCommand, payload: &mut Vec<u8>) -> Result<()> {
+        let expected = command.code() as u32;
+        let received = self.ipc_retrying(command, payload)?;
+        if received != expected {
+            return Err(Error::UnexpectedReply {
+                expected,
+                received,
+                reply: std::mem::take(payload),
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `ipc()`, but returns the reply along with its type code without checking it.
+    pub fn ipc_with_code(&mut self, command: IpcCommand) -> Result<Reply> {
+        let mut payload = Vec::new();
+        let code = self.ipc_retrying(command, &mut payload)?;
+        Ok(Reply { code, payload })
+    }
+
+    /// Send `command` and put its reply into `payload`. If the connection fails and there is a
+    /// reconnect policy, reconnect, and send it again once if that is harmless, see
+    /// `set_reconnect_policy()`. Returns the code of the reply.
+    fn ipc_retrying(&mut self, command: IpcCommand, payload: &mut Vec<u8>) -> Result<u32> {
+        if self.reconnect_policy.is_none() {
+            return self.ipc_once(command, payload);
+        }
+        match self.ipc_once(command.clone(), payload) {
+            Err(err) => {
+                self.recover(err)?;
+                if !command.is_idempotent() {
+                    return Err(Error::Disconnected);
+                }
+                self.ipc_once(command, payload)
+            }
+            ok => ok,
+        }
+    }
+
+    /// Run `commands` in one RUN_COMMAND round trip, separated by `;`, and parse the outcome of
+    /// each. Raw commands which are themselves compound have an outcome per part. Nothing is sent
+    /// for an empty batch.
+    pub fn run_batch(&mut self, commands: &[Command]) -> Result<Vec<CommandOutcome>> {
+        if commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        let batch = CommandSeq::new().for_each(commands, Command::clone);
+        self.run_typed(batch)
+    }
+
+    fn ipc_once(&mut self, command: IpcCommand, payload: &mut Vec<u8>) -> Result<u32> {
+        self.blocking(|client| client.ipc_blocking(command, payload))
+    }
+
+    /// Run `f` with the socket in blocking mode, for waiting on a reply even when the client is
+    /// non-blocking.
+    fn blocking<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if !self.nonblocking {
+            return f(self);
+        }
+        self.set_nonblocking(false)?;
+        let result = f(self);
+        let restored = self.set_nonblocking(true);
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
+
+    fn ipc_blocking(&mut self, command: IpcCommand, payload: &mut Vec<u8>) -> Result<u32> {
+        let deadline = self
+            .timeouts
+            .request
+            .map(|timeout| Instant::now() + timeout);
         self.send_command(command)?;
         loop {
-            let (payload_type, payload) = self.read_response()?;
-            if payload_type & IpcEvent::Workspace as u32 > 0 {
-                if let Some(ref tx) = self.subscription_events {
-                    tx.send((IpcEvent::from_u32(payload_type).unwrap(), payload))
-                        .map_err(|_| Error::SubscriptionError)?;
+            match self.route_next(true)? {
+                Routed::Reply(code) => {
+                    std::mem::swap(payload, &mut self.payload);
+                    return Ok(code);
+                }
+                Routed::Handled => {}
+                Routed::Empty => self.receive_before(deadline).map_err(map_timeout)?,
+            }
+        }
+    }
+
+    /// See `SwayClientJson::visit_tree()`. If the connection is lost, it is reconnected according
+    /// to the policy, but the walk isn't started over, since the visitor may have seen part of
+    /// the tree already: this returns `Error::Disconnected` instead.
+    pub(crate) fn visit_tree_streaming<F: FnMut(&RawNode) -> Visit>(
+        &mut self,
+        visitor: F,
+    ) -> Result<()> {
+        match self.blocking(|client| client.visit_tree_blocking(visitor)) {
+            Err(err) => {
+                self.recover(err)?;
+                Err(Error::Disconnected)
+            }
+            ok => ok,
+        }
+    }
+
+    fn visit_tree_blocking<F: FnMut(&RawNode) -> Visit>(&mut self, visitor: F) -> Result<()> {
+        let deadline = self
+            .timeouts
+            .request
+            .map(|timeout| Instant::now() + timeout);
+        self.send_command(ipc_command::get_tree())?;
+        // Events, and replies to earlier commands, are routed as usual until the reply starts.
+        let (header, start) = loop {
+            if self.connection.pending_replies() == 1 {
+                if let Some(start) = self.connection.take_reply_start() {
+                    break start;
+                }
+            }
+            match self.route_next(true)? {
+                Routed::Reply(received) => {
+                    return Err(Error::UnexpectedReply {
+                        expected: IpcCommandCode::GetTree as u32,
+                        received,
+                        reply: std::mem::take(&mut self.payload),
+                    })
                 }
-            } else {
-                debug_assert_eq!(code, payload_type);
-                return Ok(payload);
+                Routed::Handled => {}
+                Routed::Empty => self.receive_before(deadline).map_err(map_timeout)?,
             }
+        };
+
+        let rest = (header.payload_len as usize - start.len()) as u64;
+        let mut reader = io::BufReader::with_capacity(
+            READ_CHUNK,
+            io::Cursor::new(start).chain((&self.socket).take(rest)),
+        );
+        let walked = match crate::tree::visit_reader(&mut reader, visitor) {
+            Err(Error::Json(err)) if err.is_io() => return Err(map_timeout(Error::Io(err.into()))),
+            walked => walked,
+        };
+        // Whatever the walk didn't get to, so that the next frame is decoded from its start.
+        io::copy(&mut reader, &mut io::sink()).map_err(|err| map_timeout(Error::Io(err)))?;
+        if reader.get_ref().get_ref().1.limit() > 0 {
+            return Err(Error::Disconnected);
         }
+        walked
+    }
+
+    /// Wait until sway has processed every command sent so far, and the events they caused have
+    /// been routed, like `xdotool sync`. This sends a tick with a unique payload and polls until
+    /// its event comes back, which sway sends after those events. Tick events are subscribed to
+    /// if they weren't already, and are then only used for this rather than passed on. Waits
+    /// up to the request timeout, if there is one.
+    /// ```no_run
+    /// use ksway::{IpcEvent, SwayClient};
+    ///
+    /// let mut client = ksway::Client::connect()?;
+    /// let rx = client.subscribe(vec![IpcEvent::Window])?;
+    /// client.run("exec foot")?;
+    /// client.sync()?;
+    /// // Every window event caused by commands sent before is in `rx` now.
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    pub fn sync(&mut self) -> Result<()> {
+        if !self.subscribed.contains(EventMask::TICK) {
+            self.add_subscribed(EventMask::TICK)?;
+            self.sync_ticks = true;
+        }
+        self.syncs += 1;
+        let token = format!("ksway-sync-{}-{}", std::process::id(), self.syncs);
+        self.sync_token = Some(token.clone());
+        let synced = self.blocking(|client| client.wait_for_sync(token));
+        self.sync_token = None;
+        synced
+    }
+
+    fn wait_for_sync(&mut self, token: String) -> Result<()> {
+        let deadline = self
+            .timeouts
+            .request
+            .map(|timeout| Instant::now() + timeout);
+        self.ipc(ipc_command::tick(token))?;
+        while self.sync_token.is_some() {
+            if let Routed::Empty = self.route_next(false)? {
+                self.receive_before(deadline).map_err(map_timeout)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `receive()`, but waiting only until `deadline` instead of the read timeout, if
+    /// there is one.
+    fn receive_before(&mut self, deadline: Option<Instant>) -> Result<()> {
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return self.receive(),
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+        self.socket.set_read_timeout(Some(remaining))?;
+        let result = self.receive();
+        self.socket.set_read_timeout(self.timeouts.read)?;
+        result
+    }
+
+    /// Keep a `FocusHistory` of up to `capacity` windows from now on, for `focus_previous()`.
+    /// Window events are subscribed to if they weren't already, and are then only used for this
+    /// rather than passed on. They are only read while polling or waiting for replies, so a
+    /// client which does neither doesn't see focus changes.
+    pub fn track_focus(&mut self, capacity: usize) -> Result<()> {
+        if !self.subscribed.contains(EventMask::WINDOW) {
+            self.add_subscribed(EventMask::WINDOW)?;
+            self.focus_windows = true;
+        }
+        let mut history = FocusHistory::new(capacity);
+        history.prune(&self.get_tree_json()?);
+        self.focus_history = Some(history);
+        Ok(())
+    }
+
+    /// See `track_focus()`.
+    pub fn focus_history(&self) -> Option<&FocusHistory> {
+        self.focus_history.as_ref()
     }
 
-    /// Subscribe to events from sway. You can only subscribe once for a client connection, but
-    /// there's really no point to subscribing multiple times. It will return
-    /// Error::AlreadySubscribed if you attempt to do so.
+    /// Focus the most recently focused window other than the focused one which still exists,
+    /// like alt-tab. Without `track_focus()`, or when the history has no other window yet, that
+    /// is the first of `tree::focus_order()`, which prefers windows on the focused workspace.
     ///
-    /// Returns a crossbeam channel that you can use to poll for events.
+    /// Returns the con_id of the window which was focused, or `None` if there is no other
+    /// window.
+    /// ```no_run
+    /// ksway::Client::connect()?.focus_previous()?;
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    pub fn focus_previous(&mut self) -> Result<Option<u64>> {
+        let tree = self.get_tree_json()?;
+        let mut order: Vec<u64> = match &mut self.focus_history {
+            Some(history) => {
+                history.prune(&tree);
+                history.iter().collect()
+            }
+            None => Vec::new(),
+        };
+        order.extend(tree::focus_order(&tree));
+        let focused = tree::windows(&tree)
+            .find(|window| window.node.is_focused())
+            .and_then(|window| window.id());
+        let target = match order.into_iter().find(|id| Some(*id) != focused) {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        self.run(cmd!([con_id=target] "focus"))?;
+        Ok(Some(target))
+    }
+
+    /// Subscribe to events from sway.
+    ///
+    /// Returns a crossbeam channel that you can use to poll for events. Calling this again, or
+    /// after `subscribe_typed()`, returns Error::AlreadySubscribed, see `subscribe_more()` to
+    /// subscribe to more events on the same channel. Once the channel is dropped, `poll()`
+    /// fails with Error::SubscriptionError for every event it receives.
     ///
     /// In order to receive events, you must call `client.poll()` to check for new subscription
     /// events. You can see an example of this in the examples.
     /// A minimal loop is as such:
     /// ```no_run
-    /// use ksway::IpcEvent;
+    /// use ksway::{EventMask, IpcEvent};
     ///
     /// let mut client = ksway::Client::connect()?;
     ///
-    /// let rx = client.subscribe(vec![IpcEvent::Window, IpcEvent::Tick])?;
+    /// let rx = client.subscribe(EventMask::WINDOW | EventMask::TICK)?;
     /// loop {
     ///     while let Ok((payload_type, payload)) = rx.try_recv() {
     ///         match payload_type {
@@ -133,17 +1052,1184 @@ impl Client {
     /// }
     /// # Ok::<(), ksway::Error>(())
     /// ```
-    pub fn subscribe(
+    pub fn subscribe<E: Into<EventMask>>(
         &mut self,
-        event_types: Vec<IpcEvent>,
+        event_types: E,
     ) -> Result<chan::Receiver<(IpcEvent, Vec<u8>)>> {
         if self.subscription_events.is_some() {
             return Err(Error::AlreadySubscribed);
         }
         let (tx, rx) = chan::unbounded();
-        self.subscription_events = Some(tx);
-        self.ipc(ipc_command::subscribe(event_types))?;
+        self.add_subscribed(event_types.into())?;
+        self.subscription_events = Some(Subscription::Raw(tx));
+        Ok(rx)
+    }
 
+    /// Like `subscribe()`, but the events arrive parsed, see `events::SwayEvent`. An event
+    /// which can't be parsed arrives as an error, and doesn't end the subscription.
+    /// ```no_run
+    /// use ksway::{events::SwayEvent, IpcEvent};
+    ///
+    /// let mut client = ksway::Client::connect()?;
+    /// let rx = client.subscribe_typed(vec![IpcEvent::Window, IpcEvent::Mode])?;
+    /// loop {
+    ///     while let Ok(event) = rx.try_recv() {
+    ///         match event? {
+    ///             SwayEvent::Window(event) => println!("{:?} {:?}", event.change, event.container.id),
+    ///             SwayEvent::Mode(event) => println!("mode {}", event.change),
+    ///             _ => {}
+    ///         }
+    ///     }
+    ///     client.poll()?;
+    /// }
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    pub fn subscribe_typed<E: Into<EventMask>>(
+        &mut self,
+        event_types: E,
+    ) -> Result<chan::Receiver<Result<SwayEvent>>> {
+        if self.subscription_events.is_some() {
+            return Err(Error::AlreadySubscribed);
+        }
+        let (tx, rx) = chan::unbounded();
+        self.add_subscribed(event_types.into())?;
+        self.subscription_events = Some(Subscription::Typed(tx));
         Ok(rx)
     }
+
+    /// Subscribe to more events, which arrive on the channel that `subscribe()` or
+    /// `subscribe_typed()` returned. Without either, they are received but dropped.
+    /// ```no_run
+    /// use ksway::EventMask;
+    ///
+    /// let mut client = ksway::Client::connect()?;
+    /// let rx = client.subscribe(EventMask::WORKSPACE)?;
+    /// client.subscribe_more(EventMask::WINDOW)?;
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    pub fn subscribe_more<E: Into<EventMask>>(&mut self, event_types: E) -> Result<()> {
+        self.add_subscribed(event_types.into())
+    }
+
+    /// Send a SUBSCRIBE for those of `event_types` which weren't subscribed to yet, if any.
+    /// Sway adds to the events of a connection on every SUBSCRIBE, and sending one for `tick`
+    /// again would send another `first` tick event.
+    fn add_subscribed(&mut self, event_types: EventMask) -> Result<()> {
+        let new = event_types - self.subscribed;
+        if !new.is_empty() {
+            self.ipc(ipc_command::subscribe(new))?;
+            self.subscribed |= new;
+        }
+        if event_types.contains(EventMask::TICK) {
+            self.sync_ticks = false;
+        }
+        if event_types.contains(EventMask::WINDOW) {
+            self.focus_windows = false;
+        }
+        Ok(())
+    }
+
+    /// Subscribe to events and iterate over them, blocking on the socket until the next one
+    /// arrives. This consumes the client, since a subscribed connection is only good for events.
+    /// ```no_run
+    /// use ksway::IpcEvent;
+    ///
+    /// for event in ksway::Client::connect()?.events(vec![IpcEvent::Window])? {
+    ///     let (payload_type, payload) = event?;
+    /// }
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    /// The iterator ends after the first error.
+    pub fn events<E: Into<EventMask>>(mut self, event_types: E) -> Result<Events> {
+        let rx = self.subscribe(event_types)?;
+        self.set_timeouts(self.timeouts.clone().read(None))?;
+        Ok(Events {
+            client: Some(self),
+            rx,
+            cancel: None,
+        })
+    }
+
+    /// Split the connection into a `Sender` for commands and a `Receiver` for events, each
+    /// owning its own handle to the socket, so that one thread can send commands while another
+    /// blocks on events:
+    /// ```no_run
+    /// use std::thread;
+    /// use ksway::{IpcEvent, SwayClient};
+    ///
+    /// let (mut sender, receiver) = ksway::Client::connect()?.split()?;
+    /// thread::spawn(move || -> ksway::Result<()> {
+    ///     sender.subscribe(vec![IpcEvent::Window])?;
+    ///     sender.run("workspace 2")?;
+    ///     Ok(())
+    /// });
+    /// for event in receiver {
+    ///     let (event, payload) = event?;
+    /// }
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    /// Replies arrive on the socket along with the events, so it is the `Receiver` which reads
+    /// them and hands them to the `Sender`: a command only gets its reply while the `Receiver`
+    /// is being read. The `Sender` waits for the request timeout, or the read timeout if there
+    /// is none, see `Timeouts`. Reconnecting isn't supported by the halves. Events which the
+    /// client already received stay on the channel `subscribe()` returned.
+    pub fn split(self) -> Result<(Sender, Receiver)> {
+        let reply_timeout = self.timeouts.request.or(self.timeouts.read);
+        self.socket.set_read_timeout(None)?;
+        self.socket.set_nonblocking(false)?;
+        let waiting = Arc::new(Mutex::new(Some(VecDeque::new())));
+        let sender = Sender {
+            socket: Mutex::new(self.socket.try_clone()?),
+            waiting: Arc::clone(&waiting),
+            subscribed: Mutex::new(self.subscribed),
+            reply_timeout,
+        };
+        let receiver = Receiver {
+            socket: self.socket,
+            connection: self.connection,
+            read_buffer: self.read_buffer,
+            payload: self.payload,
+            waiting,
+            failed: false,
+        };
+        Ok((sender, receiver))
+    }
+}
+
+impl AsFd for Client {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+/// The socket, which changes when reconnecting, so register it again after a
+/// `Warning::Reconnected`.
+impl AsRawFd for Client {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// An iterator over subscription events, see `Client::events()`.
+pub struct Events {
+    /// `None` after an error.
+    client: Option<Client>,
+    rx: chan::Receiver<(IpcEvent, Vec<u8>)>,
+    cancel: Option<CancelToken>,
+}
+
+impl Events {
+    /// End with `Error::Cancelled` once `token` is cancelled, also while reconnecting. The
+    /// socket is then checked with a short read timeout instead of blocking, so this happens
+    /// within a fraction of a second. The other timeouts are kept.
+    pub fn cancel_on(mut self, token: CancelToken) -> Result<Self> {
+        if let Some(client) = &mut self.client {
+            client.set_timeouts(client.timeouts.clone().read(Some(cancel::CHECK_INTERVAL)))?;
+            if let Some(policy) = client.reconnect_policy.take() {
+                client.reconnect_policy = Some(policy.cancel_on(token.clone()));
+            }
+        }
+        self.cancel = Some(token);
+        Ok(self)
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<(IpcEvent, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Ok(event) = self.rx.try_recv() {
+                return Some(Ok(event));
+            }
+            let client = self.client.as_mut()?;
+            let polled = match &self.cancel {
+                Some(token) => token.check().and_then(|_| client.poll()),
+                None => client.poll(),
+            };
+            if let Err(err) = polled {
+                self.client = None;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Who is waiting for a reply, in the order the commands were sent, or `None` once the
+/// `Receiver` is gone.
+type Waiting = Arc<Mutex<Option<VecDeque<chan::Sender<Result<Reply>>>>>>;
+
+/// The half of a split `Client` which sends commands, see `Client::split()`. It only needs
+/// `&self`, so it can be shared between threads, with several commands in flight at once.
+pub struct Sender {
+    /// Held while queueing in `waiting`, so that the queue is in the order commands were
+    /// written in.
+    socket: Mutex<UnixStream>,
+    waiting: Waiting,
+    subscribed: Mutex<EventMask>,
+    reply_timeout: Option<Duration>,
+}
+
+impl Sender {
+    /// Like `Client::ipc()`, with the reply read by the `Receiver`. Returns `Error::Timeout` if
+    /// it doesn't arrive in time, e.g. because the `Receiver` isn't being read,
+    /// `Error::UnexpectedReply` if the reply is for another kind of command, and
+    /// `Error::OversizedReply` if it was dropped for being too long.
+    pub fn ipc(&self, command: IpcCommand) -> Result<Vec<u8>> {
+        let expected = command.code() as u32;
+        let (tx, rx) = chan::bounded(1);
+        {
+            let mut socket = self.socket.lock().unwrap();
+            self.waiting
+                .lock()
+                .unwrap()
+                .as_mut()
+                .ok_or_else(closed)?
+                .push_back(tx);
+            if let Err(err) = socket.write_all(&core::encode(&command)) {
+                if let Some(waiting) = self.waiting.lock().unwrap().as_mut() {
+                    waiting.pop_back();
+                }
+                return Err(map_timeout(Error::Io(err)));
+            }
+        }
+        let reply = match self.reply_timeout {
+            Some(timeout) => rx.recv_timeout(timeout).map_err(|err| match err {
+                chan::RecvTimeoutError::Timeout => Error::Timeout,
+                chan::RecvTimeoutError::Disconnected => closed(),
+            })??,
+            None => rx.recv().map_err(|_| closed())??,
+        };
+        if reply.code != expected {
+            return Err(Error::UnexpectedReply {
+                expected,
+                received: reply.code,
+                reply: reply.payload,
+            });
+        }
+        Ok(reply.payload)
+    }
+
+    /// Subscribe to more events, which arrive on the `Receiver`. Like `Client::subscribe_more()`,
+    /// only the events which weren't subscribed to yet are sent.
+    pub fn subscribe<E: Into<EventMask>>(&self, event_types: E) -> Result<()> {
+        let mut subscribed = self.subscribed.lock().unwrap();
+        let new = event_types.into() - *subscribed;
+        if !new.is_empty() {
+            self.ipc(ipc_command::subscribe(new))?;
+            *subscribed |= new;
+        }
+        Ok(())
+    }
+
+    /// Close the connection, which ends the `Receiver` and fails any command still waiting.
+    pub fn shutdown(&self) -> Result<()> {
+        Ok(self.socket.lock().unwrap().shutdown(Shutdown::Both)?)
+    }
+}
+
+impl HasIpc for Sender {
+    fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
+        Sender::ipc(self, command)
+    }
+}
+
+impl SwayClient for Sender {}
+impl SwayClientJson for Sender {}
+
+/// The error of a command whose reply won't come anymore, since the `Receiver` is gone.
+fn closed() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "the receiver of the connection to sway was dropped or failed",
+    ))
+}
+
+/// The half of a split `Client` which receives events, and the replies to the `Sender`'s
+/// commands, see `Client::split()`. Iterating over it ends after the first error.
+pub struct Receiver {
+    socket: UnixStream,
+    /// Buffers what was read but not routed yet, including by the client before the split.
+    connection: Connection,
+    read_buffer: Vec<u8>,
+    payload: Vec<u8>,
+    waiting: Waiting,
+    /// Whether `recv()` failed, which ends the iterator.
+    failed: bool,
+}
+
+impl Receiver {
+    /// Block until the next event, handing the replies which arrive before it to the `Sender`.
+    /// Events we don't know the type of are skipped.
+    pub fn recv(&mut self) -> Result<(IpcEvent, Vec<u8>)> {
+        loop {
+            let (code, payload) = self.recv_code()?;
+            if let Some(event) = code.event_type() {
+                return Ok((event, payload));
+            }
+        }
+    }
+
+    /// Like `recv()`, but returns events we don't know the type of too, with their code.
+    pub fn recv_code(&mut self) -> Result<(EventCode, Vec<u8>)> {
+        let received = self.recv_event();
+        if received.is_err() {
+            self.close();
+        }
+        received
+    }
+
+    /// Nobody is going to answer anymore, wake the sender up and fail its later commands.
+    fn close(&self) {
+        *self.waiting.lock().unwrap() = None;
+    }
+
+    fn recv_event(&mut self) -> Result<(EventCode, Vec<u8>)> {
+        loop {
+            // Replies to commands the client sent before the split, which it gave up on.
+            let late = self.connection.pending_replies() > 0;
+            let polled = self.connection.poll_into(&mut self.payload);
+            for skipped in self.connection.take_skipped() {
+                match skipped {
+                    // Unless it was the late reply, it was the reply the sender is waiting for.
+                    Skipped::Oversized { len, payload_type }
+                        if !MessageKind::is_event(payload_type)
+                            && !matches!(polled, Err(Error::OversizedReply { .. })) =>
+                    {
+                        self.answer(Err(Error::OversizedReply {
+                            len,
+                            max: self.connection.max_payload(),
+                        }))
+                    }
+                    _ => {}
+                }
+            }
+            let payload_type = match polled {
+                Err(Error::OversizedReply { .. }) => continue,
+                polled => polled?,
+            };
+            let payload_type = match payload_type {
+                Some(payload_type) => payload_type,
+                None => {
+                    let wanted = self.connection.missing().clamp(READ_CHUNK, MAX_READ);
+                    if self.read_buffer.len() < wanted {
+                        self.read_buffer.resize(wanted, 0);
+                    }
+                    let n = self.socket.read(&mut self.read_buffer[..wanted])?;
+                    if n == 0 {
+                        return Err(Error::Disconnected);
+                    }
+                    self.connection.receive(&self.read_buffer[..n]);
+                    continue;
+                }
+            };
+            match EventCode::from_raw(payload_type) {
+                Some(code) => return Ok((code, self.payload.clone())),
+                None if late => {}
+                None => {
+                    let payload = std::mem::take(&mut self.payload);
+                    self.answer(Ok(Reply {
+                        code: payload_type,
+                        payload,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Hand a reply to the sender waiting the longest.
+    fn answer(&self, reply: Result<Reply>) {
+        let waiting = self
+            .waiting
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(VecDeque::pop_front);
+        if let Some(tx) = waiting {
+            // The sender might have given up, which is fine.
+            let _ = tx.send(reply);
+        }
+    }
+}
+
+impl Iterator for Receiver {
+    type Item = Result<(IpcEvent, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let received = self.recv();
+        self.failed = received.is_err();
+        Some(received)
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{self, Header};
+    use crate::EventType;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::thread;
+
+    fn frame(payload_type: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Header {
+            payload_len: payload.len() as u32,
+            payload_type,
+        }
+        .write_to(&mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn interleaved_frames() {
+        let path = std::env::temp_dir().join(format!("ksway-poll-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // A fake sway which answers the subscribe, then sends a stray reply and an event in
+        // small pieces.
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut header = [0u8; core::HEADER_LEN];
+            socket.read_exact(&mut header).unwrap();
+            let header = Header::parse(&header).unwrap();
+            let mut payload = vec![0u8; header.payload_len as usize];
+            socket.read_exact(&mut payload).unwrap();
+            let mut out = frame(header.payload_type, b"{\"success\":true}");
+            out.extend(frame(0, b"[]"));
+            out.extend(frame(EventType::Window.code().raw(), b"{}"));
+            for chunk in out.chunks(5) {
+                socket.write_all(chunk).unwrap();
+                socket.flush().unwrap();
+            }
+        });
+
+        let mut client = Client::connect_to_path(&path).unwrap();
+        let warnings = client.warnings();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        while rx.is_empty() {
+            client.poll().unwrap();
+        }
+        server.join().unwrap();
+        assert_eq!(rx.recv().unwrap(), (IpcEvent::Window, b"{}".to_vec()));
+        assert_eq!(
+            warnings.try_iter().collect::<Vec<_>>(),
+            vec![Warning::UnsolicitedReply(0)]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Answer one command on `socket` with `reply`.
+    fn answer(socket: &mut UnixStream, reply: &[u8]) {
+        let mut header = [0u8; core::HEADER_LEN];
+        socket.read_exact(&mut header).unwrap();
+        let header = Header::parse(&header).unwrap();
+        let mut payload = vec![0u8; header.payload_len as usize];
+        socket.read_exact(&mut payload).unwrap();
+        socket
+            .write_all(&frame(header.payload_type, reply))
+            .unwrap();
+    }
+
+    #[test]
+    fn reconnect_policy() {
+        let path =
+            std::env::temp_dir().join(format!("ksway-reconnect-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // A fake sway which hangs up after the subscribe, then answers the renewed subscribe on
+        // a new connection and sends an event.
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            answer(&mut socket, b"{\"success\":true}");
+            drop(socket);
+            let (mut socket, _) = listener.accept().unwrap();
+            answer(&mut socket, b"{\"success\":true}");
+            socket
+                .write_all(&frame(
+                    EventType::Tick.code().raw(),
+                    b"{\"first\":false,\"payload\":\"hi\"}",
+                ))
+                .unwrap();
+        });
+
+        let mut client = Client::connect_to_path(&path).unwrap();
+        client.set_reconnect_policy(Some(
+            ReconnectPolicy::new()
+                .max_attempts(3)
+                .backoff(Duration::from_millis(1), Duration::from_millis(10)),
+        ));
+        let warnings = client.warnings();
+        let rx = client.subscribe_typed(vec![IpcEvent::Tick]).unwrap();
+        let mut events = Vec::new();
+        while events.len() < 2 {
+            client.poll().unwrap();
+            events.extend(rx.try_iter().map(Result::unwrap));
+        }
+        server.join().unwrap();
+        assert_eq!(events[0], SwayEvent::Reconnected);
+        assert_eq!(events[1].event_type(), Some(IpcEvent::Tick));
+        assert_eq!(warnings.try_recv(), Ok(Warning::Reconnected(1)));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reconnect_retries_only_queries() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(&ipc_command::get_version(), "{}");
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        client.set_reconnect_policy(Some(
+            ReconnectPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        ));
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+
+        // It may have run already, so it isn't sent again.
+        server.disconnect();
+        assert!(matches!(client.run("exec foot"), Err(Error::Disconnected)));
+        server.disconnect();
+        assert_eq!(client.get_version().unwrap(), b"{}");
+        assert!(server.run_commands().is_empty());
+
+        let ticks: Vec<_> = rx
+            .try_iter()
+            .map(|(event, payload)| (event, TickEvent::parse(&payload).unwrap()))
+            .collect();
+        let reconnected = TickEvent {
+            first: true,
+            payload: RECONNECTED_TICK.into(),
+        };
+        assert_eq!(
+            ticks,
+            vec![
+                (IpcEvent::Tick, reconnected.clone()),
+                (IpcEvent::Tick, reconnected)
+            ]
+        );
+        let subscribes = server
+            .received()
+            .into_iter()
+            .filter(|frame| frame.payload == br#"["window"]"#)
+            .count();
+        assert_eq!(subscribes, 3);
+    }
+
+    #[test]
+    fn reconnect_resubscribes() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        server.disconnect();
+        client.reconnect().unwrap();
+        assert_eq!(client.socket_path(), server.path());
+
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        while rx.is_empty() {
+            client.poll().unwrap();
+        }
+        assert_eq!(rx.recv().unwrap(), (IpcEvent::Window, b"{}".to_vec()));
+        client.run("nop").unwrap();
+        server.assert_run_commands(&["nop"]);
+        let subscribes = server
+            .received()
+            .into_iter()
+            .filter(|frame| frame.payload_type == crate::IpcCommandCode::Subscribe as u32)
+            .map(|frame| frame.payload)
+            .collect::<Vec<_>>();
+        assert_eq!(subscribes, vec![br#"["window"]"#.to_vec(); 2]);
+    }
+
+    #[test]
+    fn incremental_subscribe() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Tick]).unwrap();
+        client
+            .subscribe_more(vec![IpcEvent::Window, IpcEvent::Tick])
+            .unwrap();
+        client.subscribe_more(vec![IpcEvent::Tick]).unwrap();
+        // Only what is new, so that sway doesn't send another `first` tick.
+        let subscribes: Vec<_> = server
+            .received()
+            .into_iter()
+            .map(|frame| String::from_utf8(frame.payload).unwrap())
+            .collect();
+        assert_eq!(subscribes, vec![r#"["tick"]"#, r#"["window"]"#]);
+        assert!(matches!(
+            client.subscribe(vec![IpcEvent::Mode]),
+            Err(Error::AlreadySubscribed)
+        ));
+        assert!(matches!(
+            client.subscribe_typed(vec![IpcEvent::Mode]),
+            Err(Error::AlreadySubscribed)
+        ));
+
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        while rx.is_empty() {
+            client.poll().unwrap();
+        }
+        assert_eq!(rx.recv().unwrap().0, IpcEvent::Window);
+
+        // Events don't pile up for a receiver which is gone, and commands still work.
+        drop(rx);
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        client.run("nop").unwrap();
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        assert!(matches!(client.poll(), Err(Error::SubscriptionError)));
+    }
+
+    #[test]
+    fn events_iterator() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut events = Client::connect_to_path(server.path())
+            .unwrap()
+            .events(vec![IpcEvent::Window, IpcEvent::Tick])
+            .unwrap();
+        server.send_event(IpcEvent::Window, b"1").unwrap();
+        server.send_event(IpcEvent::Tick, b"2").unwrap();
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            (IpcEvent::Window, b"1".to_vec())
+        );
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            (IpcEvent::Tick, b"2".to_vec())
+        );
+
+        // Without a reconnect policy, it ends after the error.
+        server.disconnect();
+        assert!(matches!(events.next(), Some(Err(Error::Disconnected))));
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn cancel_events() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let token = CancelToken::new();
+        let mut events = Client::connect_to_path(server.path())
+            .unwrap()
+            .events(vec![IpcEvent::Tick])
+            .unwrap()
+            .cancel_on(token.clone())
+            .unwrap();
+        server.send_event(IpcEvent::Tick, b"{}").unwrap();
+        assert_eq!(events.next().unwrap().unwrap().0, IpcEvent::Tick);
+        token.cancel();
+        assert!(matches!(events.next(), Some(Err(Error::Cancelled))));
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn run_batch() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::run("kill; reload"),
+            r#"[{"success": true}, {"success": false, "parse_error": true, "error": "nope"}]"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert!(client.run_batch(&[]).unwrap().is_empty());
+        let outcomes = client
+            .run_batch(&[crate::command::kill(), crate::command::reload()])
+            .unwrap();
+        assert!(outcomes[0].success);
+        assert!(outcomes[1].parse_error);
+        assert_eq!(outcomes[1].error.as_deref(), Some("nope"));
+        assert_eq!(server.run_commands(), vec!["kill; reload"]);
+    }
+
+    #[test]
+    fn ipc_into_reuses_buffers() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let tree = format!(r#"{{"name": "{}"}}"#, "x".repeat(1000));
+        server.reply(&ipc_command::get_tree(), tree.as_str());
+        server.reply(&ipc_command::get_version(), "{}");
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+
+        let mut payload = Vec::new();
+        client
+            .ipc_into(ipc_command::get_tree(), &mut payload)
+            .unwrap();
+        assert_eq!(payload, tree.as_bytes());
+        // The first reply leaves `payload` with the client's buffer and the client with the
+        // empty one, the next swaps them back.
+        for _ in 0..2 {
+            client
+                .ipc_into(ipc_command::get_version(), &mut payload)
+                .unwrap();
+        }
+        assert_eq!(payload, b"{}");
+        assert!(payload.capacity() >= tree.len());
+    }
+
+    #[test]
+    fn split() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(&ipc_command::get_version(), "{}");
+        let client = Client::connect_to_path(server.path()).unwrap();
+        let (mut sender, mut receiver) = client.split().unwrap();
+        let mut sender = thread::scope(|scope| {
+            let sending = scope.spawn(|| {
+                sender.subscribe(vec![IpcEvent::Tick]).unwrap();
+                sender.run("focus").unwrap();
+                assert_eq!(sender.get_version().unwrap(), b"{}");
+                server.send_event(IpcEvent::Tick, b"{}").unwrap();
+                sender
+            });
+            assert_eq!(receiver.recv().unwrap(), (IpcEvent::Tick, b"{}".to_vec()));
+            sending.join().unwrap()
+        });
+        assert_eq!(server.run_commands(), vec!["focus"]);
+
+        drop(receiver);
+        assert!(matches!(sender.run("nop"), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn poll_timeout() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Tick]).unwrap();
+
+        let start = Instant::now();
+        let polled = client.poll_timeout(Duration::from_millis(50)).unwrap();
+        assert_eq!(polled, PollOutcome::TimedOut);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(client.poll_until(start).unwrap(), PollOutcome::TimedOut);
+
+        server.send_event(IpcEvent::Tick, b"{}").unwrap();
+        let polled = client.poll_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(polled, PollOutcome::Event);
+        assert_eq!(rx.try_recv().unwrap().0, IpcEvent::Tick);
+    }
+
+    #[test]
+    fn connect_wait_until_sway_is_up() {
+        let path = std::env::temp_dir().join(format!("ksway-wait-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let start = Instant::now();
+        let err = retry_connect(start + Duration::from_millis(150), || {
+            Client::connect_to_path(&path)
+        });
+        assert!(matches!(err, Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::NotFound));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        let server = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(150));
+                let listener = UnixListener::bind(&path).unwrap();
+                listener.accept().unwrap().0
+            })
+        };
+        retry_connect(Instant::now() + Duration::from_secs(5), || {
+            Client::connect_to_path(&path)
+        })
+        .unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn sync() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        client.sync().unwrap();
+        assert_eq!(
+            rx.try_iter().map(|(event, _)| event).collect::<Vec<_>>(),
+            vec![IpcEvent::Window]
+        );
+        server
+            .send_event(IpcEvent::Tick, br#"{"first": false, "payload": "x"}"#)
+            .unwrap();
+        client.sync().unwrap();
+        assert!(rx.is_empty());
+
+        // Ticks subscribed to explicitly are passed on, except those of `sync()`.
+        client.subscribe_more(vec![IpcEvent::Tick]).unwrap();
+        server
+            .send_event(IpcEvent::Tick, br#"{"first": false, "payload": "x"}"#)
+            .unwrap();
+        client.sync().unwrap();
+        assert_eq!(rx.try_iter().count(), 1);
+        let ticks = server
+            .received()
+            .into_iter()
+            .filter(|frame| frame.payload_type == crate::IpcCommandCode::SendTick as u32)
+            .count();
+        assert_eq!(ticks, 3);
+    }
+
+    #[test]
+    fn focus_previous() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_tree(),
+            r#"{"id": 1, "type": "root", "focus": [10], "nodes": [
+                {"id": 10, "type": "workspace", "name": "1", "focus": [4, 3, 2], "nodes": [
+                    {"id": 2, "type": "con"},
+                    {"id": 3, "type": "con"},
+                    {"id": 4, "type": "con", "focused": true}
+                ]}
+            ]}"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert_eq!(client.focus_previous().unwrap(), Some(3));
+
+        let rx = client.subscribe(vec![IpcEvent::Mode]).unwrap();
+        client.track_focus(10).unwrap();
+        for id in [2, 9, 4] {
+            let event = format!(r#"{{"change": "focus", "container": {{"id": {}}}}}"#, id);
+            server
+                .send_event(IpcEvent::Window, event.as_bytes())
+                .unwrap();
+        }
+        client.sync().unwrap();
+        // Only subscribed to by `track_focus()`, so not passed on.
+        assert!(rx.is_empty());
+        assert_eq!(client.focus_history().unwrap().alt_tab_target(), Some(9));
+        // 9 isn't in the tree anymore.
+        assert_eq!(client.focus_previous().unwrap(), Some(2));
+        assert_eq!(
+            client.focus_history().unwrap().iter().collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        server.assert_run_commands(&[r#"[con_id="3"] focus"#, r#"[con_id="2"] focus"#]);
+    }
+
+    #[test]
+    fn focus_previous_without_history() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_tree(),
+            r#"{"id": 1, "type": "root", "focus": [10], "nodes": [
+                {"id": 10, "type": "workspace", "name": "1", "focus": [4, 3], "nodes": [
+                    {"id": 3, "type": "con"},
+                    {"id": 4, "type": "con", "focused": true}
+                ]}
+            ]}"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        client.track_focus(10).unwrap();
+        server
+            .send_event(
+                IpcEvent::Window,
+                br#"{"change": "focus", "container": {"id": 4}}"#,
+            )
+            .unwrap();
+        client.sync().unwrap();
+        // Only the focused window is known, so the tree decides.
+        assert_eq!(client.focus_previous().unwrap(), Some(3));
+
+        // Still tracking after reconnecting, without a subscription channel.
+        server.disconnect();
+        client.reconnect().unwrap();
+        server
+            .send_event(
+                IpcEvent::Window,
+                br#"{"change": "focus", "container": {"id": 3}}"#,
+            )
+            .unwrap();
+        client.sync().unwrap();
+        assert_eq!(
+            client.focus_history().unwrap().iter().collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        let subscribes: Vec<_> = server
+            .received()
+            .into_iter()
+            .filter(|frame| frame.payload_type == crate::IpcCommandCode::Subscribe as u32)
+            .map(|frame| String::from_utf8(frame.payload).unwrap())
+            .collect();
+        assert_eq!(
+            subscribes,
+            vec![r#"["window"]"#, r#"["tick"]"#, r#"["window","tick"]"#]
+        );
+    }
+
+    #[test]
+    fn polling_reconnects() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        client.set_reconnect_policy(Some(
+            ReconnectPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        ));
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        server.disconnect();
+        // The hangup may take a moment to be seen.
+        while rx.is_empty() {
+            assert_eq!(client.try_poll().unwrap(), None);
+        }
+        let (event, payload) = rx.recv().unwrap();
+        assert_eq!(event, IpcEvent::Tick);
+        assert_eq!(
+            TickEvent::parse(&payload).unwrap().payload,
+            RECONNECTED_TICK
+        );
+
+        server.disconnect();
+        assert_eq!(
+            client.poll_timeout(Duration::from_secs(5)).unwrap(),
+            PollOutcome::Reconnected
+        );
+    }
+
+    #[test]
+    fn try_poll_never_blocks() {
+        let server = crate::testing::MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert!(client.as_raw_fd() >= 0);
+        let rx = client.subscribe(vec![IpcEvent::Tick]).unwrap();
+
+        let start = std::time::Instant::now();
+        assert_eq!(client.try_poll().unwrap(), None);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(rx.is_empty());
+
+        server.send_event(IpcEvent::Tick, b"{}").unwrap();
+        server.send_event(IpcEvent::Tick, b"{}").unwrap();
+        while rx.len() < 2 {
+            client.try_poll().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// A client subscribed to tick events through a fake sway, along with the fake's end of the
+    /// connection, to write frames to by hand.
+    fn subscribed_to_fake(name: &str) -> (Client, chan::Receiver<(IpcEvent, Vec<u8>)>, UnixStream) {
+        let path = std::env::temp_dir().join(format!("ksway-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut header = [0u8; core::HEADER_LEN];
+            socket.read_exact(&mut header).unwrap();
+            let header = Header::parse(&header).unwrap();
+            let mut payload = vec![0u8; header.payload_len as usize];
+            socket.read_exact(&mut payload).unwrap();
+            socket
+                .write_all(&frame(header.payload_type, br#"{"success": true}"#))
+                .unwrap();
+            socket
+        });
+
+        let mut client = Client::connect_to_path(&path).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Tick]).unwrap();
+        let socket = server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+        (client, rx, socket)
+    }
+
+    #[test]
+    fn nonblocking_partial_frames() {
+        let (mut client, rx, mut socket) = subscribed_to_fake("nonblock");
+        client.set_nonblocking(true).unwrap();
+        client.poll().unwrap();
+
+        let event = frame(IpcEvent::Tick.code().raw(), b"{}");
+        socket.write_all(&event[..5]).unwrap();
+        assert_eq!(client.try_poll().unwrap(), None);
+        socket.write_all(&event[5..]).unwrap();
+        socket.write_all(&event).unwrap();
+        assert_eq!(client.try_poll().unwrap(), Some(2));
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn timeout_mid_frame() {
+        let (mut client, rx, mut socket) = subscribed_to_fake("mid-frame");
+        client
+            .set_timeouts(Timeouts::new().read(Some(Duration::from_millis(10))))
+            .unwrap();
+
+        // Time out once inside the header and once inside the payload: the stream stays in sync.
+        let event = frame(IpcEvent::Tick.code().raw(), br#"{"first": true}"#);
+        socket.write_all(&event[..3]).unwrap();
+        client.poll().unwrap();
+        let wait = Duration::from_millis(20);
+        assert_eq!(client.poll_timeout(wait).unwrap(), PollOutcome::TimedOut);
+        socket.write_all(&event[3..core::HEADER_LEN + 4]).unwrap();
+        assert_eq!(client.poll_timeout(wait).unwrap(), PollOutcome::TimedOut);
+        assert!(rx.is_empty());
+        socket.write_all(&event[core::HEADER_LEN + 4..]).unwrap();
+        socket
+            .write_all(&frame(IpcEvent::Tick.code().raw(), b"{}"))
+            .unwrap();
+        while rx.len() < 2 {
+            client.poll().unwrap();
+        }
+        let payloads: Vec<_> = rx.try_iter().map(|(_, payload)| payload).collect();
+        assert_eq!(
+            payloads,
+            vec![br#"{"first": true}"#.to_vec(), b"{}".to_vec()]
+        );
+    }
+
+    #[test]
+    fn request_deadline() {
+        let path = std::env::temp_dir().join(format!("ksway-timeout-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // A fake sway which never answers.
+        let server = thread::spawn(move || listener.accept().unwrap().0);
+
+        let mut client = Client::connect_to_path(&path).unwrap();
+        let _socket = server.join().unwrap();
+        client
+            .set_timeouts(
+                Timeouts::new()
+                    .read(Some(Duration::from_millis(10)))
+                    .request(Some(Duration::from_millis(50))),
+            )
+            .unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            client.ipc(ipc_command::get_version()),
+            Err(Error::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        client
+            .set_timeouts(Timeouts::new().read(Some(Duration::from_millis(10))))
+            .unwrap();
+        assert!(matches!(
+            client.ipc(ipc_command::get_version()),
+            Err(Error::Timeout)
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn warnings() {
+        let path = std::env::temp_dir().join(format!("ksway-client-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // A fake sway which sends an unknown event, then replies with the wrong type.
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut header = [0u8; core::HEADER_LEN];
+            socket.read_exact(&mut header).unwrap();
+            let header = Header::parse(&header).unwrap();
+            let mut payload = vec![0u8; header.payload_len as usize];
+            socket.read_exact(&mut payload).unwrap();
+            let mut out = frame(0x8000_00ff, b"{}");
+            out.extend(frame(header.payload_type + 1, b"[]"));
+            socket.write_all(&out).unwrap();
+        });
+
+        let mut client = Client::connect_to_path(&path).unwrap();
+        let warnings = client.warnings();
+        assert!(matches!(
+            client.ipc(ipc_command::run("nop")),
+            Err(Error::UnexpectedReply {
+                expected: 0,
+                received: 1,
+                ..
+            })
+        ));
+        server.join().unwrap();
+        assert_eq!(
+            warnings.try_iter().collect::<Vec<_>>(),
+            vec![Warning::UnknownEvent(
+                EventCode::from_raw(0x8000_00ff).unwrap()
+            )]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn visit_tree_from_the_socket() {
+        use crate::tree::Visit;
+
+        let server = crate::testing::MockServer::start().unwrap();
+        // Larger than a single read, with the keys in the order sway sends them.
+        let workspaces: Vec<_> = (2..5000)
+            .map(|id| format!(r#"{{"id": {0}, "type": "workspace", "name": "{0}"}}"#, id))
+            .collect();
+        let tree = format!(
+            r#"{{"id": 1, "type": "root", "nodes": [{}]}}"#,
+            workspaces.join(", ")
+        );
+        assert!(tree.len() > READ_CHUNK);
+        server.reply(&ipc_command::get_tree(), tree);
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        server
+            .send_event(IpcEvent::Window, br#"{"change":"focus"}"#)
+            .unwrap();
+
+        let mut visited = Vec::new();
+        client
+            .visit_tree(|node| {
+                visited.push(node.id);
+                if node.name.as_deref() == Some("3") {
+                    return Visit::Stop;
+                }
+                Visit::Continue
+            })
+            .unwrap();
+        assert_eq!(visited, vec![1, 2, 3]);
+        assert_eq!(rx.try_recv().unwrap().0, IpcEvent::Window);
+
+        // The rest of the reply was read, and the stream is still in sync.
+        assert_eq!(client.run("nop").unwrap(), br#"[{"success":true}]"#);
+        let mut count = 0;
+        client
+            .visit_tree(|_| {
+                count += 1;
+                Visit::Continue
+            })
+            .unwrap();
+        assert_eq!(count, 4999);
+    }
+
+    #[test]
+    fn oversized_payloads_and_resync() {
+        let server = crate::testing::MockServer::start().unwrap();
+        server.reply(&ipc_command::get_tree(), vec![b' '; 100]);
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        client.set_max_payload(64);
+        let warnings = client.warnings();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+
+        assert!(matches!(
+            client.get_tree(),
+            Err(Error::OversizedReply { len: 100, max: 64 })
+        ));
+        // The stream is still in sync.
+        assert_eq!(client.run("nop").unwrap(), br#"[{"success":true}]"#);
+
+        server.send_event(IpcEvent::Window, &[b' '; 100]).unwrap();
+        server.send_raw(b"garbage").unwrap();
+        server
+            .send_event(IpcEvent::Window, br#"{"change":"focus"}"#)
+            .unwrap();
+        client.poll().unwrap();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            (IpcEvent::Window, br#"{"change":"focus"}"#.to_vec())
+        );
+        assert_eq!(
+            warnings.try_iter().collect::<Vec<_>>(),
+            vec![
+                Warning::OversizedPayload {
+                    len: 100,
+                    payload_type: 4
+                },
+                Warning::OversizedPayload {
+                    len: 100,
+                    payload_type: IpcEvent::Window.code().raw()
+                },
+                Warning::Resync { skipped: 7 },
+            ]
+        );
+    }
 }
@@ -0,0 +1,213 @@
+//! Merging bursts of related events before they reach consumers which fetch state for each.
+//!
+//! Renaming a terminal tab can cause a dozen `title` events for the same window, and cycling
+//! through workspaces a focus event per workspace passed, while a bar only needs to re-render
+//! once. `Coalescer` holds events of the types it is configured for until their window has passed
+//! and hands out one summary per burst:
+//! ```no_run
+//! use std::time::Duration;
+//! use ksway::{coalesce::Coalescer, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut coalescer = Coalescer::new()
+//!     .window(IpcEvent::Window, Duration::from_millis(50))
+//!     .window(IpcEvent::Workspace, Duration::from_millis(50));
+//! let rx = client.subscribe(vec![IpcEvent::Window, IpcEvent::Workspace])?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         coalescer.push(event, serde_json::from_slice(&payload)?);
+//!     }
+//!     while let Some((event, payload)) = coalescer.pop() {
+//!         println!("{:?} x{}: {}", event, payload["coalesced"], payload["change"]);
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::events::WorkspaceChange;
+use crate::{IpcEvent, JsonValue, SwayJsonExt};
+
+/// Events of the same burst, see `burst_key()`.
+#[derive(Debug, Clone)]
+struct Burst {
+    event: IpcEvent,
+    key: String,
+    first: Instant,
+    payload: JsonValue,
+    count: u64,
+}
+
+/// Which burst an event belongs to:
+///
+/// - window events with the same `change` for the same container;
+/// - workspace focus events, whichever workspace they are for;
+/// - other workspace events with the same `change` for the same workspace;
+/// - any other events with the same `change`.
+fn burst_key(event: IpcEvent, payload: &JsonValue) -> String {
+    let change = payload["change"].as_str().unwrap_or("");
+    match event {
+        IpcEvent::Window => format!("{}:{:?}", change, payload["container"].con_id()),
+        IpcEvent::Workspace if WorkspaceChange::from(change) == WorkspaceChange::Focus => {
+            change.to_owned()
+        }
+        IpcEvent::Workspace => format!("{}:{:?}", change, payload["current"].con_id()),
+        _ => change.to_string(),
+    }
+}
+
+fn is_focus_burst(burst: &Burst) -> bool {
+    burst.event == IpcEvent::Workspace
+        && WorkspaceChange::from_payload(&burst.payload) == Some(WorkspaceChange::Focus)
+}
+
+/// Holds events of the configured types for a window of time after the first of a burst, then
+/// hands out the last event of the burst with `coalesced` set to the number of events it stands
+/// for. A burst of workspace focus events keeps the `old` workspace of its first event, and is
+/// dropped when focus ends up back where it started.
+///
+/// Events of other types are handed out as soon as they are pushed, so they can overtake held
+/// events of the configured types.
+#[derive(Debug, Clone, Default)]
+pub struct Coalescer {
+    windows: HashMap<IpcEvent, Duration>,
+    bursts: Vec<Burst>,
+    ready: VecDeque<(IpcEvent, JsonValue)>,
+}
+
+impl Coalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge events of type `event` which arrive within `window` of the first of their burst.
+    pub fn window(mut self, event: IpcEvent, window: Duration) -> Self {
+        self.windows.insert(event, window);
+        self
+    }
+
+    /// Add an event received from sway.
+    pub fn push(&mut self, event: IpcEvent, payload: JsonValue) {
+        self.push_at(event, payload, Instant::now())
+    }
+
+    fn push_at(&mut self, event: IpcEvent, payload: JsonValue, now: Instant) {
+        if !self.windows.contains_key(&event) {
+            self.ready.push_back((event, payload));
+            return;
+        }
+        let key = burst_key(event, &payload);
+        match self
+            .bursts
+            .iter_mut()
+            .find(|burst| burst.event == event && burst.key == key)
+        {
+            Some(burst) => {
+                let old = burst.payload["old"].take();
+                burst.payload = payload;
+                if is_focus_burst(burst) {
+                    burst.payload["old"] = old;
+                }
+                burst.count += 1;
+            }
+            None => self.bursts.push(Burst {
+                event,
+                key,
+                first: now,
+                payload,
+                count: 1,
+            }),
+        }
+    }
+
+    /// The next event whose window has passed, if any.
+    pub fn pop(&mut self) -> Option<(IpcEvent, JsonValue)> {
+        self.pop_at(Instant::now())
+    }
+
+    fn pop_at(&mut self, now: Instant) -> Option<(IpcEvent, JsonValue)> {
+        let windows = &self.windows;
+        let (expired, held): (Vec<_>, Vec<_>) = self
+            .bursts
+            .drain(..)
+            .partition(|burst| now.duration_since(burst.first) >= windows[&burst.event]);
+        self.bursts = held;
+        self.ready.extend(expired.into_iter().filter_map(summary));
+        self.ready.pop_front()
+    }
+
+    /// When the next held burst is due, to bound how long to wait for events.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.bursts
+            .iter()
+            .map(|burst| burst.first + self.windows[&burst.event])
+            .min()
+    }
+
+    /// Every event still held or not yet popped, without waiting, e.g. before shutting down.
+    pub fn flush(&mut self) -> Vec<(IpcEvent, JsonValue)> {
+        let bursts: Vec<_> = self.bursts.drain(..).collect();
+        self.ready.extend(bursts.into_iter().filter_map(summary));
+        self.ready.drain(..).collect()
+    }
+}
+
+/// The event standing for a whole burst, or `None` for focus which ended where it started.
+fn summary(mut burst: Burst) -> Option<(IpcEvent, JsonValue)> {
+    if is_focus_burst(&burst)
+        && burst.count > 1
+        && !burst.payload["old"].is_null()
+        && burst.payload["old"].con_id() == burst.payload["current"].con_id()
+    {
+        return None;
+    }
+    burst.payload["coalesced"] = burst.count.into();
+    Some((burst.event, burst.payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_bursts() {
+        let window = Duration::from_millis(50);
+        let mut coalescer = Coalescer::new()
+            .window(IpcEvent::Window, window)
+            .window(IpcEvent::Workspace, window);
+        let start = Instant::now();
+        let title =
+            |id: u64, name: &str| json!({"change": "title", "container": {"id": id, "name": name}});
+        let focus = |old: u64, current: u64| json!({"change": "focus", "old": {"id": old}, "current": {"id": current}});
+
+        for name in &["a", "ab", "abc"] {
+            coalescer.push_at(IpcEvent::Window, title(1, name), start);
+        }
+        coalescer.push_at(IpcEvent::Window, title(2, "x"), start);
+        coalescer.push_at(IpcEvent::Mode, json!({"change": "resize"}), start);
+        assert_eq!(coalescer.next_deadline(), Some(start + window));
+        assert_eq!(coalescer.pop_at(start).unwrap().0, IpcEvent::Mode);
+        assert!(coalescer.pop_at(start).is_none());
+
+        let later = start + window;
+        let (event, payload) = coalescer.pop_at(later).unwrap();
+        assert_eq!(event, IpcEvent::Window);
+        assert_eq!(payload["container"]["name"], "abc");
+        assert_eq!(payload["coalesced"], 3);
+        assert_eq!(coalescer.pop_at(later).unwrap().1["coalesced"], 1);
+        assert!(coalescer.pop_at(later).is_none());
+
+        // Going 3 -> 4 -> 5 is one move from 3 to 5, and 5 -> 6 -> 5 is none at all.
+        coalescer.push_at(IpcEvent::Workspace, focus(3, 4), later);
+        coalescer.push_at(IpcEvent::Workspace, focus(4, 5), later);
+        let (_, payload) = coalescer.flush().pop().unwrap();
+        assert_eq!(payload["old"]["id"], 3);
+        assert_eq!(payload["current"]["id"], 5);
+        coalescer.push_at(IpcEvent::Workspace, focus(5, 6), later);
+        coalescer.push_at(IpcEvent::Workspace, focus(6, 5), later);
+        assert!(coalescer.flush().is_empty());
+    }
+}
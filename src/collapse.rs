@@ -0,0 +1,142 @@
+//! Flattening split containers which are left holding a single child.
+//!
+//! Closing or moving windows out of a split leaves the split behind with whatever is left in it,
+//! so the tree gets deeper than it looks, and `focus parent` or `move` behave in surprising
+//! ways. `Collapser` removes those containers with `split none`, which needs sway 1.8:
+//! ```no_run
+//! use ksway::{collapse::Collapser, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let collapser = Collapser::new();
+//! collapser.collapse_all(&mut client)?;
+//! let rx = client.subscribe(vec![IpcEvent::Window])?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         collapser.handle_event(&mut client, event, &serde_json::from_slice(&payload)?)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use crate::command::{self, Split};
+use crate::criteria::con_id;
+use crate::events::WindowChange;
+use crate::tree::Tree;
+use crate::{IpcEvent, JsonValue, Result, SwayClientJson};
+
+/// The layouts of the containers which are flattened by default.
+const SPLITS: &[&str] = &["splith", "splitv"];
+
+#[derive(Debug, Clone)]
+pub struct Collapser {
+    layouts: Vec<String>,
+}
+
+impl Default for Collapser {
+    fn default() -> Self {
+        Self {
+            layouts: SPLITS.iter().map(|layout| layout.to_string()).collect(),
+        }
+    }
+}
+
+impl Collapser {
+    /// Flatten split containers, but not tabbed or stacked ones.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also flatten tabbed and stacked containers with a single tab.
+    pub fn tabbed(mut self, tabbed: bool) -> Self {
+        self.layouts
+            .retain(|layout| SPLITS.contains(&layout.as_str()));
+        if tabbed {
+            self.layouts.extend(vec!["tabbed".into(), "stacked".into()]);
+        }
+        self
+    }
+
+    /// The commands flattening every redundant container of `tree`, see
+    /// `Tree::redundant_containers()`. `split none` on the only child removes its parent.
+    fn commands(&self, tree: &Tree) -> Vec<String> {
+        let layouts: Vec<&str> = self.layouts.iter().map(String::as_str).collect();
+        tree.redundant_containers(&layouts)
+            .into_iter()
+            .map(|(_, survivor)| {
+                command::split(Split::None)
+                    .with_criteria(vec![con_id(survivor.id)])
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Whether an event can leave a container behind with a single child.
+    fn is_relevant(event: IpcEvent, payload: &JsonValue) -> bool {
+        event == IpcEvent::Window
+            && matches!(
+                WindowChange::from_payload(payload),
+                Some(WindowChange::Close | WindowChange::Move | WindowChange::Floating)
+            )
+    }
+
+    /// Flatten every redundant container in the tree, e.g. at startup.
+    pub fn collapse_all<C: SwayClientJson>(&self, client: &mut C) -> Result<()> {
+        let tree = Tree::from(client.get_tree_typed()?);
+        let commands = self.commands(&tree);
+        if !commands.is_empty() {
+            client.run(commands.join("; "))?;
+        }
+        Ok(())
+    }
+
+    /// Feed a subscription event, flattening containers after windows close or move.
+    pub fn handle_event<C: SwayClientJson>(
+        &self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        if Self::is_relevant(event, payload) {
+            self.collapse_all(client)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn flatten_commands() {
+        let rect = json!({"x": 0, "y": 0, "width": 1, "height": 1});
+        let tree: crate::reply::Node = serde_json::from_value(json!({
+        "id": 1, "type": "workspace", "layout": "splith", "rect": rect, "nodes": [
+            {"id": 2, "type": "con", "layout": "splitv", "rect": rect, "nodes": [
+                {"id": 3, "type": "con", "layout": "none", "rect": rect},
+            ]},
+            {"id": 4, "type": "con", "layout": "tabbed", "rect": rect, "nodes": [
+                {"id": 5, "type": "con", "layout": "none", "rect": rect},
+            ]},
+        ]}))
+        .unwrap();
+        let tree = Tree::from(tree);
+        assert_eq!(
+            Collapser::new().commands(&tree),
+            vec![r#"[con_id="3"] split none"#]
+        );
+        assert_eq!(
+            Collapser::new().tabbed(true).commands(&tree),
+            vec![r#"[con_id="5"] split none"#, r#"[con_id="3"] split none"#]
+        );
+        assert!(Collapser::is_relevant(
+            IpcEvent::Window,
+            &json!({"change": "close"})
+        ));
+        assert!(!Collapser::is_relevant(
+            IpcEvent::Window,
+            &json!({"change": "focus"})
+        ));
+    }
+}
@@ -0,0 +1,608 @@
+//! The i3-ipc protocol without any IO.
+//!
+//! Everything in here works on byte buffers, so that it can be driven by whatever event loop an
+//! application already has (glib, calloop, ...), while reusing the same framing, parsing and
+//! command generation as `ksway::Client`, which is just a thin blocking adapter on top of this.
+//!
+//! A frame is the magic string `i3-ipc`, followed by the payload length and the payload type as
+//! native endian `u32`s, followed by the payload itself.
+//!
+//! ```
+//! use ksway::{core::{Connection, Message}, ipc_command};
+//!
+//! let mut connection = Connection::new();
+//! connection.send(&ipc_command::get_version());
+//! let outgoing = connection.take_outgoing();
+//! // ... write `outgoing` to the socket, and feed whatever is read back:
+//! # let mut reply = b"i3-ipc".to_vec();
+//! # reply.extend(&2u32.to_ne_bytes());
+//! # reply.extend(&7u32.to_ne_bytes());
+//! # reply.extend(b"{}");
+//! connection.receive(&reply);
+//! while let Some(message) = connection.poll()? {
+//!     match message {
+//!         Message::Reply { code, payload } => println!("reply to {}: {:?}", code, payload),
+//!         Message::Event { code, payload } => println!("event {:?}: {:?}", code, payload),
+//!     }
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::VecDeque;
+
+use crate::{Error, EventCode, EventType, IpcCommand, Result};
+
+pub const MAGIC: &[u8; 6] = b"i3-ipc";
+/// The magic string plus the payload length and type.
+pub const HEADER_LEN: usize = MAGIC.len() + 8;
+/// The longest payload a `Decoder` accepts by default, far more than the GET_TREE reply of even
+/// a very busy session.
+pub const DEFAULT_MAX_PAYLOAD: u32 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub payload_len: u32,
+    pub payload_type: u32,
+}
+
+impl Header {
+    /// Returns `Error::InvalidHeader` if the magic string is missing, which means the stream is
+    /// out of sync.
+    pub fn parse(bytes: &[u8; HEADER_LEN]) -> Result<Self> {
+        if &bytes[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidHeader);
+        }
+        let field = |offset: usize| {
+            let mut field = [0u8; 4];
+            field.copy_from_slice(&bytes[offset..offset + 4]);
+            u32::from_ne_bytes(field)
+        };
+        Ok(Self {
+            payload_len: field(MAGIC.len()),
+            payload_type: field(MAGIC.len() + 4),
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.payload_len.to_ne_bytes());
+        out.extend_from_slice(&self.payload_type.to_ne_bytes());
+    }
+}
+
+/// Whether a frame is the reply to a command or an event, from its payload type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    Reply,
+    Event,
+}
+
+impl MessageKind {
+    /// Events have the high bit set, including events which don't exist yet.
+    pub fn of(payload_type: u32) -> Self {
+        if payload_type & EventCode::EVENT_BIT != 0 {
+            MessageKind::Event
+        } else {
+            MessageKind::Reply
+        }
+    }
+
+    pub fn is_event(payload_type: u32) -> bool {
+        Self::of(payload_type) == MessageKind::Event
+    }
+}
+
+/// The name sway uses for the event with this payload type, e.g. `window` for `0x80000003`, see
+/// `EventType::name()`. Returns `None` for replies and unknown events.
+pub fn event_code_to_name(payload_type: u32) -> Option<&'static str> {
+    EventCode::from_raw(payload_type)?
+        .event_type()
+        .map(EventType::name)
+}
+
+/// A decoded frame, classified by its payload type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// The reply to a command, `code` being the code of the command.
+    Reply {
+        code: u32,
+        payload: Vec<u8>,
+    },
+    Event {
+        code: EventCode,
+        payload: Vec<u8>,
+    },
+}
+
+impl Message {
+    pub fn from_frame(payload_type: u32, payload: Vec<u8>) -> Self {
+        match EventCode::from_raw(payload_type) {
+            Some(code) => Message::Event { code, payload },
+            None => Message::Reply {
+                code: payload_type,
+                payload,
+            },
+        }
+    }
+}
+
+/// Append the frame for `command` to `out`.
+pub fn encode_into(command: &IpcCommand, out: &mut Vec<u8>) {
+    let events;
+    let payload: &[u8] = match command {
+        IpcCommand::Run(command) | IpcCommand::GetBarConfigFor(command) => command.as_bytes(),
+        IpcCommand::SendTick(payload) => payload,
+        IpcCommand::Subscribe(events_) => {
+            events = serde_json::to_vec(events_).expect("event names always serialize");
+            &events
+        }
+        _ => &[],
+    };
+    Header {
+        payload_len: payload.len() as u32,
+        payload_type: command.code() as u32,
+    }
+    .write_to(out);
+    out.extend_from_slice(payload);
+}
+
+/// The frame for `command`.
+pub fn encode(command: &IpcCommand) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(command, &mut out);
+    out
+}
+
+/// What a `Decoder` skipped instead of decoding, to keep going, see `Decoder::take_skipped()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skipped {
+    /// A frame whose payload is longer than the limit, which was dropped without buffering it.
+    Oversized { len: u32, payload_type: u32 },
+    /// Bytes which didn't start with the magic string, up to the next one, because the stream
+    /// was out of sync.
+    Resync { skipped: usize },
+}
+
+/// What `Decoder::step()` found.
+pub(crate) enum Step {
+    Frame(u32),
+    Skipped(Skipped),
+    Incomplete,
+}
+
+/// Whether `bytes` start with the magic string, or with as much of it as they are long.
+fn starts_with_magic(bytes: &[u8]) -> bool {
+    let len = bytes.len().min(MAGIC.len());
+    bytes[..len] == MAGIC[..len]
+}
+
+/// Turns a stream of bytes, fed in arbitrarily sized chunks, back into messages.
+///
+/// Frames whose payload is longer than `max_payload()` are skipped, and so is anything which
+/// doesn't start with the magic string, up to the next one, so that a corrupt header can
+/// neither make it buffer gigabytes nor leave it stuck. Both are reported by `take_skipped()`.
+#[derive(Debug)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    max_payload: u32,
+    /// How much more of an oversized frame is still to be dropped.
+    discarding: usize,
+    /// How many bytes were dropped while looking for the magic string.
+    resyncing: usize,
+    skipped: Vec<Skipped>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            discarding: 0,
+            resyncing: 0,
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The longest payload which is decoded, `DEFAULT_MAX_PAYLOAD` unless changed with
+    /// `set_max_payload()`.
+    pub fn max_payload(&self) -> u32 {
+        self.max_payload
+    }
+
+    pub fn set_max_payload(&mut self, max_payload: u32) {
+        self.max_payload = max_payload;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// How many bytes have been fed but not decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Everything which was skipped since the last call, in order. It piles up until taken.
+    pub fn take_skipped(&mut self) -> Vec<Skipped> {
+        std::mem::take(&mut self.skipped)
+    }
+
+    /// How many more bytes complete the frame being decoded, as far as its header tells: the
+    /// rest of the header if it isn't complete, 0 if a frame is complete, the header invalid or
+    /// the frame too long to be decoded. Useful to read a large reply with a single read
+    /// instead of many small ones.
+    pub fn missing(&self) -> usize {
+        if self.discarding > 0 {
+            return self.discarding.saturating_sub(self.buffer.len());
+        }
+        if self.buffer.len() < HEADER_LEN {
+            return HEADER_LEN - self.buffer.len();
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..HEADER_LEN]);
+        match Header::parse(&header) {
+            Ok(header) if header.payload_len <= self.max_payload => {
+                (HEADER_LEN + header.payload_len as usize).saturating_sub(self.buffer.len())
+            }
+            _ => 0,
+        }
+    }
+
+    /// Decode the next complete message, or `None` if more bytes are needed.
+    pub fn decode(&mut self) -> Result<Option<Message>> {
+        let mut payload = Vec::new();
+        let payload_type = self.decode_into(&mut payload)?;
+        Ok(payload_type.map(|payload_type| Message::from_frame(payload_type, payload)))
+    }
+
+    /// Like `decode()`, but the payload of the next complete frame replaces the contents of
+    /// `payload`, reusing its allocation, and only the frame's payload type is returned.
+    /// `payload` is left alone if more bytes are needed.
+    pub fn decode_into(&mut self, payload: &mut Vec<u8>) -> Result<Option<u32>> {
+        loop {
+            match self.step(payload) {
+                Step::Frame(payload_type) => return Ok(Some(payload_type)),
+                Step::Skipped(skipped) => self.skipped.push(skipped),
+                Step::Incomplete => return Ok(None),
+            }
+        }
+    }
+
+    /// Take the header of the next frame and as much of its payload as was fed, leaving the rest
+    /// of the payload to the caller, e.g. to parse a large reply as it is read. Returns `None`
+    /// unless a complete header is buffered whose frame `accept`s, and which isn't oversized.
+    /// The caller has to consume exactly the rest of the payload before feeding anything else.
+    pub fn take_frame_start<P: FnOnce(&Header) -> bool>(
+        &mut self,
+        accept: P,
+    ) -> Option<(Header, Vec<u8>)> {
+        if self.discarding > 0 || self.buffer.len() < HEADER_LEN || !self.buffer.starts_with(MAGIC)
+        {
+            return None;
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..HEADER_LEN]);
+        let header = Header::parse(&header).ok()?;
+        if header.payload_len > self.max_payload || !accept(&header) {
+            return None;
+        }
+        let end = self
+            .buffer
+            .len()
+            .min(HEADER_LEN + header.payload_len as usize);
+        let payload = self.buffer[HEADER_LEN..end].to_vec();
+        self.buffer.drain(..end);
+        Some((header, payload))
+    }
+
+    /// Decode the next frame, or skip over one thing which can't be decoded. What was skipped
+    /// is returned rather than recorded for `take_skipped()`.
+    pub(crate) fn step(&mut self, payload: &mut Vec<u8>) -> Step {
+        if self.discarding > 0 {
+            let len = self.discarding.min(self.buffer.len());
+            self.buffer.drain(..len);
+            self.discarding -= len;
+            if self.discarding > 0 {
+                return Step::Incomplete;
+            }
+        }
+        if !starts_with_magic(&self.buffer) {
+            let start = (1..self.buffer.len())
+                .find(|&start| starts_with_magic(&self.buffer[start..]))
+                .unwrap_or(self.buffer.len());
+            self.buffer.drain(..start);
+            self.resyncing += start;
+        }
+        if self.resyncing > 0 && self.buffer.len() >= MAGIC.len() {
+            let skipped = std::mem::take(&mut self.resyncing);
+            return Step::Skipped(Skipped::Resync { skipped });
+        }
+        if self.buffer.len() < HEADER_LEN {
+            return Step::Incomplete;
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..HEADER_LEN]);
+        let header = Header::parse(&header).expect("the buffer starts with the magic string");
+        let frame_len = HEADER_LEN + header.payload_len as usize;
+        if header.payload_len > self.max_payload {
+            self.discarding = frame_len;
+            return Step::Skipped(Skipped::Oversized {
+                len: header.payload_len,
+                payload_type: header.payload_type,
+            });
+        }
+        if self.buffer.len() < frame_len {
+            return Step::Incomplete;
+        }
+        payload.clear();
+        payload.extend_from_slice(&self.buffer[HEADER_LEN..frame_len]);
+        self.buffer.drain(..frame_len);
+        Step::Frame(header.payload_type)
+    }
+}
+
+/// The state of one connection: outgoing bytes which still need to be written, a decoder for
+/// incoming bytes, and the codes of the commands still waiting for their reply. Sway answers
+/// commands in order, so replies are matched up with the oldest outstanding command, and a
+/// reply of another type means the stream is out of sync.
+#[derive(Debug, Default)]
+pub struct Connection {
+    decoder: Decoder,
+    outgoing: Vec<u8>,
+    pending: VecDeque<u32>,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a command. Its bytes are available from `take_outgoing()`.
+    pub fn send(&mut self, command: &IpcCommand) {
+        encode_into(command, &mut self.outgoing);
+        self.pending.push_back(command.code() as u32);
+    }
+
+    /// The bytes which need to be written to the socket.
+    pub fn take_outgoing(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Feed bytes read from the socket.
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.decoder.feed(bytes);
+    }
+
+    /// See `Decoder::missing()`.
+    pub fn missing(&self) -> usize {
+        self.decoder.missing()
+    }
+
+    /// See `Decoder::max_payload()`.
+    pub fn max_payload(&self) -> u32 {
+        self.decoder.max_payload()
+    }
+
+    pub fn set_max_payload(&mut self, max_payload: u32) {
+        self.decoder.set_max_payload(max_payload);
+    }
+
+    /// See `Decoder::take_skipped()`.
+    pub fn take_skipped(&mut self) -> Vec<Skipped> {
+        self.decoder.take_skipped()
+    }
+
+    /// The number of commands which haven't received their reply yet.
+    pub fn pending_replies(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The code of the command which the next reply is expected to be for.
+    pub fn next_reply_code(&self) -> Option<u32> {
+        self.pending.front().copied()
+    }
+
+    /// Like `Decoder::take_frame_start()`, for the reply to the oldest outstanding command,
+    /// which is then no longer outstanding.
+    pub fn take_reply_start(&mut self) -> Option<(Header, Vec<u8>)> {
+        let expected = *self.pending.front()?;
+        let start = self
+            .decoder
+            .take_frame_start(|header| header.payload_type == expected)?;
+        self.pending.pop_front();
+        Some(start)
+    }
+
+    /// The next message received, if a complete one is available. A reply whose type isn't that
+    /// of the oldest outstanding command is `Error::UnexpectedReply`, after which that command
+    /// is no longer outstanding. Replies while nothing is outstanding are returned as they are.
+    ///
+    /// An oversized reply is skipped like any other oversized frame, see `Decoder`, and if a
+    /// command was outstanding, it no longer is and this returns `Error::OversizedReply`.
+    pub fn poll(&mut self) -> Result<Option<Message>> {
+        let mut payload = Vec::new();
+        let payload_type = self.poll_into(&mut payload)?;
+        Ok(payload_type.map(|payload_type| Message::from_frame(payload_type, payload)))
+    }
+
+    /// Like `poll()`, but the payload goes into `payload`, see `Decoder::decode_into()`.
+    pub fn poll_into(&mut self, payload: &mut Vec<u8>) -> Result<Option<u32>> {
+        let payload_type = loop {
+            match self.decoder.step(payload) {
+                Step::Frame(payload_type) if MessageKind::is_event(payload_type) => {
+                    return Ok(Some(payload_type))
+                }
+                Step::Frame(payload_type) => break payload_type,
+                Step::Skipped(skipped) => {
+                    self.decoder.skipped.push(skipped);
+                    if let Skipped::Oversized { len, payload_type } = skipped {
+                        if !MessageKind::is_event(payload_type)
+                            && self.pending.pop_front().is_some()
+                        {
+                            return Err(Error::OversizedReply {
+                                len,
+                                max: self.decoder.max_payload,
+                            });
+                        }
+                    }
+                }
+                Step::Incomplete => return Ok(None),
+            }
+        };
+        match self.pending.pop_front() {
+            Some(expected) if expected != payload_type => Err(Error::UnexpectedReply {
+                expected,
+                received: payload_type,
+                reply: std::mem::take(payload),
+            }),
+            _ => Ok(Some(payload_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ipc_command, EventType};
+
+    fn frame(payload_type: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Header {
+            payload_len: payload.len() as u32,
+            payload_type,
+        }
+        .write_to(&mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn decode_in_chunks() {
+        let mut bytes = frame(EventType::Window.code().raw(), b"{\"change\":\"new\"}");
+        bytes.extend(frame(0, b"[{\"success\":true}]"));
+
+        let mut connection = Connection::new();
+        connection.send(&ipc_command::run("nop"));
+        assert_eq!(connection.take_outgoing(), frame(0, b"nop"));
+        assert_eq!(connection.pending_replies(), 1);
+
+        let mut messages = Vec::new();
+        for chunk in bytes.chunks(5) {
+            connection.receive(chunk);
+            while let Some(message) = connection.poll().unwrap() {
+                messages.push(message);
+            }
+        }
+        assert_eq!(
+            messages,
+            vec![
+                Message::Event {
+                    code: EventType::Window.code(),
+                    payload: b"{\"change\":\"new\"}".to_vec()
+                },
+                Message::Reply {
+                    code: 0,
+                    payload: b"[{\"success\":true}]".to_vec()
+                },
+            ]
+        );
+        assert_eq!(connection.pending_replies(), 0);
+    }
+
+    #[test]
+    fn replies_match_commands() {
+        let mut connection = Connection::new();
+        connection.send(&ipc_command::get_tree());
+        connection.send(&ipc_command::get_version());
+        connection.receive(&frame(4, b"{}"));
+        connection.receive(&frame(1, b"[]"));
+        connection.receive(&frame(0, b"[]"));
+        assert_eq!(
+            connection.poll().unwrap(),
+            Some(Message::Reply {
+                code: 4,
+                payload: b"{}".to_vec()
+            })
+        );
+        assert!(matches!(
+            connection.poll(),
+            Err(Error::UnexpectedReply {
+                expected: 7,
+                received: 1,
+                ..
+            })
+        ));
+        assert_eq!(connection.pending_replies(), 0);
+        // Unsolicited, which is up to the caller.
+        assert!(matches!(
+            connection.poll().unwrap(),
+            Some(Message::Reply { code: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_bytes() {
+        let bytes = frame(4, &[b'x'; 1000]);
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.missing(), HEADER_LEN);
+        decoder.feed(&bytes[..5]);
+        assert_eq!(decoder.missing(), HEADER_LEN - 5);
+        decoder.feed(&bytes[5..100]);
+        assert_eq!(decoder.missing(), bytes.len() - 100);
+        decoder.feed(&bytes[100..]);
+        assert_eq!(decoder.missing(), 0);
+        assert!(decoder.decode().unwrap().is_some());
+        assert_eq!(decoder.missing(), HEADER_LEN);
+    }
+
+    #[test]
+    fn classify() {
+        assert_eq!(MessageKind::of(0), MessageKind::Reply);
+        assert_eq!(MessageKind::of(101), MessageKind::Reply);
+        assert!(MessageKind::is_event(EventType::Tick.code().raw()));
+        assert!(MessageKind::is_event(0x8000_00ff));
+        assert_eq!(event_code_to_name(0x8000_0003), Some("window"));
+        assert_eq!(event_code_to_name(0x8000_0015), Some("input"));
+        assert_eq!(event_code_to_name(0x8000_00ff), None);
+        assert_eq!(event_code_to_name(3), None);
+    }
+
+    #[test]
+    fn resync_and_oversized() {
+        let mut decoder = Decoder::new();
+        decoder.set_max_payload(4);
+        let mut bytes = b"xxi3-".to_vec();
+        bytes.extend(frame(1, b"too long"));
+        bytes.extend(frame(2, b"ok"));
+        let mut messages = Vec::new();
+        for chunk in bytes.chunks(3) {
+            decoder.feed(chunk);
+            while let Some(message) = decoder.decode().unwrap() {
+                messages.push(message);
+            }
+        }
+        assert_eq!(
+            messages,
+            vec![Message::Reply {
+                code: 2,
+                payload: b"ok".to_vec()
+            }]
+        );
+        assert_eq!(
+            decoder.take_skipped(),
+            vec![
+                Skipped::Resync { skipped: 5 },
+                Skipped::Oversized {
+                    len: 8,
+                    payload_type: 1
+                },
+            ]
+        );
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
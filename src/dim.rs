@@ -0,0 +1,199 @@
+//! Dimming unfocused windows by lowering their opacity.
+//!
+//! ```no_run
+//! use ksway::{dim::Dimmer, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut dimmer = Dimmer::new(0.8).app("mpv", 1.0).app("foot", 0.9);
+//! dimmer.dim_all(&mut client)?;
+//! let rx = client.subscribe(vec![IpcEvent::Window])?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         let payload = serde_json::from_slice(&payload)?;
+//!         dimmer.handle_event(&mut client, event, &payload)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashMap;
+
+use crate::events::WindowChange;
+use crate::tree::{app_identifier, windows};
+use crate::{cmd, IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// Sets the opacity of every window but the focused one to a level, which can differ per
+/// application, including windows which open without focus. Only `opacity` commands are sent,
+/// which don't cause window events of their own, and nothing is sent when focus stays on the
+/// same window.
+#[derive(Debug, Clone)]
+pub struct Dimmer {
+    unfocused: f64,
+    apps: HashMap<String, f64>,
+    /// The focused window and its app identifier.
+    focused: Option<(u64, Option<String>)>,
+}
+
+impl Dimmer {
+    /// Dim unfocused windows to `unfocused`, from 0 for invisible to 1 for opaque.
+    pub fn new(unfocused: f64) -> Self {
+        Self {
+            unfocused,
+            apps: HashMap::new(),
+            focused: None,
+        }
+    }
+
+    /// Dim unfocused windows of the application `app_id` to `unfocused` instead, see
+    /// `tree::app_identifier()`. Use 1 to never dim them.
+    pub fn app<S: Into<String>>(mut self, app_id: S, unfocused: f64) -> Self {
+        self.apps.insert(app_id.into(), unfocused);
+        self
+    }
+
+    fn level(&self, app_id: Option<&str>) -> f64 {
+        app_id
+            .and_then(|app_id| self.apps.get(app_id))
+            .copied()
+            .unwrap_or(self.unfocused)
+    }
+
+    /// Set the opacity of every window according to whether it is focused, e.g. at startup.
+    pub fn dim_all<C: SwayClientJson>(&mut self, client: &mut C) -> Result<()> {
+        let tree = client.get_tree_json()?;
+        let mut commands = Vec::new();
+        for window in windows(&tree) {
+            let id = match window.id() {
+                Some(id) => id,
+                None => continue,
+            };
+            let app_id = window.app_identifier();
+            if window.node.is_focused() {
+                self.focused = Some((id, app_id.map(ToOwned::to_owned)));
+                commands.push(cmd!([con_id=id] "opacity 1").to_string());
+            } else {
+                let level = self.level(app_id);
+                commands.push(cmd!([con_id=id] "opacity {}", level).to_string());
+            }
+        }
+        if !commands.is_empty() {
+            client.run(commands.join("; "))?;
+        }
+        Ok(())
+    }
+
+    /// The commands for a window event, keeping track of the focused window.
+    fn react(&mut self, event: IpcEvent, payload: &JsonValue) -> Vec<String> {
+        if event != IpcEvent::Window {
+            return Vec::new();
+        }
+        let container = &payload["container"];
+        let id = match container.con_id() {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        match WindowChange::from_payload(payload) {
+            // Windows which open unfocused, e.g. on another workspace, would stay opaque. Those
+            // which do get focus are made opaque again by the focus event which follows.
+            Some(WindowChange::New) if !container.is_focused() => {
+                let level = self.level(app_identifier(container));
+                vec![cmd!([con_id=id] "opacity {}", level).to_string()]
+            }
+            Some(WindowChange::Focus) => {
+                if self
+                    .focused
+                    .as_ref()
+                    .is_some_and(|(focused, _)| *focused == id)
+                {
+                    return Vec::new();
+                }
+                let mut commands = Vec::new();
+                if let Some((previous, app_id)) = self.focused.take() {
+                    let level = self.level(app_id.as_deref());
+                    commands.push(cmd!([con_id=previous] "opacity {}", level).to_string());
+                }
+                commands.push(cmd!([con_id=id] "opacity 1").to_string());
+                self.focused = Some((id, app_identifier(container).map(ToOwned::to_owned)));
+                commands
+            }
+            Some(WindowChange::Close) => {
+                if self
+                    .focused
+                    .as_ref()
+                    .is_some_and(|(focused, _)| *focused == id)
+                {
+                    self.focused = None;
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Feed a subscription event, updating opacities when focus moves to another window.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        let commands = self.react(event, payload);
+        if !commands.is_empty() {
+            client.run(commands.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn dim_previous_window() {
+        let mut dimmer = Dimmer::new(0.8).app("mpv", 1.0);
+        let focus = |id: u64, app_id: &str| json!({"change": "focus", "container": {"id": id, "app_id": app_id}});
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &focus(1, "mpv")),
+            vec![r#"[con_id="1"] opacity 1"#]
+        );
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &focus(2, "foot")),
+            vec![r#"[con_id="1"] opacity 1"#, r#"[con_id="2"] opacity 1"#]
+        );
+        assert!(dimmer.react(IpcEvent::Window, &focus(2, "foot")).is_empty());
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &focus(3, "kitty")),
+            vec![r#"[con_id="2"] opacity 0.8"#, r#"[con_id="3"] opacity 1"#]
+        );
+        // A closed window doesn't need to be dimmed anymore.
+        assert!(dimmer
+            .react(
+                IpcEvent::Window,
+                &json!({"change": "close", "container": {"id": 3}})
+            )
+            .is_empty());
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &focus(2, "foot")),
+            vec![r#"[con_id="2"] opacity 1"#]
+        );
+    }
+
+    #[test]
+    fn dim_new_windows() {
+        let mut dimmer = Dimmer::new(0.8).app("mpv", 1.0);
+        let new = |id: u64, app_id: &str, focused: bool| json!({"change": "new", "container": {"id": id, "app_id": app_id, "focused": focused}});
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &new(4, "foot", false)),
+            vec![r#"[con_id="4"] opacity 0.8"#]
+        );
+        assert_eq!(
+            dimmer.react(IpcEvent::Window, &new(5, "mpv", false)),
+            vec![r#"[con_id="5"] opacity 1"#]
+        );
+        assert!(dimmer
+            .react(IpcEvent::Window, &new(6, "foot", true))
+            .is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Calling closures for events, instead of matching on every event by hand.
+//!
+//! Handlers are registered per event type, or per `change` of window events, and
+//! `EventDispatcher::dispatch_loop()` subscribes to what they need, polls, parses and calls them:
+//! ```no_run
+//! use ksway::{cmd, dispatch::EventDispatcher, events::WindowChange, IpcEvent, SwayClient};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! EventDispatcher::new()
+//!     .on_window(WindowChange::New, |client, event| {
+//!         if event.container.app_id.as_deref() == Some("mpv") {
+//!             client.run(cmd!([con_id=event.container.id] "floating enable"))?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .on(IpcEvent::Mode, |_, payload| {
+//!         println!("mode: {}", payload["change"]);
+//!         Ok(())
+//!     })
+//!     .dispatch_loop(&mut client)?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//! Services with a `handle_event()` method can be fed with `on_any()`.
+use crate::cancel::CancelToken;
+use crate::events::{WindowChange, WindowEvent};
+use crate::{Client, EventMask, IpcEvent, JsonValue, Result};
+
+type Handler = Box<dyn FnMut(&mut Client, &JsonValue) -> Result<()> + Send>;
+type AnyHandler = Box<dyn FnMut(&mut Client, IpcEvent, &JsonValue) -> Result<()> + Send>;
+type WindowHandler = Box<dyn FnMut(&mut Client, &WindowEvent) -> Result<()> + Send>;
+
+/// Closures to call for events, in the order they were registered: those for any event first,
+/// then those for the event's type, then those for the `change` of a window event. The first
+/// error stops the dispatch and is returned.
+#[derive(Default)]
+pub struct EventDispatcher {
+    any: Vec<(EventMask, AnyHandler)>,
+    handlers: Vec<(IpcEvent, Handler)>,
+    window: Vec<(WindowChange, WindowHandler)>,
+    cancel: Option<CancelToken>,
+}
+
+impl EventDispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `handler` with the parsed payload of every event of type `event`.
+    pub fn on<F>(mut self, event: IpcEvent, handler: F) -> Self
+    where
+        F: FnMut(&mut Client, &JsonValue) -> Result<()> + Send + 'static,
+    {
+        self.handlers.push((event, Box::new(handler)));
+        self
+    }
+
+    /// Call `handler` for every event of the types in `events`, e.g. to feed a service's
+    /// `handle_event()`.
+    pub fn on_any<E, F>(mut self, events: E, handler: F) -> Self
+    where
+        E: Into<EventMask>,
+        F: FnMut(&mut Client, IpcEvent, &JsonValue) -> Result<()> + Send + 'static,
+    {
+        self.any.push((events.into(), Box::new(handler)));
+        self
+    }
+
+    /// Call `handler` with every window event whose `change` is `change`, parsed.
+    pub fn on_window<F>(mut self, change: WindowChange, handler: F) -> Self
+    where
+        F: FnMut(&mut Client, &WindowEvent) -> Result<()> + Send + 'static,
+    {
+        self.window.push((change, Box::new(handler)));
+        self
+    }
+
+    /// Make `dispatch_loop()` return `Error::Cancelled` once `token` is cancelled. It is checked
+    /// between polls, so within the client's read timeout, see `Timeouts`. To also stop while
+    /// reconnecting, give the client's `ReconnectPolicy` the same token.
+    pub fn cancel_on(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// The events the handlers need to be subscribed to.
+    pub fn events(&self) -> EventMask {
+        let mut events: EventMask = self.handlers.iter().map(|(event, _)| *event).collect();
+        for (any, _) in &self.any {
+            events |= *any;
+        }
+        if !self.window.is_empty() {
+            events |= EventMask::WINDOW;
+        }
+        events
+    }
+
+    /// Call the handlers for one event. A window event is only parsed into a `WindowEvent` if
+    /// a handler for its `change` is registered.
+    pub fn dispatch(&mut self, client: &mut Client, event: IpcEvent, payload: &[u8]) -> Result<()> {
+        let json: JsonValue = serde_json::from_slice(payload)?;
+        for (events, handler) in &mut self.any {
+            if events.contains_event(event) {
+                handler(client, event, &json)?;
+            }
+        }
+        for (_, handler) in self.handlers.iter_mut().filter(|(e, _)| *e == event) {
+            handler(client, &json)?;
+        }
+        if event != IpcEvent::Window {
+            return Ok(());
+        }
+        let change = WindowChange::from_payload(&json);
+        let mut handlers = self
+            .window
+            .iter_mut()
+            .filter(|(c, _)| Some(c) == change.as_ref())
+            .peekable();
+        if handlers.peek().is_some() {
+            let parsed: WindowEvent = serde_json::from_value(json)?;
+            for (_, handler) in handlers {
+                handler(client, &parsed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribe `client` to the events the handlers need, then poll and dispatch forever. Only
+    /// returns on an error, from the connection or a handler, or once cancelled, see
+    /// `cancel_on()`.
+    pub fn dispatch_loop(mut self, client: &mut Client) -> Result<()> {
+        let rx = client.subscribe(self.events())?;
+        let cancel = self.cancel.clone().unwrap_or_default();
+        loop {
+            while let Ok((event, payload)) = rx.try_recv() {
+                self.dispatch(client, event, &payload)?;
+            }
+            cancel.check()?;
+            client.poll()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::{Error, ReconnectPolicy, SwayClient};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn handlers_in_order() {
+        let server = MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = |name: &'static str| {
+            let calls = Arc::clone(&calls);
+            move || calls.lock().unwrap().push(name)
+        };
+        let (any, mode, new, focus) = (log("any"), log("mode"), log("new"), log("focus"));
+        let mut dispatcher = EventDispatcher::new()
+            .on_window(WindowChange::New, move |client, event| {
+                new();
+                client.run(format!("[con_id={}] floating enable", event.container.id))?;
+                Ok(())
+            })
+            .on_window(WindowChange::Focus, move |_, _| {
+                focus();
+                Ok(())
+            })
+            .on(IpcEvent::Mode, move |_, _| {
+                mode();
+                Err(Error::Timeout)
+            })
+            .on_any(vec![IpcEvent::Window, IpcEvent::Mode], move |_, _, _| {
+                any();
+                Ok(())
+            });
+        assert_eq!(dispatcher.events(), EventMask::WINDOW | EventMask::MODE);
+
+        let window = br#"{"change": "new", "container": {"id": 7, "type": "con", "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}}"#;
+        dispatcher
+            .dispatch(&mut client, IpcEvent::Window, window)
+            .unwrap();
+        // A change nobody handles isn't parsed, so the missing container doesn't matter.
+        dispatcher
+            .dispatch(&mut client, IpcEvent::Window, br#"{"change": "title"}"#)
+            .unwrap();
+        assert!(matches!(
+            dispatcher.dispatch(&mut client, IpcEvent::Mode, br#"{"change": "resize"}"#),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["any", "new", "any", "any", "mode"]
+        );
+        server.assert_run_commands(&["[con_id=7] floating enable"]);
+    }
+
+    #[test]
+    fn cancelled_loop() {
+        let server = MockServer::start().unwrap();
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let token = CancelToken::new();
+        client.set_reconnect_policy(Some(ReconnectPolicy::new().cancel_on(token.clone())));
+        let dispatcher = EventDispatcher::new()
+            .on(IpcEvent::Tick, |_, _| Ok(()))
+            .cancel_on(token.clone());
+        let looping = std::thread::spawn(move || {
+            let result = dispatcher.dispatch_loop(&mut client);
+            (result, client)
+        });
+        // Reconnects forever, until cancelled.
+        server.disconnect();
+        drop(server);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        token.cancel();
+        let (result, _) = looping.join().unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+    }
+}
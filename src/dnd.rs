@@ -0,0 +1,258 @@
+//! Do-not-disturb mode for workspaces, e.g. while presenting or recording.
+//!
+//! While you are on a workspace which has do-not-disturb enabled, `DndMode`:
+//! - clears the urgency of any window which becomes urgent,
+//! - refocuses the previous window when a newly created window grabs focus,
+//! - and records whatever it suppressed so it can be reviewed later.
+//!
+//! It only needs window events to do so:
+//! ```no_run
+//! use ksway::{dnd::DndMode, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut dnd = DndMode::new();
+//! dnd.enable("3:slides");
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Window])?;
+//! loop {
+//!     while let Ok((payload_type, payload)) = rx.try_recv() {
+//!         dnd.handle_event(&mut client, payload_type, &serde_json::from_slice(&payload)?)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::WindowChange;
+use crate::tree::app_identifier;
+use crate::{cmd, Command, IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressedKind {
+    /// The window became urgent and its urgency was cleared.
+    Urgent,
+    /// The window was newly created and took focus, which was given back.
+    FocusSteal,
+}
+
+/// Something `DndMode` prevented from happening.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SuppressedEvent {
+    pub kind: SuppressedKind,
+    pub con_id: u64,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    /// The do-not-disturb workspace which was focused at the time.
+    pub workspace: String,
+    pub at: SystemTime,
+}
+
+#[derive(Debug, Default)]
+pub struct DndMode {
+    workspaces: HashSet<String>,
+    /// The last window focus which we let through and its workspace. Do-not-disturb applies while
+    /// this workspace has it enabled.
+    last_focused: Option<(u64, String)>,
+    /// Windows which have been created but haven't been focused yet.
+    new_windows: HashSet<u64>,
+    suppressed: Vec<SuppressedEvent>,
+}
+
+impl DndMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enable<S: Into<String>>(&mut self, workspace: S) {
+        self.workspaces.insert(workspace.into());
+    }
+
+    pub fn disable(&mut self, workspace: &str) {
+        self.workspaces.remove(workspace);
+    }
+
+    /// Toggle do-not-disturb for a workspace, returning whether it is now enabled.
+    pub fn toggle(&mut self, workspace: &str) -> bool {
+        if !self.workspaces.remove(workspace) {
+            self.workspaces.insert(workspace.to_owned());
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_enabled(&self, workspace: &str) -> bool {
+        self.workspaces.contains(workspace)
+    }
+
+    /// Everything suppressed so far, oldest first.
+    pub fn suppressed(&self) -> &[SuppressedEvent] {
+        &self.suppressed
+    }
+
+    /// Take the suppressed events for review, clearing the record.
+    pub fn take_suppressed(&mut self) -> Vec<SuppressedEvent> {
+        std::mem::take(&mut self.suppressed)
+    }
+
+    /// The workspace that do-not-disturb currently applies to, if any.
+    fn active_workspace(&self) -> Option<&str> {
+        self.last_focused
+            .as_ref()
+            .map(|(_, workspace)| workspace.as_str())
+            .filter(|workspace| self.is_enabled(workspace))
+    }
+
+    fn suppress(&mut self, kind: SuppressedKind, con_id: u64, container: &JsonValue) {
+        let workspace = match self.active_workspace() {
+            Some(workspace) => workspace.to_owned(),
+            None => return,
+        };
+        self.suppressed.push(SuppressedEvent {
+            kind,
+            con_id,
+            app_id: app_identifier(container).map(ToOwned::to_owned),
+            title: container["name"].as_str().map(ToOwned::to_owned),
+            workspace,
+            at: SystemTime::now(),
+        });
+    }
+
+    /// Decide what to do about a window event. `focused_workspace` is only consulted for focus
+    /// changes which are let through.
+    fn react<F: FnOnce() -> Result<Option<String>>>(
+        &mut self,
+        payload: &JsonValue,
+        focused_workspace: F,
+    ) -> Result<Option<Command>> {
+        let container = &payload["container"];
+        let id = match container.con_id() {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        match WindowChange::from_payload(payload) {
+            Some(WindowChange::New) => {
+                self.new_windows.insert(id);
+            }
+            Some(WindowChange::Close) => {
+                self.new_windows.remove(&id);
+            }
+            Some(WindowChange::Urgent)
+                if container["urgent"].as_bool() == Some(true)
+                    && self.active_workspace().is_some() =>
+            {
+                self.suppress(SuppressedKind::Urgent, id, container);
+                return Ok(Some(cmd!([con_id=id] "urgent disable")));
+            }
+            Some(WindowChange::Focus) => {
+                let is_new = self.new_windows.remove(&id);
+                if let (true, Some(_), Some((previous, _))) =
+                    (is_new, self.active_workspace(), &self.last_focused)
+                {
+                    if *previous != id {
+                        let previous = *previous;
+                        self.suppress(SuppressedKind::FocusSteal, id, container);
+                        return Ok(Some(cmd!([con_id=previous] "focus")));
+                    }
+                }
+                if let Some(workspace) = focused_workspace()? {
+                    self.last_focused = Some((id, workspace));
+                }
+            }
+            _ => (),
+        }
+        Ok(None)
+    }
+
+    /// Feed a subscription event to the do-not-disturb logic, running whatever command is needed
+    /// to undo a disturbance.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        if event != IpcEvent::Window {
+            return Ok(());
+        }
+        let command = self.react(payload, || {
+            Ok(client
+                .focused_workspace()?
+                .and_then(|ws| ws["name"].as_str().map(ToOwned::to_owned)))
+        })?;
+        if let Some(command) = command {
+            client.run(command)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(change: &str, id: u64, urgent: bool) -> JsonValue {
+        json!({"change": change, "container": {"id": id, "urgent": urgent, "app_id": "chat"}})
+    }
+
+    #[test]
+    fn suppresses_disturbances() {
+        let mut dnd = DndMode::new();
+        dnd.enable("slides");
+        let on = |ws: &'static str| move || Ok(Some(ws.to_string()));
+
+        // Not on a do-not-disturb workspace yet.
+        assert_eq!(
+            dnd.react(&event("focus", 1, false), on("other")).unwrap(),
+            None
+        );
+        assert_eq!(
+            dnd.react(&event("urgent", 2, true), on("other")).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            dnd.react(&event("focus", 3, false), on("slides")).unwrap(),
+            None
+        );
+        assert_eq!(
+            dnd.react(&event("urgent", 2, true), on("slides")).unwrap(),
+            Some(cmd!([con_id=2] "urgent disable"))
+        );
+        // Clearing the urgency isn't itself a disturbance.
+        assert_eq!(
+            dnd.react(&event("urgent", 2, false), on("slides")).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            dnd.react(&event("new", 4, false), on("slides")).unwrap(),
+            None
+        );
+        assert_eq!(
+            dnd.react(&event("focus", 4, false), on("slides")).unwrap(),
+            Some(cmd!([con_id=3] "focus"))
+        );
+        // Focusing it deliberately later is fine.
+        assert_eq!(
+            dnd.react(&event("focus", 4, false), on("slides")).unwrap(),
+            None
+        );
+
+        let kinds: Vec<_> = dnd
+            .take_suppressed()
+            .iter()
+            .map(|e| (e.kind, e.con_id))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![(SuppressedKind::Urgent, 2), (SuppressedKind::FocusSteal, 4)]
+        );
+        assert!(dnd.suppressed().is_empty());
+    }
+}
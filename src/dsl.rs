@@ -0,0 +1,161 @@
+//! A compact language for actions, so that a single helper binary can be driven from sway
+//! bindings instead of needing one binary per action:
+//! ```text
+//! bindsym $mod+Tab exec ksway-do focus-next workspace=__focused__
+//! bindsym $mod+Shift+Tab exec ksway-do focus-prev workspace=__focused__
+//! bindsym $mod+grave exec ksway-do dropdown toggle term
+//! ```
+//!
+//! An action is a verb followed by its arguments, separated by whitespace. Arguments can be
+//! double quoted to include whitespace. The verbs are:
+//!
+//! - `focus-next [criteria...]` and `focus-prev [criteria...]`: cycle through the windows matching
+//!   the criteria, written like they are between sway's brackets (`class=firefox`, `floating`).
+//!   See `ksway::focus::focus_next()`.
+//! - `dropdown toggle <mark>`: show or hide the scratchpad window with the mark `<mark>`.
+//! - `workspace-left` and `workspace-right`: move the focused workspace one slot on its output.
+//!   See `ksway::workspace`.
+//! - `exec <command...>`: the same as sway's `exec`.
+//! - `run <command...>`: run any sway command as is.
+use crate::criteria::{self, Criteria};
+use crate::{command, focus, workspace, Command, ParseError, Result, SwayClient, SwayClientJson};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    FocusNext(Vec<Criteria>),
+    FocusPrev(Vec<Criteria>),
+    DropdownToggle(String),
+    MoveWorkspaceLeft,
+    MoveWorkspaceRight,
+    Run(Command),
+}
+
+/// Split on whitespace, keeping double quoted parts together. Backslashes escape the next
+/// character inside of quotes.
+fn split_words(input: &str) -> std::result::Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err(ParseError(format!("unterminated quote: {}", input))),
+                    }
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+impl Action {
+    /// Build an action from words which have already been split, e.g. the arguments of a
+    /// process.
+    pub fn from_words<S: AsRef<str>, I: IntoIterator<Item = S>>(
+        words: I,
+    ) -> std::result::Result<Self, ParseError> {
+        let words: Vec<S> = words.into_iter().collect();
+        let words: Vec<&str> = words.iter().map(AsRef::as_ref).collect();
+        let criteria = |words: &[&str]| -> std::result::Result<Vec<Criteria>, ParseError> {
+            words.iter().map(|word| word.parse()).collect()
+        };
+        Ok(match words.as_slice() {
+            ["focus-next", rest @ ..] => Action::FocusNext(criteria(rest)?),
+            ["focus-prev", rest @ ..] => Action::FocusPrev(criteria(rest)?),
+            ["dropdown", "toggle", mark] => Action::DropdownToggle(mark.to_string()),
+            ["workspace-left"] => Action::MoveWorkspaceLeft,
+            ["workspace-right"] => Action::MoveWorkspaceRight,
+            ["exec", rest @ ..] if !rest.is_empty() => Action::Run(command::exec(rest.join(" "))),
+            ["run", rest @ ..] if !rest.is_empty() => Action::Run(command::raw(rest.join(" "))),
+            _ => return Err(ParseError(format!("invalid action: {}", words.join(" ")))),
+        })
+    }
+
+    /// Perform the action.
+    pub fn execute<C: SwayClientJson>(&self, client: &mut C) -> Result<()> {
+        match self {
+            Action::FocusNext(criteria) => {
+                focus::focus_next(client, criteria, 1)?;
+            }
+            Action::FocusPrev(criteria) => {
+                focus::focus_next(client, criteria, -1)?;
+            }
+            Action::DropdownToggle(mark) => {
+                client.run(
+                    command::raw("scratchpad show")
+                        .with_criteria(vec![criteria::con_mark(mark.clone())]),
+                )?;
+            }
+            Action::MoveWorkspaceLeft => {
+                workspace::move_workspace_left(client)?;
+            }
+            Action::MoveWorkspaceRight => {
+                workspace::move_workspace_right(client)?;
+            }
+            Action::Run(command) => {
+                SwayClient::run(client, command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Action {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, ParseError> {
+        Action::from_words(split_words(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::criteria::*;
+
+    #[test]
+    fn parse_actions() {
+        assert_eq!(
+            "focus-next class=firefox floating".parse(),
+            Ok(Action::FocusNext(vec![class("firefox"), floating()]))
+        );
+        assert_eq!(
+            r#"focus-prev title="My \"Window\"" workspace=__focused__"#.parse(),
+            Ok(Action::FocusPrev(vec![
+                title(r#"My "Window""#),
+                workspace(focused())
+            ]))
+        );
+        assert_eq!(
+            "dropdown toggle term".parse(),
+            Ok(Action::DropdownToggle("term".into()))
+        );
+        assert_eq!("  workspace-left ".parse(), Ok(Action::MoveWorkspaceLeft));
+        assert_eq!(
+            "run move scratchpad".parse(),
+            Ok(Action::Run(command::raw("move scratchpad")))
+        );
+        assert_eq!(
+            Action::from_words(vec!["exec", "st", "-e", "htop"]),
+            Ok(Action::Run(command::exec("st -e htop")))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("focus-next nonsense=1".parse::<Action>().is_err());
+        assert!("focus-next con_id=abc".parse::<Action>().is_err());
+        assert!("dropdown toggle".parse::<Action>().is_err());
+        assert!("exec".parse::<Action>().is_err());
+        assert!(r#"focus-next title="oops"#.parse::<Action>().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Typed subscription event payloads.
+//!
+//! `SwayEvent::parse()` turns any event into the struct for its type, and
+//! `Client::subscribe_typed()` does so for every event it receives. The structs can also be
+//! parsed on their own:
+//!
+//! ```no_run
+//! use ksway::{events::{WorkspaceChange, WorkspaceEvent}, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let rx = client.subscribe(vec![IpcEvent::Workspace])?;
+//! loop {
+//!     while let Ok((_, payload)) = rx.try_recv() {
+//!         let event = WorkspaceEvent::parse(&payload)?;
+//!         if let (WorkspaceChange::Urgent, Some(current)) = (event.change, &event.current) {
+//!             println!("{:?} urgent: {}", current.name, current.urgent);
+//!         }
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::reply::Node;
+use crate::{IpcEvent, JsonValue, Result};
+
+/// An enum of the `change` strings of an event type, which keeps those it doesn't know in
+/// `Other` and (de)serializes as the plain string.
+macro_rules! change_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident => $change:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            /// A change this version doesn't know about.
+            Other(String),
+        }
+
+        impl $name {
+            /// The `change` of an event payload, `None` if it doesn't have one.
+            pub fn from_payload(payload: &JsonValue) -> Option<Self> {
+                payload["change"].as_str().map(Self::from)
+            }
+
+            /// The string sway uses for this change.
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $change,)*
+                    $name::Other(change) => change,
+                }
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(change: &str) -> Self {
+                match change {
+                    $($change => $name::$variant,)*
+                    other => $name::Other(other.to_owned()),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                Ok(Self::from(String::deserialize(deserializer)?.as_str()))
+            }
+        }
+    };
+}
+
+change_enum!(
+    /// The `change` of a workspace event.
+    WorkspaceChange {
+        Init => "init",
+        Empty => "empty",
+        Focus => "focus",
+        Move => "move",
+        Rename => "rename",
+        Urgent => "urgent",
+        Reload => "reload",
+    }
+);
+
+/// The workspace node in a workspace event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceNode {
+    pub id: u64,
+    pub name: Option<String>,
+    /// The number at the start of the name, or -1 if there isn't one.
+    #[serde(default = "no_num")]
+    pub num: i64,
+    pub output: Option<String>,
+    #[serde(default)]
+    pub urgent: bool,
+    #[serde(default)]
+    pub focused: bool,
+    /// False if sway didn't say, as it only reports this in some versions.
+    #[serde(default)]
+    pub visible: bool,
+}
+
+fn no_num() -> i64 {
+    -1
+}
+
+/// A workspace event. `current` is the workspace the change is about, and `old` is the
+/// previously focused workspace for `Focus`. Either is `None` when sway sends null, e.g. for
+/// `Reload`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceEvent {
+    pub change: WorkspaceChange,
+    pub current: Option<WorkspaceNode>,
+    #[serde(default)]
+    pub old: Option<WorkspaceNode>,
+}
+
+macro_rules! impl_parse {
+    ($($event:ty),*) => {$(
+        impl $event {
+            pub fn parse(payload: &[u8]) -> Result<Self> {
+                Ok(serde_json::from_slice(payload)?)
+            }
+
+            pub fn from_json(payload: &JsonValue) -> Result<Self> {
+                Ok(Self::deserialize(payload)?)
+            }
+        }
+    )*};
+}
+
+impl_parse!(
+    WorkspaceEvent,
+    WindowEvent,
+    ModeEvent,
+    BindingEvent,
+    TickEvent,
+    ShutdownEvent
+);
+
+change_enum!(
+    /// The `change` of a window event.
+    WindowChange {
+        New => "new",
+        Close => "close",
+        Focus => "focus",
+        Title => "title",
+        FullscreenMode => "fullscreen_mode",
+        Move => "move",
+        Floating => "floating",
+        Urgent => "urgent",
+        Mark => "mark",
+    }
+);
+
+/// A window event. `container` is the window as it is after the change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowEvent {
+    pub change: WindowChange,
+    pub container: Node,
+}
+
+/// A binding mode was entered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModeEvent {
+    /// The name of the mode, `default` when leaving a mode.
+    pub change: String,
+    #[serde(default)]
+    pub pango_markup: bool,
+}
+
+/// The binding which was run, in a binding event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub command: String,
+    /// The modifiers, e.g. `Mod4` and `Shift`.
+    #[serde(default)]
+    pub event_state_mask: Vec<String>,
+    /// The key or button code, 0 for bindings to a symbol.
+    #[serde(default)]
+    pub input_code: i64,
+    /// The key symbol, `None` for bindings to a code.
+    pub symbol: Option<String>,
+    /// `keyboard` or `mouse`.
+    pub input_type: String,
+}
+
+/// A binding was run. `change` is always `run`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BindingEvent {
+    pub change: String,
+    pub binding: Binding,
+}
+
+/// Sent by SEND_TICK, and once right after subscribing with `first` set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TickEvent {
+    pub first: bool,
+    pub payload: String,
+}
+
+/// Sway is exiting. `change` is always `exit`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShutdownEvent {
+    pub change: String,
+}
+
+/// Any event, parsed according to its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwayEvent {
+    Workspace(WorkspaceEvent),
+    /// Boxed since containers are large.
+    Window(Box<WindowEvent>),
+    Mode(ModeEvent),
+    Binding(BindingEvent),
+    Tick(TickEvent),
+    Shutdown(ShutdownEvent),
+    /// Events without a typed payload yet, e.g. `BarconfigUpdate`.
+    Other(IpcEvent, JsonValue),
+    /// Not an event from sway, but a client with a `ReconnectPolicy` lost its connection and
+    /// reconnected. Events from in between are lost, so any state built from them should be
+    /// refreshed.
+    Reconnected,
+}
+
+impl SwayEvent {
+    pub fn parse(event: IpcEvent, payload: &[u8]) -> Result<Self> {
+        Ok(match event {
+            IpcEvent::Workspace => SwayEvent::Workspace(WorkspaceEvent::parse(payload)?),
+            IpcEvent::Window => SwayEvent::Window(Box::new(WindowEvent::parse(payload)?)),
+            IpcEvent::Mode => SwayEvent::Mode(ModeEvent::parse(payload)?),
+            IpcEvent::Binding => SwayEvent::Binding(BindingEvent::parse(payload)?),
+            IpcEvent::Tick => SwayEvent::Tick(TickEvent::parse(payload)?),
+            IpcEvent::Shutdown => SwayEvent::Shutdown(ShutdownEvent::parse(payload)?),
+            IpcEvent::Output
+            | IpcEvent::BarconfigUpdate
+            | IpcEvent::BarStatusUpdate
+            | IpcEvent::Input => SwayEvent::Other(event, serde_json::from_slice(payload)?),
+        })
+    }
+
+    /// `None` for `Reconnected`, which doesn't come from sway.
+    pub fn event_type(&self) -> Option<IpcEvent> {
+        Some(match self {
+            SwayEvent::Workspace(_) => IpcEvent::Workspace,
+            SwayEvent::Window(_) => IpcEvent::Window,
+            SwayEvent::Mode(_) => IpcEvent::Mode,
+            SwayEvent::Binding(_) => IpcEvent::Binding,
+            SwayEvent::Tick(_) => IpcEvent::Tick,
+            SwayEvent::Shutdown(_) => IpcEvent::Shutdown,
+            SwayEvent::Other(event, _) => *event,
+            SwayEvent::Reconnected => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_workspace_event() {
+        let event = WorkspaceEvent::parse(
+            br#"{
+                "change": "focus",
+                "current": {"id": 5, "name": "2:web", "num": 2, "output": "DP-1",
+                            "urgent": false, "focused": true, "nodes": []},
+                "old": {"id": 4, "name": "chat", "output": "DP-1", "urgent": true}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(event.change, WorkspaceChange::Focus);
+        let current = event.current.unwrap();
+        assert_eq!((current.num, current.focused), (2, true));
+        let old = event.old.unwrap();
+        assert_eq!((old.num, old.urgent), (-1, true));
+
+        let reload = WorkspaceEvent::parse(br#"{"change": "reload", "current": null}"#).unwrap();
+        assert_eq!(reload.change, WorkspaceChange::Reload);
+        assert_eq!(reload.current, None);
+        let other = WorkspaceEvent::parse(br#"{"change": "sparkle", "current": null}"#).unwrap();
+        assert_eq!(other.change, WorkspaceChange::Other("sparkle".into()));
+        assert_eq!(
+            serde_json::to_string(&other).unwrap(),
+            r#"{"change":"sparkle","current":null,"old":null}"#
+        );
+        assert_eq!(
+            WindowChange::from_payload(&serde_json::json!({"change": "fullscreen_mode"})),
+            Some(WindowChange::FullscreenMode)
+        );
+    }
+
+    #[test]
+    fn parse_sway_events() {
+        let window = SwayEvent::parse(
+            IpcEvent::Window,
+            br#"{"change": "fullscreen_mode", "container": {"id": 7, "type": "con",
+                 "app_id": "mpv", "fullscreen_mode": 1,
+                 "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}}"#,
+        )
+        .unwrap();
+        match &window {
+            SwayEvent::Window(event) => {
+                assert_eq!(event.change, WindowChange::FullscreenMode);
+                assert_eq!(event.container.app_identifier(), Some("mpv"));
+            }
+            _ => panic!("{:?}", window),
+        }
+        assert_eq!(window.event_type(), Some(IpcEvent::Window));
+
+        let binding = BindingEvent::parse(
+            br#"{"change": "run", "binding": {"command": "workspace 2",
+                 "event_state_mask": ["Mod4"], "input_code": 0, "symbol": "2",
+                 "input_type": "keyboard"}}"#,
+        )
+        .unwrap();
+        assert_eq!(binding.binding.event_state_mask, vec!["Mod4"]);
+        assert_eq!(
+            SwayEvent::parse(IpcEvent::Tick, br#"{"first": true, "payload": ""}"#).unwrap(),
+            SwayEvent::Tick(TickEvent {
+                first: true,
+                payload: String::new()
+            })
+        );
+        assert!(SwayEvent::parse(IpcEvent::Mode, b"{}").is_err());
+    }
+}
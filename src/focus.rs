@@ -0,0 +1,303 @@
+//! Helpers for moving focus between windows.
+use crate::command::{self, FocusTarget};
+use crate::criteria::{Criteria, Matcher};
+use crate::json::windows;
+use crate::reply::NodeType;
+use crate::tree::{self, app_identifier, NodeRef, Tree};
+use crate::{cmd, CommandSeq, JsonValue, Result, SwayClient, SwayClientJson, SwayJsonExt};
+
+/// Focus the window `offset` places away from the focused one, among the windows matching every
+/// one of `criteria` in tree order, wrapping around at the ends. If the focused window doesn't
+/// match, counting starts from the first matching window instead. Use a negative `offset` to go
+/// backwards.
+///
+/// The criteria are evaluated client side, with the semantics sway gives them, see
+/// `criteria::Matcher`. Fails if a regular expression is invalid.
+///
+/// Returns the con_id of the window which was focused, or `None` if nothing matched.
+pub fn focus_next<C: SwayClientJson>(
+    client: &mut C,
+    criteria: &[Criteria],
+    offset: isize,
+) -> Result<Option<u64>> {
+    let tree = client.get_tree_json()?;
+    let matcher = Matcher::new(criteria)?.focused_in(&tree);
+    let candidates: Vec<_> = tree::windows(&tree)
+        .filter(|window| matcher.matches(window))
+        .collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let focused_idx = candidates
+        .iter()
+        .position(|window| window.node.is_focused())
+        .unwrap_or(0) as isize;
+    let target = (focused_idx + offset).rem_euclid(candidates.len() as isize) as usize;
+    let target = match candidates[target].id() {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    client.run(cmd!([con_id=target] "focus"))?;
+    Ok(Some(target))
+}
+
+/// A simple fuzzy score of how well `candidate` matches `query`, or `None` if it doesn't contain
+/// the characters of `query` in order, ignoring case. Consecutive characters and characters at
+/// the start of a word score higher. This is the scorer of `focus_best_match()`.
+pub fn fuzzy_score(candidate: &str, query: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.to_lowercase().chars() {
+        let idx = start + candidate[start..].iter().position(|&other| other == c)?;
+        score += 1;
+        if previous == Some(idx.wrapping_sub(1)) {
+            score += 5;
+        }
+        if idx == 0 || !candidate[idx - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(idx);
+        start = idx + 1;
+    }
+    Some(score)
+}
+
+/// The con_id of the window whose title or app identifier scores highest with `scorer`, the
+/// first one in tree order on a tie.
+fn best_match<S: Fn(&str, &str) -> Option<i64>>(
+    tree: &JsonValue,
+    query: &str,
+    scorer: S,
+) -> Option<u64> {
+    let mut best: Option<(i64, u64)> = None;
+    for window in windows(tree) {
+        let id = match window.node.con_id() {
+            Some(id) => id,
+            None => continue,
+        };
+        let score = [window.node.name(), app_identifier(window.node)]
+            .iter()
+            .flatten()
+            .filter_map(|haystack| scorer(haystack, query))
+            .max();
+        if let Some(score) = score {
+            if best.map_or(true, |(best, _)| score > best) {
+                best = Some((score, id));
+            }
+        }
+    }
+    best.map(|(_, id)| id)
+}
+
+/// Focus the window whose title or app identifier best matches `query`, see `fuzzy_score()`,
+/// e.g. for a keybinding which jumps to a window by typing part of its name.
+///
+/// Returns the con_id of the window which was focused, or `None` if nothing matched.
+pub fn focus_best_match<C: SwayClientJson>(client: &mut C, query: &str) -> Result<Option<u64>> {
+    focus_best_match_with(client, query, fuzzy_score)
+}
+
+/// Scores like skim and fzf do, with the `fuzzy-matcher` crate, for `focus_best_match_with()`:
+/// ```no_run
+/// use ksway::focus::{focus_best_match_with, skim_score};
+///
+/// let mut client = ksway::Client::connect()?;
+/// focus_best_match_with(&mut client, "ffx", skim_score)?;
+/// # Ok::<(), ksway::Error>(())
+/// ```
+#[cfg(feature = "fuzzy-matcher")]
+pub fn skim_score(candidate: &str, query: &str) -> Option<i64> {
+    use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+    use std::sync::OnceLock;
+
+    static MATCHER: OnceLock<SkimMatcherV2> = OnceLock::new();
+    MATCHER
+        .get_or_init(SkimMatcherV2::default)
+        .fuzzy_match(candidate, query)
+}
+
+/// Like `focus_best_match()`, with another scorer taking the candidate and the query, e.g.
+/// `skim_score()` with the `fuzzy-matcher` feature.
+pub fn focus_best_match_with<C: SwayClientJson, S: Fn(&str, &str) -> Option<i64>>(
+    client: &mut C,
+    query: &str,
+    scorer: S,
+) -> Result<Option<u64>> {
+    let tree = client.get_tree_json()?;
+    let target = match best_match(&tree, query, scorer) {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    client.run(cmd!([con_id=target] "focus"))?;
+    Ok(Some(target))
+}
+
+/// How many times to `focus parent` from the focused node to reach the nearest ancestor matching
+/// `predicate`, and that ancestor's con_id. Sway doesn't focus above the workspace.
+fn parent_steps<P: Fn(&NodeRef<'_>) -> bool>(tree: &Tree, predicate: P) -> Option<(usize, u64)> {
+    let focused = tree.find_focused()?;
+    let mut steps = 0;
+    for ancestor in focused.ancestors() {
+        if matches!(ancestor.node_type, NodeType::Output | NodeType::Root) {
+            return None;
+        }
+        steps += 1;
+        if predicate(&ancestor) {
+            return Some((steps, ancestor.id));
+        }
+    }
+    None
+}
+
+/// How many times to `focus child` from the focused node to reach a window, following the most
+/// recently focused child like sway does, and that window's con_id.
+fn child_steps(tree: &Tree) -> Option<(usize, u64)> {
+    let mut node = tree.find_focused()?;
+    let mut steps = 0;
+    while let Some(child) = node
+        .focus
+        .first()
+        .and_then(|id| node.children().find(|child| child.id == *id))
+    {
+        node = child;
+        steps += 1;
+    }
+    Some((steps, node.id))
+}
+
+/// Send `focus {target}` `steps` times as one command.
+fn focus_repeatedly<C: SwayClient>(
+    client: &mut C,
+    target: FocusTarget,
+    steps: usize,
+) -> Result<()> {
+    if steps > 0 {
+        let seq = CommandSeq::new().for_each(0..steps, |_| command::focus_on(target.clone()));
+        client.run(seq)?;
+    }
+    Ok(())
+}
+
+/// Focus the nearest ancestor of the focused node which matches `predicate`, up to its
+/// workspace, with as many `focus parent` as it takes, e.g. the tabbed container a window is
+/// in:
+/// ```no_run
+/// use ksway::focus::focus_parent_until;
+///
+/// let mut client = ksway::Client::connect()?;
+/// focus_parent_until(&mut client, |node| node.layout.as_deref() == Some("tabbed"))?;
+/// # Ok::<(), ksway::Error>(())
+/// ```
+/// Returns the con_id of the ancestor which was focused, or `None` without sending anything if
+/// none matched.
+pub fn focus_parent_until<C, P>(client: &mut C, predicate: P) -> Result<Option<u64>>
+where
+    C: SwayClientJson,
+    P: Fn(&NodeRef<'_>) -> bool,
+{
+    let tree = Tree::from(client.get_tree_typed()?);
+    let (steps, id) = match parent_steps(&tree, predicate) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    focus_repeatedly(client, FocusTarget::Parent, steps)?;
+    Ok(Some(id))
+}
+
+/// Focus the window which `focus child` eventually leads to from the focused container, e.g.
+/// after `focus_parent_until()`, with as many `focus child` as it takes.
+///
+/// Returns the con_id of the window, which is the focused node itself if it has no children,
+/// or `None` if nothing is focused.
+pub fn focus_child_deepest<C: SwayClientJson>(client: &mut C) -> Result<Option<u64>> {
+    let tree = Tree::from(client.get_tree_typed()?);
+    let (steps, id) = match child_steps(&tree) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    focus_repeatedly(client, FocusTarget::Child, steps)?;
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fuzzy_matching() {
+        assert_eq!(fuzzy_score("Firefox", ""), Some(0));
+        assert_eq!(fuzzy_score("Firefox", "xf"), None);
+        assert!(fuzzy_score("Firefox", "fire") > fuzzy_score("Firefox", "frfx"));
+        assert!(fuzzy_score("foot - vim", "vim") > fuzzy_score("evil monkey", "vim"));
+
+        let tree = json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "output", "name": "DP-1", "nodes": [
+                {"id": 3, "type": "workspace", "name": "1", "nodes": [
+                    {"id": 10, "type": "con", "name": "~/src - fish", "app_id": "foot"},
+                    {"id": 11, "type": "con", "name": "Mozilla Firefox", "app_id": "firefox"},
+                    {"id": 12, "type": "con", "name": "Steam", "app_id": null,
+                     "window_properties": {"class": "Steam"}},
+                ]},
+            ]},
+        ]});
+        assert_eq!(best_match(&tree, "ffox", fuzzy_score), Some(11));
+        assert_eq!(best_match(&tree, "FOOT", fuzzy_score), Some(10));
+        assert_eq!(best_match(&tree, "stm", fuzzy_score), Some(12));
+        assert_eq!(best_match(&tree, "qq", fuzzy_score), None);
+        #[cfg(feature = "fuzzy-matcher")]
+        {
+            assert_eq!(best_match(&tree, "ffox", skim_score), Some(11));
+            assert_eq!(best_match(&tree, "stm", skim_score), Some(12));
+            assert_eq!(best_match(&tree, "qq", skim_score), None);
+        }
+    }
+
+    #[test]
+    fn parent_and_child_steps() {
+        let rect = json!({"x": 0, "y": 0, "width": 1, "height": 1});
+        let tree: crate::reply::Node = serde_json::from_value(json!({
+            "id": 1, "type": "root", "rect": rect, "nodes": [
+            {"id": 2, "type": "output", "rect": rect, "nodes": [
+                {"id": 3, "type": "workspace", "rect": rect, "layout": "splith", "focus": [4], "nodes": [
+                    {"id": 4, "type": "con", "rect": rect, "layout": "tabbed", "focus": [6, 5], "nodes": [
+                        {"id": 5, "type": "con", "rect": rect},
+                        {"id": 6, "type": "con", "rect": rect, "layout": "splitv", "focus": [7], "nodes": [
+                            {"id": 7, "type": "con", "rect": rect, "focused": true},
+                        ]},
+                    ]},
+                ]},
+            ]},
+        ]}))
+        .unwrap();
+        let tree = Tree::from(tree);
+        let tabbed = |node: &NodeRef<'_>| node.layout.as_deref() == Some("tabbed");
+        assert_eq!(parent_steps(&tree, tabbed), Some((2, 4)));
+        assert_eq!(
+            parent_steps(&tree, |node| node.node_type == NodeType::Workspace),
+            Some((3, 3))
+        );
+        assert_eq!(
+            parent_steps(&tree, |node| node.node_type == NodeType::Output),
+            None
+        );
+        assert_eq!(child_steps(&tree), Some((0, 7)));
+
+        // A focused workspace leads down to its most recently focused window.
+        let tree = Tree::parse(
+            &serde_json::to_vec(&json!({
+            "id": 3, "type": "workspace", "rect": rect, "focused": true, "focus": [4], "nodes": [
+                {"id": 4, "type": "con", "rect": rect, "focus": [6, 5], "nodes": [
+                    {"id": 5, "type": "con", "rect": rect},
+                    {"id": 6, "type": "con", "rect": rect},
+                ]},
+            ]}))
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(child_steps(&tree), Some((2, 6)));
+    }
+}
@@ -0,0 +1,201 @@
+//! Which windows were focused most recently, e.g. for alt-tab, kept by a daemon which feeds it
+//! window events:
+//!
+//! ```no_run
+//! use ksway::{cmd, focus_history::FocusHistory, IpcEvent, SwayClient};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut history = FocusHistory::default();
+//! let rx = client.subscribe(vec![IpcEvent::Window, IpcEvent::Tick])?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         let payload = serde_json::from_slice(&payload)?;
+//!         history.handle_event(event, &payload);
+//!         // `swaymsg -t send_tick alt-tab` from a binding.
+//!         if event == IpcEvent::Tick && payload["payload"] == "alt-tab" {
+//!             if let Some(id) = history.alt_tab_target() {
+//!                 client.run(cmd!([con_id=id] "focus"))?;
+//!             }
+//!         }
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::{HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::WindowChange;
+use crate::persist::Persistent;
+use crate::tree::windows;
+use crate::{IpcEvent, JsonValue, SwayJsonExt};
+
+/// The con_ids of windows by when they were last focused, the focused one first, without
+/// duplicates. Once full, the window focused longest ago is forgotten. Closed windows are
+/// removed on their `close` event, or by `prune()` for events which were missed.
+///
+/// Implements `Persistent` to be kept in a `persist::Store` across restarts of the daemon. Since
+/// windows may have closed in the meantime, `prune()` it after loading.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FocusHistory {
+    capacity: usize,
+    ids: VecDeque<u64>,
+}
+
+impl Persistent for FocusHistory {
+    const KIND: &'static str = "focus-history";
+    const VERSION: u32 = 1;
+}
+
+impl Default for FocusHistory {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl FocusHistory {
+    pub const DEFAULT_CAPACITY: usize = 100;
+
+    /// Remember at most `capacity` windows, at least 1.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            ids: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The con_ids, most recently focused first.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ids.iter().copied()
+    }
+
+    /// The window focused `n` focus changes ago, where 0 is the focused window and 1 the one
+    /// before it.
+    pub fn last_focused(&self, n: usize) -> Option<u64> {
+        self.ids.get(n).copied()
+    }
+
+    /// The window to switch to with alt-tab, the one focused before the focused one.
+    pub fn alt_tab_target(&self) -> Option<u64> {
+        self.last_focused(1)
+    }
+
+    /// Note that `id` got focus.
+    pub fn focused(&mut self, id: u64) {
+        self.remove(id);
+        self.ids.push_front(id);
+        self.ids.truncate(self.capacity);
+    }
+
+    /// Forget `id`, e.g. because it was closed. Returns whether it was known.
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.ids.iter().position(|known| *known == id) {
+            Some(index) => {
+                self.ids.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget the windows which aren't in `tree`, a GET_TREE reply, and note the focused window
+    /// if it is new. For startup, or after a reconnect, when close events may have been missed.
+    pub fn prune(&mut self, tree: &JsonValue) {
+        let mut focused = None;
+        let alive: HashSet<u64> = windows(tree)
+            .filter_map(|window| {
+                let id = window.id()?;
+                if window.node.is_focused() {
+                    focused = Some(id);
+                }
+                Some(id)
+            })
+            .collect();
+        self.ids.retain(|id| alive.contains(id));
+        if let Some(focused) = focused {
+            if self.last_focused(0) != Some(focused) {
+                self.focused(focused);
+            }
+        }
+    }
+
+    /// Feed a subscription event. Only `focus` and `close` window events change the history.
+    pub fn handle_event(&mut self, event: IpcEvent, payload: &JsonValue) {
+        if event != IpcEvent::Window {
+            return;
+        }
+        let id = match payload["container"].con_id() {
+            Some(id) => id,
+            None => return,
+        };
+        match WindowChange::from_payload(payload) {
+            Some(WindowChange::Focus) => self.focused(id),
+            Some(WindowChange::Close) => {
+                self.remove(id);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn mru_order_and_pruning() {
+        let mut history = FocusHistory::new(3);
+        let window = |change: &str, id: u64| json!({"change": change, "container": {"id": id}});
+        for id in [1, 2, 3, 2, 4] {
+            history.handle_event(IpcEvent::Window, &window("focus", id));
+        }
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![4, 2, 3]);
+        assert_eq!(history.alt_tab_target(), Some(2));
+        assert_eq!(history.last_focused(3), None);
+
+        history.handle_event(IpcEvent::Window, &window("close", 2));
+        history.handle_event(IpcEvent::Window, &window("title", 5));
+        history.handle_event(IpcEvent::Workspace, &window("focus", 6));
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![4, 3]);
+
+        let tree = json!({"id": 1, "type": "root", "nodes": [{"id": 10, "type": "workspace", "name": "1", "nodes": [
+            {"id": 3, "type": "con"},
+            {"id": 7, "type": "con", "focused": true},
+        ]}]});
+        history.prune(&tree);
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![7, 3]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        use crate::persist::{JsonFile, Store};
+
+        let dir = std::env::temp_dir().join(format!("ksway-focus-history-{}", std::process::id()));
+        let store = JsonFile::new(dir.join("focus-history.json"));
+        let mut history = FocusHistory::new(5);
+        for id in [1, 2, 3] {
+            history.focused(id);
+        }
+        store.save(&history).unwrap();
+        let loaded: FocusHistory = store.load().unwrap().unwrap();
+        assert_eq!(loaded, history);
+        assert_eq!(loaded.capacity(), 5);
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), vec![3, 2, 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
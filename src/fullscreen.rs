@@ -0,0 +1,146 @@
+//! Fullscreen across all outputs.
+//!
+//! Sway only remembers a single fullscreen state per container, so going global fullscreen from
+//! workspace fullscreen and back loses the workspace fullscreen, and any window which was
+//! fullscreen on another workspace is kicked out of it. `GlobalFullscreen` remembers those modes
+//! and puts them back when global fullscreen is turned off.
+use std::collections::HashMap;
+
+use crate::tree::nodes;
+use crate::{cmd, JsonValue, Result, SwayClientJson};
+
+/// The `fullscreen_mode` of a container in the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    None,
+    /// Fullscreen on its own workspace's output.
+    Workspace,
+    /// Fullscreen spanning every output.
+    Global,
+}
+
+impl FullscreenMode {
+    pub fn from_node(node: &JsonValue) -> Self {
+        match node["fullscreen_mode"].as_u64() {
+            Some(1) => FullscreenMode::Workspace,
+            Some(2) => FullscreenMode::Global,
+            _ => FullscreenMode::None,
+        }
+    }
+}
+
+/// The con_id and mode of every fullscreen container in a GET_TREE reply.
+pub fn fullscreen_containers(tree: &JsonValue) -> Vec<(u64, FullscreenMode)> {
+    nodes(tree)
+        .filter_map(|node| match FullscreenMode::from_node(node.node) {
+            FullscreenMode::None => None,
+            mode => Some((node.id()?, mode)),
+        })
+        .collect()
+}
+
+/// The con_id of the container which is global fullscreen, if any.
+pub fn global_fullscreen_window<C: SwayClientJson>(client: &mut C) -> Result<Option<u64>> {
+    let tree = client.get_tree_json()?;
+    Ok(fullscreen_containers(&tree)
+        .into_iter()
+        .find(|(_, mode)| *mode == FullscreenMode::Global)
+        .map(|(id, _)| id))
+}
+
+/// Make `con_id` fullscreen across all outputs. Use `GlobalFullscreen` to get back to the
+/// previous state afterwards.
+pub fn fullscreen_global<C: SwayClientJson>(client: &mut C, con_id: u64) -> Result<()> {
+    client.run(cmd!([con_id=con_id] "fullscreen enable global"))?;
+    Ok(())
+}
+
+/// The commands which put containers back into the fullscreen modes they had before.
+fn restore_commands(con_id: u64, previous: &[(u64, FullscreenMode)]) -> Vec<String> {
+    let mut commands = vec![cmd!([con_id=con_id] "fullscreen disable").to_string()];
+    for &(id, mode) in previous {
+        let command = match mode {
+            FullscreenMode::None => continue,
+            FullscreenMode::Workspace => "fullscreen enable",
+            FullscreenMode::Global => "fullscreen enable global",
+        };
+        commands.push(cmd!([con_id=id] "{}", command).to_string());
+    }
+    commands
+}
+
+/// Toggles global fullscreen while remembering which containers were fullscreen before, so that
+/// turning it off restores them.
+#[derive(Debug, Default)]
+pub struct GlobalFullscreen {
+    /// The fullscreen containers from before each global fullscreen container went global.
+    previous: HashMap<u64, Vec<(u64, FullscreenMode)>>,
+}
+
+impl GlobalFullscreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `con_id` global fullscreen, remembering the current fullscreen state.
+    pub fn enable<C: SwayClientJson>(&mut self, client: &mut C, con_id: u64) -> Result<()> {
+        let tree = client.get_tree_json()?;
+        let current = fullscreen_containers(&tree);
+        if current.contains(&(con_id, FullscreenMode::Global)) {
+            return Ok(());
+        }
+        fullscreen_global(client, con_id)?;
+        self.previous.insert(con_id, current);
+        Ok(())
+    }
+
+    /// Turn off global fullscreen for `con_id` and restore the fullscreen state from before
+    /// `enable()`. Containers which weren't enabled through here are simply unfullscreened.
+    pub fn disable<C: SwayClientJson>(&mut self, client: &mut C, con_id: u64) -> Result<()> {
+        let previous = self.previous.remove(&con_id).unwrap_or_default();
+        client.run(restore_commands(con_id, &previous).join("; "))?;
+        Ok(())
+    }
+
+    /// Toggle global fullscreen for `con_id`, returning whether it is now enabled.
+    pub fn toggle<C: SwayClientJson>(&mut self, client: &mut C, con_id: u64) -> Result<bool> {
+        if global_fullscreen_window(client)? == Some(con_id) {
+            self.disable(client, con_id)?;
+            Ok(false)
+        } else {
+            self.enable(client, con_id)?;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn restore_previous_modes() {
+        let tree = json!({
+            "id": 1,
+            "type": "root",
+            "nodes": [{
+                "id": 2,
+                "type": "workspace",
+                "nodes": [
+                    {"id": 10, "type": "con", "fullscreen_mode": 1, "nodes": []},
+                    {"id": 11, "type": "con", "fullscreen_mode": 0, "nodes": []},
+                ],
+            }],
+        });
+        let previous = fullscreen_containers(&tree);
+        assert_eq!(previous, vec![(10, FullscreenMode::Workspace)]);
+        assert_eq!(
+            restore_commands(11, &previous),
+            vec![
+                "[con_id=\"11\"] fullscreen disable",
+                "[con_id=\"10\"] fullscreen enable"
+            ]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Rectangles of windows, workspaces and outputs, e.g. to move a floating window to a corner of
+//! its workspace:
+//!
+//! ```no_run
+//! use ksway::geometry::Alignment;
+//! use ksway::{cmd, SwayClient, SwayClientJson, SwayJsonExt};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let workspace = client.focused_workspace()?.and_then(|ws| ws.rect());
+//! let window = client.focused_window()?.and_then(|window| window.rect());
+//! if let (Some(workspace), Some(window)) = (workspace, window) {
+//!     let moved = window.align(workspace.inset(10, 10), &[Alignment::Bottom, Alignment::Right]);
+//!     client.run(cmd!("move absolute position {} {}", moved.x, moved.y))?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{reply, JsonValue, ParseError};
+
+/// The `rect` of a node in a reply, in pixels of the layout, where `x` grows to the right and
+/// `y` downwards. Displayed as an X geometry, `WxH+X+Y`, e.g. `640x480+0+-10`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl Rect {
+    pub const fn new(x: i64, y: i64, width: i64, height: i64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The `rect` of a node, workspace or output in a JSON reply or event, or `None` if it is
+    /// missing or isn't a rect. The same as `SwayJsonExt::rect()`.
+    pub fn from_node(node: &JsonValue) -> Option<Rect> {
+        Rect::deserialize(&node["rect"]).ok()
+    }
+
+    /// Moved by `dx` to the right and `dy` down.
+    pub fn translate(&self, dx: i64, dy: i64) -> Rect {
+        Rect::new(self.x + dx, self.y + dy, self.width, self.height)
+    }
+
+    /// The x just past the right edge.
+    pub fn right(&self) -> i64 {
+        self.x + self.width
+    }
+
+    /// The y just past the bottom edge.
+    pub fn bottom(&self) -> i64 {
+        self.y + self.height
+    }
+
+    /// The center, rounded towards the top left.
+    pub fn center(&self) -> (i64, i64) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    pub fn contains_point(&self, x: i64, y: i64) -> bool {
+        (self.x..self.right()).contains(&x) && (self.y..self.bottom()).contains(&y)
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// The area both cover, or `None` if they don't overlap. Rects which only touch don't.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+        if x < right && y < bottom {
+            Some(Rect::new(x, y, right - x, bottom - y))
+        } else {
+            None
+        }
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The shortest distance between the edges, 0 if they touch or overlap.
+    pub fn distance(&self, other: &Rect) -> f64 {
+        let dx = (other.x - self.right()).max(self.x - other.right()).max(0);
+        let dy = (other.y - self.bottom())
+            .max(self.y - other.bottom())
+            .max(0);
+        (dx as f64).hypot(dy as f64)
+    }
+
+    /// The distance between the centers.
+    pub fn center_distance(&self, other: &Rect) -> f64 {
+        let ((x1, y1), (x2, y2)) = (self.center(), other.center());
+        ((x2 - x1) as f64).hypot((y2 - y1) as f64)
+    }
+
+    /// Shrunk by `dx` on the left and right and `dy` on the top and bottom, e.g. for gaps.
+    /// Negative values grow it, and it never gets smaller than empty.
+    pub fn inset(&self, dx: i64, dy: i64) -> Rect {
+        let width = (self.width - 2 * dx).max(0);
+        let height = (self.height - 2 * dy).max(0);
+        Rect::new(self.x + dx, self.y + dy, width, height)
+    }
+
+    /// Moved, and if it is larger then shrunk, to be within `bounds`.
+    pub fn clamp(&self, bounds: &Rect) -> Rect {
+        let width = self.width.min(bounds.width);
+        let height = self.height.min(bounds.height);
+        Rect::new(
+            self.x.clamp(bounds.x, bounds.right() - width),
+            self.y.clamp(bounds.y, bounds.bottom() - height),
+            width,
+            height,
+        )
+    }
+
+    /// Moved against the edges or to the center of `within`, one alignment after the other.
+    /// The size doesn't change, and an empty list leaves it where it is.
+    pub fn align(&self, within: Rect, alignments: &[Alignment]) -> Rect {
+        let mut rect = *self;
+        let center_x = within.x + (within.width - self.width) / 2;
+        let center_y = within.y + (within.height - self.height) / 2;
+        for alignment in alignments {
+            match alignment {
+                Alignment::Top => rect.y = within.y,
+                Alignment::Bottom => rect.y = within.bottom() - self.height,
+                Alignment::Left => rect.x = within.x,
+                Alignment::Right => rect.x = within.right() - self.width,
+                Alignment::Center => {
+                    rect.x = center_x;
+                    rect.y = center_y;
+                }
+                Alignment::CenterX => rect.x = center_x,
+                Alignment::CenterY => rect.y = center_y,
+            }
+        }
+        rect
+    }
+}
+
+impl From<&reply::Node> for Rect {
+    fn from(node: &reply::Node) -> Self {
+        node.rect
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}{:+}{:+}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Where `Rect::align()` moves a rect to, written in snake case, e.g. `top` or `center_x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    Top,
+    Left,
+    Right,
+    Bottom,
+    Center,
+    CenterX,
+    CenterY,
+}
+
+impl Alignment {
+    const NAMES: &'static [(&'static str, Alignment)] = &[
+        ("top", Alignment::Top),
+        ("left", Alignment::Left),
+        ("right", Alignment::Right),
+        ("bottom", Alignment::Bottom),
+        ("center", Alignment::Center),
+        ("center_x", Alignment::CenterX),
+        ("center_y", Alignment::CenterY),
+    ];
+}
+
+impl FromStr for Alignment {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        Alignment::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, alignment)| *alignment)
+            .ok_or_else(|| ParseError(format!("unknown alignment: {}", s)))
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Alignment::NAMES
+            .iter()
+            .find(|(_, alignment)| alignment == self)
+            .expect("every alignment has a name");
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn from_nodes() {
+        let window = json!({"id": 4, "type": "con", "rect": {"x": 0, "y": -10, "width": 640, "height": 480}});
+        let rect = Rect::from_node(&window).unwrap();
+        assert_eq!(rect.to_string(), "640x480+0-10");
+        assert_eq!(rect.translate(5, 10).to_string(), "640x480+5+0");
+        let node: reply::Node = serde_json::from_value(window).unwrap();
+        assert_eq!(Rect::from(&node), rect);
+        assert_eq!(Rect::from_node(&json!({"rect": {"x": 0}})), None);
+    }
+
+    #[test]
+    fn align_clamp_and_intersect() {
+        let workspace = Rect::new(0, 20, 1000, 500);
+        let window = Rect::new(300, 300, 200, 100);
+        let aligned = |alignments: &[Alignment]| {
+            let rect = window.align(workspace, alignments);
+            (rect.x, rect.y)
+        };
+        assert_eq!(aligned(&[]), (300, 300));
+        assert_eq!(aligned(&[Alignment::Top, Alignment::Right]), (800, 20));
+        assert_eq!(
+            aligned(&[Alignment::Bottom, Alignment::CenterX]),
+            (400, 420)
+        );
+        assert_eq!(
+            window.align(workspace.inset(10, 10), &[Alignment::Center]),
+            Rect::new(400, 220, 200, 100)
+        );
+        let names: Vec<Alignment> = ["top", "center_y"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(names, vec![Alignment::Top, Alignment::CenterY]);
+        assert_eq!(Alignment::CenterX.to_string(), "center_x");
+        assert!("middle".parse::<Alignment>().is_err());
+
+        assert_eq!(
+            Rect::new(900, -50, 300, 100).clamp(&workspace),
+            Rect::new(700, 20, 300, 100)
+        );
+        assert_eq!(
+            Rect::new(0, 0, 2000, 100).clamp(&workspace),
+            Rect::new(0, 20, 1000, 100)
+        );
+        assert!(workspace.contains(&window) && !window.contains(&workspace));
+        assert!(window.contains_point(300, 399) && !window.contains_point(500, 300));
+
+        let right = Rect::new(500, 300, 100, 100);
+        assert_eq!(window.intersection(&right), None);
+        assert_eq!(window.distance(&right), 0.0);
+        assert_eq!(
+            window.intersection(&Rect::new(450, 350, 100, 100)),
+            Some(Rect::new(450, 350, 50, 50))
+        );
+        assert_eq!(window.distance(&Rect::new(503, 404, 10, 10)), 5.0);
+        assert_eq!(window.center_distance(&Rect::new(400, 350, 0, 0)), 0.0);
+        assert_eq!(Rect::new(0, 0, 10, 10).inset(6, 0).width, 0);
+    }
+}
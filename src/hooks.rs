@@ -0,0 +1,194 @@
+//! Running commands around workspace switches, e.g. to adjust gaps, pause media or change the
+//! bar mode.
+//!
+//! Sway only reports a switch after it happened, so commands can only run before a switch which
+//! is made through `WorkspaceHooks::switch()`, e.g. from a binding running a small tool instead
+//! of `workspace` directly. Commands after a switch run for every switch, however it was made:
+//! ```no_run
+//! use ksway::{command, hooks::WorkspaceHooks, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut hooks = WorkspaceHooks::new()
+//!     .on_before_workspace_switch(|_| vec![command::raw("exec playerctl pause")])
+//!     .on_after_workspace_switch(|switch| match switch.to.as_str() {
+//!         "music" => vec![command::raw("bar mode dock")],
+//!         _ => vec![command::raw("bar mode hide")],
+//!     });
+//! let rx = client.subscribe(vec![IpcEvent::Workspace])?;
+//! hooks.switch(&mut client, "music")?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         hooks.handle_event(&mut client, event, &serde_json::from_slice(&payload)?)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use crate::command::{self, WorkspaceTarget};
+use crate::events::WorkspaceChange;
+use crate::{Command, IpcEvent, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// A switch from one workspace to another, by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Switch {
+    /// `None` if no workspace was focused, e.g. right after sway started.
+    pub from: Option<String>,
+    pub to: String,
+}
+
+type Hook = Box<dyn FnMut(&Switch) -> Vec<Command> + Send>;
+
+/// Hooks which return the commands to run around a workspace switch, in the order they were
+/// registered. The ordering relative to sway's workspace `focus` event is:
+///
+/// - commands from before hooks are sent along with the switch itself, ahead of it in the same
+///   message, so sway has run them before it switches and sends the event;
+/// - commands from after hooks are run when that event is fed to `handle_event()`, so feed it
+///   before passing the event on to consumers which should see their effect.
+#[derive(Default)]
+pub struct WorkspaceHooks {
+    before: Vec<Hook>,
+    after: Vec<Hook>,
+}
+
+/// The switch a workspace `focus` event stands for, if that is what `payload` is.
+fn switch_from_event(event: IpcEvent, payload: &JsonValue) -> Option<Switch> {
+    if event != IpcEvent::Workspace
+        || WorkspaceChange::from_payload(payload) != Some(WorkspaceChange::Focus)
+    {
+        return None;
+    }
+    Some(Switch {
+        from: payload["old"].name().map(str::to_owned),
+        to: payload["current"].name()?.to_owned(),
+    })
+}
+
+/// Run the commands of `hooks` for `switch`, followed by `then`, as one message.
+fn run_hooks<C: SwayClientJson>(
+    client: &mut C,
+    hooks: &mut [Hook],
+    switch: &Switch,
+    then: Option<Command>,
+) -> Result<()> {
+    let commands: Vec<String> = hooks
+        .iter_mut()
+        .flat_map(|hook| hook(switch))
+        .chain(then)
+        .map(|command| command.to_string())
+        .collect();
+    if !commands.is_empty() {
+        client.run(commands.join("; "))?;
+    }
+    Ok(())
+}
+
+impl WorkspaceHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the commands `hook` returns before each switch made with `switch()`.
+    pub fn on_before_workspace_switch<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&Switch) -> Vec<Command> + Send + 'static,
+    {
+        self.before.push(Box::new(hook));
+        self
+    }
+
+    /// Run the commands `hook` returns after each switch, when its event is fed to
+    /// `handle_event()`.
+    pub fn on_after_workspace_switch<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&Switch) -> Vec<Command> + Send + 'static,
+    {
+        self.after.push(Box::new(hook));
+        self
+    }
+
+    /// Switch to the workspace named `to`, running the before hooks first. Switching to the
+    /// focused workspace does nothing.
+    pub fn switch<C: SwayClientJson>(&mut self, client: &mut C, to: &str) -> Result<()> {
+        let workspaces = client.get_workspaces_json()?;
+        let from = workspaces
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|ws| ws.is_focused())
+            .and_then(|ws| ws.name())
+            .map(str::to_owned);
+        if from.as_deref() == Some(to) {
+            return Ok(());
+        }
+        let switch = Switch {
+            from,
+            to: to.to_owned(),
+        };
+        let command = command::switch_workspace(WorkspaceTarget::Name(switch.to.clone()));
+        run_hooks(client, &mut self.before, &switch, Some(command))
+    }
+
+    /// Run the after hooks if `event` is a workspace `focus` event.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        match switch_from_event(event, payload) {
+            Some(switch) => run_hooks(client, &mut self.after, &switch, None),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::{ipc_command, Client};
+    use serde_json::json;
+
+    #[test]
+    fn commands_around_switch() {
+        let server = MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_workspaces(),
+            r#"[{"name": "1", "focused": true}, {"name": "2", "focused": false}]"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let mut hooks = WorkspaceHooks::new()
+            .on_before_workspace_switch(|switch| {
+                vec![command::raw(format!(
+                    "gaps inner all set {}",
+                    switch.to.len()
+                ))]
+            })
+            .on_before_workspace_switch(|_| Vec::new())
+            .on_after_workspace_switch(|switch| {
+                vec![command::raw(format!(
+                    "mark --add left-{}",
+                    switch.from.as_deref().unwrap_or("none")
+                ))]
+            });
+
+        hooks.switch(&mut client, "2").unwrap();
+        hooks.switch(&mut client, "1").unwrap();
+        let focus = json!({"change": "focus", "old": {"name": "1"}, "current": {"name": "2"}});
+        hooks
+            .handle_event(&mut client, IpcEvent::Workspace, &focus)
+            .unwrap();
+        hooks
+            .handle_event(
+                &mut client,
+                IpcEvent::Workspace,
+                &json!({"change": "init", "current": {"name": "3"}}),
+            )
+            .unwrap();
+        server.assert_run_commands(&[
+            r#"gaps inner all set 1; workspace "2""#,
+            "mark --add left-1",
+        ]);
+    }
+}
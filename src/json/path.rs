@@ -0,0 +1,118 @@
+//! Paths into replies and events, such as `rect/width` or `nodes/0/name`, e.g. to let a user
+//! pick what a script prints.
+//!
+//! ```
+//! use ksway::json::path::Path;
+//! use serde_json::json;
+//!
+//! let window = json!({"rect": {"width": 640}, "marks": ["a", "b"]});
+//! let width: Path = "rect/width".parse()?;
+//! assert_eq!(width.get(&window), Some(&json!(640)));
+//! assert_eq!("marks/1".parse::<Path>()?.get(&window), Some(&json!("b")));
+//! assert_eq!("rect/height".parse::<Path>()?.get(&window), None);
+//! # Ok::<(), ksway::ParseError>(())
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{JsonValue, ParseError};
+
+/// Keys of objects and indices of arrays, written separated by `/`. A part is an index when it
+/// is a number and the value at that point is an array, and a key otherwise. The empty path is
+/// the whole value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Path(Vec<String>);
+
+impl Path {
+    pub fn new<I, S>(parts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Path(parts.into_iter().map(Into::into).collect())
+    }
+
+    pub fn parts(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The value at this path, or `None` if there is nothing there.
+    pub fn get<'a>(&self, value: &'a JsonValue) -> Option<&'a JsonValue> {
+        self.0.iter().try_fold(value, |value, part| match value {
+            JsonValue::Object(object) => object.get(part),
+            JsonValue::Array(array) => array.get(part.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    pub fn get_mut<'a>(&self, value: &'a mut JsonValue) -> Option<&'a mut JsonValue> {
+        self.0.iter().try_fold(value, |value, part| match value {
+            JsonValue::Object(object) => object.get_mut(part),
+            JsonValue::Array(array) => array.get_mut(part.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// Like `get()`, but takes the value out of `value` rather than borrowing it, which saves a
+    /// clone when the rest isn't needed.
+    pub fn take(&self, value: JsonValue) -> Option<JsonValue> {
+        self.0.iter().try_fold(value, |value, part| match value {
+            JsonValue::Object(mut object) => object.remove(part),
+            JsonValue::Array(mut array) => {
+                let index = part.parse::<usize>().ok()?;
+                if index < array.len() {
+                    Some(array.swap_remove(index))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+}
+
+impl FromStr for Path {
+    type Err = ParseError;
+
+    /// A leading `/` is allowed, like in a JSON pointer, but empty parts aren't.
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let s = s.strip_prefix('/').unwrap_or(s);
+        if s.is_empty() {
+            return Ok(Path::default());
+        }
+        if s.split('/').any(str::is_empty) {
+            return Err(ParseError(format!("empty part in path: {}", s)));
+        }
+        Ok(Path::new(s.split('/')))
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn get_and_take() {
+        let mut value = json!({"nodes": [{"name": "a"}, {"name": "b"}], "0": "key"});
+        let path: Path = "/nodes/1/name".parse().unwrap();
+        assert_eq!(path.to_string(), "nodes/1/name");
+        assert_eq!(path.get(&value), Some(&json!("b")));
+        // A number is only an index into arrays.
+        assert_eq!(Path::new(vec!["0"]).get(&value), Some(&json!("key")));
+        assert_eq!("nodes/2".parse::<Path>().unwrap().get(&value), None);
+        assert_eq!("nodes/x".parse::<Path>().unwrap().get(&value), None);
+        assert_eq!("".parse::<Path>().unwrap().get(&value), Some(&value));
+        assert!("nodes//name".parse::<Path>().is_err());
+
+        *path.get_mut(&mut value).unwrap() = json!("c");
+        assert_eq!(path.take(value.clone()), Some(json!("c")));
+        assert_eq!("nodes/0/name/x".parse::<Path>().unwrap().take(value), None);
+    }
+}
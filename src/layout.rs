@@ -0,0 +1,590 @@
+//! Workspace layouts: defaults per workspace, and saving and restoring how windows are split.
+//!
+//! Sway's `workspace_layout` applies to every workspace. `LayoutDefaults` instead applies a
+//! layout to specific workspaces whenever they are created, and whenever the first tiling window
+//! appears on them after they were emptied:
+//! ```no_run
+//! use ksway::{layout::{Layout, LayoutDefaults}, IpcEvent};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut layouts = LayoutDefaults::new();
+//! layouts.set("2:chat", Layout::Tabbed);
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Workspace, IpcEvent::Window])?;
+//! loop {
+//!     while let Ok((payload_type, payload)) = rx.try_recv() {
+//!         layouts.handle_event(&mut client, payload_type, &serde_json::from_slice(&payload)?)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! `SavedLayout` snapshots how the windows of a workspace are split, and puts them back that way
+//! later, like `i3-save-tree` but through IPC only:
+//! ```no_run
+//! use ksway::layout::SavedLayout;
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let saved = SavedLayout::save(&mut client, "3:code")?.expect("no such workspace");
+//! std::fs::write("code.json", serde_json::to_vec(&saved)?)?;
+//! // ... later, once the windows are open again:
+//! saved.restore(&mut client)?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::{self, quote};
+use crate::criteria::{con_id, con_mark, exact};
+use crate::events::{WindowChange, WorkspaceChange, WorkspaceEvent};
+use crate::json::windows;
+use crate::tree::{self, app_identifier, NodeKind, SCRATCHPAD_WORKSPACE};
+use crate::{cmd, Command, IpcEvent, JsonValue, ParseError, Result, SwayClientJson, SwayJsonExt};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    Tabbed,
+    Stacking,
+    SplitH,
+    SplitV,
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Layout::Tabbed => "tabbed",
+            Layout::Stacking => "stacking",
+            Layout::SplitH => "splith",
+            Layout::SplitV => "splitv",
+        })
+    }
+}
+
+impl FromStr for Layout {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, ParseError> {
+        match s {
+            "tabbed" => Ok(Layout::Tabbed),
+            "stacking" => Ok(Layout::Stacking),
+            "splith" => Ok(Layout::SplitH),
+            "splitv" => Ok(Layout::SplitV),
+            _ => Err(ParseError(format!("unknown layout {:?}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LayoutDefaults {
+    layouts: HashMap<String, Layout>,
+}
+
+impl LayoutDefaults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set<S: Into<String>>(&mut self, workspace: S, layout: Layout) {
+        self.layouts.insert(workspace.into(), layout);
+    }
+
+    pub fn remove(&mut self, workspace: &str) -> Option<Layout> {
+        self.layouts.remove(workspace)
+    }
+
+    pub fn get(&self, workspace: &str) -> Option<Layout> {
+        self.layouts.get(workspace).cloned()
+    }
+
+    /// Decide which command applies the default layout for an event, if any.
+    /// `window_workspace` is only consulted for new windows, and returns the name of the
+    /// workspace a window is on along with how many tiling windows that workspace has.
+    fn react<F: FnOnce(u64) -> Result<Option<(String, usize)>>>(
+        &self,
+        event: IpcEvent,
+        payload: &JsonValue,
+        window_workspace: F,
+    ) -> Result<Option<Command>> {
+        match (event, WindowChange::from_payload(payload)) {
+            // A workspace is focused when it is created, so the plain command applies to it.
+            (IpcEvent::Workspace, _) => {
+                let event = WorkspaceEvent::from_json(payload)?;
+                Ok(match (event.change, event.current) {
+                    (WorkspaceChange::Init, Some(current)) if current.focused => current
+                        .name
+                        .and_then(|name| self.get(&name))
+                        .map(|layout| cmd!("layout {}", layout)),
+                    _ => None,
+                })
+            }
+            (IpcEvent::Window, Some(WindowChange::New)) => {
+                let container = &payload["container"];
+                let id = match container.con_id() {
+                    Some(id) if container["type"].as_str() == Some("con") => id,
+                    _ => return Ok(None),
+                };
+                Ok(match window_workspace(id)? {
+                    // The workspace's only window sets the layout of the workspace itself.
+                    Some((workspace, 1)) => self
+                        .get(&workspace)
+                        .map(|layout| cmd!([con_id=id] "layout {}", layout)),
+                    _ => None,
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Feed a subscription event, applying a default layout if needed.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        if self.layouts.is_empty() {
+            return Ok(());
+        }
+        let command = self.react(event, payload, |id| {
+            let tree = client.get_tree_json()?;
+            let windows = windows(&tree);
+            let workspace = match windows
+                .iter()
+                .find(|window| window.node.con_id() == Some(id))
+            {
+                Some(window) => window.workspace,
+                None => return Ok(None),
+            };
+            let tiling = windows
+                .iter()
+                .filter(|window| {
+                    window.workspace == workspace && window.node["type"].as_str() == Some("con")
+                })
+                .count();
+            Ok(Some((workspace.to_owned(), tiling)))
+        })?;
+        if let Some(command) = command {
+            client.run(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where windows are parked while a layout is rebuilt, so that the splits they were in go away.
+const PARKING_WORKSPACE: &str = "__ksway_layout";
+
+/// A tiling container in a `SavedLayout`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SavedNode {
+    Window {
+        /// The con_id of the window, which it is found by first when restoring.
+        id: u64,
+        /// See `tree::app_identifier()`, which a window is found by when the id is gone, e.g.
+        /// after restarting sway.
+        app: Option<String>,
+        title: Option<String>,
+        /// The share of its parent's width or height, from 0 to 1.
+        percent: Option<f64>,
+    },
+    Split {
+        layout: Layout,
+        percent: Option<f64>,
+        children: Vec<SavedNode>,
+    },
+}
+
+impl SavedNode {
+    /// `None` for containers without windows.
+    fn from_json(node: &JsonValue) -> Option<Self> {
+        let percent = node["percent"].as_f64();
+        let children: Vec<SavedNode> = node["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(SavedNode::from_json)
+            .collect();
+        // Windows have the layout `none`, so a container with a layout is a split, even if it
+        // is empty.
+        if let Some(layout) = node["layout"].as_str().and_then(|l| l.parse().ok()) {
+            if children.is_empty() {
+                return None;
+            }
+            return Some(SavedNode::Split {
+                layout,
+                percent,
+                children,
+            });
+        }
+        Some(SavedNode::Window {
+            id: node.con_id()?,
+            app: app_identifier(node).map(str::to_owned),
+            title: node.name().map(str::to_owned),
+            percent,
+        })
+    }
+}
+
+/// A `SavedNode` whose windows were found in the current tree.
+#[derive(Debug)]
+enum Resolved {
+    Window(u64, Option<f64>),
+    Split(Layout, Option<f64>, Vec<Resolved>),
+}
+
+impl Resolved {
+    fn first_window(&self) -> u64 {
+        match self {
+            Resolved::Window(id, _) => *id,
+            Resolved::Split(_, _, children) => children[0].first_window(),
+        }
+    }
+
+    fn windows(&self, ids: &mut Vec<u64>) {
+        match self {
+            Resolved::Window(id, _) => ids.push(*id),
+            Resolved::Split(_, _, children) => children.iter().for_each(|c| c.windows(ids)),
+        }
+    }
+}
+
+/// Find the windows of `saved` among `live`, which maps con_ids to app identifiers. Windows
+/// whose id is still there are taken first, the rest are matched by app in order. Windows which
+/// aren't found and splits left empty are dropped.
+fn resolve(saved: &[SavedNode], live: &HashMap<u64, Option<&str>>) -> Vec<Resolved> {
+    fn ids(nodes: &[SavedNode], out: &mut HashSet<u64>) {
+        for node in nodes {
+            match node {
+                SavedNode::Window { id, .. } => {
+                    out.insert(*id);
+                }
+                SavedNode::Split { children, .. } => ids(children, out),
+            }
+        }
+    }
+    fn walk(
+        nodes: &[SavedNode],
+        live: &HashMap<u64, Option<&str>>,
+        used: &mut HashSet<u64>,
+    ) -> Vec<Resolved> {
+        nodes
+            .iter()
+            .filter_map(|node| match node {
+                SavedNode::Window {
+                    id, app, percent, ..
+                } => {
+                    let found = if live.contains_key(id) {
+                        Some(*id)
+                    } else {
+                        let mut candidates: Vec<u64> = live
+                            .iter()
+                            .filter(|(id, live_app)| {
+                                !used.contains(*id) && app.is_some() && **live_app == app.as_deref()
+                            })
+                            .map(|(id, _)| *id)
+                            .collect();
+                        candidates.sort_unstable();
+                        candidates.first().copied()
+                    }?;
+                    used.insert(found);
+                    Some(Resolved::Window(found, *percent))
+                }
+                SavedNode::Split {
+                    layout,
+                    percent,
+                    children,
+                } => {
+                    let children = walk(children, live, used);
+                    if children.is_empty() {
+                        None
+                    } else {
+                        Some(Resolved::Split(*layout, *percent, children))
+                    }
+                }
+            })
+            .collect()
+    }
+    let mut used = HashSet::new();
+    ids(saved, &mut used);
+    used.retain(|id| live.contains_key(id));
+    walk(saved, live, &mut used)
+}
+
+/// The tiling layout of a workspace: how its windows are split, in which layouts and sizes.
+/// Floating windows aren't part of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedLayout {
+    pub workspace: String,
+    pub layout: Layout,
+    pub children: Vec<SavedNode>,
+}
+
+/// Generates the commands which rebuild a layout, marking every container along the way.
+#[derive(Default)]
+struct Builder {
+    commands: Vec<String>,
+    /// Every container's mark, along with the layout of its parent and its saved share of it if
+    /// it should be resized to that.
+    marks: Vec<(String, Layout, Option<f64>)>,
+}
+
+impl Builder {
+    /// The mark for the next container. The last container of a parent gets whatever is left,
+    /// so only the others are resized.
+    fn mark(&mut self, parent: Layout, percent: Option<f64>, last: bool) -> String {
+        let mark = format!("_ksway_layout_{}", self.marks.len());
+        self.marks
+            .push((mark.clone(), parent, percent.filter(|_| !last)));
+        mark
+    }
+
+    /// Rebuild `node` around its first window, which is already where `node` belongs.
+    fn build(&mut self, node: &Resolved, parent: Layout, last: bool) {
+        let first = node.first_window();
+        match node {
+            Resolved::Window(id, percent) => {
+                let mark = self.mark(parent, *percent, last);
+                self.push(command::add_mark(mark).with_criteria(vec![con_id(*id)]));
+            }
+            Resolved::Split(layout, percent, children) => {
+                let split = match layout {
+                    Layout::SplitH => "horizontal",
+                    _ => "vertical",
+                };
+                self.push(cmd!([con_id=first] "split {}", split));
+                self.push(cmd!([con_id=first] "layout {}", layout));
+                // Splitting made a new container around the window, which is marked so that
+                // the other children can be moved into it.
+                let mark = self.mark(parent, *percent, last);
+                self.push(cmd!([con_id=first] "focus"));
+                self.push(cmd!("focus parent"));
+                self.push(command::add_mark(mark.clone()));
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        let window = child.first_window();
+                        self.push(cmd!([con_id=window] "move container to mark {}", quote(&mark)));
+                    }
+                    self.build(child, *layout, i + 1 == children.len());
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, command: Command) {
+        self.commands.push(command.to_string());
+    }
+}
+
+impl SavedLayout {
+    /// Snapshot a workspace node from GET_TREE.
+    pub fn from_workspace(workspace: &JsonValue) -> Option<Self> {
+        Some(Self {
+            workspace: workspace.name()?.to_owned(),
+            layout: workspace["layout"].as_str()?.parse().ok()?,
+            children: workspace["nodes"]
+                .as_array()?
+                .iter()
+                .filter_map(SavedNode::from_json)
+                .collect(),
+        })
+    }
+
+    /// Snapshot the workspace named `workspace`, `None` if there is none.
+    pub fn save<C: SwayClientJson>(client: &mut C, workspace: &str) -> Result<Option<Self>> {
+        let tree = client.get_tree_json()?;
+        Ok(tree::nodes(&tree)
+            .find(|node| node.kind == NodeKind::Workspace && node.workspace == Some(workspace))
+            .and_then(|node| Self::from_workspace(node.node)))
+    }
+
+    /// Snapshot every workspace except the scratchpad.
+    pub fn save_all<C: SwayClientJson>(client: &mut C) -> Result<Vec<Self>> {
+        let tree = client.get_tree_json()?;
+        Ok(tree::nodes(&tree)
+            .filter(|node| {
+                node.kind == NodeKind::Workspace && node.workspace != Some(SCRATCHPAD_WORKSPACE)
+            })
+            .filter_map(|node| Self::from_workspace(node.node))
+            .collect())
+    }
+
+    /// The commands which rebuild this layout from the windows in `tree`:
+    ///
+    /// 1. the windows are parked on a temporary workspace, which dissolves the splits they are
+    ///    in now;
+    /// 2. each top level container is rebuilt from its first window, by moving that to the
+    ///    workspace, splitting it, marking the new container and moving the other windows into
+    ///    it by that mark;
+    /// 3. every container is resized to its saved share of its parent;
+    /// 4. the marks are removed and focus goes back to where it was.
+    fn commands(&self, tree: &JsonValue) -> Vec<String> {
+        let live: HashMap<u64, Option<&str>> = tree::windows(tree)
+            .filter(|node| node.kind != NodeKind::Scratchpad)
+            .filter_map(|node| Some((node.id()?, node.app_identifier())))
+            .collect();
+        let resolved = resolve(&self.children, &live);
+        if resolved.is_empty() {
+            return Vec::new();
+        }
+        let mut builder = Builder::default();
+        let mut ids = Vec::new();
+        resolved.iter().for_each(|node| node.windows(&mut ids));
+        for id in ids {
+            builder
+                .push(cmd!([con_id=id] "move container to workspace {}", quote(PARKING_WORKSPACE)));
+        }
+        for (i, node) in resolved.iter().enumerate() {
+            let first = node.first_window();
+            builder.push(
+                cmd!([con_id=first] "move container to workspace {}", quote(&self.workspace)),
+            );
+            if i == 0 {
+                builder.push(cmd!([con_id=first] "layout {}", self.layout));
+            }
+            builder.build(node, self.layout, i + 1 == resolved.len());
+        }
+
+        let Builder {
+            mut commands,
+            marks,
+        } = builder;
+        for (mark, parent, percent) in &marks {
+            let dimension = match parent {
+                Layout::SplitH => "width",
+                Layout::SplitV => "height",
+                Layout::Tabbed | Layout::Stacking => continue,
+            };
+            if let Some(percent) = percent {
+                let resize = cmd!("resize set {} {} ppt", dimension, (percent * 100.0).round());
+                commands.push(
+                    resize
+                        .with_criteria(vec![con_mark(exact(mark))])
+                        .to_string(),
+                );
+            }
+        }
+        for (mark, _, _) in &marks {
+            commands.push(command::unmark(mark.as_str()).to_string());
+        }
+        if let Some(focused) = tree::nodes(tree).find(|node| node.node.is_focused()) {
+            if let Some(id) = focused.id().filter(|_| focused.kind.is_container()) {
+                commands.push(cmd!([con_id=id] "focus").to_string());
+            }
+        }
+        commands
+    }
+
+    /// Rebuild this layout from the windows which are open now. Saved windows which can't be
+    /// found are skipped, and windows on the workspace which aren't part of the layout are left
+    /// after it.
+    pub fn restore<C: SwayClientJson>(&self, client: &mut C) -> Result<()> {
+        let tree = client.get_tree_json()?;
+        let commands = self.commands(&tree);
+        if !commands.is_empty() {
+            client.run(commands.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn apply_defaults() {
+        let mut layouts = LayoutDefaults::new();
+        layouts.set("chat", Layout::Tabbed);
+        let on = |ws: &'static str, count: usize| move |_| Ok(Some((ws.to_string(), count)));
+
+        let init = |name: &str| json!({"change": "init", "current": {"id": 1, "name": name, "focused": true}});
+        assert_eq!(
+            layouts
+                .react(IpcEvent::Workspace, &init("chat"), on("chat", 0))
+                .unwrap(),
+            Some(cmd!("layout tabbed"))
+        );
+        assert_eq!(
+            layouts
+                .react(IpcEvent::Workspace, &init("web"), on("web", 0))
+                .unwrap(),
+            None
+        );
+
+        let new = json!({"change": "new", "container": {"id": 5, "type": "con"}});
+        assert_eq!(
+            layouts
+                .react(IpcEvent::Window, &new, on("chat", 1))
+                .unwrap(),
+            Some(cmd!([con_id=5] "layout tabbed"))
+        );
+        assert_eq!(
+            layouts
+                .react(IpcEvent::Window, &new, on("chat", 2))
+                .unwrap(),
+            None
+        );
+        assert_eq!("splitv".parse::<Layout>(), Ok(Layout::SplitV));
+        assert!("grid".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let window = |id: u64, app: &str, percent: f64| json!({"id": id, "type": "con", "app_id": app, "name": app, "percent": percent, "nodes": []});
+        let workspace = json!({"id": 2, "type": "workspace", "name": "1", "layout": "splith", "nodes": [
+            window(10, "foot", 0.5),
+            {"id": 3, "type": "con", "layout": "splitv", "percent": 0.5, "nodes": [
+                window(11, "firefox", 0.7),
+                window(12, "mpv", 0.3),
+            ]},
+            {"id": 4, "type": "con", "layout": "splitv", "nodes": []},
+        ]});
+        let saved = SavedLayout::from_workspace(&workspace).unwrap();
+        assert_eq!(saved.children.len(), 2);
+        let json = serde_json::to_value(&saved).unwrap();
+        assert_eq!(json["children"][1]["split"]["layout"], "splitv");
+        assert_eq!(serde_json::from_value::<SavedLayout>(json).unwrap(), saved);
+
+        // Firefox was restarted, and mpv is focused.
+        let mut mpv = window(12, "mpv", 1.0);
+        mpv["focused"] = true.into();
+        let tree = json!({"id": 1, "type": "root", "nodes": [{"id": 5, "type": "output", "name": "DP-1", "nodes": [
+            {"id": 6, "type": "workspace", "name": "2", "layout": "splith", "nodes": [
+                window(21, "firefox", 0.5), window(10, "foot", 0.5), mpv,
+            ]},
+        ]}]});
+        assert_eq!(
+            saved.commands(&tree),
+            vec![
+                r#"[con_id="10"] move container to workspace "__ksway_layout""#,
+                r#"[con_id="21"] move container to workspace "__ksway_layout""#,
+                r#"[con_id="12"] move container to workspace "__ksway_layout""#,
+                r#"[con_id="10"] move container to workspace "1""#,
+                r#"[con_id="10"] layout splith"#,
+                r#"[con_id="10"] mark --add "_ksway_layout_0""#,
+                r#"[con_id="21"] move container to workspace "1""#,
+                r#"[con_id="21"] split vertical"#,
+                r#"[con_id="21"] layout splitv"#,
+                r#"[con_id="21"] focus"#,
+                "focus parent",
+                r#"mark --add "_ksway_layout_1""#,
+                r#"[con_id="21"] mark --add "_ksway_layout_2""#,
+                r#"[con_id="12"] move container to mark "_ksway_layout_1""#,
+                r#"[con_id="12"] mark --add "_ksway_layout_3""#,
+                r#"[con_mark="^_ksway_layout_0$"] resize set width 50 ppt"#,
+                r#"[con_mark="^_ksway_layout_2$"] resize set height 70 ppt"#,
+                r#"unmark "_ksway_layout_0""#,
+                r#"unmark "_ksway_layout_1""#,
+                r#"unmark "_ksway_layout_2""#,
+                r#"unmark "_ksway_layout_3""#,
+                r#"[con_id="12"] focus"#,
+            ]
+        );
+    }
+}
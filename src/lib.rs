@@ -1,30 +1,281 @@
+pub mod app;
+pub mod archive;
+#[cfg(feature = "tokio")]
+pub mod async_client;
+pub mod bar;
+pub mod broadcast;
+pub mod cancel;
+pub mod capture;
+pub mod checkpoint;
 pub mod client;
-
-pub use client::Client;
+pub mod coalesce;
+pub mod collapse;
+pub mod core;
+pub mod dim;
+pub mod dispatch;
+pub mod dnd;
+pub mod dsl;
+pub mod events;
+pub mod focus;
+pub mod focus_history;
+pub mod fullscreen;
+pub mod geometry;
+pub mod hooks;
+pub mod layout;
+#[cfg(feature = "mlua")]
+pub mod lua;
+pub mod outputs;
+pub mod persist;
+pub mod process;
+pub mod query;
+pub mod reply;
+pub mod restrict;
+pub mod rules;
+pub mod shared;
+pub mod sniff;
+pub mod testing;
+#[cfg(feature = "threaded")]
+pub mod threaded;
+pub mod tree;
+pub mod version;
+pub mod workspace;
+
+pub use cancel::CancelToken;
+pub use client::{Client, PollOutcome, ReconnectPolicy, Reply, Timeouts, Warning};
+pub use shared::SharedClient;
 
 use itertools::join;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 pub use serde_json::Value as JsonValue;
-use std::io::{self, Write};
+use std::io;
 use std::path::PathBuf;
 
+/// The kind of an event. This is what you subscribe to, and it serializes to the name sway
+/// expects in a SUBSCRIBE payload, see `name()`. The discriminant is the event's position in
+/// the protocol, use `EventCode` for the value which goes over the wire.
 // Naming convention: https://docs.microsoft.com/en-us/dotnet/standard/design-guidelines/enum
-#[derive(Serialize, Debug, num_derive::FromPrimitive)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u32)]
-pub enum IpcEvent {
-    Workspace = 0x8000_0000,
-    Mode = 0x8000_0002,
-    Window = 0x8000_0003,
-    BarconfigUpdate = 0x8000_0004,
-    Binding = 0x8000_0005,
-    Shutdown = 0x8000_0006,
-    Tick = 0x8000_0007,
-    BarStatusUpdate = 0x8000_0014,
-}
-
-#[derive(Debug)]
-enum IpcCommandCode {
+pub enum EventType {
+    Workspace = 0x00,
+    /// Sent when outputs change. The payload is only `{"change": "unspecified"}`, so query
+    /// GET_OUTPUTS for the details.
+    Output = 0x01,
+    Mode = 0x02,
+    Window = 0x03,
+    BarconfigUpdate = 0x04,
+    Binding = 0x05,
+    Shutdown = 0x06,
+    Tick = 0x07,
+    /// Sway only. Sway calls it `bar_state_update`.
+    BarStatusUpdate = 0x14,
+    /// Sway only. Sent when input devices are added, removed or reconfigured.
+    Input = 0x15,
+}
+
+/// Kept so that existing code continues to work. New code should prefer `EventType`.
+pub use EventType as IpcEvent;
+
+impl EventType {
+    pub const ALL: &'static [EventType] = &[
+        EventType::Workspace,
+        EventType::Output,
+        EventType::Mode,
+        EventType::Window,
+        EventType::BarconfigUpdate,
+        EventType::Binding,
+        EventType::Shutdown,
+        EventType::Tick,
+        EventType::BarStatusUpdate,
+        EventType::Input,
+    ];
+
+    /// The name sway uses for every event type, in SUBSCRIBE payloads and
+    /// `core::event_code_to_name()`.
+    const NAMES: &'static [(EventType, &'static str)] = &[
+        (EventType::Workspace, "workspace"),
+        (EventType::Output, "output"),
+        (EventType::Mode, "mode"),
+        (EventType::Window, "window"),
+        (EventType::BarconfigUpdate, "barconfig_update"),
+        (EventType::Binding, "binding"),
+        (EventType::Shutdown, "shutdown"),
+        (EventType::Tick, "tick"),
+        (EventType::BarStatusUpdate, "bar_state_update"),
+        (EventType::Input, "input"),
+    ];
+
+    /// The name sway uses for this event type, e.g. `window`.
+    pub fn name(self) -> &'static str {
+        let (_, name) = Self::NAMES
+            .iter()
+            .find(|(event, _)| *event == self)
+            .expect("every event type has a name");
+        name
+    }
+
+    /// The event type sway calls `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, known)| *known == name)
+            .map(|(event, _)| *event)
+    }
+
+    /// The type code used by event frames of this kind.
+    pub fn code(self) -> EventCode {
+        EventCode(EventCode::EVENT_BIT | self as u32)
+    }
+
+    /// Returns `None` for events this crate doesn't know about yet.
+    pub fn from_code(code: EventCode) -> Option<Self> {
+        Self::ALL.iter().copied().find(|event| event.code() == code)
+    }
+}
+
+/// The type code of an event frame as it appears on the wire, i.e. the event's position with the
+/// high bit set. Unlike `EventType` this can represent events that this crate doesn't know about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventCode(u32);
+
+impl EventCode {
+    /// Set on every event frame's type code, and never on a command reply's.
+    pub const EVENT_BIT: u32 = 0x8000_0000;
+
+    /// Returns `None` if `code` is the type of a command reply rather than an event.
+    pub fn from_raw(code: u32) -> Option<Self> {
+        if core::MessageKind::is_event(code) {
+            Some(EventCode(code))
+        } else {
+            None
+        }
+    }
+
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub fn event_type(self) -> Option<EventType> {
+        EventType::from_code(self)
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        EventType::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown event type `{}`", name)))
+    }
+}
+
+impl From<EventType> for EventCode {
+    fn from(event: EventType) -> Self {
+        event.code()
+    }
+}
+
+impl std::convert::TryFrom<EventCode> for EventType {
+    type Error = EventCode;
+
+    fn try_from(code: EventCode) -> std::result::Result<Self, EventCode> {
+        EventType::from_code(code).ok_or(code)
+    }
+}
+
+bitflags::bitflags! {
+    /// A set of event types, e.g. `EventMask::WINDOW | EventMask::TICK`, which is what the
+    /// `subscribe()` methods take. It serializes to the names sway expects in a SUBSCRIBE
+    /// payload, in the order of `EventType::ALL`. A `Vec<EventType>` converts into one too.
+    #[derive(Default)]
+    pub struct EventMask: u32 {
+        const WORKSPACE = 1 << EventType::Workspace as u32;
+        const OUTPUT = 1 << EventType::Output as u32;
+        const MODE = 1 << EventType::Mode as u32;
+        const WINDOW = 1 << EventType::Window as u32;
+        const BARCONFIG_UPDATE = 1 << EventType::BarconfigUpdate as u32;
+        const BINDING = 1 << EventType::Binding as u32;
+        const SHUTDOWN = 1 << EventType::Shutdown as u32;
+        const TICK = 1 << EventType::Tick as u32;
+        const BAR_STATUS_UPDATE = 1 << EventType::BarStatusUpdate as u32;
+        const INPUT = 1 << EventType::Input as u32;
+    }
+}
+
+impl EventMask {
+    pub const fn from_event(event: EventType) -> Self {
+        Self::from_bits_truncate(1 << event as u32)
+    }
+
+    pub fn contains_event(self, event: EventType) -> bool {
+        self.contains(Self::from_event(event))
+    }
+
+    /// The event types in the set, in the order of `EventType::ALL`.
+    pub fn events(self) -> impl Iterator<Item = EventType> {
+        EventType::ALL
+            .iter()
+            .copied()
+            .filter(move |&event| self.contains_event(event))
+    }
+}
+
+impl From<EventType> for EventMask {
+    fn from(event: EventType) -> Self {
+        Self::from_event(event)
+    }
+}
+
+impl std::iter::FromIterator<EventType> for EventMask {
+    fn from_iter<I: IntoIterator<Item = EventType>>(events: I) -> Self {
+        events
+            .into_iter()
+            .fold(Self::empty(), |mask, event| mask | Self::from_event(event))
+    }
+}
+
+impl From<Vec<EventType>> for EventMask {
+    fn from(events: Vec<EventType>) -> Self {
+        events.into_iter().collect()
+    }
+}
+
+impl From<&[EventType]> for EventMask {
+    fn from(events: &[EventType]) -> Self {
+        events.iter().copied().collect()
+    }
+}
+
+impl Serialize for EventMask {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.events())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventMask {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        Vec::<EventType>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// The type code of a request, and of its reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcCommandCode {
     RunCommand = 0,
     GetWorkspaces = 1,
     Subscribe = 2,
@@ -36,61 +287,53 @@ enum IpcCommandCode {
     GetBindingModes = 8,
     GetConfig = 9,
     SendTick = 10,
+    GetBindingState = 12,
+    GetInputs = 100,
+    GetSeats = 101,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IpcCommand {
     Run(String),
+    /// The ids of the configured bars.
     GetBarConfig,
+    /// The configuration of the bar with this id.
+    GetBarConfigFor(String),
     GetBindingModes,
+    /// Sway only.
+    GetBindingState,
     GetConfig,
+    /// Sway only.
+    GetInputs,
     GetMarks,
     GetOutputs,
+    /// Sway only.
+    GetSeats,
     GetTree,
     GetVersion,
     GetWorkspaces,
     SendTick(Vec<u8>),
-    Subscribe(Vec<IpcEvent>),
+    Subscribe(EventMask),
 }
 
 impl IpcCommand {
-    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write_all(b"i3-ipc")?;
-        match self {
-            IpcCommand::Run(command) => {
-                let payload = command.as_bytes();
-                w.write_all(&(payload.len() as u32).to_ne_bytes())?;
-                w.write_all(&(self.code() as u32).to_ne_bytes())?;
-                w.write_all(payload)?;
-            }
-            IpcCommand::SendTick(payload) => {
-                w.write_all(&(payload.len() as u32).to_ne_bytes())?;
-                w.write_all(&(self.code() as u32).to_ne_bytes())?;
-                w.write_all(payload)?;
-            }
-            IpcCommand::Subscribe(events) => {
-                let mut payload = Vec::new();
-                serde_json::to_writer(&mut payload, &events)?;
-                w.write_all(&(payload.len() as u32).to_ne_bytes())?;
-                w.write_all(&(self.code() as u32).to_ne_bytes())?;
-                w.write_all(&payload)?;
-            }
-            _ => {
-                w.write_all(&0u32.to_ne_bytes())?;
-                w.write_all(&(self.code() as u32).to_ne_bytes())?;
-            }
-        }
-        Ok(())
+    /// Whether sending this twice is harmless: queries only read, and SUBSCRIBE only adds to
+    /// what the connection is subscribed to.
+    fn is_idempotent(&self) -> bool {
+        !matches!(self, IpcCommand::Run(_) | IpcCommand::SendTick(_))
     }
 
     fn code(&self) -> IpcCommandCode {
         use IpcCommandCode::*;
         match self {
-            IpcCommand::GetBarConfig => GetBarConfig,
+            IpcCommand::GetBarConfig | IpcCommand::GetBarConfigFor(_) => GetBarConfig,
             IpcCommand::GetBindingModes => GetBindingModes,
+            IpcCommand::GetBindingState => GetBindingState,
             IpcCommand::GetConfig => GetConfig,
+            IpcCommand::GetInputs => GetInputs,
             IpcCommand::GetMarks => GetMarks,
             IpcCommand::GetOutputs => GetOutputs,
+            IpcCommand::GetSeats => GetSeats,
             IpcCommand::GetTree => GetTree,
             IpcCommand::GetVersion => GetVersion,
             IpcCommand::GetWorkspaces => GetWorkspaces,
@@ -105,38 +348,167 @@ impl IpcCommand {
 pub enum Error {
     /// Could not find or reliably guess a SWAYSOCK
     SockPathNotFound,
-    /// Generic error for subscription problems. Currently includes send failure on the channel
-    /// used to contain subscription events.
+    /// An event couldn't be delivered because the receiver of the subscription channel was
+    /// dropped.
     SubscriptionError,
-    /// Error thrown when you try to subscribe multiple times on a single connection, which is
-    /// not supported.
+    /// Error thrown when you try to subscribe more than once with `Client::subscribe()` or
+    /// `Client::subscribe_typed()` on a single connection, see `Client::subscribe_more()`.
     AlreadySubscribed,
+    /// A frame didn't start with the `i3-ipc` magic string, which means we are out of sync with
+    /// the stream.
+    InvalidHeader,
+    /// The reply to a command was longer than the limit, see `core::Decoder`, and was skipped.
+    #[display(
+        fmt = "skipped a reply of {} bytes, more than the limit of {}",
+        len,
+        max
+    )]
+    OversizedReply {
+        len: u32,
+        max: u32,
+    },
+    /// No reply arrived in time, see `Timeouts`.
+    Timeout,
+    /// Sway closed the connection, e.g. because it exited.
+    Disconnected,
+    /// A `CancelToken` was cancelled.
+    Cancelled,
+    /// The reply to a command had the type of a different command, which means the stream is
+    /// out of sync. See `Client::ipc_with_code()` to get the reply anyway.
+    #[display(
+        fmt = "expected a reply of type {}, but received type {}",
+        expected,
+        received
+    )]
+    UnexpectedReply {
+        expected: u32,
+        received: u32,
+        /// The payload of the reply which was received.
+        reply: Vec<u8>,
+    },
+    /// The reply to `command`, e.g. `GetTree`, couldn't be parsed.
+    #[display(fmt = "invalid {:?} reply: {}", command, error)]
+    InvalidReply {
+        command: IpcCommandCode,
+        reply: Vec<u8>,
+        error: serde_json::Error,
+    },
+    /// A `command` request of `SwayClientJson` failed with `error`, which is `Io`, `Timeout` or
+    /// `Disconnected`.
+    #[display(fmt = "{:?} request failed: {}", command, error)]
+    Request {
+        command: IpcCommandCode,
+        error: Box<Error>,
+    },
+    /// Sway ran `command`, or a part of it, and reported `error`, see
+    /// `SwayClientJson::run_checked()`.
+    #[display(fmt = "`{}` failed: {}", command, error)]
+    CommandFailed {
+        command: String,
+        error: String,
+    },
+    /// A `RestrictedClient` refused to send `request`, because `what` isn't allowed: the verb
+    /// of one of its commands, or the type of the query.
+    #[display(fmt = "{} is not permitted: {}", what, request)]
+    NotPermitted {
+        what: String,
+        request: String,
+    },
     Io(io::Error),
     Json(serde_json::Error),
+    Parse(ParseError),
 }
 
 impl std::error::Error for Error {}
 
+/// Failure to parse user supplied text, such as criteria or `ksway::dsl` actions.
+#[derive(derive_more::Display, Debug, Clone, PartialEq)]
+#[display(fmt = "{}", "_0")]
+pub struct ParseError(pub String);
+
+impl std::error::Error for ParseError {}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Try to guess the value of SWAYSOCK by first checking for the environment variable or using the
-/// most recently created sock file at /run/user/$UID/sway-ipc.*.sock. This is useful for the
-/// situation where a command is being run from systemd or outside of the GUI environment.
+/// The uid this process runs as, from the owner of `/proc/self`. `None` without procfs.
+fn current_uid() -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata("/proc/self").ok().map(|m| m.uid())
+}
+
+/// The sway sockets of the current user, most recently created first: those in `/run/user/$UID`
+/// and in `XDG_RUNTIME_DIR`, which is usually the same directory. Sockets which aren't owned by
+/// the current user are left out, so another user's sway is never picked on a multi-user system.
+/// Useful to let the user choose when more than one sway is running, e.g. nested ones.
+pub fn list_sway_sockets() -> Vec<PathBuf> {
+    let uid = current_uid();
+    let dirs = uid
+        .map(|uid| PathBuf::from(format!("/run/user/{}", uid)))
+        .into_iter()
+        .chain(std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from));
+    sway_sockets_in(dirs, uid)
+}
+
+/// The sway sockets directly in `dirs` owned by `uid`, if known, most recently created first.
+fn sway_sockets_in<I>(dirs: I, uid: Option<u32>) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    use std::os::unix::fs::MetadataExt;
+    let mut sockets: Vec<_> = dirs
+        .into_iter()
+        .filter_map(|dir| {
+            globwalk::GlobWalkerBuilder::from_patterns(dir, &["sway-ipc.*.sock"])
+                .max_depth(1)
+                .build()
+                .ok()
+        })
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.into_path();
+            let metadata = path.metadata().ok()?;
+            if uid.is_some_and(|uid| metadata.uid() != uid) {
+                return None;
+            }
+            Some((metadata.modified().ok()?, path))
+        })
+        .collect();
+    sockets.sort_by(|a, b| b.cmp(a));
+    sockets.dedup_by(|a, b| a.1 == b.1);
+    sockets.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Try to guess the value of SWAYSOCK, in order:
+///
+/// - the SWAYSOCK environment variable;
+/// - the most recently created socket of the current user, see `list_sway_sockets()`;
+/// - what `sway --get-socketpath` prints.
+///
+/// This is useful for the situation where a command is being run from systemd or outside of the
+/// GUI environment.
+///
+/// SWAYSOCK is ignored if it points to a file which doesn't exist anymore, which happens to long
+/// running processes when sway is restarted, since the socket name contains sway's pid.
 pub fn guess_sway_socket_path() -> Result<PathBuf> {
-    match std::env::var("SWAYSOCK") {
-        Ok(path) => Ok(PathBuf::from(path)),
-        Err(_) => {
-            let entry = globwalk::glob("/run/user/*/sway-ipc.*.sock")
-                // Failed to get glob
-                .map_err(|_| Error::SockPathNotFound)?
-                .next()
-                // No entries found
-                .ok_or_else(|| Error::SockPathNotFound)?
-                // Failed to unwrap entry. oof
-                .map_err(|_| Error::SockPathNotFound)?;
-            Ok(entry.into_path())
+    if let Some(path) = std::env::var_os("SWAYSOCK").map(PathBuf::from) {
+        if path.exists() {
+            return Ok(path);
         }
     }
+    if let Some(path) = list_sway_sockets().into_iter().next() {
+        return Ok(path);
+    }
+    let output = std::process::Command::new("sway")
+        .arg("--get-socketpath")
+        .stderr(std::process::Stdio::null())
+        .output()
+        .map_err(|_| Error::SockPathNotFound)?;
+    let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if output.status.success() && path.exists() {
+        Ok(path)
+    } else {
+        Err(Error::SockPathNotFound)
+    }
 }
 
 pub trait HasIpc {
@@ -157,7 +529,14 @@ impl HasIpc for Client {
 }
 
 impl SwayClient for Client {}
-impl SwayClientJson for Client {}
+impl SwayClientJson for Client {
+    fn visit_tree<F: FnMut(&crate::tree::RawNode) -> crate::tree::Visit>(
+        &mut self,
+        visitor: F,
+    ) -> Result<()> {
+        self.visit_tree_streaming(visitor)
+    }
+}
 
 pub trait SwayClient: HasIpc {
     /// Alias for `client.ipc(ipc_command::run(...))`. Accepts any string as a parameter, which
@@ -180,14 +559,27 @@ pub trait SwayClient: HasIpc {
         self.ipc(crate::ipc_command::get_bar_config())
     }
 
+    fn get_bar_config_for(&mut self, id: &str) -> Result<Vec<u8>> {
+        self.ipc(crate::ipc_command::get_bar_config_for(id))
+    }
+
     fn get_binding_modes(&mut self) -> Result<Vec<u8>> {
         self.ipc(crate::ipc_command::get_binding_modes())
     }
 
+    /// The currently active binding mode.
+    fn get_binding_state(&mut self) -> Result<Vec<u8>> {
+        self.ipc(crate::ipc_command::get_binding_state())
+    }
+
     fn get_config(&mut self) -> Result<Vec<u8>> {
         self.ipc(crate::ipc_command::get_config())
     }
 
+    fn get_inputs(&mut self) -> Result<Vec<u8>> {
+        self.ipc(crate::ipc_command::get_inputs())
+    }
+
     fn get_marks(&mut self) -> Result<Vec<u8>> {
         self.ipc(crate::ipc_command::get_marks())
     }
@@ -196,6 +588,10 @@ pub trait SwayClient: HasIpc {
         self.ipc(crate::ipc_command::get_outputs())
     }
 
+    fn get_seats(&mut self) -> Result<Vec<u8>> {
+        self.ipc(crate::ipc_command::get_seats())
+    }
+
     fn get_tree(&mut self) -> Result<Vec<u8>> {
         self.ipc(crate::ipc_command::get_tree())
     }
@@ -209,37 +605,84 @@ pub trait SwayClient: HasIpc {
     }
 }
 
-mod json {
-    use super::{JsonValue, Result, SwayClient};
+/// Replies as `JsonValue`s, see `SwayClientJson`, and paths into them.
+pub mod json {
+    pub mod path;
 
-    pub fn preorder<T, F: FnMut(&JsonValue) -> Option<T>>(
-        value: &JsonValue,
-        visitor: &mut F,
-    ) -> Option<T> {
-        match visitor(value) {
-            None => (),
-            value => return value,
-        }
-        if let Some(obj) = value.as_object() {
-            for (_k, v) in obj.iter() {
-                match preorder(v, visitor) {
-                    None => (),
-                    value => return value,
-                }
-            }
-        } else if let Some(arr) = value.as_array() {
-            for v in arr.iter() {
-                match preorder(v, visitor) {
-                    None => (),
-                    value => return value,
-                }
-            }
+    use serde::de::DeserializeOwned;
+
+    use super::{reply, Error, IpcCommandCode, JsonValue, Result, SwayClient};
+
+    /// A window found in a GET_TREE reply, along with where it lives.
+    #[derive(Debug, Clone, Copy)]
+    pub struct WindowRef<'a> {
+        pub node: &'a JsonValue,
+        pub workspace: &'a str,
+        pub output: &'a str,
+    }
+
+    /// Collect every window in a GET_TREE reply along with its workspace and output, tiling and
+    /// floating alike, in tree order. See `tree::windows()`.
+    pub fn windows(tree: &JsonValue) -> Vec<WindowRef<'_>> {
+        crate::tree::windows(tree)
+            .filter_map(|node| {
+                Some(WindowRef {
+                    node: node.node,
+                    workspace: node.workspace?,
+                    output: node.output?,
+                })
+            })
+            .collect()
+    }
+
+    /// `(con_id, workspace name)` for every window in a GET_TREE reply, see `windows()`.
+    pub fn windows_by_workspace(tree: &JsonValue) -> Vec<(u64, String)> {
+        windows(tree)
+            .into_iter()
+            .filter_map(|window| Some((window.node["id"].as_u64()?, window.workspace.to_owned())))
+            .collect()
+    }
+
+    /// Parse the reply to a `command` request, failing with `Error::InvalidReply`. A failure of
+    /// the request itself, like a timeout, is wrapped in `Error::Request`. An empty payload is
+    /// treated as `null`, so it only parses into types which accept that, like `Option`.
+    fn reply_to_typed<T: DeserializeOwned>(
+        command: IpcCommandCode,
+        reply: Result<Vec<u8>>,
+    ) -> Result<T> {
+        let payload = reply.map_err(|error| match error {
+            Error::Io(_) | Error::Timeout | Error::Disconnected => Error::Request {
+                command,
+                error: Box::new(error),
+            },
+            error => error,
+        })?;
+        let parsed = if payload.is_empty() {
+            T::deserialize(JsonValue::Null)
+        } else {
+            serde_json::from_slice(&payload)
+        };
+        parsed.map_err(|error| Error::InvalidReply {
+            command,
+            reply: payload,
+            error,
+        })
+    }
+
+    /// Like `reply_to_typed()`, but an empty payload is an empty list.
+    fn reply_to_list<T: DeserializeOwned>(
+        command: IpcCommandCode,
+        reply: Result<Vec<u8>>,
+    ) -> Result<Vec<T>> {
+        match reply {
+            Ok(payload) if payload.is_empty() => Ok(Vec::new()),
+            reply => reply_to_typed(command, reply),
         }
-        None
     }
 
-    fn payload_to_json(payload: Vec<u8>) -> Result<JsonValue> {
-        Ok(serde_json::from_slice(&payload)?)
+    /// An empty payload gives `JsonValue::Null`.
+    fn reply_to_json(command: IpcCommandCode, reply: Result<Vec<u8>>) -> Result<JsonValue> {
+        reply_to_typed(command, reply)
     }
 
     pub trait SwayClientJson: SwayClient {
@@ -252,77 +695,261 @@ mod json {
         /// The raw bytes are returned in order to avoid dependency on any particular json
         /// implementation.
         fn run_json<T: ToString>(&mut self, command: T) -> Result<JsonValue> {
-            payload_to_json(self.run(command)?)
+            reply_to_json(IpcCommandCode::RunCommand, self.run(command))
+        }
+
+        /// Like `run()`, but parses the outcome of each command. A command which failed is only
+        /// reported in its outcome, see `run_checked()`.
+        fn run_typed<T: ToString>(&mut self, command: T) -> Result<Vec<reply::CommandOutcome>> {
+            reply_to_list(IpcCommandCode::RunCommand, self.run(command))
+        }
+
+        /// Like `run_typed()`, but fails with `Error::CommandFailed` along with sway's error
+        /// message if any of the commands failed.
+        fn run_checked<T: ToString>(&mut self, command: T) -> Result<Vec<reply::CommandOutcome>> {
+            let command = command.to_string();
+            let outcomes = self.run_typed(&command)?;
+            match outcomes.iter().find(|outcome| !outcome.success) {
+                Some(failed) => Err(Error::CommandFailed {
+                    command,
+                    error: failed.error.clone().unwrap_or_default(),
+                }),
+                None => Ok(outcomes),
+            }
         }
 
         fn get_bar_config_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_bar_config()?)
+            reply_to_json(IpcCommandCode::GetBarConfig, self.get_bar_config())
         }
 
         fn get_binding_modes_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_binding_modes()?)
+            reply_to_json(IpcCommandCode::GetBindingModes, self.get_binding_modes())
+        }
+
+        fn get_binding_state_json(&mut self) -> Result<JsonValue> {
+            reply_to_json(IpcCommandCode::GetBindingState, self.get_binding_state())
         }
 
         fn get_config_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_config()?)
+            reply_to_json(IpcCommandCode::GetConfig, self.get_config())
+        }
+
+        fn get_inputs_json(&mut self) -> Result<JsonValue> {
+            reply_to_json(IpcCommandCode::GetInputs, self.get_inputs())
         }
 
         fn get_marks_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_marks()?)
+            reply_to_json(IpcCommandCode::GetMarks, self.get_marks())
         }
 
         fn get_outputs_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_outputs()?)
+            reply_to_json(IpcCommandCode::GetOutputs, self.get_outputs())
+        }
+
+        fn get_seats_json(&mut self) -> Result<JsonValue> {
+            reply_to_json(IpcCommandCode::GetSeats, self.get_seats())
         }
 
         fn get_tree_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_tree()?)
+            reply_to_json(IpcCommandCode::GetTree, self.get_tree())
         }
 
         fn get_version_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_version()?)
+            reply_to_json(IpcCommandCode::GetVersion, self.get_version())
         }
 
         fn get_workspaces_json(&mut self) -> Result<JsonValue> {
-            payload_to_json(self.get_workspaces()?)
+            reply_to_json(IpcCommandCode::GetWorkspaces, self.get_workspaces())
+        }
+
+        /// The ids of the configured bars.
+        fn get_bar_ids_typed(&mut self) -> Result<Vec<String>> {
+            reply_to_list(IpcCommandCode::GetBarConfig, self.get_bar_config())
+        }
+
+        fn get_bar_config_typed(&mut self, id: &str) -> Result<reply::BarConfig> {
+            reply_to_typed(IpcCommandCode::GetBarConfig, self.get_bar_config_for(id))
+        }
+
+        fn get_binding_state_typed(&mut self) -> Result<reply::BindingState> {
+            reply_to_typed(IpcCommandCode::GetBindingState, self.get_binding_state())
+        }
+
+        /// The full config of every bar, fetching the ids first.
+        fn get_bar_configs(&mut self) -> Result<Vec<reply::BarConfig>> {
+            self.get_bar_ids_typed()?
+                .iter()
+                .map(|id| self.get_bar_config_typed(id))
+                .collect()
+        }
+
+        fn get_config_typed(&mut self) -> Result<reply::Config> {
+            reply_to_typed(IpcCommandCode::GetConfig, self.get_config())
+        }
+
+        fn get_inputs_typed(&mut self) -> Result<Vec<reply::Input>> {
+            reply_to_list(IpcCommandCode::GetInputs, self.get_inputs())
+        }
+
+        fn get_outputs_typed(&mut self) -> Result<Vec<reply::Output>> {
+            reply_to_list(IpcCommandCode::GetOutputs, self.get_outputs())
+        }
+
+        fn get_seats_typed(&mut self) -> Result<Vec<reply::Seat>> {
+            reply_to_list(IpcCommandCode::GetSeats, self.get_seats())
+        }
+
+        fn get_tree_typed(&mut self) -> Result<reply::Node> {
+            reply_to_typed(IpcCommandCode::GetTree, self.get_tree())
+        }
+
+        fn get_version_typed(&mut self) -> Result<reply::Version> {
+            reply_to_typed(IpcCommandCode::GetVersion, self.get_version())
+        }
+
+        fn get_workspaces_typed(&mut self) -> Result<Vec<reply::Workspace>> {
+            reply_to_list(IpcCommandCode::GetWorkspaces, self.get_workspaces())
+        }
+
+        /// Walk GET_TREE without building a `JsonValue` of it, see `tree::visit_reader()`. A
+        /// `Client` parses the reply as it reads it from the socket, and stops reading once the
+        /// visitor returns `Visit::Stop`, discarding the rest. Other clients read the whole reply
+        /// first.
+        fn visit_tree<F: FnMut(&crate::tree::RawNode) -> crate::tree::Visit>(
+            &mut self,
+            visitor: F,
+        ) -> Result<()> {
+            crate::tree::visit(&self.get_tree()?, visitor)
         }
 
         fn focused_workspace(&mut self) -> Result<Option<JsonValue>> {
-            Ok(self.get_workspaces_json()?.as_array().and_then(|arr| {
-                arr.iter()
-                    .find(|c| c["focused"].as_bool() == Some(true))
-                    .cloned()
-            }))
+            Ok(self
+                .get_workspaces_json()?
+                .as_array()
+                .and_then(|arr| arr.iter().find(|c| c.is_focused()).cloned()))
         }
 
         fn focused_window(&mut self) -> Result<Option<JsonValue>> {
             let tree_data = self.get_tree_json()?;
 
-            Ok(preorder(&tree_data, &mut |value| {
-                if value["focused"].as_bool() == Some(true) {
-                    return Some(value.clone());
-                }
-                None
-            }))
+            Ok(crate::tree::nodes(&tree_data)
+                .find(|node| node.node.is_focused())
+                .map(|node| node.node.clone()))
+        }
+
+        /// The container which has `mark`, with everything below it. Sway keeps each mark on at
+        /// most one container.
+        fn find_by_mark(&mut self, mark: &str) -> Result<Option<reply::Node>> {
+            Ok(self
+                .get_tree_typed()?
+                .iter()
+                .find(|node| node.marks.iter().any(|m| m == mark))
+                .cloned())
+        }
+    }
+
+    /// Typed accessors for the fields every node, workspace and output in a reply has, e.g.
+    /// `node.rect()?.width` instead of `node["rect"]["width"].as_i64()?`. Missing or mistyped
+    /// fields give `None`, or `false` for the flags.
+    pub trait SwayJsonExt {
+        fn rect(&self) -> Option<reply::Rect>;
+        fn con_id(&self) -> Option<u64>;
+        /// The `app_id` of Wayland windows only, see `tree::app_identifier()` for XWayland.
+        fn app_id(&self) -> Option<&str>;
+        fn name(&self) -> Option<&str>;
+        fn is_focused(&self) -> bool;
+        /// Whether this is a floating container, from its `type`. Windows nested in a floating
+        /// container aren't, see `tree::NodeKind::Floating` for those.
+        fn is_floating(&self) -> bool;
+    }
+
+    impl SwayJsonExt for JsonValue {
+        fn rect(&self) -> Option<reply::Rect> {
+            reply::Rect::from_node(self)
+        }
+
+        fn con_id(&self) -> Option<u64> {
+            self["id"].as_u64()
+        }
+
+        fn app_id(&self) -> Option<&str> {
+            self["app_id"].as_str()
+        }
+
+        fn name(&self) -> Option<&str> {
+            self["name"].as_str()
+        }
+
+        fn is_focused(&self) -> bool {
+            self["focused"].as_bool() == Some(true)
+        }
+
+        fn is_floating(&self) -> bool {
+            self["type"].as_str() == Some("floating_con")
         }
     }
 }
 
-pub use json::SwayClientJson;
+pub use json::{SwayClientJson, SwayJsonExt};
 
 pub mod criteria {
     use std::fmt::Display;
 
-    #[derive(derive_more::Display, Debug)]
+    use regex::Regex;
+
+    use crate::tree::{self, Node, NodeKind};
+    use crate::{JsonValue, ParseError};
+
+    /// Characters with a special meaning in the regular expressions sway compares values with.
+    const REGEX_SPECIAL: &str = "\\.+*?()|[]{}^$";
+
+    /// Escape the backslashes and double quotes in a value so that it stays within the quotes
+    /// sway reads it from, like `command::quote()`.
+    fn escape<T: Display>(value: &T) -> String {
+        value.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    /// Undo `escape()`. A backslash before anything else is kept as is.
+    fn unescape(value: &str) -> String {
+        let mut unescaped = String::with_capacity(value.len());
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some(&next)) if next == '\\' || next == '"' => {
+                    unescaped.push(next);
+                    chars.next();
+                }
+                (c, _) => unescaped.push(c),
+            }
+        }
+        unescaped
+    }
+
+    /// A regular expression matching exactly `value`, for the criteria which take one, e.g.
+    /// `title(exact("a.out (1)"))` to not also match `a-out (12)`.
+    pub fn exact(value: &str) -> String {
+        let mut regex = String::with_capacity(value.len() + 2);
+        regex.push('^');
+        for c in value.chars() {
+            if REGEX_SPECIAL.contains(c) {
+                regex.push('\\');
+            }
+            regex.push(c);
+        }
+        regex.push('$');
+        regex
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, PartialEq)]
     pub enum Criteria {
         /// Compare value against the app id. Can be a regular expression. If value is __focused__, then the app id must be the same as that of the
         /// currently focused window. app_id are specific to Wayland applications.
-        #[display(fmt = "app_id=\"{}\"", "_0")]
+        #[display(fmt = "app_id=\"{}\"", "escape(_0)")]
         AppId(OrFocused<String>),
 
         /// Compare value against the window class. Can be a regular expression. If value is __focused__, then the window class must be the same as
         /// that of the currently focused window. class are specific to X11 applications.
-        #[display(fmt = "class=\"{}\"", "_0")]
+        #[display(fmt = "class=\"{}\"", "escape(_0)")]
         Class(OrFocused<String>),
 
         /// Compare against the internal container ID, which you can find via IPC. If value is __focused__, then the id must be the same as that of the
@@ -331,7 +958,7 @@ pub mod criteria {
         ConId(OrFocused<u64>),
 
         /// Compare against the window marks. Can be a regular expression.
-        #[display(fmt = "con_mark=\"{}\"", "_0")]
+        #[display(fmt = "con_mark=\"{}\"", "escape(_0)")]
         ConMark(String),
 
         /// Matches floating windows.
@@ -344,12 +971,16 @@ pub mod criteria {
 
         /// Compare value against the window instance. Can be a regular expression. If value is __focused__, then the window instance must be the same
         /// as that of the currently focused window.
-        #[display(fmt = "instance=\"{}\"", "_0")]
+        #[display(fmt = "instance=\"{}\"", "escape(_0)")]
         Instance(OrFocused<String>),
 
+        /// Compare value against the process ID of the window. Must be numeric.
+        #[display(fmt = "pid=\"{}\"", "_0")]
+        Pid(u64),
+
         /// Compare value against the window shell, such as "xdg_shell" or "xwayland".  Can be a regular expression. If value is __focused__, then the
         /// shell must be the same as that of the currently focused window.
-        #[display(fmt = "shell=\"{}\"", "_0")]
+        #[display(fmt = "shell=\"{}\"", "escape(_0)")]
         Shell(OrFocused<String>),
 
         /// Matches tiling windows.
@@ -358,32 +989,123 @@ pub mod criteria {
 
         /// Compare against the window title. Can be a regular expression. If value is __focused__, then the window title must be the same as that of
         /// the currently focused window.
-        #[display(fmt = "title=\"{}\"", "_0")]
+        #[display(fmt = "title=\"{}\"", "escape(_0)")]
         Title(OrFocused<String>),
 
-        /// Compares the urgent state of the window. Can be "first", "last", "latest", "newest", "oldest" or "recent".
-        // TODO make enum
+        /// Compares the urgent state of the window, see `Urgency`.
         #[display(fmt = "urgent=\"{}\"", "_0")]
-        Urgent(String),
+        Urgent(Urgency),
 
         /// Compare against the window role (WM_WINDOW_ROLE). Can be a regular expression. If value is __focused__, then the window role must be the
         /// same as that of the currently focused window.
-        #[display(fmt = "window_role=\"{}\"", "_0")]
+        #[display(fmt = "window_role=\"{}\"", "escape(_0)")]
         WindowRole(OrFocused<String>),
 
-        /// Compare against the window type (_NET_WM_WINDOW_TYPE). Possible values are normal, dialog, utility, toolbar, splash, menu, dropdown_menu,
-        /// popup_menu, tooltip and notification.
-        // TODO make enum
-        #[display(fmt = "window_type=\"{}\"", "_0")]
-        WindowType(String),
+        /// Compare against the window type (_NET_WM_WINDOW_TYPE), see `WindowType`.
+        #[display(fmt = "window_type=\"{}\"", "escape(_0)")]
+        WindowType(WindowType),
 
         /// Compare against the workspace name for this view. Can be a regular expression. If the value is __focused__, then all the views on the cur‐
         /// rently focused workspace matches.
-        #[display(fmt = "workspace=\"{}\"", "_0")]
+        #[display(fmt = "workspace=\"{}\"", "escape(_0)")]
         Workspace(OrFocused<String>),
     }
 
-    #[derive(derive_more::Display, Debug)]
+    /// Which of the urgent windows the `urgent` criteria matches. Strings convert into one, with
+    /// values sway doesn't list ending up in `Other`.
+    #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+    pub enum Urgency {
+        /// The window which became urgent first, same as `Oldest`.
+        #[display(fmt = "first")]
+        First,
+        /// The window which became urgent last, same as `Latest`, `Newest` and `Recent`.
+        #[display(fmt = "last")]
+        Last,
+        #[display(fmt = "latest")]
+        Latest,
+        #[display(fmt = "newest")]
+        Newest,
+        #[display(fmt = "oldest")]
+        Oldest,
+        #[display(fmt = "recent")]
+        Recent,
+        #[display(fmt = "{}", "_0")]
+        Other(String),
+    }
+
+    impl From<&str> for Urgency {
+        fn from(s: &str) -> Self {
+            match s {
+                "first" => Urgency::First,
+                "last" => Urgency::Last,
+                "latest" => Urgency::Latest,
+                "newest" => Urgency::Newest,
+                "oldest" => Urgency::Oldest,
+                "recent" => Urgency::Recent,
+                other => Urgency::Other(other.to_owned()),
+            }
+        }
+    }
+
+    impl From<String> for Urgency {
+        fn from(s: String) -> Self {
+            Urgency::from(s.as_str())
+        }
+    }
+
+    /// The X11 window type (_NET_WM_WINDOW_TYPE) the `window_type` criteria matches. Strings
+    /// convert into one, with types sway doesn't list ending up in `Other`.
+    #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+    pub enum WindowType {
+        #[display(fmt = "normal")]
+        Normal,
+        #[display(fmt = "dialog")]
+        Dialog,
+        #[display(fmt = "utility")]
+        Utility,
+        #[display(fmt = "toolbar")]
+        Toolbar,
+        #[display(fmt = "splash")]
+        Splash,
+        #[display(fmt = "menu")]
+        Menu,
+        #[display(fmt = "dropdown_menu")]
+        DropdownMenu,
+        #[display(fmt = "popup_menu")]
+        PopupMenu,
+        #[display(fmt = "tooltip")]
+        Tooltip,
+        #[display(fmt = "notification")]
+        Notification,
+        #[display(fmt = "{}", "_0")]
+        Other(String),
+    }
+
+    impl From<&str> for WindowType {
+        fn from(s: &str) -> Self {
+            match s {
+                "normal" => WindowType::Normal,
+                "dialog" => WindowType::Dialog,
+                "utility" => WindowType::Utility,
+                "toolbar" => WindowType::Toolbar,
+                "splash" => WindowType::Splash,
+                "menu" => WindowType::Menu,
+                "dropdown_menu" => WindowType::DropdownMenu,
+                "popup_menu" => WindowType::PopupMenu,
+                "tooltip" => WindowType::Tooltip,
+                "notification" => WindowType::Notification,
+                other => WindowType::Other(other.to_owned()),
+            }
+        }
+    }
+
+    impl From<String> for WindowType {
+        fn from(s: String) -> Self {
+            WindowType::from(s.as_str())
+        }
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, PartialEq)]
     pub enum OrFocused<T> {
         #[display(fmt = "__focused__")]
         Focused,
@@ -419,6 +1141,310 @@ pub mod criteria {
         OrFocused::Focused
     }
 
+    impl std::str::FromStr for Criteria {
+        type Err = ParseError;
+
+        /// Parse a single criteria in the syntax sway uses between the brackets, e.g.
+        /// `app_id="firefox"`, `con_id=__focused__` or `floating`. Quotes around the value are
+        /// optional, and escaped quotes within them are unescaped.
+        fn from_str(s: &str) -> Result<Self, ParseError> {
+            let invalid = || ParseError(format!("invalid criteria: {}", s));
+            let (key, value) = match s.find('=') {
+                Some(i) => {
+                    let value = &s[i + 1..];
+                    let value =
+                        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                            unescape(&value[1..value.len() - 1])
+                        } else {
+                            value.to_owned()
+                        };
+                    (&s[..i], Some(value))
+                }
+                None => (s, None),
+            };
+            let text = |value: &str| -> OrFocused<String> {
+                match value {
+                    "__focused__" => OrFocused::Focused,
+                    value => OrFocused::Value(value.to_owned()),
+                }
+            };
+            let number = |value: &str| value.parse::<u64>().map_err(|_| invalid());
+            Ok(match (key, value.as_deref()) {
+                ("floating", None) => Criteria::Floating,
+                ("tiling", None) => Criteria::Tiling,
+                ("app_id", Some(v)) => Criteria::AppId(text(v)),
+                ("class", Some(v)) => Criteria::Class(text(v)),
+                ("con_id", Some("__focused__")) => Criteria::ConId(OrFocused::Focused),
+                ("con_id", Some(v)) => Criteria::ConId(OrFocused::Value(number(v)?)),
+                ("con_mark", Some(v)) => Criteria::ConMark(v.to_owned()),
+                ("id", Some(v)) => Criteria::Id(number(v)?),
+                ("instance", Some(v)) => Criteria::Instance(text(v)),
+                ("pid", Some(v)) => Criteria::Pid(number(v)?),
+                ("shell", Some(v)) => Criteria::Shell(text(v)),
+                ("title", Some(v)) => Criteria::Title(text(v)),
+                ("urgent", Some(v)) => Criteria::Urgent(v.into()),
+                ("window_role", Some(v)) => Criteria::WindowRole(text(v)),
+                ("window_type", Some(v)) => Criteria::WindowType(v.into()),
+                ("workspace", Some(v)) => Criteria::Workspace(text(v)),
+                _ => return Err(invalid()),
+            })
+        }
+    }
+
+    impl Criteria {
+        /// Build criteria from a JSON object such as `{"app_id": "kitty", "floating": true}`,
+        /// e.g. from a rules file. Keys are the same as in sway's syntax. `floating` and `tiling`
+        /// must be `true`, ids must be numbers, everything else a string, and any value which
+        /// can be `__focused__` may be that string. Anything else is an error, including
+        /// unknown keys and criteria which conflict with each other.
+        pub fn from_json(value: &JsonValue) -> Result<Vec<Criteria>, ParseError> {
+            let object = value
+                .as_object()
+                .ok_or_else(|| ParseError(format!("criteria must be an object: {}", value)))?;
+            let mut criteria: Vec<Criteria> = Vec::with_capacity(object.len());
+            for (key, value) in object {
+                let invalid = |expected: &str| {
+                    ParseError(format!(
+                        "invalid criteria {}: expected {}, got {}",
+                        key, expected, value
+                    ))
+                };
+                let string = || value.as_str().ok_or_else(|| invalid("a string"));
+                let text = || -> Result<OrFocused<String>, ParseError> {
+                    Ok(match string()? {
+                        "__focused__" => OrFocused::Focused,
+                        s => OrFocused::Value(s.to_owned()),
+                    })
+                };
+                let number = || value.as_u64().ok_or_else(|| invalid("a number"));
+                let flag = || match value {
+                    JsonValue::Bool(true) => Ok(()),
+                    _ => Err(invalid("true")),
+                };
+                let c = match key.as_str() {
+                    "floating" => flag().map(|_| Criteria::Floating)?,
+                    "tiling" => flag().map(|_| Criteria::Tiling)?,
+                    "app_id" => Criteria::AppId(text()?),
+                    "class" => Criteria::Class(text()?),
+                    "con_id" if value == "__focused__" => Criteria::ConId(OrFocused::Focused),
+                    "con_id" => Criteria::ConId(OrFocused::Value(
+                        number().map_err(|_| invalid("a number or \"__focused__\""))?,
+                    )),
+                    "con_mark" => Criteria::ConMark(string()?.to_owned()),
+                    "id" => Criteria::Id(number()?),
+                    "instance" => Criteria::Instance(text()?),
+                    "pid" => Criteria::Pid(number()?),
+                    "shell" => Criteria::Shell(text()?),
+                    "title" => Criteria::Title(text()?),
+                    "urgent" => Criteria::Urgent(string()?.into()),
+                    "window_role" => Criteria::WindowRole(text()?),
+                    "window_type" => Criteria::WindowType(string()?.into()),
+                    "workspace" => Criteria::Workspace(text()?),
+                    _ => return Err(ParseError(format!("unknown criteria: {}", key))),
+                };
+                if let Some(other) = criteria.iter().find(|other| other.conflicts_with(&c)) {
+                    return Err(ParseError(format!(
+                        "criteria {} conflicts with {}",
+                        c, other
+                    )));
+                }
+                criteria.push(c);
+            }
+            Ok(criteria)
+        }
+
+        /// The key of this criteria in sway's syntax, e.g. `con_id`.
+        pub fn key(&self) -> &'static str {
+            match self {
+                Criteria::AppId(_) => "app_id",
+                Criteria::Class(_) => "class",
+                Criteria::ConId(_) => "con_id",
+                Criteria::ConMark(_) => "con_mark",
+                Criteria::Floating => "floating",
+                Criteria::Id(_) => "id",
+                Criteria::Instance(_) => "instance",
+                Criteria::Pid(_) => "pid",
+                Criteria::Shell(_) => "shell",
+                Criteria::Tiling => "tiling",
+                Criteria::Title(_) => "title",
+                Criteria::Urgent(_) => "urgent",
+                Criteria::WindowRole(_) => "window_role",
+                Criteria::WindowType(_) => "window_type",
+                Criteria::Workspace(_) => "workspace",
+            }
+        }
+
+        /// Whether both criteria can't be in the same set, because sway only keeps one value per
+        /// key, or because they contradict each other like `floating` and `tiling`.
+        pub fn conflicts_with(&self, other: &Criteria) -> bool {
+            match (self, other) {
+                (Criteria::Floating, Criteria::Tiling) | (Criteria::Tiling, Criteria::Floating) => {
+                    true
+                }
+                _ => self.key() == other.key(),
+            }
+        }
+
+        /// The string property of a window node which this criteria compares against.
+        fn string_property<'a>(&self, node: &'a JsonValue) -> Option<&'a str> {
+            let properties = &node["window_properties"];
+            match self {
+                Criteria::AppId(_) => node["app_id"].as_str(),
+                Criteria::Class(_) => properties["class"].as_str(),
+                Criteria::Instance(_) => properties["instance"].as_str(),
+                Criteria::Shell(_) => node["shell"].as_str(),
+                Criteria::Title(_) => node["name"].as_str(),
+                Criteria::WindowRole(_) => properties["window_role"].as_str(),
+                _ => None,
+            }
+        }
+
+        /// Evaluate this criteria client side against a node from `tree::nodes()`, the way sway
+        /// would, see `Matcher`. `__focused__` can't be resolved from the node alone, so such
+        /// criteria never match here, and neither does an invalid regular expression.
+        pub fn matches(&self, node: &Node<'_>) -> bool {
+            Matcher::new(std::slice::from_ref(self)).is_ok_and(|matcher| matcher.matches(node))
+        }
+
+        /// The regular expression this criteria compares with, if any.
+        fn pattern(&self) -> Option<&str> {
+            match self {
+                Criteria::AppId(OrFocused::Value(value))
+                | Criteria::Class(OrFocused::Value(value))
+                | Criteria::Instance(OrFocused::Value(value))
+                | Criteria::Shell(OrFocused::Value(value))
+                | Criteria::Title(OrFocused::Value(value))
+                | Criteria::WindowRole(OrFocused::Value(value))
+                | Criteria::Workspace(OrFocused::Value(value))
+                | Criteria::ConMark(value) => Some(value),
+                _ => None,
+            }
+        }
+    }
+
+    /// A set of criteria compiled to evaluate them client side with the semantics sway gives
+    /// them, so that event filters and rules can use the same criteria as commands:
+    /// ```no_run
+    /// use ksway::criteria::{app_id, floating, Matcher};
+    /// use ksway::{tree, SwayClientJson};
+    ///
+    /// let mut client = ksway::Client::connect()?;
+    /// let tree = client.get_tree_json()?;
+    /// let matcher = Matcher::new(&[app_id("^mpv$"), floating()])?.focused_in(&tree);
+    /// for window in tree::windows(&tree).filter(|node| matcher.matches(node)) {
+    ///     println!("{}", window.node["name"]);
+    /// }
+    /// # Ok::<(), ksway::Error>(())
+    /// ```
+    ///
+    /// Like sway, a node matches when every criteria does, regular expressions match anywhere in
+    /// the value unless anchored, and a window without the property compared, e.g. the `class`
+    /// of a Wayland window, doesn't match. Only containers ever match. `urgent` only checks that
+    /// the window is urgent, since which one became urgent first or last isn't in the tree.
+    #[derive(Debug, Clone)]
+    pub struct Matcher {
+        criteria: Vec<(Criteria, Option<Regex>)>,
+        /// The focused window, which `__focused__` criteria compare against.
+        focused: Option<JsonValue>,
+        /// The focused workspace, for `workspace=__focused__`.
+        focused_workspace: Option<String>,
+    }
+
+    impl Matcher {
+        /// Compile the regular expressions of `criteria`. Returns an error if one is invalid.
+        pub fn new(criteria: &[Criteria]) -> Result<Self, ParseError> {
+            let criteria = criteria
+                .iter()
+                .map(|c| {
+                    let regex = c
+                        .pattern()
+                        .map(|pattern| {
+                            Regex::new(pattern).map_err(|err| {
+                                ParseError(format!("invalid regular expression in {}: {}", c, err))
+                            })
+                        })
+                        .transpose()?;
+                    Ok((c.clone(), regex))
+                })
+                .collect::<Result<_, ParseError>>()?;
+            Ok(Self {
+                criteria,
+                focused: None,
+                focused_workspace: None,
+            })
+        }
+
+        /// Resolve `__focused__` to the focused window and workspace of a GET_TREE reply. Until
+        /// then, criteria with `__focused__` never match.
+        pub fn focused_in(mut self, tree: &JsonValue) -> Self {
+            let focused = tree::nodes(tree).find(|node| node.node["focused"] == true);
+            self.focused = focused
+                .filter(|node| node.kind.is_container())
+                .map(|node| node.node.clone());
+            self.focused_workspace = focused.and_then(|node| node.workspace).map(str::to_owned);
+            self
+        }
+
+        /// Whether `node` matches every criteria.
+        pub fn matches(&self, node: &Node<'_>) -> bool {
+            node.kind.is_container()
+                && self
+                    .criteria
+                    .iter()
+                    .all(|(c, regex)| self.matches_one(c, regex.as_ref(), node))
+        }
+
+        fn matches_one(&self, c: &Criteria, regex: Option<&Regex>, node: &Node<'_>) -> bool {
+            let json = node.node;
+            let is_match = |value: Option<&str>| {
+                value.is_some_and(|value| regex.is_some_and(|regex| regex.is_match(value)))
+            };
+            match c {
+                Criteria::Floating => {
+                    node.kind == NodeKind::Floating || json["type"] == "floating_con"
+                }
+                Criteria::Tiling => node.kind == NodeKind::Tiling,
+                Criteria::ConId(OrFocused::Value(id)) => json["id"].as_u64() == Some(*id),
+                Criteria::ConId(OrFocused::Focused) => self
+                    .focused
+                    .as_ref()
+                    .is_some_and(|focused| focused["id"] == json["id"]),
+                Criteria::ConMark(_) => json["marks"]
+                    .as_array()
+                    .is_some_and(|marks| marks.iter().any(|mark| is_match(mark.as_str()))),
+                Criteria::Id(id) => json["window"].as_u64() == Some(*id),
+                Criteria::Pid(pid) => json["pid"].as_u64() == Some(*pid),
+                Criteria::Urgent(_) => json["urgent"].as_bool() == Some(true),
+                Criteria::WindowType(kind) => {
+                    json["window_type"].as_str().map(WindowType::from).as_ref() == Some(kind)
+                }
+                Criteria::Workspace(OrFocused::Value(_)) => is_match(node.workspace),
+                Criteria::Workspace(OrFocused::Focused) => {
+                    node.workspace.is_some() && node.workspace == self.focused_workspace.as_deref()
+                }
+                Criteria::AppId(value)
+                | Criteria::Class(value)
+                | Criteria::Instance(value)
+                | Criteria::Shell(value)
+                | Criteria::Title(value)
+                | Criteria::WindowRole(value) => {
+                    let actual = c.string_property(json);
+                    match value {
+                        OrFocused::Value(_) => is_match(actual),
+                        OrFocused::Focused => {
+                            actual.is_some()
+                                && actual
+                                    == self
+                                        .focused
+                                        .as_ref()
+                                        .and_then(|focused| c.string_property(focused))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     /// Compare value against the app id. Can be a regular expression. If value is __focused__, then the app id must be the same as that of the
     /// currently focused window. app_id are specific to Wayland applications.
     pub fn app_id<S: Display, T: Into<OrFocused<S>>>(t: T) -> Criteria {
@@ -458,6 +1484,11 @@ pub mod criteria {
         Criteria::Instance(t.into().map(|s| s.to_string()))
     }
 
+    /// Compare value against the process ID of the window. Must be numeric.
+    pub fn pid<T: Into<u64>>(t: T) -> Criteria {
+        Criteria::Pid(t.into())
+    }
+
     /// Compare value against the window shell, such as "xdg_shell" or "xwayland".  Can be a regular expression. If value is __focused__, then the
     /// shell must be the same as that of the currently focused window.
     pub fn shell<S: Display, T: Into<OrFocused<S>>>(t: T) -> Criteria {
@@ -475,10 +1506,9 @@ pub mod criteria {
         Criteria::Title(t.into().map(|s| s.to_string()))
     }
 
-    /// Compares the urgent state of the window. Can be "first", "last", "latest", "newest", "oldest" or "recent".
-    // TODO make enum
-    pub fn urgent<T: Display>(t: T) -> Criteria {
-        Criteria::Urgent(t.to_string())
+    /// Compares the urgent state of the window, see `Urgency`.
+    pub fn urgent<T: Into<Urgency>>(urgency: T) -> Criteria {
+        Criteria::Urgent(urgency.into())
     }
 
     /// Compare against the window role (WM_WINDOW_ROLE). Can be a regular expression. If value is __focused__, then the window role must be the
@@ -487,11 +1517,10 @@ pub mod criteria {
         Criteria::WindowRole(t.into().map(|s| s.to_string()))
     }
 
-    /// Compare against the window type (_NET_WM_WINDOW_TYPE). Possible values are normal, dialog, utility, toolbar, splash, menu, dropdown_menu,
-    /// popup_menu, tooltip and notification.
-    // TODO make enum
-    pub fn window_type<T: Display>(t: T) -> Criteria {
-        Criteria::WindowType(t.to_string())
+    /// Compare against the window type (_NET_WM_WINDOW_TYPE), given as a `WindowType` or its
+    /// name.
+    pub fn window_type<T: Into<WindowType>>(t: T) -> Criteria {
+        Criteria::WindowType(t.into())
     }
 
     /// Compare against the workspace name for this view. Can be a regular expression. If the value is __focused__, then all the views on the cur‐
@@ -502,36 +1531,1006 @@ pub mod criteria {
 }
 
 pub mod command {
+    use std::fmt::Display;
+
     use super::Command;
+    pub use crate::layout::Layout;
 
-    pub fn exec<T: Into<String>>(t: T) -> Command {
-        Command::Exec(t.into())
+    /// `s` in double quotes, with quotes and backslashes escaped, for names which may contain
+    /// spaces.
+    pub(crate) fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
     }
 
-    pub fn raw<T: Into<String>>(t: T) -> Command {
-        Command::Raw(t.into())
+    /// `" {t}"`, or nothing for `None`, for optional trailing arguments.
+    pub(crate) fn optional<T: Display>(t: &Option<T>) -> String {
+        t.as_ref().map(|t| format!(" {}", t)).unwrap_or_default()
     }
-}
-
-pub mod ipc_command {
-    use super::IpcCommand;
-    use super::IpcEvent;
 
-    pub fn get_bar_config() -> IpcCommand {
-        IpcCommand::GetBarConfig
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Direction {
+        #[display(fmt = "left")]
+        Left,
+        #[display(fmt = "right")]
+        Right,
+        #[display(fmt = "up")]
+        Up,
+        #[display(fmt = "down")]
+        Down,
     }
-    pub fn get_binding_modes() -> IpcCommand {
-        IpcCommand::GetBindingModes
-    }
-    pub fn get_config() -> IpcCommand {
+
+    /// What `focus` moves to, see `focus_on()`.
+    #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+    pub enum FocusTarget {
+        #[display(fmt = "{}", "_0")]
+        Direction(Direction),
+        #[display(fmt = "parent")]
+        Parent,
+        #[display(fmt = "child")]
+        Child,
+        /// The next window in the parent container, wrapping around.
+        #[display(fmt = "next")]
+        Next,
+        #[display(fmt = "prev")]
+        Prev,
+        /// Switch between the floating and the tiling windows.
+        #[display(fmt = "mode_toggle")]
+        ModeToggle,
+        #[display(fmt = "floating")]
+        Floating,
+        #[display(fmt = "tiling")]
+        Tiling,
+        #[display(fmt = "output {}", "quote(_0)")]
+        Output(String),
+    }
+
+    /// Where `move` puts the container, see `move_to()`.
+    #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+    pub enum MoveTarget {
+        /// Swap with the neighbour in a direction, or move floating windows by an amount of pixels.
+        #[display(fmt = "{}{}", "_0", r#"optional(&_1.map(|px| format!("{} px", px)))"#)]
+        Direction(Direction, Option<u32>),
+        #[display(fmt = "container to workspace {}", "quote(_0)")]
+        Workspace(String),
+        #[display(fmt = "container to workspace number {}", "_0")]
+        WorkspaceNumber(i64),
+        #[display(fmt = "container to output {}", "quote(_0)")]
+        Output(String),
+        #[display(fmt = "container to mark {}", "quote(_0)")]
+        Mark(String),
+        #[display(fmt = "scratchpad")]
+        Scratchpad,
+        /// Floating windows only, in pixels from the top left corner of the workspace.
+        #[display(fmt = "position {} {}", "_0", "_1")]
+        Position(i64, i64),
+        /// Floating windows only.
+        #[display(fmt = "position center")]
+        Center,
+        /// The whole focused workspace.
+        #[display(fmt = "workspace to output {}", "quote(_0)")]
+        WorkspaceToOutput(String),
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Dimension {
+        #[display(fmt = "width")]
+        Width,
+        #[display(fmt = "height")]
+        Height,
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Unit {
+        #[display(fmt = "px")]
+        Px,
+        /// Percentage points of the parent, for tiling windows.
+        #[display(fmt = "ppt")]
+        Ppt,
+    }
+
+    /// See `resize()`.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Resize {
+        #[display(fmt = "grow {} {} {}", "_0", "_1", "_2")]
+        Grow(Dimension, u32, Unit),
+        #[display(fmt = "shrink {} {} {}", "_0", "_1", "_2")]
+        Shrink(Dimension, u32, Unit),
+        /// Set the width and then the height.
+        #[display(fmt = "set {} {} {} {}", "_0", "_2", "_1", "_2")]
+        Set(u32, u32, Unit),
+    }
+
+    /// Which workspace `workspace` switches to, see `workspace()`.
+    #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+    pub enum WorkspaceTarget {
+        #[display(fmt = "{}", "quote(_0)")]
+        Name(String),
+        /// The workspace whose name starts with this number, which is created if needed.
+        #[display(fmt = "number {}", "_0")]
+        Number(i64),
+        #[display(fmt = "next")]
+        Next,
+        #[display(fmt = "prev")]
+        Prev,
+        #[display(fmt = "next_on_output")]
+        NextOnOutput,
+        #[display(fmt = "prev_on_output")]
+        PrevOnOutput,
+        #[display(fmt = "back_and_forth")]
+        BackAndForth,
+    }
+
+    /// For switches like `fullscreen`, `floating` and `sticky`.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Toggle {
+        #[display(fmt = "enable")]
+        Enable,
+        #[display(fmt = "disable")]
+        Disable,
+        #[display(fmt = "toggle")]
+        Toggle,
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Border {
+        #[display(fmt = "none")]
+        None,
+        /// With a title bar, and optionally a border width in pixels.
+        #[display(fmt = "normal{}", "optional(_0)")]
+        Normal(Option<u32>),
+        #[display(fmt = "pixel{}", "optional(_0)")]
+        Pixel(Option<u32>),
+        /// Let the client draw its own decorations.
+        #[display(fmt = "csd")]
+        Csd,
+        #[display(fmt = "toggle")]
+        Toggle,
+    }
+
+    /// How the focused container is split for the next window.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Split {
+        #[display(fmt = "horizontal")]
+        Horizontal,
+        #[display(fmt = "vertical")]
+        Vertical,
+        #[display(fmt = "toggle")]
+        Toggle,
+        /// Undo a split of a container with a single child.
+        #[display(fmt = "none")]
+        None,
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FocusFollowsMouse {
+        #[display(fmt = "yes")]
+        Yes,
+        #[display(fmt = "no")]
+        No,
+        /// Also focus when the mouse moves within the same window.
+        #[display(fmt = "always")]
+        Always,
+    }
+
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MouseWarping {
+        /// Move the mouse to the center of the output when focusing another output.
+        #[display(fmt = "output")]
+        Output,
+        /// Move the mouse to the center of the window when focusing another window.
+        #[display(fmt = "container")]
+        Container,
+        #[display(fmt = "none")]
+        None,
+    }
+
+    /// The layout of new workspaces, see `workspace_layout()`.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum WorkspaceLayout {
+        #[display(fmt = "default")]
+        Default,
+        #[display(fmt = "stacking")]
+        Stacking,
+        #[display(fmt = "tabbed")]
+        Tabbed,
+    }
+
+    /// The orientation of new workspaces, see `default_orientation()`.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Orientation {
+        #[display(fmt = "horizontal")]
+        Horizontal,
+        #[display(fmt = "vertical")]
+        Vertical,
+        /// Horizontal on outputs which are wider than they are high, vertical otherwise.
+        #[display(fmt = "auto")]
+        Auto,
+    }
+
+    pub fn exec<T: Into<String>>(t: T) -> Command {
+        Command::Exec(t.into())
+    }
+
+    pub fn exec_always<T: Into<String>>(t: T) -> Command {
+        Command::ExecAlways(t.into())
+    }
+
+    pub fn workspace_auto_back_and_forth(enabled: bool) -> Command {
+        Command::WorkspaceAutoBackAndForth(enabled)
+    }
+
+    pub fn focus_follows_mouse(mode: FocusFollowsMouse) -> Command {
+        Command::FocusFollowsMouse(mode)
+    }
+
+    pub fn mouse_warping(mode: MouseWarping) -> Command {
+        Command::MouseWarping(mode)
+    }
+
+    /// Only allowed in the config file: sway fails it when sent with `run()`, so this is only
+    /// useful to generate a config. Use `layout` on the workspace for an existing one.
+    pub fn workspace_layout(layout: WorkspaceLayout) -> Command {
+        Command::WorkspaceLayout(layout)
+    }
+
+    /// Only allowed in the config file, like `workspace_layout()`.
+    pub fn default_orientation(orientation: Orientation) -> Command {
+        Command::DefaultOrientation(orientation)
+    }
+
+    /// Focus the container matched by the criteria.
+    pub fn focus() -> Command {
+        Command::Focus(None)
+    }
+
+    pub fn focus_on(target: FocusTarget) -> Command {
+        Command::Focus(Some(target))
+    }
+
+    pub fn move_to(target: MoveTarget) -> Command {
+        Command::Move(target)
+    }
+
+    pub fn resize(resize: Resize) -> Command {
+        Command::Resize(resize)
+    }
+
+    /// Named so that it doesn't clash with `criteria::workspace()` when both are glob imported.
+    pub fn switch_workspace(target: WorkspaceTarget) -> Command {
+        Command::Workspace(target)
+    }
+
+    pub fn layout(layout: Layout) -> Command {
+        Command::Layout(layout)
+    }
+
+    /// Cycle through the split layouts, or all layouts with `all`.
+    pub fn layout_toggle(all: bool) -> Command {
+        Command::LayoutToggle(all)
+    }
+
+    pub fn kill() -> Command {
+        Command::Kill
+    }
+
+    pub fn fullscreen(toggle: Toggle) -> Command {
+        Command::Fullscreen(toggle)
+    }
+
+    /// Named so that it doesn't clash with `criteria::floating()` when both are glob imported.
+    pub fn set_floating(toggle: Toggle) -> Command {
+        Command::Floating(toggle)
+    }
+
+    pub fn sticky(toggle: Toggle) -> Command {
+        Command::Sticky(toggle)
+    }
+
+    pub fn border(border: Border) -> Command {
+        Command::Border(border)
+    }
+
+    /// From 0 for invisible to 1 for opaque.
+    pub fn opacity(opacity: f64) -> Command {
+        Command::Opacity(opacity)
+    }
+
+    pub fn split(split: Split) -> Command {
+        Command::Split(split)
+    }
+
+    /// What happens to the other marks of a container which is marked.
+    #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MarkMode {
+        /// Remove them, which is what sway does without a flag.
+        #[display(fmt = "--replace")]
+        Replace,
+        /// Keep them.
+        #[display(fmt = "--add")]
+        Add,
+    }
+
+    /// Mark the container with `mark`, replacing its other marks. A mark moves from whichever
+    /// container had it before.
+    pub fn mark<T: Into<String>>(mark: T) -> Command {
+        Command::Mark {
+            mark: mark.into(),
+            mode: MarkMode::Replace,
+            toggle: false,
+        }
+    }
+
+    /// Mark the container with `mark`, keeping its other marks.
+    pub fn add_mark<T: Into<String>>(mark: T) -> Command {
+        Command::Mark {
+            mark: mark.into(),
+            mode: MarkMode::Add,
+            toggle: false,
+        }
+    }
+
+    /// Remove `mark` if the container has it, otherwise add it to its other marks.
+    pub fn toggle_mark<T: Into<String>>(mark: T) -> Command {
+        Command::Mark {
+            mark: mark.into(),
+            mode: MarkMode::Add,
+            toggle: true,
+        }
+    }
+
+    /// Remove `mark` from whichever container has it.
+    pub fn unmark<T: Into<String>>(mark: T) -> Command {
+        Command::Unmark(Some(mark.into()))
+    }
+
+    /// Remove every mark, or those of the matched containers when used with criteria.
+    pub fn unmark_all() -> Command {
+        Command::Unmark(None)
+    }
+
+    /// Show the scratchpad window, or cycle through them.
+    pub fn scratchpad_show() -> Command {
+        Command::ScratchpadShow
+    }
+
+    pub fn reload() -> Command {
+        Command::Reload
+    }
+
+    pub fn raw<T: Into<String>>(t: T) -> Command {
+        Command::Raw(t.into())
+    }
+
+    /// Configuring outputs at runtime, e.g. `output::mode("DP-1", Mode::new(2560, 1440))`. Use
+    /// `*` as the name for every output.
+    pub mod output {
+        use super::quote;
+        use crate::Command;
+
+        /// A resolution, and optionally the refresh rate in Hz. Sway picks the highest refresh
+        /// rate for the resolution without one.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct Mode {
+            pub width: u32,
+            pub height: u32,
+            pub refresh: Option<f64>,
+        }
+
+        impl Mode {
+            pub fn new(width: u32, height: u32) -> Self {
+                Self {
+                    width,
+                    height,
+                    refresh: None,
+                }
+            }
+
+            pub fn refresh(mut self, hz: f64) -> Self {
+                self.refresh = Some(hz);
+                self
+            }
+        }
+
+        impl std::fmt::Display for Mode {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}x{}", self.width, self.height)?;
+                if let Some(refresh) = self.refresh {
+                    write!(f, "@{}Hz", refresh)?;
+                }
+                Ok(())
+            }
+        }
+
+        /// Rotation counter-clockwise in degrees, optionally after flipping horizontally.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Transform {
+            #[display(fmt = "normal")]
+            Normal,
+            #[display(fmt = "90")]
+            Rotate90,
+            #[display(fmt = "180")]
+            Rotate180,
+            #[display(fmt = "270")]
+            Rotate270,
+            #[display(fmt = "flipped")]
+            Flipped,
+            #[display(fmt = "flipped-90")]
+            Flipped90,
+            #[display(fmt = "flipped-180")]
+            Flipped180,
+            #[display(fmt = "flipped-270")]
+            Flipped270,
+        }
+
+        impl std::str::FromStr for Transform {
+            type Err = crate::ParseError;
+
+            /// The form sway uses, in commands and in the `transform` of GET_OUTPUTS replies.
+            fn from_str(s: &str) -> Result<Self, crate::ParseError> {
+                Ok(match s {
+                    "normal" => Transform::Normal,
+                    "90" => Transform::Rotate90,
+                    "180" => Transform::Rotate180,
+                    "270" => Transform::Rotate270,
+                    "flipped" => Transform::Flipped,
+                    "flipped-90" => Transform::Flipped90,
+                    "flipped-180" => Transform::Flipped180,
+                    "flipped-270" => Transform::Flipped270,
+                    _ => return Err(crate::ParseError(format!("unknown transform: {}", s))),
+                })
+            }
+        }
+
+        /// How a background image is fitted to the output.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Fit {
+            #[display(fmt = "stretch")]
+            Stretch,
+            /// Scale to cover the output, cropping the image.
+            #[display(fmt = "fill")]
+            Fill,
+            /// Scale to fit within the output, with borders.
+            #[display(fmt = "fit")]
+            Fit,
+            #[display(fmt = "center")]
+            Center,
+            #[display(fmt = "tile")]
+            Tile,
+        }
+
+        /// Drawn by `swaybg`, which sway starts as needed.
+        #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+        pub enum Background {
+            #[display(fmt = "{} {}", "quote(_0)", "_1")]
+            Image(String, Fit),
+            /// A color in `#rrggbb` form.
+            #[display(fmt = "{} solid_color", "_0")]
+            SolidColor(String),
+        }
+
+        /// What an `output` command changes, see the functions of this module.
+        #[derive(derive_more::Display, Debug, Clone, PartialEq)]
+        pub enum Setting {
+            #[display(fmt = "mode {}", "_0")]
+            Mode(Mode),
+            /// In layout coordinates, i.e. after scaling.
+            #[display(fmt = "position {} {}", "_0", "_1")]
+            Position(i64, i64),
+            #[display(fmt = "scale {}", "_0")]
+            Scale(f64),
+            #[display(fmt = "transform {}", "_0")]
+            Transform(Transform),
+            #[display(fmt = "dpms {}", r#"if *_0 { "on" } else { "off" }"#)]
+            Dpms(bool),
+            #[display(fmt = "power {}", r#"if *_0 { "on" } else { "off" }"#)]
+            Power(bool),
+            #[display(fmt = "bg {}", "_0")]
+            Background(Background),
+            #[display(fmt = "enable")]
+            Enable,
+            #[display(fmt = "disable")]
+            Disable,
+        }
+
+        pub fn set<S: Into<String>>(name: S, setting: Setting) -> Command {
+            Command::Output(name.into(), setting)
+        }
+
+        pub fn mode<S: Into<String>>(name: S, mode: Mode) -> Command {
+            set(name, Setting::Mode(mode))
+        }
+
+        pub fn position<S: Into<String>>(name: S, x: i64, y: i64) -> Command {
+            set(name, Setting::Position(x, y))
+        }
+
+        pub fn scale<S: Into<String>>(name: S, scale: f64) -> Command {
+            set(name, Setting::Scale(scale))
+        }
+
+        pub fn transform<S: Into<String>>(name: S, transform: Transform) -> Command {
+            set(name, Setting::Transform(transform))
+        }
+
+        /// Superseded by `power()` in sway 1.8, see `Command::for_version()` to pick the one
+        /// the running sway understands.
+        pub fn dpms<S: Into<String>>(name: S, on: bool) -> Command {
+            set(name, Setting::Dpms(on))
+        }
+
+        pub fn power<S: Into<String>>(name: S, on: bool) -> Command {
+            set(name, Setting::Power(on))
+        }
+
+        pub fn bg<S: Into<String>>(name: S, background: Background) -> Command {
+            set(name, Setting::Background(background))
+        }
+
+        pub fn enable<S: Into<String>>(name: S) -> Command {
+            set(name, Setting::Enable)
+        }
+
+        pub fn disable<S: Into<String>>(name: S) -> Command {
+            set(name, Setting::Disable)
+        }
+    }
+
+    /// Configuring input devices at runtime, e.g. `input::tap("type:touchpad", true)`. The
+    /// identifier is one from GET_INPUTS, `type:<type>` for every device of a type, or `*`.
+    pub mod input {
+        use super::quote;
+        use crate::Command;
+
+        fn enabled(enabled: bool) -> &'static str {
+            if enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum AccelProfile {
+            #[display(fmt = "adaptive")]
+            Adaptive,
+            #[display(fmt = "flat")]
+            Flat,
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ScrollMethod {
+            #[display(fmt = "none")]
+            None,
+            #[display(fmt = "two_finger")]
+            TwoFinger,
+            #[display(fmt = "edge")]
+            Edge,
+            #[display(fmt = "on_button_down")]
+            OnButtonDown,
+        }
+
+        /// Whether the device sends events.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum SendEvents {
+            #[display(fmt = "enabled")]
+            Enabled,
+            #[display(fmt = "disabled")]
+            Disabled,
+            /// Touchpads only.
+            #[display(fmt = "disabled_on_external_mouse")]
+            DisabledOnExternalMouse,
+        }
+
+        /// Which of the configured keyboard layouts is active.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum LayoutIndex {
+            #[display(fmt = "next")]
+            Next,
+            #[display(fmt = "prev")]
+            Prev,
+            /// The position in `xkb_layout`, from 0.
+            #[display(fmt = "{}", "_0")]
+            Index(u32),
+        }
+
+        /// What an `input` command changes, see the functions of this module.
+        #[derive(derive_more::Display, Debug, Clone, PartialEq)]
+        pub enum Setting {
+            /// A comma separated list of layouts, e.g. `us,de`.
+            #[display(fmt = "xkb_layout {}", "quote(_0)")]
+            XkbLayout(String),
+            #[display(fmt = "xkb_variant {}", "quote(_0)")]
+            XkbVariant(String),
+            #[display(fmt = "xkb_options {}", "quote(_0)")]
+            XkbOptions(String),
+            #[display(fmt = "xkb_switch_layout {}", "_0")]
+            XkbSwitchLayout(LayoutIndex),
+            /// In milliseconds.
+            #[display(fmt = "repeat_delay {}", "_0")]
+            RepeatDelay(u32),
+            /// In characters per second.
+            #[display(fmt = "repeat_rate {}", "_0")]
+            RepeatRate(u32),
+            #[display(fmt = "accel_profile {}", "_0")]
+            AccelProfile(AccelProfile),
+            /// From -1 to 1.
+            #[display(fmt = "pointer_accel {}", "_0")]
+            PointerAccel(f64),
+            #[display(fmt = "natural_scroll {}", "enabled(*_0)")]
+            NaturalScroll(bool),
+            #[display(fmt = "scroll_method {}", "_0")]
+            ScrollMethod(ScrollMethod),
+            #[display(fmt = "scroll_factor {}", "_0")]
+            ScrollFactor(f64),
+            #[display(fmt = "tap {}", "enabled(*_0)")]
+            Tap(bool),
+            #[display(fmt = "dwt {}", "enabled(*_0)")]
+            DisableWhileTyping(bool),
+            #[display(fmt = "left_handed {}", "enabled(*_0)")]
+            LeftHanded(bool),
+            #[display(fmt = "middle_emulation {}", "enabled(*_0)")]
+            MiddleEmulation(bool),
+            #[display(fmt = "events {}", "_0")]
+            Events(SendEvents),
+        }
+
+        pub fn set<S: Into<String>>(identifier: S, setting: Setting) -> Command {
+            Command::Input(identifier.into(), setting)
+        }
+
+        pub fn xkb_layout<S: Into<String>, T: Into<String>>(identifier: S, layouts: T) -> Command {
+            set(identifier, Setting::XkbLayout(layouts.into()))
+        }
+
+        pub fn xkb_variant<S: Into<String>, T: Into<String>>(
+            identifier: S,
+            variants: T,
+        ) -> Command {
+            set(identifier, Setting::XkbVariant(variants.into()))
+        }
+
+        pub fn xkb_options<S: Into<String>, T: Into<String>>(identifier: S, options: T) -> Command {
+            set(identifier, Setting::XkbOptions(options.into()))
+        }
+
+        pub fn xkb_switch_layout<S: Into<String>>(identifier: S, layout: LayoutIndex) -> Command {
+            set(identifier, Setting::XkbSwitchLayout(layout))
+        }
+
+        pub fn repeat_delay<S: Into<String>>(identifier: S, millis: u32) -> Command {
+            set(identifier, Setting::RepeatDelay(millis))
+        }
+
+        pub fn repeat_rate<S: Into<String>>(identifier: S, per_second: u32) -> Command {
+            set(identifier, Setting::RepeatRate(per_second))
+        }
+
+        pub fn accel_profile<S: Into<String>>(identifier: S, profile: AccelProfile) -> Command {
+            set(identifier, Setting::AccelProfile(profile))
+        }
+
+        pub fn pointer_accel<S: Into<String>>(identifier: S, accel: f64) -> Command {
+            set(identifier, Setting::PointerAccel(accel))
+        }
+
+        pub fn natural_scroll<S: Into<String>>(identifier: S, enabled: bool) -> Command {
+            set(identifier, Setting::NaturalScroll(enabled))
+        }
+
+        pub fn scroll_method<S: Into<String>>(identifier: S, method: ScrollMethod) -> Command {
+            set(identifier, Setting::ScrollMethod(method))
+        }
+
+        pub fn scroll_factor<S: Into<String>>(identifier: S, factor: f64) -> Command {
+            set(identifier, Setting::ScrollFactor(factor))
+        }
+
+        pub fn tap<S: Into<String>>(identifier: S, enabled: bool) -> Command {
+            set(identifier, Setting::Tap(enabled))
+        }
+
+        /// Disable the touchpad while typing.
+        pub fn dwt<S: Into<String>>(identifier: S, enabled: bool) -> Command {
+            set(identifier, Setting::DisableWhileTyping(enabled))
+        }
+
+        pub fn left_handed<S: Into<String>>(identifier: S, enabled: bool) -> Command {
+            set(identifier, Setting::LeftHanded(enabled))
+        }
+
+        pub fn middle_emulation<S: Into<String>>(identifier: S, enabled: bool) -> Command {
+            set(identifier, Setting::MiddleEmulation(enabled))
+        }
+
+        pub fn events<S: Into<String>>(identifier: S, events: SendEvents) -> Command {
+            set(identifier, Setting::Events(events))
+        }
+    }
+
+    /// Configuring seats at runtime, e.g. `seat::cursor("*", Cursor::Set(100, 200))` to warp the
+    /// cursor. Use `*` as the name for every seat, or `-` for the current one.
+    pub mod seat {
+        use crate::Command;
+
+        /// Pointer buttons by their X11 number, as `seat cursor press` takes them.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Button {
+            #[display(fmt = "button1")]
+            Left,
+            #[display(fmt = "button2")]
+            Middle,
+            #[display(fmt = "button3")]
+            Right,
+            #[display(fmt = "button4")]
+            ScrollUp,
+            #[display(fmt = "button5")]
+            ScrollDown,
+            #[display(fmt = "button6")]
+            ScrollLeft,
+            #[display(fmt = "button7")]
+            ScrollRight,
+            #[display(fmt = "button8")]
+            Back,
+            #[display(fmt = "button9")]
+            Forward,
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Cursor {
+            /// Move by an amount of pixels.
+            #[display(fmt = "move {} {}", "_0", "_1")]
+            Move(i64, i64),
+            /// Move to a position in layout coordinates.
+            #[display(fmt = "set {} {}", "_0", "_1")]
+            Set(i64, i64),
+            #[display(fmt = "press {}", "_0")]
+            Press(Button),
+            #[display(fmt = "release {}", "_0")]
+            Release(Button),
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum HideCursor {
+            /// Hide the cursor after it wasn't moved for this many milliseconds, never with 0.
+            #[display(fmt = "{}", "_0")]
+            After(u32),
+            #[display(fmt = "when-typing {}", r#"if *_0 { "enable" } else { "disable" }"#)]
+            WhenTyping(bool),
+        }
+
+        /// How keyboards of the seat are grouped to share their modifier and layout state.
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum KeyboardGrouping {
+            #[display(fmt = "none")]
+            None,
+            /// Group keyboards with the same keymap and repeat settings.
+            #[display(fmt = "smart")]
+            Smart,
+        }
+
+        /// What a `seat` command changes, see the functions of this module.
+        #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+        pub enum Setting {
+            #[display(fmt = "cursor {}", "_0")]
+            Cursor(Cursor),
+            #[display(fmt = "hide_cursor {}", "_0")]
+            HideCursor(HideCursor),
+            #[display(fmt = "keyboard_grouping {}", "_0")]
+            KeyboardGrouping(KeyboardGrouping),
+        }
+
+        pub fn set<S: Into<String>>(name: S, setting: Setting) -> Command {
+            Command::Seat(name.into(), setting)
+        }
+
+        pub fn cursor<S: Into<String>>(name: S, cursor: Cursor) -> Command {
+            set(name, Setting::Cursor(cursor))
+        }
+
+        pub fn hide_cursor<S: Into<String>>(name: S, hide: HideCursor) -> Command {
+            set(name, Setting::HideCursor(hide))
+        }
+
+        pub fn keyboard_grouping<S: Into<String>>(name: S, grouping: KeyboardGrouping) -> Command {
+            set(name, Setting::KeyboardGrouping(grouping))
+        }
+    }
+
+    /// Adding and removing key bindings at runtime:
+    /// ```
+    /// use ksway::command::{self, bind::{self, Binding, Flag, Modifier}};
+    ///
+    /// let binding = Binding::sym("Print").modifier(Modifier::Super).flag(Flag::Release);
+    /// assert_eq!(
+    ///     bind::bind(binding.clone(), command::exec("grim")).to_string(),
+    ///     r#"bindsym --release Mod4+Print "exec grim""#
+    /// );
+    /// assert_eq!(bind::unbind(binding).to_string(), "unbindsym --release Mod4+Print");
+    /// ```
+    pub mod bind {
+        use crate::Command;
+
+        #[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Modifier {
+            #[display(fmt = "Shift")]
+            Shift,
+            /// Caps Lock.
+            #[display(fmt = "Lock")]
+            Lock,
+            #[display(fmt = "Ctrl")]
+            Ctrl,
+            #[display(fmt = "Mod1")]
+            Alt,
+            #[display(fmt = "Mod2")]
+            Mod2,
+            #[display(fmt = "Mod3")]
+            Mod3,
+            /// The logo key.
+            #[display(fmt = "Mod4")]
+            Super,
+            #[display(fmt = "Mod5")]
+            Mod5,
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+        pub enum Flag {
+            /// Run the command when the key is released rather than pressed.
+            #[display(fmt = "--release")]
+            Release,
+            /// Also run the command while the screen is locked.
+            #[display(fmt = "--locked")]
+            Locked,
+            /// Translate the keysym to a keycode of the first configured layout, so the binding
+            /// stays on the same key with other layouts.
+            #[display(fmt = "--to-code")]
+            ToCode,
+            /// Only for this device, an identifier from GET_INPUTS.
+            #[display(fmt = "--input-device={}", "_0")]
+            InputDevice(String),
+            #[display(fmt = "--no-warn")]
+            NoWarn,
+            #[display(fmt = "--no-repeat")]
+            NoRepeat,
+            /// Also run the command while shortcuts are inhibited, e.g. by a virtual machine.
+            #[display(fmt = "--inhibited")]
+            Inhibited,
+        }
+
+        #[derive(derive_more::Display, Debug, Clone, PartialEq, Eq)]
+        pub enum Key {
+            /// A keysym name, e.g. `Return`.
+            #[display(fmt = "{}", "_0")]
+            Sym(String),
+            /// A hardware keycode, as shown by `wev`.
+            #[display(fmt = "{}", "_0")]
+            Code(u32),
+        }
+
+        /// A key combination and the flags of a binding, e.g. `--release Mod4+Print`.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Binding {
+            pub flags: Vec<Flag>,
+            pub modifiers: Vec<Modifier>,
+            pub key: Key,
+        }
+
+        impl Binding {
+            pub fn sym<S: Into<String>>(key: S) -> Self {
+                Self {
+                    flags: Vec::new(),
+                    modifiers: Vec::new(),
+                    key: Key::Sym(key.into()),
+                }
+            }
+
+            pub fn code(code: u32) -> Self {
+                Self {
+                    flags: Vec::new(),
+                    modifiers: Vec::new(),
+                    key: Key::Code(code),
+                }
+            }
+
+            pub fn modifier(mut self, modifier: Modifier) -> Self {
+                self.modifiers.push(modifier);
+                self
+            }
+
+            pub fn modifiers(mut self, modifiers: &[Modifier]) -> Self {
+                self.modifiers.extend_from_slice(modifiers);
+                self
+            }
+
+            pub fn flag(mut self, flag: Flag) -> Self {
+                self.flags.push(flag);
+                self
+            }
+
+            /// `sym` or `code`, for the name of the command.
+            pub(crate) fn kind(&self) -> &'static str {
+                match self.key {
+                    Key::Sym(_) => "sym",
+                    Key::Code(_) => "code",
+                }
+            }
+        }
+
+        impl std::fmt::Display for Binding {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                for flag in &self.flags {
+                    write!(f, "{} ", flag)?;
+                }
+                for modifier in &self.modifiers {
+                    write!(f, "{}+", modifier)?;
+                }
+                write!(f, "{}", self.key)
+            }
+        }
+
+        /// `bindsym` or `bindcode`, depending on the key of `binding`.
+        pub fn bind(binding: Binding, command: Command) -> Command {
+            Command::Bind(binding, Box::new(command))
+        }
+
+        /// `unbindsym` or `unbindcode`, depending on the key of `binding`. The flags have to be
+        /// the same as those the binding was made with.
+        pub fn unbind(binding: Binding) -> Command {
+            Command::Unbind(binding)
+        }
+
+        pub fn bindsym<S: Into<String>>(
+            modifiers: &[Modifier],
+            key: S,
+            command: Command,
+        ) -> Command {
+            bind(Binding::sym(key).modifiers(modifiers), command)
+        }
+
+        pub fn bindcode(modifiers: &[Modifier], code: u32, command: Command) -> Command {
+            bind(Binding::code(code).modifiers(modifiers), command)
+        }
+
+        pub fn unbindsym<S: Into<String>>(modifiers: &[Modifier], key: S) -> Command {
+            unbind(Binding::sym(key).modifiers(modifiers))
+        }
+
+        pub fn unbindcode(modifiers: &[Modifier], code: u32) -> Command {
+            unbind(Binding::code(code).modifiers(modifiers))
+        }
+    }
+}
+
+pub mod ipc_command {
+    use super::EventMask;
+    use super::IpcCommand;
+
+    pub fn get_bar_config() -> IpcCommand {
+        IpcCommand::GetBarConfig
+    }
+    pub fn get_bar_config_for<T: Into<String>>(id: T) -> IpcCommand {
+        IpcCommand::GetBarConfigFor(id.into())
+    }
+    pub fn get_binding_modes() -> IpcCommand {
+        IpcCommand::GetBindingModes
+    }
+    pub fn get_binding_state() -> IpcCommand {
+        IpcCommand::GetBindingState
+    }
+    pub fn get_config() -> IpcCommand {
         IpcCommand::GetConfig
     }
+    pub fn get_inputs() -> IpcCommand {
+        IpcCommand::GetInputs
+    }
     pub fn get_marks() -> IpcCommand {
         IpcCommand::GetMarks
     }
     pub fn get_outputs() -> IpcCommand {
         IpcCommand::GetOutputs
     }
+    pub fn get_seats() -> IpcCommand {
+        IpcCommand::GetSeats
+    }
     pub fn get_tree() -> IpcCommand {
         IpcCommand::GetTree
     }
@@ -550,12 +2549,12 @@ pub mod ipc_command {
         IpcCommand::SendTick(t.into())
     }
 
-    pub fn subscribe<T: Into<Vec<IpcEvent>>>(t: T) -> IpcCommand {
+    pub fn subscribe<T: Into<EventMask>>(t: T) -> IpcCommand {
         IpcCommand::Subscribe(t.into())
     }
 }
 
-#[derive(derive_more::Display, Debug)]
+#[derive(derive_more::Display, Debug, Clone, PartialEq)]
 #[display(
     fmt = "[{}] {}",
     r#"join(criteria.iter().map(ToString::to_string), " ")"#,
@@ -566,30 +2565,289 @@ pub struct CriteriaCommand {
     command: Box<Command>,
 }
 
-#[derive(derive_more::Display, Debug)]
+#[derive(derive_more::Display, Debug, Clone, PartialEq)]
 pub enum Command {
     #[display(fmt = "{}", "_0")]
     WithCriteria(CriteriaCommand),
     #[display(fmt = "exec {}", "_0")]
     Exec(String),
+    /// Unlike `exec` in a config file, this is also run again when the config is reloaded.
+    #[display(fmt = "exec_always {}", "_0")]
+    ExecAlways(String),
+    #[display(
+        fmt = "workspace_auto_back_and_forth {}",
+        r#"if *_0 { "yes" } else { "no" }"#
+    )]
+    WorkspaceAutoBackAndForth(bool),
+    #[display(fmt = "focus_follows_mouse {}", "_0")]
+    FocusFollowsMouse(command::FocusFollowsMouse),
+    #[display(fmt = "mouse_warping {}", "_0")]
+    MouseWarping(command::MouseWarping),
+    /// Config file only.
+    #[display(fmt = "workspace_layout {}", "_0")]
+    WorkspaceLayout(command::WorkspaceLayout),
+    /// Config file only.
+    #[display(fmt = "default_orientation {}", "_0")]
+    DefaultOrientation(command::Orientation),
+    #[display(fmt = "focus{}", "command::optional(_0)")]
+    Focus(Option<command::FocusTarget>),
+    #[display(fmt = "move {}", "_0")]
+    Move(command::MoveTarget),
+    #[display(fmt = "resize {}", "_0")]
+    Resize(command::Resize),
+    #[display(fmt = "workspace {}", "_0")]
+    Workspace(command::WorkspaceTarget),
+    #[display(fmt = "layout {}", "_0")]
+    Layout(command::Layout),
+    #[display(fmt = "layout toggle {}", r#"if *_0 { "all" } else { "split" }"#)]
+    LayoutToggle(bool),
+    #[display(fmt = "kill")]
+    Kill,
+    #[display(fmt = "fullscreen {}", "_0")]
+    Fullscreen(command::Toggle),
+    #[display(fmt = "floating {}", "_0")]
+    Floating(command::Toggle),
+    #[display(fmt = "sticky {}", "_0")]
+    Sticky(command::Toggle),
+    #[display(fmt = "border {}", "_0")]
+    Border(command::Border),
+    #[display(fmt = "opacity {}", "_0")]
+    Opacity(f64),
+    #[display(fmt = "split {}", "_0")]
+    Split(command::Split),
+    #[display(
+        fmt = "mark {} {}{}",
+        "mode",
+        r#"if *toggle { "--toggle " } else { "" }"#,
+        "command::quote(mark)"
+    )]
+    Mark {
+        mark: String,
+        mode: command::MarkMode,
+        toggle: bool,
+    },
+    #[display(
+        fmt = "unmark{}",
+        "command::optional(&_0.as_deref().map(command::quote))"
+    )]
+    Unmark(Option<String>),
+    #[display(fmt = "scratchpad show")]
+    ScratchpadShow,
+    #[display(fmt = "reload")]
+    Reload,
+    #[display(fmt = "output {} {}", "command::quote(_0)", "_1")]
+    Output(String, command::output::Setting),
+    #[display(fmt = "input {} {}", "command::quote(_0)", "_1")]
+    Input(String, command::input::Setting),
+    #[display(fmt = "seat {} {}", "command::quote(_0)", "_1")]
+    Seat(String, command::seat::Setting),
+    #[display(
+        fmt = "bind{} {} {}",
+        "_0.kind()",
+        "_0",
+        "command::quote(&_1.to_string())"
+    )]
+    Bind(command::bind::Binding, Box<Command>),
+    #[display(fmt = "unbind{} {}", "_0.kind()", "_0")]
+    Unbind(command::bind::Binding),
     #[display(fmt = "{}", "_0")]
     Raw(String),
 }
 
+/// Add `new` to `criteria`. A criteria which conflicts with an earlier one replaces it in its
+/// position, see `Command::with_criteria()`.
+fn merge_criteria(criteria: &mut Vec<criteria::Criteria>, new: Vec<criteria::Criteria>) {
+    for c in new {
+        match criteria
+            .iter()
+            .position(|existing| existing.conflicts_with(&c))
+        {
+            Some(i) => criteria[i] = c,
+            None => criteria.push(c),
+        }
+    }
+}
+
 impl Command {
-    /// Prepend criteria to this command. A vec is used so that ordering can be deterministic,
-    /// which can be useful.
+    /// Prepend criteria to this command. Criteria are written in the order they were first added,
+    /// so the output is deterministic. Sway only keeps one value per key, so a criteria with the
+    /// same key as an earlier one (or `floating` against `tiling`) replaces it in its position,
+    /// e.g. adding `con_id=2` to `[con_id=1 app_id=foot]` gives `[con_id=2 app_id=foot]`.
     pub fn with_criteria(self, criteria: Vec<criteria::Criteria>) -> Self {
         match self {
             Command::WithCriteria(mut cmd) => {
-                cmd.criteria.extend(criteria);
+                merge_criteria(&mut cmd.criteria, criteria);
                 Command::WithCriteria(cmd)
             }
-            _ => Command::WithCriteria(CriteriaCommand {
-                criteria,
-                command: Box::new(self),
-            }),
+            _ => {
+                let mut merged = Vec::with_capacity(criteria.len());
+                merge_criteria(&mut merged, criteria);
+                Command::WithCriteria(CriteriaCommand {
+                    criteria: merged,
+                    command: Box::new(self),
+                })
+            }
+        }
+    }
+
+    /// The criteria of this command, in the order they are written.
+    pub fn criteria(&self) -> &[criteria::Criteria] {
+        match self {
+            Command::WithCriteria(cmd) => &cmd.criteria,
+            _ => &[],
+        }
+    }
+
+    /// This command followed by `next`, separated by `;`, see `CommandSeq`.
+    pub fn then(self, next: Command) -> CommandSeq {
+        CommandSeq::new().then(self).then(next)
+    }
+
+    /// This command followed by `next` on the same containers, separated by `,`, see
+    /// `CommandSeq`.
+    pub fn and(self, next: Command) -> CommandSeq {
+        CommandSeq::new().then(self).and(next)
+    }
+
+    /// This command in the syntax `version` of sway understands, for the commands whose syntax
+    /// changed between versions: `output ... power`, which older versions only know as
+    /// `output ... dpms`, and which deprecated `dpms`. Anything else, including raw commands, is
+    /// left as it is.
+    pub fn for_version(self, version: version::SwayVersion) -> Command {
+        use command::output::Setting;
+        let power = version.supports(version::Capability::OutputPower);
+        match self {
+            Command::Output(name, Setting::Power(on)) if !power => {
+                Command::Output(name, Setting::Dpms(on))
+            }
+            Command::Output(name, Setting::Dpms(on)) if power => {
+                Command::Output(name, Setting::Power(on))
+            }
+            Command::WithCriteria(CriteriaCommand { criteria, command }) => {
+                Command::WithCriteria(CriteriaCommand {
+                    criteria,
+                    command: Box::new(command.for_version(version)),
+                })
+            }
+            Command::Bind(binding, command) => {
+                Command::Bind(binding, Box::new(command.for_version(version)))
+            }
+            command => command,
+        }
+    }
+
+    /// The command `swaymsg` sends for these arguments, e.g. the `"$@"` of a script which used to
+    /// call it. Like `swaymsg`, the arguments are joined with single spaces and nothing is quoted,
+    /// so `["workspace", "1: web"]` is `workspace 1: web`, and quotes meant for sway have to be
+    /// part of the arguments.
+    pub fn from_args<I, S>(args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut command = String::new();
+        for (i, arg) in args.into_iter().enumerate() {
+            if i > 0 {
+                command.push(' ');
+            }
+            command.push_str(arg.as_ref());
         }
+        Command::Raw(command)
+    }
+}
+
+/// A compound command assembled from runtime state, which sway runs in order as one command,
+/// e.g. `[app_id=mpv] fullscreen disable, move scratchpad; workspace 1`.
+/// ```no_run
+/// use ksway::{cmd, CommandSeq, SwayClient};
+///
+/// let mut client = ksway::Client::connect()?;
+/// let (urgent, windows) = (true, vec![3, 5]);
+/// let seq = CommandSeq::new()
+///     .when(urgent, cmd!("workspace 1"))
+///     .for_each(windows, |id| cmd!([con_id=id] "move scratchpad"))
+///     .then(cmd!("scratchpad show"));
+/// client.run(seq)?;
+/// # Ok::<(), ksway::Error>(())
+/// ```
+///
+/// The commands are in groups separated by `;`, and the commands of a group by `,`. Sway applies
+/// the criteria of the first command of a group to the rest of the group, so the later ones
+/// shouldn't have criteria of their own.
+/// ```
+/// use ksway::{cmd, command};
+///
+/// let seq = cmd!([app_id="mpv"] "fullscreen disable")
+///     .and(command::move_to(command::MoveTarget::Scratchpad))
+///     .then(command::switch_workspace(command::WorkspaceTarget::Number(1)));
+/// assert_eq!(
+///     seq.to_string(),
+///     r#"[app_id="mpv"] fullscreen disable, move scratchpad; workspace number 1"#
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandSeq {
+    commands: Vec<Command>,
+    /// Whether each command is in the same group as the one before it.
+    grouped: Vec<bool>,
+}
+
+impl CommandSeq {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `command` in a group of its own.
+    pub fn then(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self.grouped.push(false);
+        self
+    }
+
+    /// Add `command` to the last group, so it runs on the same containers.
+    pub fn and(mut self, command: Command) -> Self {
+        self.grouped.push(!self.commands.is_empty());
+        self.commands.push(command);
+        self
+    }
+
+    /// Add `command` only if `condition` holds.
+    pub fn when(self, condition: bool, command: Command) -> Self {
+        if condition {
+            self.then(command)
+        } else {
+            self
+        }
+    }
+
+    /// Add a command for every item, each in a group of its own.
+    pub fn for_each<I, F>(self, items: I, mut f: F) -> Self
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> Command,
+    {
+        items.into_iter().fold(self, |seq, item| seq.then(f(item)))
+    }
+
+    /// The commands in order, across groups.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl std::fmt::Display for CommandSeq {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, (command, grouped)) in self.commands.iter().zip(&self.grouped).enumerate() {
+            if i > 0 {
+                f.write_str(if *grouped { ", " } else { "; " })?;
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
     }
 }
 
@@ -599,6 +2857,9 @@ macro_rules! cmd {
       cmd!($($rest)*).with_criteria(vec![$($crate::criteria::$k($($v)?)),*])
     //   cmd!($($rest)*).with_criteria(vec![$($k($($v)?)),*])
   };
+  (exec_always $($args:tt)*) => {
+      $crate::command::exec_always(format!($($args)*))
+  };
   (exec $($args:tt)*) => {
       $crate::command::exec(format!($($args)*))
   };
@@ -611,16 +2872,36 @@ macro_rules! cmd {
 mod tests {
     use super::*;
 
+    #[test]
+    fn sockets_in_runtime_dir() {
+        let dir = std::env::temp_dir().join(format!("ksway-runtime-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("wayland-1"), b"").unwrap();
+        let _older = std::os::unix::net::UnixListener::bind(dir.join("sway-ipc.0.1.sock")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let _newer = std::os::unix::net::UnixListener::bind(dir.join("sway-ipc.0.2.sock")).unwrap();
+
+        // Listed twice, as when XDG_RUNTIME_DIR is /run/user/$UID.
+        let sockets = sway_sockets_in(vec![dir.clone(), dir.clone()], current_uid());
+        assert_eq!(
+            sockets,
+            vec![dir.join("sway-ipc.0.2.sock"), dir.join("sway-ipc.0.1.sock")]
+        );
+        assert!(sway_sockets_in(vec![dir.clone()], current_uid().map(|uid| uid + 1)).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     fn verify_buffer(buf: &[u8], code: IpcCommandCode, payload: &[u8]) {
         let prefix = b"i3-ipc";
         assert_eq!(&buf[0..prefix.len()], prefix);
         assert_eq!(
             buf[prefix.len()..prefix.len() + 4],
-            dbg!(payload.len() as u64).to_ne_bytes()
+            dbg!(payload.len() as u32).to_ne_bytes()
         );
         assert_eq!(
             buf[prefix.len() + 4..prefix.len() + 8],
-            dbg!(code as u64).to_ne_bytes()
+            dbg!(code as u32).to_ne_bytes()
         );
         assert_eq!(&buf[prefix.len() + 8..], payload);
     }
@@ -628,32 +2909,70 @@ mod tests {
     #[test]
     fn ipc_command_write() {
         {
-            let mut buffer = Vec::new();
             // dbg!(IpcCommand::Run("exec st".into()))
-            dbg!(ipc_command::run("exec st"))
-                .write(&mut buffer)
-                .unwrap();
+            let buffer = core::encode(&dbg!(ipc_command::run("exec st")));
             verify_buffer(&buffer, IpcCommandCode::RunCommand, b"exec st");
         }
         {
-            let mut buffer = Vec::new();
             // dbg!(IpcCommand::SendTick("HELLO WORLD".into()))
-            dbg!(ipc_command::tick("HELLO WORLD"))
-                .write(&mut buffer)
-                .unwrap();
+            let buffer = core::encode(&dbg!(ipc_command::tick("HELLO WORLD")));
             verify_buffer(&buffer, IpcCommandCode::SendTick, b"HELLO WORLD");
         }
         {
             use IpcEvent::*;
-            let mut buffer = Vec::new();
             // dbg!(IpcCommand::Subscribe(vec![Window, Tick]))
-            dbg!(ipc_command::subscribe(vec![Window, Tick]))
-                .write(&mut buffer)
-                .unwrap();
+            let buffer = core::encode(&dbg!(ipc_command::subscribe(vec![Window, Tick])));
             verify_buffer(&buffer, IpcCommandCode::Subscribe, b"[\"window\",\"tick\"]");
         }
     }
 
+    #[test]
+    fn event_codes() {
+        use std::convert::TryFrom;
+
+        assert_eq!(EventType::Workspace.code().raw(), 0x8000_0000);
+        // The output event, which the broadcaster needs, sits between workspace and mode.
+        let output = EventCode::from_raw(0x8000_0001).unwrap();
+        assert_eq!(EventType::try_from(output), Ok(EventType::Output));
+        assert_eq!(EventType::Mode.code().raw(), 0x8000_0002);
+        assert_eq!(EventType::BarStatusUpdate.code().raw(), 0x8000_0014);
+        assert_eq!(EventType::Input.code().raw(), 0x8000_0015);
+        for &event in EventType::ALL {
+            assert_eq!(EventType::try_from(event.code()), Ok(event));
+            assert_eq!(EventType::from_name(event.name()), Some(event));
+        }
+        assert_eq!(EventType::NAMES.len(), EventType::ALL.len());
+        assert_eq!(EventCode::from_raw(4), None);
+        let unknown = EventCode::from_raw(0x8000_00ff).unwrap();
+        assert_eq!(EventType::try_from(unknown), Err(unknown));
+        assert_eq!(
+            serde_json::to_string(&EventType::BarconfigUpdate).unwrap(),
+            r#""barconfig_update""#
+        );
+    }
+
+    #[test]
+    fn event_mask() {
+        const EVENTS: EventMask = EventMask::TICK.union(EventMask::WINDOW);
+        assert_eq!(
+            EVENTS,
+            EventMask::from(vec![EventType::Tick, EventType::Window])
+        );
+        assert!(EVENTS.contains_event(EventType::Window));
+        assert!(!EVENTS.contains_event(EventType::Workspace));
+        assert_eq!(
+            serde_json::to_string(&EVENTS).unwrap(),
+            r#"["window","tick"]"#
+        );
+        let mask: EventMask = serde_json::from_str(r#"["bar_state_update","output"]"#).unwrap();
+        assert_eq!(mask, EventMask::OUTPUT | EventMask::BAR_STATUS_UPDATE);
+        assert_eq!(
+            serde_json::to_string(&(EventMask::BAR_STATUS_UPDATE | EventMask::INPUT)).unwrap(),
+            r#"["bar_state_update","input"]"#
+        );
+        assert_eq!(EventMask::all().events().count(), EventType::ALL.len());
+    }
+
     #[test]
     fn criteria_command() {
         use command::*;
@@ -674,5 +2993,549 @@ mod tests {
                 .to_string(),
             r#"[con_mark="123" con_id="123" workspace="__focused__"] 123123"#
         );
+        assert_eq!(
+            &raw("focus").with_criteria(vec![pid(4242u32)]).to_string(),
+            r#"[pid="4242"] focus"#
+        );
+        assert_eq!("pid=4242".parse::<Criteria>(), Ok(pid(4242u32)));
+        assert_eq!(urgent(Urgency::Latest).to_string(), r#"urgent="latest""#);
+        assert_eq!(
+            "urgent=oldest".parse::<Criteria>(),
+            Ok(urgent(Urgency::Oldest))
+        );
+        assert_eq!(
+            "urgent=soon".parse::<Criteria>(),
+            Ok(urgent(Urgency::Other("soon".into())))
+        );
+        assert_eq!(urgent("newest"), urgent(Urgency::Newest));
+        assert_eq!(window_type("dialog"), window_type(WindowType::Dialog));
+        assert_eq!(
+            window_type(WindowType::DropdownMenu).to_string(),
+            r#"window_type="dropdown_menu""#
+        );
+        assert_eq!(
+            window_type("desktop"),
+            Criteria::WindowType(WindowType::Other("desktop".into()))
+        );
+    }
+
+    #[test]
+    fn criteria_escaping() {
+        use criteria::*;
+
+        let quoted = title(r#"say "hi" \o/"#);
+        assert_eq!(quoted.to_string(), r#"title="say \"hi\" \\o/""#);
+        assert_eq!(quoted.to_string().parse::<Criteria>(), Ok(quoted));
+        for value in &[r"C:\", r#"\""#, r"\\", r#"a\\"b"#] {
+            let c = title(*value);
+            assert_eq!(c.to_string().parse::<Criteria>(), Ok(c));
+        }
+        assert_eq!(exact("a.out (1)"), r"^a\.out \(1\)$");
+        assert_eq!(app_id(exact("[x]")).to_string(), r#"app_id="^\\[x\\]$""#);
+
+        let tree = serde_json::json!({"type": "root", "nodes": [
+            {"type": "workspace", "name": "1", "nodes": [
+                {"type": "con", "id": 1, "name": "a.out (1)", "marks": ["m*"]},
+            ]},
+        ]});
+        let window = tree::windows(&tree).next().unwrap();
+        assert!(title(exact("a.out (1)")).matches(&window));
+        assert!(!title(exact("a.out")).matches(&window));
+        assert!(con_mark(exact("m*")).matches(&window));
+        assert!(workspace(exact("1")).matches(&window));
+        // A regular expression, in which the parentheses are a group.
+        assert!(!title("^a.out (1)$").matches(&window));
+    }
+
+    #[test]
+    fn criteria_matcher() {
+        use criteria::*;
+
+        let tree = serde_json::json!({"type": "root", "nodes": [{"type": "output", "name": "DP-1", "nodes": [
+            {"type": "workspace", "name": "1", "nodes": [
+                {"type": "con", "id": 10, "name": "vim main.rs", "app_id": "foot", "focused": true},
+                {"type": "con", "id": 11, "name": "Mozilla Firefox", "shell": "xwayland",
+                 "window_properties": {"class": "firefox"}, "marks": ["web"]},
+            ]},
+            {"type": "workspace", "name": "2", "floating_nodes": [
+                {"type": "floating_con", "id": 12, "name": "mpv", "app_id": "mpv", "urgent": true},
+            ]},
+        ]}]});
+        let matching = |criteria: &[Criteria]| -> Vec<u64> {
+            let matcher = Matcher::new(criteria).unwrap().focused_in(&tree);
+            tree::windows(&tree)
+                .filter(|node| matcher.matches(node))
+                .filter_map(|node| node.id())
+                .collect()
+        };
+
+        assert_eq!(matching(&[title("main")]), vec![10]);
+        assert_eq!(matching(&[title("^(vim|mpv)")]), vec![10, 12]);
+        assert_eq!(matching(&[class(".")]), vec![11]);
+        assert_eq!(matching(&[con_mark("^w".into())]), vec![11]);
+        assert_eq!(matching(&[floating()]), vec![12]);
+        assert_eq!(
+            matching(&[tiling(), workspace("1".to_string())]),
+            vec![10, 11]
+        );
+        assert_eq!(matching(&[urgent(Urgency::Latest)]), vec![12]);
+        assert_eq!(matching(&[con_id(focused())]), vec![10]);
+        assert_eq!(matching(&[workspace(focused())]), vec![10, 11]);
+        assert_eq!(matching(&[app_id::<String, _>(focused())]), vec![10]);
+        assert_eq!(matching(&[]), vec![10, 11, 12]);
+        assert!(Matcher::new(&[title("(")]).is_err());
+
+        let mpv = tree::windows(&tree).last().unwrap();
+        assert!(app_id("mp").matches(&mpv));
+        assert!(!con_id(focused()).matches(&mpv));
+    }
+
+    #[test]
+    fn typed_commands() {
+        use command::*;
+
+        let commands = [
+            focus(),
+            focus_on(FocusTarget::Direction(Direction::Left)),
+            focus_on(FocusTarget::Output("HDMI-A-1".into())),
+            move_to(MoveTarget::Direction(Direction::Up, Some(20))),
+            move_to(MoveTarget::Workspace("2: web".into())),
+            move_to(MoveTarget::Scratchpad),
+            resize(Resize::Grow(Dimension::Width, 10, Unit::Ppt)),
+            resize(Resize::Set(800, 600, Unit::Px)),
+            switch_workspace(WorkspaceTarget::Number(3)),
+            switch_workspace(WorkspaceTarget::Name(r#"say "hi""#.into())),
+            layout(Layout::Tabbed),
+            layout_toggle(false),
+            kill(),
+            fullscreen(Toggle::Toggle),
+            set_floating(Toggle::Enable),
+            sticky(Toggle::Disable),
+            border(Border::Pixel(Some(2))),
+            border(Border::Normal(None)),
+            opacity(0.5),
+            split(Split::Vertical),
+            mark("a"),
+            add_mark("a"),
+            toggle_mark("b"),
+            unmark("a"),
+            unmark_all(),
+            scratchpad_show(),
+        ];
+        assert_eq!(
+            commands.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "focus",
+                "focus left",
+                r#"focus output "HDMI-A-1""#,
+                "move up 20 px",
+                r#"move container to workspace "2: web""#,
+                "move scratchpad",
+                "resize grow width 10 ppt",
+                "resize set 800 px 600 px",
+                "workspace number 3",
+                r#"workspace "say \"hi\"""#,
+                "layout tabbed",
+                "layout toggle split",
+                "kill",
+                "fullscreen toggle",
+                "floating enable",
+                "sticky disable",
+                "border pixel 2",
+                "border normal",
+                "opacity 0.5",
+                "split vertical",
+                r#"mark --replace "a""#,
+                r#"mark --add "a""#,
+                r#"mark --add --toggle "b""#,
+                r#"unmark "a""#,
+                "unmark",
+                "scratchpad show",
+            ]
+        );
+        assert_eq!(
+            kill().with_criteria(vec![criteria::con_id(4)]).to_string(),
+            r#"[con_id="4"] kill"#
+        );
+    }
+
+    #[test]
+    fn json_accessors() {
+        let node = serde_json::json!({
+            "id": 7, "name": "vim", "app_id": "foot", "focused": true, "type": "floating_con",
+            "rect": {"x": 1, "y": 2, "width": 300, "height": 400},
+        });
+        assert_eq!(node.con_id(), Some(7));
+        assert_eq!(node.name(), Some("vim"));
+        assert_eq!(node.app_id(), Some("foot"));
+        assert_eq!(node.rect().map(|rect| rect.width), Some(300));
+        assert!(node.is_focused() && node.is_floating());
+        let empty = serde_json::json!({"rect": {"x": 1}});
+        assert_eq!((empty.con_id(), empty.rect()), (None, None));
+        assert!(!empty.is_focused() && !empty.is_floating());
+    }
+
+    #[test]
+    fn output_commands() {
+        use command::output::{self, Background, Fit, Mode, Transform};
+
+        let commands = [
+            output::mode("DP-1", Mode::new(2560, 1440).refresh(143.912)),
+            output::mode("DP-1", Mode::new(1920, 1080)),
+            output::position("DP-1", 0, -1080),
+            output::scale("eDP-1", 1.5),
+            output::transform("eDP-1", Transform::Flipped90),
+            output::dpms("*", false),
+            output::power("HDMI-A-1", true),
+            output::bg("*", Background::Image("/tmp/my wall.png".into(), Fit::Fill)),
+            output::bg("*", Background::SolidColor("#1d2021".into())),
+            output::disable("HDMI-A-1"),
+        ];
+        assert_eq!(
+            commands.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                r#"output "DP-1" mode 2560x1440@143.912Hz"#,
+                r#"output "DP-1" mode 1920x1080"#,
+                r#"output "DP-1" position 0 -1080"#,
+                r#"output "eDP-1" scale 1.5"#,
+                r#"output "eDP-1" transform flipped-90"#,
+                r#"output "*" dpms off"#,
+                r#"output "HDMI-A-1" power on"#,
+                r#"output "*" bg "/tmp/my wall.png" fill"#,
+                "output \"*\" bg #1d2021 solid_color",
+                r#"output "HDMI-A-1" disable"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn input_commands() {
+        use command::input::{self, AccelProfile, LayoutIndex, ScrollMethod, SendEvents};
+
+        let commands = [
+            input::xkb_layout("type:keyboard", "us,de"),
+            input::xkb_options("*", "grp:alt_shift_toggle"),
+            input::xkb_switch_layout("type:keyboard", LayoutIndex::Next),
+            input::repeat_delay("type:keyboard", 250),
+            input::accel_profile("1133:49291:Logitech_G502", AccelProfile::Flat),
+            input::pointer_accel("type:pointer", -0.25),
+            input::natural_scroll("type:touchpad", true),
+            input::scroll_method("type:touchpad", ScrollMethod::TwoFinger),
+            input::tap("type:touchpad", false),
+            input::events("type:touchpad", SendEvents::DisabledOnExternalMouse),
+        ];
+        assert_eq!(
+            commands.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                r#"input "type:keyboard" xkb_layout "us,de""#,
+                r#"input "*" xkb_options "grp:alt_shift_toggle""#,
+                r#"input "type:keyboard" xkb_switch_layout next"#,
+                r#"input "type:keyboard" repeat_delay 250"#,
+                r#"input "1133:49291:Logitech_G502" accel_profile flat"#,
+                r#"input "type:pointer" pointer_accel -0.25"#,
+                r#"input "type:touchpad" natural_scroll enabled"#,
+                r#"input "type:touchpad" scroll_method two_finger"#,
+                r#"input "type:touchpad" tap disabled"#,
+                r#"input "type:touchpad" events disabled_on_external_mouse"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn seat_commands() {
+        use command::seat::{self, Button, Cursor, HideCursor, KeyboardGrouping};
+
+        let commands = [
+            seat::cursor("*", Cursor::Set(960, 540)),
+            seat::cursor("seat0", Cursor::Move(-10, 5)),
+            seat::cursor("-", Cursor::Press(Button::Right)),
+            seat::cursor("-", Cursor::Release(Button::Forward)),
+            seat::hide_cursor("*", HideCursor::After(3000)),
+            seat::hide_cursor("*", HideCursor::WhenTyping(true)),
+            seat::keyboard_grouping("seat0", KeyboardGrouping::Smart),
+        ];
+        assert_eq!(
+            commands.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                r#"seat "*" cursor set 960 540"#,
+                r#"seat "seat0" cursor move -10 5"#,
+                r#"seat "-" cursor press button3"#,
+                r#"seat "-" cursor release button9"#,
+                r#"seat "*" hide_cursor 3000"#,
+                r#"seat "*" hide_cursor when-typing enable"#,
+                r#"seat "seat0" keyboard_grouping smart"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn bind_commands() {
+        use command::bind::{self, Binding, Flag, Modifier::*};
+
+        assert_eq!(
+            bind::bindsym(&[Super, Shift], "q", command::kill()).to_string(),
+            r#"bindsym Mod4+Shift+q "kill""#
+        );
+        assert_eq!(
+            bind::bindcode(&[], 121, cmd!("exec pamixer -t")).to_string(),
+            r#"bindcode 121 "exec pamixer -t""#
+        );
+        // Quoted, so that sway doesn't run the rest right away.
+        assert_eq!(
+            bind::bindsym(&[Super], "p", cmd!(r#"exec grim; exec notify-send "done""#)).to_string(),
+            r#"bindsym Mod4+p "exec grim; exec notify-send \"done\"""#
+        );
+        assert_eq!(
+            bind::bind(
+                Binding::sym("l")
+                    .modifier(Ctrl)
+                    .flag(Flag::Locked)
+                    .flag(Flag::ToCode)
+                    .flag(Flag::InputDevice("1:1:AT_Translated_Set_2_keyboard".into())),
+                command::reload()
+            )
+            .to_string(),
+            r#"bindsym --locked --to-code --input-device=1:1:AT_Translated_Set_2_keyboard Ctrl+l "reload""#
+        );
+        assert_eq!(
+            bind::unbindsym(&[Alt], "Tab").to_string(),
+            "unbindsym Mod1+Tab"
+        );
+        assert_eq!(
+            bind::unbindcode(&[Super], 36).to_string(),
+            "unbindcode Mod4+36"
+        );
+    }
+
+    #[test]
+    fn command_from_args() {
+        let args = ["[app_id=\"foot\"]", "move", "to", "workspace", "\"1: web\""];
+        assert_eq!(
+            Command::from_args(args).to_string(),
+            r#"[app_id="foot"] move to workspace "1: web""#
+        );
+        assert_eq!(
+            Command::from_args(vec!["exec".to_string(), " a  b ".to_string()]).to_string(),
+            "exec  a  b "
+        );
+        assert_eq!(Command::from_args(Vec::<String>::new()).to_string(), "");
+    }
+
+    #[test]
+    fn command_list() {
+        let list = cmd!([con_id = 3] "focus")
+            .and(command::set_floating(command::Toggle::Toggle))
+            .and(command::kill())
+            .then(cmd!("workspace 2"))
+            .then(command::reload());
+        assert_eq!(
+            list.to_string(),
+            r#"[con_id="3"] focus, floating toggle, kill; workspace 2; reload"#
+        );
+        assert_eq!(list.commands().len(), 5);
+        assert_eq!(
+            command::kill().then(command::reload()).to_string(),
+            "kill; reload"
+        );
+    }
+
+    #[test]
+    fn session_commands() {
+        use command::*;
+
+        assert_eq!(
+            cmd!(exec_always "swayidle -w {}", "timeout 300").to_string(),
+            "exec_always swayidle -w timeout 300"
+        );
+        let commands = [
+            workspace_auto_back_and_forth(true),
+            focus_follows_mouse(FocusFollowsMouse::Always),
+            mouse_warping(MouseWarping::None),
+            workspace_layout(WorkspaceLayout::Tabbed),
+            default_orientation(Orientation::Auto),
+        ];
+        assert_eq!(
+            join(commands.iter().map(ToString::to_string), "; "),
+            "workspace_auto_back_and_forth yes; focus_follows_mouse always; mouse_warping none; \
+             workspace_layout tabbed; default_orientation auto"
+        );
+    }
+
+    #[test]
+    fn command_seq() {
+        let seq = CommandSeq::new()
+            .when(false, cmd!("workspace 1"))
+            .for_each(vec![3, 5], |id| cmd!([con_id = id] "kill"))
+            .then(cmd!(exec "st"));
+        assert_eq!(seq.commands().len(), 3);
+        assert_eq!(
+            seq.to_string(),
+            r#"[con_id="3"] kill; [con_id="5"] kill; exec st"#
+        );
+        assert_eq!(CommandSeq::new().to_string(), "");
+        assert_eq!(
+            CommandSeq::new()
+                .and(command::kill())
+                .and(command::reload())
+                .to_string(),
+            "kill, reload"
+        );
+    }
+
+    #[test]
+    fn criteria_from_json() {
+        use criteria::*;
+        use serde_json::json;
+
+        assert_eq!(
+            Criteria::from_json(&json!({"app_id": "kitty", "floating": true, "con_id": 5}))
+                .unwrap(),
+            vec![app_id("kitty"), con_id(5), floating()]
+        );
+        assert_eq!(
+            Criteria::from_json(&json!({"workspace": "__focused__"})).unwrap(),
+            vec![workspace(focused())]
+        );
+        for invalid in &[
+            json!(["app_id"]),
+            json!({"floating": false}),
+            json!({"con_id": "5"}),
+            json!({"title": 1}),
+            json!({"app": "kitty"}),
+            json!({"floating": true, "tiling": true}),
+        ] {
+            assert!(Criteria::from_json(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn bar_configs() {
+        let server = testing::MockServer::start().unwrap();
+        server.reply(&ipc_command::get_bar_config(), r#"["bar-0","bar-1"]"#);
+        for id in &["bar-0", "bar-1"] {
+            server.reply(
+                &ipc_command::get_bar_config_for(*id),
+                format!(r#"{{"id":"{}","mode":"dock","position":"top"}}"#, id),
+            );
+        }
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let ids: Vec<_> = client
+            .get_bar_configs()
+            .unwrap()
+            .into_iter()
+            .map(|bar| bar.id)
+            .collect();
+        assert_eq!(ids, vec!["bar-0", "bar-1"]);
+        let payloads: Vec<_> = server
+            .received()
+            .into_iter()
+            .map(|frame| (frame.payload_type, frame.payload))
+            .collect();
+        let code = IpcCommandCode::GetBarConfig as u32;
+        assert_eq!(
+            payloads,
+            vec![
+                (code, b"".to_vec()),
+                (code, b"bar-0".to_vec()),
+                (code, b"bar-1".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_payloads() {
+        let server = testing::MockServer::start().unwrap();
+        server.reply(&ipc_command::get_workspaces(), "");
+        server.reply(&ipc_command::get_version(), "");
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert_eq!(client.get_workspaces().unwrap(), b"");
+        assert_eq!(client.get_workspaces_json().unwrap(), JsonValue::Null);
+        assert!(client.get_workspaces_typed().unwrap().is_empty());
+        assert_eq!(client.focused_workspace().unwrap(), None);
+        // A reply which can't be empty is still an error, rather than a panic.
+        match client.get_version_typed() {
+            Err(Error::InvalidReply { command, .. }) => {
+                assert_eq!(command, IpcCommandCode::GetVersion)
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_with_context() {
+        let server = testing::MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::run(""),
+            r#"[{"success": true}, {"success": false, "error": "No matching node."}]"#,
+        );
+        server.reply(&ipc_command::get_tree(), "{");
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let err = client.run_checked("nop; [con_id=0] kill").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`nop; [con_id=0] kill` failed: No matching node."
+        );
+        match client.get_tree_json() {
+            Err(Error::InvalidReply { command, reply, .. }) => {
+                assert_eq!(
+                    (command, reply.as_slice()),
+                    (IpcCommandCode::GetTree, &b"{"[..])
+                )
+            }
+            other => panic!("{:?}", other),
+        }
+        server.disconnect();
+        match client.get_workspaces_typed() {
+            Err(Error::Request { command, error }) => {
+                assert_eq!(command, IpcCommandCode::GetWorkspaces);
+                assert!(
+                    matches!(*error, Error::Disconnected | Error::Io(_)),
+                    "{}",
+                    error
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn find_by_mark() {
+        let server = testing::MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_tree(),
+            r#"{"id": 1, "type": "root", "rect": {"x": 0, "y": 0, "width": 0, "height": 0}, "nodes": [
+                {"id": 2, "type": "con", "rect": {"x": 0, "y": 0, "width": 0, "height": 0}, "marks": ["a", "b"]}
+            ]}"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert_eq!(
+            client.find_by_mark("b").unwrap().map(|node| node.id),
+            Some(2)
+        );
+        assert!(client.find_by_mark("c").unwrap().is_none());
+    }
+
+    #[test]
+    fn criteria_dedup() {
+        use command::*;
+        use criteria::*;
+
+        let command = exec("st")
+            .with_criteria(vec![con_id(1), app_id("foot"), floating()])
+            .with_criteria(vec![con_id(2), tiling(), con_mark("a".into())])
+            .with_criteria(vec![app_id("st"), app_id("kitty")]);
+        assert_eq!(
+            command.to_string(),
+            r#"[con_id="2" app_id="kitty" tiling con_mark="a"] exec st"#
+        );
+        assert_eq!(
+            command
+                .criteria()
+                .iter()
+                .map(Criteria::key)
+                .collect::<Vec<_>>(),
+            vec!["con_id", "app_id", "tiling", "con_mark"]
+        );
+        assert!(exec("st").criteria().is_empty());
     }
 }
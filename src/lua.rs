@@ -0,0 +1,275 @@
+//! A Lua scripting bridge, enabled with the `mlua` feature, for writing small automations as Lua
+//! scripts. `register()` makes the `sway` module available to `require`, and `run_file()` runs a
+//! script the way the `ksway-lua` example host does:
+//! ```lua
+//! #!/usr/bin/env ksway-lua
+//! local Sway = require 'sway'
+//!
+//! local sway = Sway.connect()
+//! for _, window in ipairs(sway:find({app_id = "mpv"})) do
+//!     sway:msg(Sway.formatCriteria({con_id = window.id}).." "..Sway.command.kill())
+//! end
+//! sway:close()
+//! ```
+//!
+//! The module has:
+//!
+//! - `connect([socket_path])`, returning a client with `msg(command)`, which returns the parsed
+//!   RUN_COMMAND reply, `getTree()`, `getWorkspaces()`, `getOutputs()`, `getMarks()`,
+//!   `getSeats()`, `getInputs()`, `getVersion()`, `focusedWindow()`, `focusedWorkspace()`,
+//!   `find(criteria)` and `close()`;
+//! - `formatCriteria(criteria)`, giving e.g. `[con_id="4" app_id="mpv"]`;
+//! - `command`, with builders returning command strings: `exec`, `execAlways`, `kill`, `focus`,
+//!   `mark`, `addMark`, `toggleMark`, `unmark`, `workspace`, `layout`, `opacity`,
+//!   `scratchpadShow` and `reload`.
+//!
+//! Criteria are tables with the same keys and values as `Criteria::from_json()`, and replies are
+//! tables with `null` turned into `nil`. Errors, from sway or invalid criteria, are raised as Lua
+//! errors.
+use std::path::Path;
+
+use itertools::join;
+use mlua::{Lua, LuaSerdeExt, SerializeOptions, Table, UserData, UserDataMethods, Value};
+
+use crate::command::{self, Layout, WorkspaceTarget};
+use crate::criteria::{Criteria, Matcher};
+use crate::{tree, Client, JsonValue, SwayClientJson};
+
+/// A client in Lua, which is `None` after `close()`.
+struct LuaClient(Option<Client>);
+
+impl LuaClient {
+    fn client(&mut self) -> mlua::Result<&mut Client> {
+        self.0
+            .as_mut()
+            .ok_or_else(|| mlua::Error::RuntimeError("the sway client is closed".into()))
+    }
+}
+
+/// `json` as a Lua value, with `null` as `nil` rather than mlua's truthy `NULL`.
+fn to_lua<'lua>(lua: &'lua Lua, json: &JsonValue) -> mlua::Result<Value<'lua>> {
+    let options = SerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+    lua.to_value_with(json, options)
+}
+
+fn criteria_from_lua(lua: &Lua, criteria: Table<'_>) -> mlua::Result<Vec<Criteria>> {
+    let json: JsonValue = lua.from_value(Value::Table(criteria))?;
+    Criteria::from_json(&json).map_err(mlua::Error::external)
+}
+
+/// Add a method returning the parsed reply of a query.
+macro_rules! query {
+    ($methods:expr, $name:literal, $query:ident) => {
+        $methods.add_method_mut($name, |lua, this, ()| {
+            let reply = this.client()?.$query().map_err(mlua::Error::external)?;
+            to_lua(lua, &reply)
+        });
+    };
+}
+
+impl UserData for LuaClient {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("msg", |lua, this, command: String| {
+            let reply = this
+                .client()?
+                .run_json(command)
+                .map_err(mlua::Error::external)?;
+            to_lua(lua, &reply)
+        });
+        query!(methods, "getTree", get_tree_json);
+        query!(methods, "getWorkspaces", get_workspaces_json);
+        query!(methods, "getOutputs", get_outputs_json);
+        query!(methods, "getMarks", get_marks_json);
+        query!(methods, "getSeats", get_seats_json);
+        query!(methods, "getInputs", get_inputs_json);
+        query!(methods, "getVersion", get_version_json);
+        methods.add_method_mut("focusedWindow", |lua, this, ()| {
+            let window = this
+                .client()?
+                .focused_window()
+                .map_err(mlua::Error::external)?;
+            to_lua(lua, &window.unwrap_or_default())
+        });
+        methods.add_method_mut("focusedWorkspace", |lua, this, ()| {
+            let workspace = this
+                .client()?
+                .focused_workspace()
+                .map_err(mlua::Error::external)?;
+            to_lua(lua, &workspace.unwrap_or_default())
+        });
+        // The containers matching the criteria, evaluated client side, see `Matcher`.
+        methods.add_method_mut("find", |lua, this, criteria: Table<'lua>| {
+            let criteria = criteria_from_lua(lua, criteria)?;
+            let tree = this
+                .client()?
+                .get_tree_json()
+                .map_err(mlua::Error::external)?;
+            let matcher = Matcher::new(&criteria)
+                .map_err(mlua::Error::external)?
+                .focused_in(&tree);
+            let found = lua.create_table()?;
+            for node in tree::nodes(&tree).filter(|node| matcher.matches(node)) {
+                found.push(to_lua(lua, node.node)?)?;
+            }
+            Ok(found)
+        });
+        methods.add_method_mut("close", |_, this, ()| {
+            this.0 = None;
+            Ok(())
+        });
+    }
+}
+
+/// Add a command builder taking `$arg` and returning the command as a string.
+macro_rules! builder {
+    ($table:expr, $lua:expr, $name:literal, |$arg:ident: $ty:ty| $command:expr) => {
+        $table.set(
+            $name,
+            $lua.create_function(|_, $arg: $ty| Ok($command.to_string()))?,
+        )?;
+    };
+}
+
+fn command_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let commands = lua.create_table()?;
+    builder!(commands, lua, "exec", |cmd: String| command::exec(cmd));
+    builder!(commands, lua, "execAlways", |cmd: String| {
+        command::exec_always(cmd)
+    });
+    builder!(commands, lua, "kill", |_args: ()| command::kill());
+    builder!(commands, lua, "focus", |_args: ()| command::focus());
+    builder!(commands, lua, "mark", |mark: String| command::mark(mark));
+    builder!(commands, lua, "addMark", |mark: String| {
+        command::add_mark(mark)
+    });
+    builder!(commands, lua, "toggleMark", |mark: String| {
+        command::toggle_mark(mark)
+    });
+    builder!(commands, lua, "unmark", |mark: Option<String>| match mark {
+        Some(mark) => command::unmark(mark),
+        None => command::unmark_all(),
+    });
+    builder!(commands, lua, "workspace", |name: String| {
+        command::switch_workspace(WorkspaceTarget::Name(name))
+    });
+    builder!(commands, lua, "opacity", |opacity: f64| {
+        command::opacity(opacity)
+    });
+    builder!(commands, lua, "scratchpadShow", |_args: ()| {
+        command::scratchpad_show()
+    });
+    builder!(commands, lua, "reload", |_args: ()| command::reload());
+    commands.set(
+        "layout",
+        lua.create_function(|lua, layout: Value<'_>| {
+            let layout: Layout = lua.from_value(layout)?;
+            Ok(command::layout(layout).to_string())
+        })?,
+    )?;
+    Ok(commands)
+}
+
+/// The `sway` module, see the module documentation.
+pub fn module(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let module = lua.create_table()?;
+    module.set(
+        "connect",
+        lua.create_function(|_, path: Option<String>| {
+            let client = match path {
+                Some(path) => Client::connect_to_path(path),
+                None => Client::connect(),
+            };
+            Ok(LuaClient(Some(client.map_err(mlua::Error::external)?)))
+        })?,
+    )?;
+    module.set(
+        "formatCriteria",
+        lua.create_function(|lua, criteria: Table<'_>| {
+            let criteria = criteria_from_lua(lua, criteria)?;
+            Ok(format!("[{}]", join(&criteria, " ")))
+        })?,
+    )?;
+    module.set("command", command_table(lua)?)?;
+    Ok(module)
+}
+
+/// Make `require 'sway'` return the module.
+pub fn register(lua: &Lua) -> mlua::Result<()> {
+    let loaded: Table<'_> = lua
+        .globals()
+        .get::<_, Table<'_>>("package")?
+        .get("loaded")?;
+    loaded.set("sway", module(lua)?)
+}
+
+/// Run the script at `path` with the module registered, and `args` as its global `arg` table,
+/// like the `lua` interpreter does. A shebang line is skipped.
+pub fn run_file<P: AsRef<Path>>(path: P, args: &[String]) -> mlua::Result<()> {
+    let path = path.as_ref();
+    let mut source = std::fs::read_to_string(path)?;
+    if source.starts_with("#!") {
+        // Keep the line, so that line numbers in errors still match.
+        let end = source.find('\n').unwrap_or(source.len());
+        source.replace_range(..end, "");
+    }
+    let lua = Lua::new();
+    register(&lua)?;
+    let arg = lua.create_sequence_from(args.iter().cloned())?;
+    arg.set(0, path.display().to_string())?;
+    lua.globals().set("arg", arg)?;
+    lua.load(&source)
+        .set_name(format!("@{}", path.display()))
+        .exec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc_command;
+    use crate::testing::MockServer;
+
+    #[test]
+    fn script() {
+        let server = MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_tree(),
+            r#"{"id": 1, "type": "root", "nodes": [
+                {"id": 2, "type": "output", "name": "DP-1", "nodes": [
+                    {"id": 3, "type": "workspace", "name": "1", "nodes": [
+                        {"id": 10, "type": "con", "app_id": "mpv", "marks": [], "focused": true},
+                        {"id": 11, "type": "con", "app_id": "foot", "marks": []}
+                    ]}
+                ]}
+            ]}"#,
+        );
+        server.reply(&ipc_command::run(""), r#"[{"success": true}]"#);
+        let lua = Lua::new();
+        register(&lua).unwrap();
+        lua.globals()
+            .set("socket", server.path().display().to_string())
+            .unwrap();
+        lua.load(
+            r#"
+            local Sway = require 'sway'
+            local sway = Sway.connect(socket)
+            local found = sway:find({app_id = "mpv"})
+            assert(#found == 1 and found[1].id == 10 and found[1].name == nil)
+            local reply = sway:msg(Sway.formatCriteria({con_id = found[1].id})
+                .." "..Sway.command.mark("a b"))
+            assert(reply[1].success)
+            sway:msg(Sway.command.layout("tabbed")..", "..Sway.command.unmark())
+            assert(not pcall(Sway.formatCriteria, {bogus = 1}))
+            sway:close()
+            assert(not pcall(sway.getTree, sway))
+            "#,
+        )
+        .exec()
+        .unwrap();
+        server.assert_run_commands(&[
+            r#"[con_id="10"] mark --replace "a b""#,
+            "layout tabbed, unmark",
+        ]);
+    }
+}
@@ -0,0 +1,473 @@
+//! Noticing outputs being plugged in, unplugged or changing mode, e.g. to move workspaces or
+//! restart a bar.
+//!
+//! Sway's output event doesn't say what changed, so `OutputWatcher` compares GET_OUTPUTS
+//! replies before and after output events, and workspace `init` events, which come with new
+//! outputs on versions without output events:
+//! ```no_run
+//! use ksway::outputs::{OutputChange, OutputWatcher};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut watcher = OutputWatcher::new(&mut client)?;
+//! let rx = client.subscribe(OutputWatcher::events())?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         let payload = serde_json::from_slice(&payload)?;
+//!         for change in watcher.handle_event(&mut client, event, &payload)? {
+//!             if let OutputChange::Connected(output) = change {
+//!                 println!("{} {} connected as {}", output.make, output.model, output.name);
+//!             }
+//!         }
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! A `ProfileManager` keeps arrangements of outputs, and applies the one saved for the outputs
+//! which are connected, like kanshi or autorandr:
+//! ```no_run
+//! use ksway::outputs::ProfileManager;
+//! use ksway::persist::{JsonFile, Store};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let store = JsonFile::in_state_dir("output-profiles")?;
+//! let mut profiles: ProfileManager = store.load()?.unwrap_or_default();
+//! profiles.snapshot(&mut client, "docked")?;
+//! store.save(&profiles)?;
+//! // ... and later, e.g. on `OutputChange::Connected`:
+//! if let Some(profile) = profiles.apply_matching(&mut client)? {
+//!     println!("applied {}", profile.name);
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use serde::{Deserialize, Serialize};
+
+use crate::command::output::{self, Transform};
+use crate::events::WorkspaceChange;
+use crate::persist::Persistent;
+use crate::reply::{Mode, Output};
+use crate::{CommandSeq, EventMask, IpcEvent, JsonValue, Result, SwayClientJson};
+
+/// A difference between two GET_OUTPUTS replies, for an output identified by its name, e.g.
+/// `DP-1`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputChange {
+    Connected(Output),
+    /// With the output as it last was.
+    Disconnected(Output),
+    /// The `current_mode` changed, which is `None` while an output is disabled.
+    ModeChanged {
+        output: Output,
+        from: Option<Mode>,
+    },
+}
+
+/// The outputs as of the last GET_OUTPUTS, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputWatcher {
+    outputs: Vec<Output>,
+}
+
+impl OutputWatcher {
+    /// Start from the outputs there are now.
+    pub fn new<C: SwayClientJson>(client: &mut C) -> Result<Self> {
+        Ok(Self::with_outputs(client.get_outputs_typed()?))
+    }
+
+    /// Start from `outputs`, e.g. an empty list to get every output as `Connected` on the
+    /// first `update()`.
+    pub fn with_outputs(outputs: Vec<Output>) -> Self {
+        Self { outputs }
+    }
+
+    /// The events to subscribe to for `handle_event()`.
+    pub fn events() -> EventMask {
+        EventMask::OUTPUT | EventMask::WORKSPACE
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Replace the known outputs with `outputs`, returning what changed: disconnected outputs
+    /// first, then connected and changed ones in the order of `outputs`.
+    pub fn diff(&mut self, outputs: Vec<Output>) -> Vec<OutputChange> {
+        let mut changes: Vec<OutputChange> = self
+            .outputs
+            .iter()
+            .filter(|old| outputs.iter().all(|new| new.name != old.name))
+            .map(|old| OutputChange::Disconnected(old.clone()))
+            .collect();
+        for new in &outputs {
+            match self.outputs.iter().find(|old| old.name == new.name) {
+                None => changes.push(OutputChange::Connected(new.clone())),
+                Some(old) if old.current_mode != new.current_mode => {
+                    changes.push(OutputChange::ModeChanged {
+                        output: new.clone(),
+                        from: old.current_mode,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        self.outputs = outputs;
+        changes
+    }
+
+    /// Query the outputs and compare them to the known ones, see `diff()`.
+    pub fn update<C: SwayClientJson>(&mut self, client: &mut C) -> Result<Vec<OutputChange>> {
+        Ok(self.diff(client.get_outputs_typed()?))
+    }
+
+    /// Feed a subscription event, returning the changes it led to. Only output events and
+    /// workspace `init` events cause a GET_OUTPUTS.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<Vec<OutputChange>> {
+        let relevant = match event {
+            IpcEvent::Output => true,
+            IpcEvent::Workspace => {
+                WorkspaceChange::from_payload(payload) == Some(WorkspaceChange::Init)
+            }
+            _ => false,
+        };
+        if relevant {
+            self.update(client)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// How one output is set up in a `Profile`. Outputs are recognized by their make, model and
+/// serial, whichever connector they are plugged into, or by the connector if they don't report
+/// a make, e.g. headless outputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutputSetup {
+    /// The connector when the profile was saved, e.g. `DP-1`.
+    pub name: String,
+    pub make: String,
+    pub model: String,
+    pub serial: String,
+    pub enabled: bool,
+    pub mode: Option<Mode>,
+    /// The position in layout coordinates.
+    pub x: i64,
+    pub y: i64,
+    pub scale: Option<f64>,
+    /// As sway writes it, e.g. `normal` or `90`.
+    pub transform: Option<String>,
+}
+
+impl OutputSetup {
+    pub fn from_output(output: &Output) -> Self {
+        Self {
+            name: output.name.clone(),
+            make: output.make.clone(),
+            model: output.model.clone(),
+            serial: output.serial.clone(),
+            enabled: output.active,
+            mode: output.current_mode,
+            x: output.rect.x,
+            y: output.rect.y,
+            // Sway reports -1 for disabled outputs.
+            scale: output.scale.filter(|scale| *scale > 0.0),
+            transform: output.transform.clone(),
+        }
+    }
+
+    pub fn matches(&self, output: &Output) -> bool {
+        let identified = !(self.make.is_empty() || self.make == "Unknown");
+        self.make == output.make
+            && self.model == output.model
+            && self.serial == output.serial
+            && (identified || self.name == output.name)
+    }
+
+    /// The commands setting up `name`, the connector it is on now.
+    fn commands(&self, name: &str) -> Result<CommandSeq> {
+        if !self.enabled {
+            return Ok(CommandSeq::new().then(output::disable(name)));
+        }
+        let mut seq = CommandSeq::new().then(output::enable(name));
+        if let Some(mode) = self.mode {
+            let mode = output::Mode::new(mode.width as u32, mode.height as u32)
+                .refresh(mode.refresh as f64 / 1000.0);
+            seq = seq.then(output::mode(name, mode));
+        }
+        seq = seq.then(output::position(name, self.x, self.y));
+        if let Some(scale) = self.scale {
+            seq = seq.then(output::scale(name, scale));
+        }
+        if let Some(transform) = &self.transform {
+            seq = seq.then(output::transform(name, transform.parse::<Transform>()?));
+        }
+        Ok(seq)
+    }
+}
+
+/// A named arrangement of a set of outputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub outputs: Vec<OutputSetup>,
+}
+
+impl Profile {
+    /// The arrangement of `outputs`, a GET_OUTPUTS reply.
+    pub fn snapshot<S: Into<String>>(name: S, outputs: &[Output]) -> Self {
+        Self {
+            name: name.into(),
+            outputs: outputs.iter().map(OutputSetup::from_output).collect(),
+        }
+    }
+
+    /// Pair every setup with one of `outputs`, preferring the connector it was saved on, or
+    /// `None` unless exactly the outputs of the profile are connected.
+    fn assign<'a>(&'a self, outputs: &'a [Output]) -> Option<Vec<(&'a OutputSetup, &'a Output)>> {
+        if self.outputs.len() != outputs.len() {
+            return None;
+        }
+        let mut free: Vec<&Output> = outputs.iter().collect();
+        self.outputs
+            .iter()
+            .map(|setup| {
+                let index = free
+                    .iter()
+                    .position(|output| setup.matches(output) && output.name == setup.name)
+                    .or_else(|| free.iter().position(|output| setup.matches(output)))?;
+                Some((setup, free.remove(index)))
+            })
+            .collect()
+    }
+
+    /// Whether the connected `outputs` are those of this profile.
+    pub fn matches(&self, outputs: &[Output]) -> bool {
+        self.assign(outputs).is_some()
+    }
+
+    /// The commands arranging `outputs` like this profile, or `None` if it doesn't match them.
+    /// Outputs are disabled first, so that enabled ones can take their place.
+    pub fn commands(&self, outputs: &[Output]) -> Result<Option<CommandSeq>> {
+        let mut assigned = match self.assign(outputs) {
+            Some(assigned) => assigned,
+            None => return Ok(None),
+        };
+        assigned.sort_by_key(|(setup, _)| setup.enabled);
+        let mut seq = CommandSeq::new();
+        for (setup, output) in assigned {
+            for command in setup.commands(&output.name)?.commands() {
+                seq = seq.then(command.clone());
+            }
+        }
+        Ok(Some(seq))
+    }
+}
+
+/// Saved `Profile`s, by name. Implements `Persistent` to be kept in a `persist::Store`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProfileManager {
+    profiles: Vec<Profile>,
+}
+
+impl Persistent for ProfileManager {
+    const KIND: &'static str = "output-profiles";
+    const VERSION: u32 = 1;
+}
+
+impl ProfileManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Add `profile`, replacing the one with the same name.
+    pub fn add(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)?;
+        Some(self.profiles.remove(index))
+    }
+
+    /// Save the current arrangement as the profile `name`.
+    pub fn snapshot<C: SwayClientJson>(&mut self, client: &mut C, name: &str) -> Result<()> {
+        let profile = Profile::snapshot(name, &client.get_outputs_typed()?);
+        self.add(profile);
+        Ok(())
+    }
+
+    /// The first profile, in the order they were added, for the connected `outputs`.
+    pub fn matching(&self, outputs: &[Output]) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches(outputs))
+    }
+
+    /// Arrange the outputs like the profile `name`. Returns whether it matched the connected
+    /// outputs, `false` too if there is no such profile.
+    pub fn apply<C: SwayClientJson>(&self, client: &mut C, name: &str) -> Result<bool> {
+        let profile = match self.get(name) {
+            Some(profile) => profile,
+            None => return Ok(false),
+        };
+        match profile.commands(&client.get_outputs_typed()?)? {
+            Some(seq) => {
+                client.run_checked(seq)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Arrange the outputs like the first profile matching them, returning that profile.
+    pub fn apply_matching<C: SwayClientJson>(&self, client: &mut C) -> Result<Option<&Profile>> {
+        let outputs = client.get_outputs_typed()?;
+        let profile = match self.matching(&outputs) {
+            Some(profile) => profile,
+            None => return Ok(None),
+        };
+        if let Some(seq) = profile.commands(&outputs)? {
+            client.run_checked(seq)?;
+        }
+        Ok(Some(profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc_command;
+    use crate::testing::MockServer;
+    use crate::Client;
+    use serde_json::json;
+
+    fn outputs(outputs: &[(&str, Option<i64>)]) -> Vec<u8> {
+        let outputs: Vec<JsonValue> = outputs
+            .iter()
+            .map(|(name, width)| {
+                let mode =
+                    width.map(|width| json!({"width": width, "height": 1080, "refresh": 60000}));
+                json!({
+                    "name": name, "active": mode.is_some(), "current_mode": mode,
+                    "rect": {"x": 0, "y": 0, "width": width.unwrap_or(0), "height": 1080},
+                })
+            })
+            .collect();
+        JsonValue::from(outputs).to_string().into_bytes()
+    }
+
+    #[test]
+    fn hotplug() {
+        let server = MockServer::start().unwrap();
+        let get_outputs = ipc_command::get_outputs();
+        server.reply(
+            &get_outputs,
+            outputs(&[("eDP-1", Some(1920)), ("DP-1", Some(2560))]),
+        );
+        // eDP-1 is disabled, DP-1 unplugged and HDMI-A-1 plugged in.
+        server.reply(
+            &get_outputs,
+            outputs(&[("eDP-1", None), ("HDMI-A-1", Some(1920))]),
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let mut watcher = OutputWatcher::new(&mut client).unwrap();
+        assert_eq!(watcher.outputs().len(), 2);
+
+        let window = json!({"change": "focus"});
+        let changes = watcher
+            .handle_event(&mut client, IpcEvent::Window, &window)
+            .unwrap();
+        assert!(changes.is_empty());
+        let unspecified = json!({"change": "unspecified"});
+        let changes = watcher
+            .handle_event(&mut client, IpcEvent::Output, &unspecified)
+            .unwrap();
+        let names: Vec<_> = changes
+            .iter()
+            .map(|change| match change {
+                OutputChange::Connected(output) => format!("+{}", output.name),
+                OutputChange::Disconnected(output) => format!("-{}", output.name),
+                OutputChange::ModeChanged { output, from } => {
+                    format!("{} {:?}", output.name, from.as_ref().map(|mode| mode.width))
+                }
+            })
+            .collect();
+        assert_eq!(names, vec!["-DP-1", "eDP-1 Some(1920)", "+HDMI-A-1"]);
+
+        let init = json!({"change": "init"});
+        let changes = watcher
+            .handle_event(&mut client, IpcEvent::Workspace, &init)
+            .unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn profiles() {
+        let monitor = |name: &str, serial: &str, x: i64, active: bool| {
+            json!({
+                "name": name, "make": "Dell Inc.", "model": "U2720Q", "serial": serial,
+                "active": active, "scale": if active { 1.5 } else { -1.0 },
+                "transform": if active { "90" } else { "normal" },
+                "current_mode": if active { json!({"width": 3840, "height": 2160, "refresh": 59997}) } else { JsonValue::Null },
+                "rect": {"x": x, "y": 0, "width": 1440, "height": 2560},
+            })
+        };
+        let docked: Vec<Output> = serde_json::from_value(json!([
+            monitor("DP-1", "A", 0, true),
+            monitor("DP-2", "B", 1440, false)
+        ]))
+        .unwrap();
+        let mut manager = ProfileManager::new();
+        manager.add(Profile::snapshot("laptop", &docked[..1]));
+        manager.add(Profile::snapshot("docked", &docked));
+        assert_eq!(manager.matching(&docked).unwrap().name, "docked");
+
+        // The same monitors, plugged into each other's connectors.
+        let swapped: Vec<Output> = serde_json::from_value(json!([
+            monitor("DP-2", "A", 0, false),
+            monitor("DP-1", "B", 0, false)
+        ]))
+        .unwrap();
+        let commands = manager
+            .get("docked")
+            .unwrap()
+            .commands(&swapped)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            commands.to_string(),
+            concat!(
+                r#"output "DP-1" disable; output "DP-2" enable; "#,
+                r#"output "DP-2" mode 3840x2160@59.997Hz; output "DP-2" position 0 0; "#,
+                r#"output "DP-2" scale 1.5; output "DP-2" transform 90"#,
+            )
+        );
+        let other: Vec<Output> =
+            serde_json::from_value(json!([monitor("DP-1", "C", 0, true)])).unwrap();
+        assert_eq!(manager.matching(&other), None);
+
+        manager.add(Profile::snapshot("laptop", &other));
+        assert_eq!(manager.profiles().len(), 2);
+        assert_eq!(manager.matching(&other).unwrap().name, "laptop");
+        assert!(manager.remove("laptop").is_some() && manager.get("laptop").is_none());
+    }
+}
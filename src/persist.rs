@@ -0,0 +1,187 @@
+//! Saving the state of long running services, so that it survives restarting the daemon.
+//!
+//! State types implement `Persistent`, which names them and versions their serialized form, and
+//! a `Store` saves and loads them. `JsonFile` is the default store:
+//! ```no_run
+//! use ksway::archive::{ArchiveConfig, ArchiveState, Archiver};
+//! use ksway::persist::{JsonFile, Store};
+//!
+//! let store = JsonFile::in_state_dir("archive")?;
+//! let state: ArchiveState = store.load()?.unwrap_or_default();
+//! let archiver = Archiver::with_state(ArchiveConfig::default(), state);
+//! // ... and every now and then, or before exiting:
+//! store.save(archiver.state())?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! What is saved is an envelope around the state, `{"kind": ..., "version": ..., "state": ...}`,
+//! so that a store can't be loaded as the wrong kind of state, and state saved by an older
+//! version can be migrated.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{Error, JsonValue, ParseError, Result};
+
+/// State which can be saved by a `Store`.
+pub trait Persistent: Serialize + DeserializeOwned {
+    /// What kind of state this is, e.g. `archive`.
+    const KIND: &'static str;
+    /// The version of the serialized form, to be bumped whenever it changes incompatibly.
+    const VERSION: u32;
+
+    /// Turn state saved with an older `version` into the current form. By default nothing can
+    /// be migrated.
+    fn migrate(version: u32, state: JsonValue) -> Result<JsonValue> {
+        let _ = state;
+        Err(Error::Parse(ParseError(format!(
+            "can't migrate {} state from version {} to {}",
+            Self::KIND,
+            version,
+            Self::VERSION
+        ))))
+    }
+}
+
+/// Where `Persistent` state is saved.
+pub trait Store {
+    /// `None` if nothing was saved yet.
+    fn load<T: Persistent>(&self) -> Result<Option<T>>;
+    fn save<T: Persistent>(&self, state: &T) -> Result<()>;
+}
+
+/// Wrap `state` in the envelope with its kind and version.
+pub fn encode<T: Persistent>(state: &T) -> Result<JsonValue> {
+    Ok(json!({
+        "kind": T::KIND,
+        "version": T::VERSION,
+        "state": serde_json::to_value(state)?,
+    }))
+}
+
+/// Unwrap state from its envelope, migrating it if it was saved with an older version. Saved
+/// state of another kind, or of a newer version, is an error.
+pub fn decode<T: Persistent>(mut envelope: JsonValue) -> Result<T> {
+    let invalid = |message: String| Error::Parse(ParseError(message));
+    if envelope["kind"] != T::KIND {
+        return Err(invalid(format!(
+            "expected {} state, got {}",
+            T::KIND,
+            envelope["kind"]
+        )));
+    }
+    let version = envelope["version"]
+        .as_u64()
+        .ok_or_else(|| invalid(format!("{} state without a version", T::KIND)))?
+        as u32;
+    let mut state = envelope["state"].take();
+    if version > T::VERSION {
+        return Err(invalid(format!(
+            "{} state has version {}, newer than {}",
+            T::KIND,
+            version,
+            T::VERSION
+        )));
+    } else if version < T::VERSION {
+        state = T::migrate(version, state)?;
+    }
+    Ok(serde_json::from_value(state)?)
+}
+
+/// Saves state as a JSON file. Writes are atomic: the file is written next to its final path
+/// and renamed into place, so a crash never leaves a truncated file behind.
+#[derive(Debug, Clone)]
+pub struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `$XDG_STATE_HOME/ksway/{name}.json`, or under `~/.local/state` if `XDG_STATE_HOME` isn't
+    /// set. Returns `Error::Io` if neither it nor `HOME` is set.
+    pub fn in_state_dir(name: &str) -> Result<Self> {
+        let dir = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+            .ok_or_else(|| std::io::Error::other("neither XDG_STATE_HOME nor HOME is set"))?;
+        Ok(Self::new(dir.join("ksway").join(format!("{}.json", name))))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Store for JsonFile {
+    fn load<T: Persistent>(&self) -> Result<Option<T>> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        decode(serde_json::from_slice(&contents)?).map(Some)
+    }
+
+    fn save<T: Persistent>(&self, state: &T) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = PathBuf::from(temp);
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&serde_json::to_vec_pretty(&encode(state)?)?)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl Persistent for Counter {
+        const KIND: &'static str = "counter";
+        const VERSION: u32 = 2;
+
+        /// Version 1 was a bare number.
+        fn migrate(version: u32, state: JsonValue) -> Result<JsonValue> {
+            match version {
+                1 => Ok(json!({ "count": state })),
+                _ => Err(Error::Parse(ParseError("unknown version".into()))),
+            }
+        }
+    }
+
+    #[test]
+    fn save_load_migrate() {
+        let dir = std::env::temp_dir().join(format!("ksway-persist-{}", std::process::id()));
+        let store = JsonFile::new(dir.join("nested/counter.json"));
+        assert_eq!(store.load::<Counter>().unwrap(), None);
+        store.save(&Counter { count: 3 }).unwrap();
+        assert_eq!(store.load::<Counter>().unwrap(), Some(Counter { count: 3 }));
+        assert_eq!(fs::read_dir(dir.join("nested")).unwrap().count(), 1);
+
+        let old = json!({"kind": "counter", "version": 1, "state": 7});
+        assert_eq!(decode::<Counter>(old).unwrap(), Counter { count: 7 });
+        let newer = json!({"kind": "counter", "version": 3, "state": {"count": 1}});
+        assert!(matches!(decode::<Counter>(newer), Err(Error::Parse(_))));
+        let other = json!({"kind": "archive", "version": 2, "state": {"count": 1}});
+        assert!(matches!(decode::<Counter>(other), Err(Error::Parse(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
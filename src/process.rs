@@ -0,0 +1,392 @@
+//! Relating processes to windows.
+//!
+//! Launch wrappers know the pid of what they spawned, but that often isn't the pid which owns the
+//! window: shells fork, and GTK single-instance apps hand off to a child. Matching against every
+//! descendant of the spawned process catches those cases.
+//!
+//! Going the other way, `terminate()` closes windows and falls back to signalling their process
+//! when they refuse to go away.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::cancel::{self, CancelToken};
+use crate::criteria::{Criteria, Matcher};
+use crate::events::WindowChange;
+use crate::json::windows;
+use crate::{cmd, Client, IpcEvent, JsonValue, Result, SwayClient, SwayClientJson, SwayJsonExt};
+
+/// Parse the parent pid out of the contents of `/proc/<pid>/stat`. The command name is
+/// parenthesized and can itself contain spaces and parentheses, so fields are counted from the
+/// last `)`.
+fn parse_ppid(stat: &str) -> Option<u32> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    // After the command name come the state and then the parent pid.
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Every pid which descends from `root`, including `root` itself, given a map from pid to parent
+/// pid.
+fn descendants(root: u32, parents: &HashMap<u32, u32>) -> HashSet<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (&pid, &ppid) in parents {
+        children.entry(ppid).or_default().push(pid);
+    }
+    let mut found = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(pid) = stack.pop() {
+        if found.insert(pid) {
+            stack.extend(children.get(&pid).into_iter().flatten());
+        }
+    }
+    found
+}
+
+/// Find `root_pid` and all of its descendant processes by walking `/proc`. Processes which exit
+/// while walking are skipped.
+pub fn process_tree(root_pid: u32) -> io::Result<HashSet<u32>> {
+    let mut parents = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if let Some(ppid) = fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .as_deref()
+            .and_then(parse_ppid)
+        {
+            parents.insert(pid, ppid);
+        }
+    }
+    Ok(descendants(root_pid, &parents))
+}
+
+/// Find the first window, in tree order, which belongs to `root_pid` or any of its descendant
+/// processes.
+pub fn find_window_for_process_tree<C: SwayClientJson>(
+    client: &mut C,
+    root_pid: u32,
+) -> Result<Option<JsonValue>> {
+    let pids = process_tree(root_pid)?;
+    let tree = client.get_tree_json()?;
+    Ok(windows(&tree)
+        .into_iter()
+        .find(|window| {
+            window.node["pid"]
+                .as_u64()
+                .is_some_and(|pid| pids.contains(&(pid as u32)))
+        })
+        .map(|window| window.node.clone()))
+}
+
+/// How a window went away in `terminate()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// The window closed after `kill`.
+    Closed,
+    /// The window closed after its process got SIGTERM.
+    Terminated,
+    /// Its process was sent SIGKILL.
+    Killed,
+    /// Signalling its process failed, e.g. with `PermissionDenied` for another user's process,
+    /// so the window is probably still open.
+    SignalFailed(io::ErrorKind),
+    /// The window didn't close and there was no process to signal, e.g. because it has no pid or
+    /// the process has already exited and is waiting to be reaped.
+    Lingering,
+}
+
+/// The con_id and pid of every window matching all of `criteria`, in tree order.
+fn targets(tree: &JsonValue, criteria: &[Criteria]) -> Result<Vec<(u64, Option<u32>)>> {
+    let matcher = Matcher::new(criteria)?.focused_in(tree);
+    Ok(crate::tree::windows(tree)
+        .filter(|window| matcher.matches(window))
+        .filter_map(|window| {
+            let pid = window.node["pid"].as_u64().map(|pid| pid as u32);
+            Some((window.id()?, pid))
+        })
+        .collect())
+}
+
+/// Whether `pid` is running, as opposed to gone or a zombie which only needs reaping and won't
+/// react to signals.
+fn is_running(pid: u32) -> bool {
+    fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| {
+            let rest = &stat[stat.rfind(')')? + 1..];
+            rest.split_whitespace().next().map(|state| state != "Z")
+        })
+        .unwrap_or(false)
+}
+
+fn signal(pid: u32, signal: libc::c_int) -> io::Result<()> {
+    // SAFETY: kill() only takes integers and touches no memory of ours.
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+        return Ok(());
+    }
+    match io::Error::last_os_error() {
+        // It exited in the meantime, which is what we wanted.
+        err if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+        err => Err(err),
+    }
+}
+
+/// Poll `events` until every window in `waiting` has closed or `deadline` has passed, removing
+/// the windows which closed.
+fn wait_for_close(
+    events: &mut Client,
+    rx: &crossbeam_channel::Receiver<(IpcEvent, Vec<u8>)>,
+    waiting: &mut HashMap<u64, Option<u32>>,
+    closed: &mut Vec<u64>,
+    deadline: Instant,
+    cancel: &CancelToken,
+) -> Result<()> {
+    while !waiting.is_empty() {
+        cancel.check()?;
+        while let Ok((event, payload)) = rx.try_recv() {
+            let payload: JsonValue = serde_json::from_slice(&payload)?;
+            if event == IpcEvent::Window
+                && WindowChange::from_payload(&payload) == Some(WindowChange::Close)
+            {
+                if let Some(id) = payload["container"].con_id() {
+                    if waiting.remove(&id).is_some() {
+                        closed.push(id);
+                    }
+                }
+            }
+        }
+        let now = Instant::now();
+        if waiting.is_empty() || now >= deadline {
+            break;
+        }
+        // Never past the deadline, and not so long that cancelling goes unnoticed.
+        events.poll_until(deadline.min(now + cancel::CHECK_INTERVAL))?;
+    }
+    Ok(())
+}
+
+/// Really close every window matching all of `criteria`. This sends `kill` and waits up to
+/// `grace` for the windows to close. The processes of windows which are still open then get
+/// SIGTERM and another `grace` period, and finally SIGKILL.
+///
+/// Close events are received on a second connection to the same socket, so `client` doesn't need
+/// to be subscribed. The criteria are evaluated client side, see `criteria::Matcher`.
+///
+/// Returns how each matching window went away, in tree order. A process which can't be
+/// signalled doesn't stop the others from being handled, see `Termination::SignalFailed`.
+pub fn terminate(
+    client: &mut Client,
+    criteria: &[Criteria],
+    grace: Duration,
+) -> Result<Vec<(u64, Termination)>> {
+    terminate_cancellable(client, criteria, grace, &CancelToken::new())
+}
+
+/// Like `terminate()`, but gives up with `Error::Cancelled` while waiting once `cancel` is
+/// cancelled. Signals which were already sent stay sent.
+pub fn terminate_cancellable(
+    client: &mut Client,
+    criteria: &[Criteria],
+    grace: Duration,
+    cancel: &CancelToken,
+) -> Result<Vec<(u64, Termination)>> {
+    let targets = targets(&client.get_tree_json()?, criteria)?;
+    if targets.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut events = Client::connect_to_path(client.socket_path())?;
+    let rx = events.subscribe(vec![IpcEvent::Window])?;
+
+    let kill = targets
+        .iter()
+        .map(|&(id, _)| cmd!([con_id=id] "kill").to_string())
+        .collect::<Vec<_>>()
+        .join("; ");
+    client.run(kill)?;
+
+    let mut waiting: HashMap<u64, Option<u32>> = targets.iter().cloned().collect();
+    let mut closed = Vec::new();
+    wait_for_close(
+        &mut events,
+        &rx,
+        &mut waiting,
+        &mut closed,
+        Instant::now() + grace,
+        cancel,
+    )?;
+    let mut outcomes: HashMap<u64, Termination> = closed
+        .drain(..)
+        .map(|id| (id, Termination::Closed))
+        .collect();
+
+    let mut lingering = HashSet::new();
+    for (&id, pid) in &waiting {
+        match pid {
+            Some(pid) if is_running(*pid) => {
+                if let Err(err) = signal(*pid, libc::SIGTERM) {
+                    outcomes.insert(id, Termination::SignalFailed(err.kind()));
+                }
+            }
+            _ => {
+                lingering.insert(id);
+            }
+        }
+    }
+    waiting.retain(|id, _| !lingering.contains(id) && !outcomes.contains_key(id));
+    wait_for_close(
+        &mut events,
+        &rx,
+        &mut waiting,
+        &mut closed,
+        Instant::now() + grace,
+        cancel,
+    )?;
+    outcomes.extend(closed.drain(..).map(|id| (id, Termination::Terminated)));
+
+    for (&id, pid) in &waiting {
+        match pid {
+            Some(pid) if is_running(*pid) => {
+                let outcome = match signal(*pid, libc::SIGKILL) {
+                    Ok(()) => Termination::Killed,
+                    Err(err) => Termination::SignalFailed(err.kind()),
+                };
+                outcomes.insert(id, outcome);
+            }
+            _ => {
+                lingering.insert(id);
+            }
+        }
+    }
+    outcomes.extend(lingering.into_iter().map(|id| (id, Termination::Lingering)));
+
+    Ok(targets
+        .into_iter()
+        .map(|(id, _)| (id, outcomes[&id]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat() {
+        assert_eq!(parse_ppid("1234 (bash) S 1000 1234 1234 0 -1"), Some(1000));
+        assert_eq!(parse_ppid("42 (Web Content) (x)) R 7 42 42 0 -1"), Some(7));
+        assert_eq!(parse_ppid("garbage"), None);
+    }
+
+    #[test]
+    fn find_descendants() {
+        let parents: HashMap<u32, u32> = vec![(2, 1), (3, 2), (4, 2), (5, 3), (6, 1), (7, 6)]
+            .into_iter()
+            .collect();
+        let mut found: Vec<_> = descendants(2, &parents).into_iter().collect();
+        found.sort();
+        assert_eq!(found, vec![2, 3, 4, 5]);
+        assert_eq!(descendants(99, &parents).len(), 1);
+    }
+
+    #[test]
+    fn own_process_tree() {
+        let me = std::process::id();
+        assert!(process_tree(me).unwrap().contains(&me));
+        assert!(is_running(me));
+    }
+
+    #[test]
+    fn terminate_targets() {
+        let tree = serde_json::json!({
+            "type": "root",
+            "nodes": [{
+                "type": "output",
+                "name": "DP-1",
+                "nodes": [{
+                    "type": "workspace",
+                    "name": "1",
+                    "nodes": [
+                        {"type": "con", "id": 10, "app_id": "foot", "pid": 100, "nodes": []},
+                        {"type": "con", "id": 11, "app_id": "firefox", "pid": 101, "nodes": []},
+                    ],
+                    "floating_nodes": [
+                        {"type": "floating_con", "id": 12, "app_id": "foot", "nodes": []},
+                    ],
+                }],
+            }],
+        });
+        assert_eq!(
+            targets(&tree, &[crate::criteria::app_id("foot")]).unwrap(),
+            vec![(10, Some(100)), (12, None)]
+        );
+    }
+
+    #[test]
+    fn terminate_windows() {
+        use crate::testing::MockServer;
+        use crate::{ipc_command, IpcCommandCode};
+        use std::sync::Arc;
+
+        let mut stubborn = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let server = Arc::new(MockServer::start().unwrap());
+        let tree = serde_json::json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "workspace", "name": "1", "nodes": [
+                {"id": 10, "type": "con", "app_id": "foot"},
+                {"id": 11, "type": "con", "app_id": "foot", "pid": stubborn.id()},
+                {"id": 12, "type": "con", "app_id": "foot"},
+            ]},
+        ]});
+        server.reply(&ipc_command::get_tree(), tree.to_string());
+        let close = |id: u64| {
+            serde_json::json!({"change": "close", "container": {"id": id}})
+                .to_string()
+                .into_bytes()
+        };
+        // 10 closes when asked to, 11 only once its process is gone, and 12 never does.
+        let sway = {
+            let server = Arc::clone(&server);
+            let pid = stubborn.id();
+            std::thread::spawn(move || {
+                while !server
+                    .received()
+                    .iter()
+                    .any(|frame| frame.payload_type == IpcCommandCode::RunCommand as u32)
+                {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                server.send_event(IpcEvent::Window, &close(10)).unwrap();
+                while is_running(pid) {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                server.send_event(IpcEvent::Window, &close(11)).unwrap();
+            })
+        };
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let grace = Duration::from_millis(300);
+        let start = Instant::now();
+        let outcomes = terminate(&mut client, &[crate::criteria::app_id("foot")], grace).unwrap();
+        let elapsed = start.elapsed();
+        sway.join().unwrap();
+        let _ = stubborn.wait();
+        assert_eq!(
+            outcomes,
+            vec![
+                (10, Termination::Closed),
+                (11, Termination::Terminated),
+                (12, Termination::Lingering),
+            ]
+        );
+        // One full grace period for 11 and 12, then 11 goes quickly.
+        assert!(elapsed >= grace && elapsed < grace * 2, "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,278 @@
+//! Filters over replies, such as `app_id==foot && rect/width>500`, for CLIs and daemons which
+//! let users pick windows with an expression rather than sway's criteria.
+//!
+//! ```
+//! use ksway::query::Query;
+//! use serde_json::json;
+//!
+//! let window = json!({"app_id": "foot", "rect": {"width": 640}, "floating": false});
+//! let query = Query::parse("app_id==foot && rect/width>500 && !floating")?;
+//! assert!(query.matches(&window));
+//! assert!(!Query::parse(r#"app_id^="fire" || rect/width<=500"#)?.matches(&window));
+//! # Ok::<(), ksway::ParseError>(())
+//! ```
+//!
+//! The grammar, where whitespace between tokens is ignored:
+//!
+//! ```text
+//! query      := and ("||" and)*
+//! and        := unary ("&&" unary)*
+//! unary      := "!" unary | "(" query ")" | comparison
+//! comparison := path [operator value]
+//! operator   := "==" | "!=" | "^=" | "$=" | ">=" | "<=" | ">" | "<"
+//! ```
+//!
+//! - A path is a `ksway::json::path::Path`, e.g. `rect/width`, and a missing value is `null`.
+//!   Without an operator, a comparison checks that the value isn't `null` or `false`.
+//! - A value is JSON, e.g. `500`, `true`, `null` or `"a b"`, or else a bare word up to the next
+//!   whitespace, `&`, `|` or `)`, which is a string.
+//! - `==` and `!=` compare JSON values, `^=` and `$=` check that a string starts or ends with
+//!   another, and `>=`, `<=`, `>` and `<` compare numbers. Comparing values of the wrong type
+//!   is false.
+use std::str::FromStr;
+
+use crate::json::path::Path;
+use crate::{JsonValue, ParseError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    StartsWith,
+    EndsWith,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+}
+
+impl Operator {
+    /// Longest first, so that `>=` isn't read as `>`.
+    const ALL: &'static [(&'static str, Operator)] = &[
+        ("==", Operator::Eq),
+        ("!=", Operator::Ne),
+        ("^=", Operator::StartsWith),
+        ("$=", Operator::EndsWith),
+        (">=", Operator::Ge),
+        ("<=", Operator::Le),
+        (">", Operator::Gt),
+        ("<", Operator::Lt),
+    ];
+
+    fn apply(self, a: &JsonValue, b: &JsonValue) -> bool {
+        let strings = || a.as_str().zip(b.as_str());
+        let numbers = || a.as_f64().zip(b.as_f64());
+        match self {
+            Operator::Eq => a == b,
+            Operator::Ne => a != b,
+            Operator::StartsWith => strings().is_some_and(|(a, b)| a.starts_with(b)),
+            Operator::EndsWith => strings().is_some_and(|(a, b)| a.ends_with(b)),
+            Operator::Ge => numbers().is_some_and(|(a, b)| a >= b),
+            Operator::Le => numbers().is_some_and(|(a, b)| a <= b),
+            Operator::Gt => numbers().is_some_and(|(a, b)| a > b),
+            Operator::Lt => numbers().is_some_and(|(a, b)| a < b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Path, Operator, JsonValue),
+    Present(Path),
+}
+
+impl Expr {
+    fn matches(&self, value: &JsonValue) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(value) && b.matches(value),
+            Expr::Or(a, b) => a.matches(value) || b.matches(value),
+            Expr::Not(a) => !a.matches(value),
+            Expr::Compare(path, op, expected) => {
+                op.apply(path.get(value).unwrap_or(&JsonValue::Null), expected)
+            }
+            Expr::Present(path) => !matches!(
+                path.get(value),
+                None | Some(JsonValue::Null) | Some(JsonValue::Bool(false))
+            ),
+        }
+    }
+}
+
+/// Characters which end a path.
+const PATH_END: &str = "=!<>^$&|()";
+
+/// A recursive descent parser over the grammar in the module documentation.
+struct Parser<'a> {
+    input: &'a str,
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, expected: &str) -> ParseError {
+        let at = self.input.len() - self.rest.len();
+        ParseError(format!(
+            "expected {} at {} in query: {}",
+            expected, at, self.input
+        ))
+    }
+
+    /// Skip whitespace, then consume `token` if it is next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn query(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("(") {
+            let expr = self.query()?;
+            if !self.eat(")") {
+                return Err(self.error("`)`"));
+            }
+            return Ok(expr);
+        }
+        // Not `!=`, which can't start a comparison anyway.
+        if !self.rest.trim_start().starts_with("!=") && self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || PATH_END.contains(c))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(self.error("a path"));
+        }
+        let path = self.rest[..end].parse()?;
+        self.rest = &self.rest[end..];
+        for (token, op) in Operator::ALL {
+            if self.eat(token) {
+                return Ok(Expr::Compare(path, *op, self.value()?));
+            }
+        }
+        Ok(Expr::Present(path))
+    }
+
+    fn value(&mut self) -> Result<JsonValue, ParseError> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with(['"', '[', '{']) {
+            let mut values = serde_json::Deserializer::from_str(self.rest).into_iter();
+            let value = match values.next() {
+                Some(Ok(value)) => value,
+                _ => return Err(self.error("a JSON value")),
+            };
+            self.rest = &self.rest[values.byte_offset()..];
+            return Ok(value);
+        }
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || "&|)".contains(c))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(self.error("a value"));
+        }
+        let word = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        Ok(serde_json::from_str(word).unwrap_or_else(|_| JsonValue::String(word.to_owned())))
+    }
+}
+
+/// A parsed filter, see the module documentation for the syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query(Expr);
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            input: query,
+            rest: query,
+        };
+        let expr = parser.query()?;
+        if !parser.rest.trim().is_empty() {
+            return Err(parser.error("`&&`, `||` or the end"));
+        }
+        Ok(Query(expr))
+    }
+
+    /// Whether `value`, e.g. a node of a GET_TREE reply, matches.
+    pub fn matches(&self, value: &JsonValue) -> bool {
+        self.0.matches(value)
+    }
+
+    /// A query which matches what both this one and `other` match.
+    pub fn and(self, other: Query) -> Query {
+        Query(Expr::And(Box::new(self.0), Box::new(other.0)))
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        Query::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn precedence_and_values() {
+        let window = json!({
+            "id": 7,
+            "name": "a && b",
+            "app_id": "foot",
+            "marks": ["x"],
+            "urgent": false,
+            "rect": {"width": 640},
+        });
+        let matches = |query: &str| Query::parse(query).unwrap().matches(&window);
+        assert!(matches("app_id==foot"));
+        assert!(matches(r#"name == "a && b""#));
+        assert!(matches(r#"marks==["x"] && marks/0==x"#));
+        // `&&` binds tighter than `||`.
+        assert!(matches("id==1 || id==7 && rect/width>=640"));
+        assert!(!matches("(id==1 || id==7) && rect/width>640"));
+        assert!(matches(
+            "!urgent && !!rect && !missing && missing==null && missing!=1"
+        ));
+        assert!(matches(
+            "app_id^=fo && app_id$=ot && id<8 && id<=7 && id>6.5"
+        ));
+        assert!(!matches("app_id>1 || id^=7"));
+        assert!(Query::parse("app_id==").is_err());
+        assert!(Query::parse("(id==7").is_err());
+        assert!(Query::parse("id==7 foo").is_err());
+        assert!(Query::parse(r#"name=="unterminated"#).is_err());
+    }
+}
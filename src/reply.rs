@@ -0,0 +1,293 @@
+//! Typed versions of the replies to the GET_* commands, see the `*_typed()` methods of
+//! `SwayClientJson`.
+//!
+//! Only the commonly used fields are covered, and fields which sway doesn't send for every kind
+//! of node are optional. Use the `*_json()` methods for anything else.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::geometry::Rect;
+
+/// An element of the GET_WORKSPACES reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Workspace {
+    pub id: u64,
+    /// The number at the start of the name, or -1 if there isn't one.
+    pub num: i64,
+    pub name: String,
+    pub visible: bool,
+    pub focused: bool,
+    pub urgent: bool,
+    pub rect: Rect,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub width: i64,
+    pub height: i64,
+    /// In mHz.
+    pub refresh: i64,
+}
+
+/// An element of the GET_OUTPUTS reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Output {
+    pub name: String,
+    #[serde(default)]
+    pub make: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub serial: String,
+    pub active: bool,
+    #[serde(default)]
+    pub primary: bool,
+    pub scale: Option<f64>,
+    pub transform: Option<String>,
+    pub current_workspace: Option<String>,
+    #[serde(default)]
+    pub modes: Vec<Mode>,
+    pub current_mode: Option<Mode>,
+    pub rect: Rect,
+}
+
+/// An element of the GET_INPUTS reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Input {
+    pub identifier: String,
+    pub name: String,
+    pub vendor: i64,
+    pub product: i64,
+    /// E.g. `keyboard`, `pointer`, `touchpad` or `switch`.
+    #[serde(rename = "type")]
+    pub input_type: String,
+    /// Keyboards only.
+    pub xkb_active_layout_name: Option<String>,
+    /// Keyboards only.
+    #[serde(default)]
+    pub xkb_layout_names: Vec<String>,
+    /// Keyboards only.
+    pub xkb_active_layout_index: Option<i64>,
+}
+
+/// An element of the GET_SEATS reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Seat {
+    pub name: String,
+    /// The number of capabilities the seat has.
+    pub capabilities: i64,
+    /// The id of the focused node, 0 if nothing is focused.
+    pub focus: u64,
+    #[serde(default)]
+    pub devices: Vec<Input>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeType {
+    Root,
+    Output,
+    Workspace,
+    Con,
+    FloatingCon,
+    Dockarea,
+}
+
+/// The X11 properties of an XWayland window.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct WindowProperties {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+    pub window_role: Option<String>,
+    pub window_type: Option<String>,
+    pub transient_for: Option<u64>,
+}
+
+/// A node of the GET_TREE reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Node {
+    pub id: u64,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub node_type: NodeType,
+    pub layout: Option<String>,
+    pub rect: Rect,
+    #[serde(default)]
+    pub window_rect: Rect,
+    #[serde(default)]
+    pub deco_rect: Rect,
+    #[serde(default)]
+    pub urgent: bool,
+    #[serde(default)]
+    pub focused: bool,
+    /// The ids of the children, most recently focused first.
+    #[serde(default)]
+    pub focus: Vec<u64>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub floating_nodes: Vec<Node>,
+    #[serde(default)]
+    pub marks: Vec<String>,
+    /// 0 for none, 1 for the workspace's output and 2 for global.
+    #[serde(default)]
+    pub fullscreen_mode: u8,
+    /// Workspaces only.
+    pub num: Option<i64>,
+    /// Workspaces only.
+    pub output: Option<String>,
+    /// Windows only, `None` for XWayland windows.
+    pub app_id: Option<String>,
+    /// Windows only.
+    pub pid: Option<u32>,
+    /// Windows only.
+    pub visible: Option<bool>,
+    /// Windows only, `xdg_shell` or `xwayland`.
+    pub shell: Option<String>,
+    /// The X11 window id of XWayland windows.
+    pub window: Option<u64>,
+    pub window_properties: Option<WindowProperties>,
+}
+
+impl Node {
+    /// This node and all of its descendants in preorder, following `nodes` before
+    /// `floating_nodes`.
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.floating_nodes.iter().rev());
+            stack.extend(node.nodes.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// The focused node among this node and its descendants.
+    pub fn find_focused(&self) -> Option<&Node> {
+        self.iter().find(|node| node.focused)
+    }
+
+    /// See `tree::app_identifier()`.
+    pub fn app_identifier(&self) -> Option<&str> {
+        crate::tree::typed_app_identifier(self.app_id.as_deref(), self.window_properties.as_ref())
+    }
+}
+
+/// An element of the RUN_COMMAND reply, one per command that was run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandOutcome {
+    pub success: bool,
+    /// Whether the command couldn't be parsed, as opposed to failing when it ran.
+    #[serde(default)]
+    pub parse_error: bool,
+    pub error: Option<String>,
+}
+
+/// The GET_VERSION reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Version {
+    pub major: i64,
+    pub minor: i64,
+    pub patch: i64,
+    pub human_readable: String,
+    pub loaded_config_file_name: Option<String>,
+}
+
+/// The GET_BAR_CONFIG reply for a bar id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BarConfig {
+    pub id: String,
+    pub mode: String,
+    pub position: String,
+    pub status_command: Option<String>,
+    pub font: Option<String>,
+    #[serde(default)]
+    pub workspace_buttons: bool,
+    #[serde(default)]
+    pub binding_mode_indicator: bool,
+    #[serde(default)]
+    pub colors: HashMap<String, String>,
+}
+
+/// The GET_BINDING_STATE reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BindingState {
+    /// The name of the active binding mode, `default` unless a mode was entered.
+    pub name: String,
+}
+
+/// The GET_CONFIG reply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Config {
+    pub config: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_replies() {
+        let workspaces: Vec<Workspace> = serde_json::from_str(
+            r#"[{"id": 4, "num": 1, "name": "1", "visible": true, "focused": true,
+                 "urgent": false, "output": "DP-1", "layout": "splith",
+                 "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}}]"#,
+        )
+        .unwrap();
+        assert_eq!(workspaces[0].rect.width, 1920);
+
+        let tree: Node = serde_json::from_str(
+            r#"{"id": 1, "name": "root", "type": "root", "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                "nodes": [{"id": 2, "name": "1", "type": "workspace", "num": 1, "output": "DP-1",
+                           "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                           "floating_nodes": [{"id": 4, "type": "floating_con", "focused": true,
+                                               "app_id": null, "pid": 7,
+                                               "window_properties": {"class": "Steam"},
+                                               "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}],
+                           "nodes": [{"id": 3, "type": "con", "app_id": "foot",
+                                      "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tree.iter().map(|node| node.id).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        let focused = tree.find_focused().unwrap();
+        assert_eq!(focused.node_type, NodeType::FloatingCon);
+        assert_eq!(focused.app_identifier(), Some("Steam"));
+
+        let version: Version = serde_json::from_str(
+            r#"{"major": 1, "minor": 5, "patch": 0, "human_readable": "1.5",
+                "loaded_config_file_name": "/etc/sway/config"}"#,
+        )
+        .unwrap();
+        assert_eq!((version.major, version.minor), (1, 5));
+
+        let inputs: Vec<Input> = serde_json::from_str(
+            r#"[{"identifier": "1:1:AT_Translated_Set_2_keyboard", "name": "AT Translated Set 2 keyboard",
+                 "vendor": 1, "product": 1, "type": "keyboard",
+                 "xkb_active_layout_name": "English (US)", "xkb_layout_names": ["English (US)", "German"],
+                 "xkb_active_layout_index": 0, "libinput": {"send_events": "enabled"}},
+                {"identifier": "2:7:SynPS/2_Synaptics_TouchPad", "name": "SynPS/2 Synaptics TouchPad",
+                 "vendor": 2, "product": 7, "type": "touchpad"}]"#,
+        )
+        .unwrap();
+        assert_eq!(inputs[0].xkb_layout_names.len(), 2);
+        assert_eq!(inputs[1].xkb_active_layout_name, None);
+
+        let seats: Vec<Seat> = serde_json::from_str(
+            r#"[{"name": "seat0", "capabilities": 3, "focus": 7,
+                 "devices": [{"identifier": "2:7:SynPS/2_Synaptics_TouchPad",
+                              "name": "SynPS/2 Synaptics TouchPad", "vendor": 2, "product": 7,
+                              "type": "touchpad"}]}]"#,
+        )
+        .unwrap();
+        assert_eq!(seats[0].devices[0].input_type, "touchpad");
+
+        let state: BindingState = serde_json::from_str(r#"{"name": "resize"}"#).unwrap();
+        assert_eq!(state.name, "resize");
+    }
+}
@@ -0,0 +1,248 @@
+//! A client which only sends what it was configured to allow, for embedding ksway in plugins or
+//! scripting engines which shouldn't be able to do everything sway can, e.g. `exec` anything.
+//!
+//! ```no_run
+//! use ksway::{ipc_command, restrict::RestrictedClient, Error, SwayClient, SwayClientJson};
+//!
+//! let mut client = RestrictedClient::new(ksway::Client::connect()?)
+//!     .allow_verbs(&["focus", "move", "layout"])
+//!     .allow_query(&ipc_command::get_tree());
+//! client.run("[app_id=\"foot\"] focus; layout tabbed")?;
+//! let tree = client.get_tree_json()?;
+//! assert!(matches!(client.run("exec rm -rf ~"), Err(Error::NotPermitted { .. })));
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! Some verbs run other commands or programs later, so allowing them allows everything: `exec`
+//! and `exec_always`, the `bind*` family, `for_window`, `bar` (its `status_command`) and
+//! `swaybg_command`/`swaynag_command`.
+use std::collections::HashSet;
+
+use crate::{Error, HasIpc, IpcCommand, Result, SwayClient, SwayClientJson};
+
+/// Skip the criteria at the start of `command`, which starts with `[`, reading them like sway's
+/// criteria parser: a value is either quoted with `"`, where `\"` doesn't end it, or runs up to
+/// a space or `]`. `None` if they aren't closed or have a token sway would reject.
+fn skip_criteria(command: &str) -> Option<&str> {
+    let bytes = command.as_bytes();
+    let skip_spaces = |i: &mut usize| {
+        while bytes.get(*i) == Some(&b' ') {
+            *i += 1;
+        }
+    };
+    let mut i = 1;
+    loop {
+        skip_spaces(&mut i);
+        match bytes.get(i)? {
+            b']' => return Some(&command[i + 1..]),
+            b'a'..=b'z' | b'_' => {}
+            _ => return None,
+        }
+        while matches!(bytes.get(i), Some(b'a'..=b'z') | Some(b'_')) {
+            i += 1;
+        }
+        skip_spaces(&mut i);
+        if bytes.get(i) != Some(&b'=') {
+            continue;
+        }
+        i += 1;
+        skip_spaces(&mut i);
+        if bytes.get(i) == Some(&b'"') {
+            i += 1;
+            while *bytes.get(i)? != b'"' || bytes[i - 1] == b'\\' {
+                i += 1;
+            }
+            i += 1;
+        } else {
+            while !matches!(bytes.get(i), None | Some(b' ') | Some(b']')) {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Split the first command off `commands` at a `;` or `,` outside of quotes, returning it along
+/// with the separator and the rest. Like sway's `argsep`, both `"` and `'` quote, and a `\`
+/// escapes the next character inside or outside of quotes. `None` if `commands` ends inside
+/// quotes or after a `\`, where sway would run whatever follows as part of the last command.
+fn split_command(commands: &str) -> Option<(&str, Option<(char, &str)>)> {
+    let (mut in_string, mut in_char, mut escaped) = (false, false, false);
+    for (i, c) in commands.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' if !in_char => in_string = !in_string,
+            '\'' if !in_string => in_char = !in_char,
+            ';' | ',' if !in_string && !in_char => {
+                return Some((&commands[..i], Some((c, &commands[i + 1..]))))
+            }
+            _ => {}
+        }
+    }
+    if in_string || in_char || escaped {
+        None
+    } else {
+        Some((commands, None))
+    }
+}
+
+/// The verb of every command in a RUN_COMMAND payload, lowercased, split the way sway splits
+/// them: at `;` and `,` outside of quotes, with criteria only at the start of a command after a
+/// `;`. `None` if a quote, escape or criteria isn't closed.
+fn verbs(commands: &str) -> Option<Vec<String>> {
+    let mut verbs = Vec::new();
+    let mut rest = commands;
+    let mut after_semicolon = true;
+    loop {
+        rest = rest.trim_start();
+        if after_semicolon && rest.starts_with('[') {
+            rest = skip_criteria(rest)?;
+        }
+        let (command, next) = split_command(rest)?;
+        if let Some(verb) = command.split_whitespace().next() {
+            verbs.push(verb.to_lowercase());
+        }
+        match next {
+            Some((separator, next)) => {
+                after_semicolon = separator == ';';
+                rest = next;
+            }
+            None => return Some(verbs),
+        }
+    }
+}
+
+/// Wraps a client, and only passes on commands whose every verb is allowed, and queries (any
+/// other request, including SUBSCRIBE and SEND_TICK) of the allowed types. Anything else fails
+/// with `Error::NotPermitted` without being sent. Nothing is allowed by default.
+pub struct RestrictedClient<C> {
+    inner: C,
+    verbs: HashSet<String>,
+    /// The codes of the allowed queries.
+    queries: HashSet<u32>,
+}
+
+impl<C: HasIpc> RestrictedClient<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            verbs: HashSet::new(),
+            queries: HashSet::new(),
+        }
+    }
+
+    /// Allow commands starting with `verb`, e.g. `focus`. Verbs are case insensitive, like in
+    /// sway.
+    pub fn allow_verb(mut self, verb: &str) -> Self {
+        self.verbs.insert(verb.to_lowercase());
+        self
+    }
+
+    pub fn allow_verbs(self, verbs: &[&str]) -> Self {
+        verbs
+            .iter()
+            .fold(self, |client, verb| client.allow_verb(verb))
+    }
+
+    /// Allow queries of the same type as `query`, e.g. `ipc_command::get_tree()`. Commands are
+    /// only ever allowed by their verbs, so this does nothing for `ipc_command::run()`.
+    pub fn allow_query(mut self, query: &IpcCommand) -> Self {
+        self.queries.insert(query.code() as u32);
+        self
+    }
+
+    /// The wrapped client, which isn't restricted anymore.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn check(&self, request: &IpcCommand) -> Result<()> {
+        let denied = |what: String, request: String| Err(Error::NotPermitted { what, request });
+        match request {
+            IpcCommand::Run(commands) => match verbs(commands) {
+                Some(verbs) => match verbs.into_iter().find(|verb| !self.verbs.contains(verb)) {
+                    Some(verb) => denied(format!("`{}`", verb), commands.clone()),
+                    None => Ok(()),
+                },
+                None => denied("unbalanced quotes or criteria".into(), commands.clone()),
+            },
+            _ if self.queries.contains(&(request.code() as u32)) => Ok(()),
+            _ => denied(format!("{:?}", request.code()), format!("{:?}", request)),
+        }
+    }
+}
+
+impl<C: HasIpc> HasIpc for RestrictedClient<C> {
+    fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
+        self.check(&command)?;
+        self.inner.ipc(command)
+    }
+}
+
+impl<C: HasIpc> SwayClient for RestrictedClient<C> {}
+impl<C: HasIpc> SwayClientJson for RestrictedClient<C> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::{ipc_command, Client};
+
+    #[test]
+    fn split_verbs() {
+        assert_eq!(
+            verbs(r#"[title="a;b\"]" app_id=x] focus; Move left , exec "c, d""#).unwrap(),
+            vec!["focus", "move", "exec"]
+        );
+        // A `\` escapes outside of quotes too, and only `"` quotes in criteria.
+        assert_eq!(
+            verbs(r#"focus \"; exec foo"#).unwrap(),
+            vec!["focus", "exec"]
+        );
+        assert_eq!(
+            verbs("[title='] exec foo, [x] focus").unwrap(),
+            vec!["exec", "[x]"]
+        );
+        // Criteria are only read at the start of a command after a `;`, like sway does.
+        assert_eq!(verbs("focus, [x] exec y").unwrap(), vec!["focus", "[x]"]);
+        assert_eq!(verbs(" ; nop;").unwrap(), vec!["nop"]);
+        assert_eq!(verbs(r#"title "a\" ; exec b""#).unwrap(), vec!["title"]);
+        assert!(verbs(r#"exec "unterminated"#).is_none());
+        assert!(verbs("[title=x exec y").is_none());
+        assert!(verbs(r#"[title="x\"] exec y"#).is_none());
+        assert!(verbs("[title=x !] exec y").is_none());
+        assert!(verbs("focus \\").is_none());
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let server = MockServer::start().unwrap();
+        let mut client = RestrictedClient::new(Client::connect_to_path(server.path()).unwrap())
+            .allow_verbs(&["focus", "Layout"])
+            .allow_query(&ipc_command::get_tree());
+
+        client.run("[con_id=1] focus; layout tabbed").unwrap();
+        client.get_tree().unwrap();
+        for denied in &[
+            "focus; exec foot",
+            "focus, kill",
+            r#"focus "unterminated"#,
+            r#"focus \"; exec foo; ""#,
+            "[title='] exec foo; '] focus",
+        ] {
+            assert!(
+                matches!(client.run(denied), Err(Error::NotPermitted { .. })),
+                "{}",
+                denied
+            );
+        }
+        match client.get_workspaces() {
+            Err(Error::NotPermitted { what, .. }) => assert_eq!(what, "GetWorkspaces"),
+            other => panic!("{:?}", other),
+        }
+        server.assert_run_commands(&["[con_id=1] focus; layout tabbed"]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Rules which run commands on windows as they appear or change, like sway's `for_window`, but
+//! evaluated client side, so they can also depend on the rest of the tree:
+//! ```no_run
+//! use ksway::command::{self, MoveTarget, Toggle};
+//! use ksway::criteria::{app_id, title};
+//! use ksway::rules::{Rule, RuleEngine};
+//! use ksway::events::WindowChange;
+//! use ksway::tree;
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut rules = RuleEngine::new()
+//!     .rule(Rule::new(&[app_id("^mpv$")])?.then(command::set_floating(Toggle::Enable)))
+//!     .rule(
+//!         // Only once a second browser window is open, and when it gets its title later.
+//!         Rule::new(&[app_id("^firefox$"), title("Meet")])?
+//!             .on(WindowChange::Title)
+//!             .when(|tree, _| {
+//!                 tree::windows(tree)
+//!                     .filter(|window| window.node["app_id"] == "firefox")
+//!                     .count()
+//!                     > 1
+//!             })
+//!             .then(command::move_to(MoveTarget::Workspace("call".into())))
+//!             .then(command::mark("call")),
+//!     );
+//! rules.apply_existing(&mut client)?;
+//! let rx = client.subscribe(RuleEngine::events())?;
+//! loop {
+//!     while let Ok((event, payload)) = rx.try_recv() {
+//!         rules.handle_event(&mut client, event, &serde_json::from_slice(&payload)?)?;
+//!     }
+//!     client.poll()?;
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashSet;
+
+use crate::criteria::{self, Criteria, Matcher};
+use crate::events::WindowChange;
+use crate::query::Query;
+use crate::tree::{self, Node};
+use crate::{
+    Command, CommandSeq, EventMask, IpcEvent, JsonValue, ParseError, Result, SwayClientJson,
+    SwayJsonExt,
+};
+
+type Condition = Box<dyn Fn(&JsonValue, &Node<'_>) -> bool + Send>;
+
+/// Commands for the windows matching criteria, and optionally a query and a condition on the
+/// whole tree. A rule applies to each window at most once, the first time it matches after a
+/// window event it is interested in, like `for_window`.
+pub struct Rule {
+    matcher: Matcher,
+    query: Option<Query>,
+    condition: Option<Condition>,
+    changes: Vec<WindowChange>,
+    actions: Vec<Command>,
+}
+
+impl Rule {
+    /// A rule for the windows matching every one of `criteria`, with the semantics sway gives
+    /// them, see `criteria::Matcher`. Fails if a regular expression is invalid.
+    pub fn new(criteria: &[Criteria]) -> std::result::Result<Self, ParseError> {
+        Ok(Self {
+            matcher: Matcher::new(criteria)?,
+            query: None,
+            condition: None,
+            changes: Vec::new(),
+            actions: Vec::new(),
+        })
+    }
+
+    /// Also require the window's JSON to match `query`.
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Also require `condition`, called with the GET_TREE reply and the window.
+    pub fn when<F>(mut self, condition: F) -> Self
+    where
+        F: Fn(&JsonValue, &Node<'_>) -> bool + Send + 'static,
+    {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    /// Also evaluate the rule on window events with `change`, e.g. `Title` for windows whose
+    /// title is only set after they appear. `New` is always evaluated.
+    pub fn on(mut self, change: WindowChange) -> Self {
+        self.changes.push(change);
+        self
+    }
+
+    /// Run `action` on the window, with a `con_id` criteria added to it, after the actions
+    /// added before.
+    pub fn then(mut self, action: Command) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    fn is_interested(&self, change: &WindowChange) -> bool {
+        *change == WindowChange::New || self.changes.contains(change)
+    }
+
+    fn matches(&self, tree: &JsonValue, window: &Node<'_>) -> bool {
+        self.matcher.clone().focused_in(tree).matches(window)
+            && self.query.as_ref().map_or(true, |q| q.matches(window.node))
+            && self.condition.as_ref().map_or(true, |c| c(tree, window))
+    }
+}
+
+/// Evaluates `Rule`s, in the order they were added, and runs the actions of those which match.
+#[derive(Default)]
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    /// The rules, by index, which were applied to each window.
+    applied: HashSet<(usize, u64)>,
+}
+
+impl RuleEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// The events to subscribe to for `handle_event()`.
+    pub fn events() -> EventMask {
+        EventMask::WINDOW
+    }
+
+    /// The actions for `window` of the rules accepted by `interested` which match it and weren't
+    /// applied to it yet, which are then considered applied.
+    fn evaluate<F>(&mut self, tree: &JsonValue, window: &Node<'_>, interested: F) -> CommandSeq
+    where
+        F: Fn(&Rule) -> bool,
+    {
+        let mut seq = CommandSeq::new();
+        let id = match window.id() {
+            Some(id) => id,
+            None => return seq,
+        };
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !interested(rule)
+                || self.applied.contains(&(index, id))
+                || !rule.matches(tree, window)
+            {
+                continue;
+            }
+            self.applied.insert((index, id));
+            for action in &rule.actions {
+                seq = seq.then(action.clone().with_criteria(vec![criteria::con_id(id)]));
+            }
+        }
+        seq
+    }
+
+    fn run<C: SwayClientJson>(client: &mut C, seq: CommandSeq) -> Result<()> {
+        if !seq.is_empty() {
+            client.run_checked(seq)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the rules to the windows which are already open, e.g. at startup, as if they had
+    /// just appeared.
+    pub fn apply_existing<C: SwayClientJson>(&mut self, client: &mut C) -> Result<()> {
+        let tree = client.get_tree_json()?;
+        let mut seq = CommandSeq::new();
+        for window in tree::windows(&tree) {
+            for command in self.evaluate(&tree, &window, |_| true).commands() {
+                seq = seq.then(command.clone());
+            }
+        }
+        Self::run(client, seq)
+    }
+
+    /// Feed a subscription event. Window events which a rule is interested in are evaluated
+    /// against a fresh GET_TREE, and the actions of the rules which match run as one command.
+    /// A failed action is returned as `Error::CommandFailed`.
+    pub fn handle_event<C: SwayClientJson>(
+        &mut self,
+        client: &mut C,
+        event: IpcEvent,
+        payload: &JsonValue,
+    ) -> Result<()> {
+        if event != IpcEvent::Window {
+            return Ok(());
+        }
+        let (change, id) = match (
+            WindowChange::from_payload(payload),
+            payload["container"].con_id(),
+        ) {
+            (Some(change), Some(id)) => (change, id),
+            _ => return Ok(()),
+        };
+        if change == WindowChange::Close {
+            self.applied.retain(|(_, applied)| *applied != id);
+            return Ok(());
+        }
+        if !self.rules.iter().any(|rule| rule.is_interested(&change)) {
+            return Ok(());
+        }
+        let tree = client.get_tree_json()?;
+        let window = match tree::nodes(&tree).find(|node| node.id() == Some(id)) {
+            Some(window) => window,
+            // Closed already.
+            None => return Ok(()),
+        };
+        let seq = self.evaluate(&tree, &window, |rule| rule.is_interested(&change));
+        Self::run(client, seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{self, MoveTarget, Toggle};
+    use crate::criteria::{app_id, title};
+    use crate::testing::MockServer;
+    use crate::{ipc_command, Client};
+    use serde_json::json;
+
+    #[test]
+    fn rules_apply_once() {
+        let server = MockServer::start().unwrap();
+        let tree = |title: &str| {
+            json!({"id": 1, "type": "root", "nodes": [
+                {"id": 2, "type": "workspace", "name": "1", "nodes": [
+                    {"id": 3, "type": "con", "app_id": "mpv", "name": "video"},
+                    {"id": 4, "type": "con", "app_id": "firefox", "name": title, "focused": true},
+                ]}
+            ]})
+            .to_string()
+        };
+        for title in &["New Tab", "New Tab", "Meet", "Meet"] {
+            server.reply(&ipc_command::get_tree(), tree(title));
+        }
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        let mut rules = RuleEngine::new()
+            .rule(
+                Rule::new(&[app_id("^mpv$")])
+                    .unwrap()
+                    .then(command::set_floating(Toggle::Enable)),
+            )
+            .rule(
+                Rule::new(&[title("Meet")])
+                    .unwrap()
+                    .on(WindowChange::Title)
+                    .query("app_id==firefox".parse().unwrap())
+                    .when(|tree, _| tree::windows(tree).count() == 2)
+                    .then(command::move_to(MoveTarget::Workspace("call".into())))
+                    .then(command::mark("call")),
+            );
+        let event = |change: &str, id: u64| json!({"change": change, "container": {"id": id}});
+
+        rules.apply_existing(&mut client).unwrap();
+        // Already applied to 3, and the title isn't right yet.
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("new", 3))
+            .unwrap();
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("title", 4))
+            .unwrap();
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("title", 4))
+            .unwrap();
+        // Forgotten when closed, so a new window with the same id gets the rules again.
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("close", 3))
+            .unwrap();
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("focus", 3))
+            .unwrap();
+        rules
+            .handle_event(&mut client, IpcEvent::Window, &event("new", 3))
+            .unwrap();
+        server.assert_run_commands(&[
+            r#"[con_id="3"] floating enable"#,
+            r#"[con_id="4"] move container to workspace "call"; [con_id="4"] mark --replace "call""#,
+            r#"[con_id="3"] floating enable"#,
+        ]);
+    }
+}
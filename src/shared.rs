@@ -0,0 +1,266 @@
+//! A client which can be used from several threads at once.
+//!
+//! `Client` needs `&mut self` for everything, and reads replies and events on the calling
+//! thread. `SharedClient` instead has a background thread own the reading half of the socket:
+//! replies are handed to whoever sent the command, matched up in order since sway answers
+//! commands in the order they were sent and checked to be for the same kind of command, and
+//! events are fanned out to every subscriber.
+//!
+//! ```no_run
+//! use std::{sync::Arc, thread};
+//! use ksway::{IpcEvent, SharedClient, SwayClient, SwayClientJson};
+//!
+//! let client = Arc::new(SharedClient::connect()?);
+//! let rx = client.subscribe(vec![IpcEvent::Window])?;
+//!
+//! let worker = Arc::clone(&client);
+//! thread::spawn(move || {
+//!     for (event, _) in rx {
+//!         let _ = (&*worker).run(format!("exec notify-send '{:?}'", event));
+//!     }
+//! });
+//! let tree = (&*client).get_tree_json()?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel as chan;
+
+use crate::client::{Receiver, Sender};
+use crate::{
+    guess_sway_socket_path, Client, EventMask, HasIpc, IpcCommand, IpcEvent, Result, SwayClient,
+    SwayClientJson,
+};
+
+type Subscriber = (EventMask, chan::Sender<(IpcEvent, Vec<u8>)>);
+
+pub struct SharedClient {
+    /// The writing half of the connection, see `Client::split()`.
+    sender: Sender,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    socket_path: PathBuf,
+    reader: Option<JoinHandle<()>>,
+}
+
+/// Read messages until the connection fails or is closed, handing replies to the `Sender` and
+/// events to the subscribers which asked for them, then disconnect them.
+fn read_loop(receiver: Receiver, subscribers: &Mutex<Vec<Subscriber>>) {
+    for received in receiver {
+        let (event, payload) = match received {
+            Ok(event) => event,
+            Err(_) => break,
+        };
+        // Drop subscribers whose receiver is gone.
+        subscribers.lock().unwrap().retain(|(events, tx)| {
+            !events.contains_event(event) || tx.send((event, payload.clone())).is_ok()
+        });
+    }
+    subscribers.lock().unwrap().clear();
+}
+
+impl SharedClient {
+    /// Connect to a specific socket and start the reader thread.
+    pub fn connect_to_path<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        Self::from_client(Client::connect_to_path(path)?)
+    }
+
+    /// Like `Client::connect()`, see `guess_sway_socket_path()`.
+    pub fn connect() -> Result<Self> {
+        Self::connect_to_path(guess_sway_socket_path()?)
+    }
+
+    /// Split `client` and start the reader thread on its `Receiver`. Commands wait for their
+    /// reply for the client's request timeout, or its read timeout if there is none.
+    pub(crate) fn from_client(client: Client) -> Result<Self> {
+        let socket_path = client.socket_path().to_path_buf();
+        let (sender, receiver) = client.split()?;
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let reader = {
+            let subscribers = Arc::clone(&subscribers);
+            thread::spawn(move || read_loop(receiver, &subscribers))
+        };
+        Ok(Self {
+            sender,
+            subscribers,
+            socket_path,
+            reader: Some(reader),
+        })
+    }
+
+    /// The socket path that we are currently connected to.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Send an ipc command and wait for its reply. Unlike `Client::ipc()` this only needs
+    /// `&self`, so several threads can have commands in flight at once.
+    pub fn ipc(&self, command: IpcCommand) -> Result<Vec<u8>> {
+        self.sender.ipc(command)
+    }
+
+    /// Subscribe to events. Unlike `Client::subscribe()` this can be called any number of times,
+    /// every subscriber receives the events it asked for on its own channel, without polling.
+    /// The channel disconnects when the connection is closed.
+    pub fn subscribe<E: Into<EventMask>>(
+        &self,
+        event_types: E,
+    ) -> Result<chan::Receiver<(IpcEvent, Vec<u8>)>> {
+        let event_types = event_types.into();
+        let (tx, rx) = chan::unbounded();
+        // Before subscribing, so that the first events aren't missed.
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push((event_types, tx.clone()));
+        if let Err(err) = self.sender.subscribe(event_types) {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|(_, subscriber)| !subscriber.same_channel(&tx));
+            return Err(err);
+        }
+        Ok(rx)
+    }
+}
+
+impl Drop for SharedClient {
+    fn drop(&mut self) {
+        // Closing the socket ends the reader thread.
+        let _ = self.sender.shutdown();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+impl HasIpc for SharedClient {
+    fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
+        SharedClient::ipc(self, command)
+    }
+}
+
+impl SwayClient for SharedClient {}
+impl SwayClientJson for SharedClient {}
+
+/// Allows using the client traits from several threads, e.g. `(&*client).get_tree_json()` on an
+/// `Arc<SharedClient>`.
+impl HasIpc for &SharedClient {
+    fn ipc(&mut self, command: IpcCommand) -> Result<Vec<u8>> {
+        SharedClient::ipc(self, command)
+    }
+}
+
+impl SwayClient for &SharedClient {}
+impl SwayClientJson for &SharedClient {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{self, Header};
+    use crate::{ipc_command, Error, EventType, Timeouts};
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::time::Duration;
+
+    fn frame(payload_type: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Header {
+            payload_len: payload.len() as u32,
+            payload_type,
+        }
+        .write_to(&mut out);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn replies_and_events() {
+        let path = std::env::temp_dir().join(format!("ksway-shared-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // A fake sway which replies with an event in between, then echoes each command back.
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut header = [0u8; core::HEADER_LEN];
+            for _ in 0..2 {
+                socket.read_exact(&mut header).unwrap();
+                let header = Header::parse(&header).unwrap();
+                let mut payload = vec![0u8; header.payload_len as usize];
+                socket.read_exact(&mut payload).unwrap();
+                let mut out = frame(EventType::Window.code().raw(), b"{}");
+                out.extend(frame(header.payload_type, &payload));
+                socket.write_all(&out).unwrap();
+            }
+        });
+
+        let client = SharedClient::connect_to_path(&path).unwrap();
+        let rx = client.subscribe(vec![IpcEvent::Window]).unwrap();
+        assert_eq!(client.ipc(ipc_command::run("nop")).unwrap(), b"nop");
+        server.join().unwrap();
+        assert_eq!(rx.recv().unwrap(), (IpcEvent::Window, b"{}".to_vec()));
+        drop(client);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reply_codes_are_checked() {
+        let path =
+            std::env::temp_dir().join(format!("ksway-shared-codes-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // Replies to GET_TREE with a payload bigger than a single read, then to RUN_COMMAND
+        // as if it were GET_VERSION.
+        let big = format!("[{}]", vec!["{}"; 5000].join(","));
+        let server = {
+            let big = big.clone();
+            thread::spawn(move || {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut header = [0u8; core::HEADER_LEN];
+                for reply in &[(None, big.as_bytes()), (Some(7), &b"{}"[..])] {
+                    socket.read_exact(&mut header).unwrap();
+                    let header = Header::parse(&header).unwrap();
+                    let mut payload = vec![0u8; header.payload_len as usize];
+                    socket.read_exact(&mut payload).unwrap();
+                    let payload_type = reply.0.unwrap_or(header.payload_type);
+                    socket.write_all(&frame(payload_type, reply.1)).unwrap();
+                }
+            })
+        };
+
+        let client = SharedClient::connect_to_path(&path).unwrap();
+        assert_eq!(client.ipc(ipc_command::get_tree()).unwrap(), big.as_bytes());
+        match client.ipc(ipc_command::run("nop")) {
+            Err(Error::UnexpectedReply {
+                expected, received, ..
+            }) => assert_eq!((expected, received), (0, 7)),
+            other => panic!("{:?}", other),
+        }
+        server.join().unwrap();
+        drop(client);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn failed_subscribe_drops_the_subscriber() {
+        let path =
+            std::env::temp_dir().join(format!("ksway-shared-sub-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // A sway which never answers.
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut client = Client::connect_to_path(&path).unwrap();
+        let _socket = listener.accept().unwrap();
+        client
+            .set_timeouts(Timeouts::new().read(Some(Duration::from_millis(50))))
+            .unwrap();
+        let client = SharedClient::from_client(client).unwrap();
+        match client.subscribe(vec![IpcEvent::Tick]) {
+            Err(Error::Timeout) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        assert!(client.subscribers.lock().unwrap().is_empty());
+        drop(client);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,244 @@
+//! Watching the raw event stream, for debugging.
+//!
+//! `Sniffer` subscribes to every event and prints each with its type, size and the time since
+//! the previous one, and can also save them to a capture file which `read_capture()` loads back:
+//! ```no_run
+//! use std::{fs::File, io};
+//! use ksway::sniff::Sniffer;
+//!
+//! let client = ksway::Client::connect()?;
+//! Sniffer::new(io::stderr())
+//!     .capture_to(File::create("sway.capture")?)?
+//!     .run(client)?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! A capture file is the magic string `ksway-capture`, followed by one record per event: the
+//! microseconds since the first event as a little endian `u64`, then the event's i3-ipc frame
+//! exactly as sway sent it.
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::core::{self, Header, HEADER_LEN};
+use crate::{Client, Error, EventCode, EventMask, JsonValue, Result};
+
+pub const CAPTURE_MAGIC: &[u8; 13] = b"ksway-capture";
+
+/// An event loaded from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    /// The time since the first event of the capture.
+    pub at: Duration,
+    pub code: EventCode,
+    pub payload: Vec<u8>,
+}
+
+/// Prints events to a writer, and optionally saves them to a capture file.
+pub struct Sniffer {
+    out: Box<dyn Write + Send>,
+    capture: Option<Box<dyn Write + Send>>,
+    /// When the first and the previous event were recorded.
+    times: Option<(Instant, Instant)>,
+}
+
+impl Sniffer {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Self {
+            out: Box::new(out),
+            capture: None,
+            times: None,
+        }
+    }
+
+    /// Also save every event to `capture`, starting with the magic string.
+    pub fn capture_to<W: Write + Send + 'static>(mut self, mut capture: W) -> Result<Self> {
+        capture.write_all(CAPTURE_MAGIC)?;
+        self.capture = Some(Box::new(capture));
+        Ok(self)
+    }
+
+    /// Subscribe to every event with `client` and record them until the connection fails, e.g.
+    /// when sway exits. Events this crate doesn't know the type of are recorded too. To stop
+    /// earlier, feed `record()` from `Client::events()` with a cancellation token instead.
+    pub fn run(&mut self, mut client: Client) -> Result<()> {
+        client.subscribe(EventMask::all())?;
+        let (_sender, mut receiver) = client.split()?;
+        loop {
+            let (code, payload) = receiver.recv_code()?;
+            self.record(code, &payload)?;
+        }
+    }
+
+    /// Print an event and add it to the capture.
+    pub fn record<E: Into<EventCode>>(&mut self, event: E, payload: &[u8]) -> Result<()> {
+        self.record_at(event.into(), payload, Instant::now())
+    }
+
+    fn record_at(&mut self, code: EventCode, payload: &[u8], now: Instant) -> Result<()> {
+        let (start, previous) = self.times.unwrap_or((now, now));
+        self.times = Some((start, now));
+        let at = now.duration_since(start);
+        writeln!(
+            self.out,
+            "[{:>10.3}s +{:>9.3}ms] {} ({:#010x}), {} bytes",
+            at.as_secs_f64(),
+            now.duration_since(previous).as_secs_f64() * 1000.0,
+            core::event_code_to_name(code.raw()).unwrap_or("unknown"),
+            code.raw(),
+            payload.len(),
+        )?;
+        match serde_json::from_slice::<JsonValue>(payload) {
+            Ok(json) => writeln!(self.out, "{:#}", json)?,
+            Err(_) => writeln!(self.out, "{}", String::from_utf8_lossy(payload))?,
+        }
+        if let Some(capture) = &mut self.capture {
+            let mut record = (at.as_micros() as u64).to_le_bytes().to_vec();
+            Header {
+                payload_len: payload.len() as u32,
+                payload_type: code.raw(),
+            }
+            .write_to(&mut record);
+            record.extend_from_slice(payload);
+            capture.write_all(&record)?;
+            capture.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Load the events of a capture file written by `Sniffer`. Returns `Error::InvalidHeader` if the
+/// magic string is missing or a frame is malformed.
+pub fn read_capture<R: Read>(mut reader: R) -> Result<Vec<CapturedEvent>> {
+    let mut magic = [0u8; CAPTURE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != CAPTURE_MAGIC {
+        return Err(Error::InvalidHeader);
+    }
+    let mut events = Vec::new();
+    loop {
+        let mut at = [0u8; 8];
+        match reader.read_exact(&mut at) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(events),
+            Err(err) => return Err(err.into()),
+        }
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let header = Header::parse(&header)?;
+        let code = EventCode::from_raw(header.payload_type).ok_or(Error::InvalidHeader)?;
+        let mut payload = vec![0u8; header.payload_len as usize];
+        reader.read_exact(&mut payload)?;
+        events.push(CapturedEvent {
+            at: Duration::from_micros(u64::from_le_bytes(at)),
+            code,
+            payload,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::IpcEvent;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A writer whose output can still be read after it was moved into the sniffer.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn print_and_capture() {
+        let (out, capture) = (Shared::default(), Shared::default());
+        let mut sniffer = Sniffer::new(out.clone())
+            .capture_to(capture.clone())
+            .unwrap();
+        let start = Instant::now();
+        sniffer
+            .record_at(IpcEvent::Tick.code(), br#"{"first":true}"#, start)
+            .unwrap();
+        sniffer
+            .record_at(
+                IpcEvent::Window.code(),
+                b"not json",
+                start + Duration::from_millis(12),
+            )
+            .unwrap();
+
+        let printed = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(
+            lines[0],
+            "[     0.000s +    0.000ms] tick (0x80000007), 14 bytes"
+        );
+        assert_eq!(lines[1..4], ["{", r#"  "first": true"#, "}"]);
+        assert_eq!(
+            lines[4],
+            "[     0.012s +   12.000ms] window (0x80000003), 8 bytes"
+        );
+        assert_eq!(lines[5], "not json");
+
+        let events = read_capture(&capture.0.lock().unwrap()[..]).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].at, Duration::from_millis(12));
+        assert_eq!(events[1].code, IpcEvent::Window.code());
+        assert_eq!(events[1].payload, b"not json");
+        assert!(matches!(
+            read_capture(&b"not-a-capture"[..]),
+            Err(Error::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn run_records_unknown_events() {
+        let server = MockServer::start().unwrap();
+        let out = Shared::default();
+        let client = Client::connect_to_path(server.path()).unwrap();
+        let running = {
+            let out = out.clone();
+            thread::spawn(move || Sniffer::new(out).run(client))
+        };
+        while server.received().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let subscribe = String::from_utf8(server.received()[0].payload.clone()).unwrap();
+        assert!(subscribe.contains(r#""bar_state_update","input""#));
+
+        server.send_event(IpcEvent::Input, b"{}").unwrap();
+        let unknown = EventCode::from_raw(0x8000_00ff).unwrap();
+        server.send_event(unknown, b"{}").unwrap();
+        while String::from_utf8_lossy(&out.0.lock().unwrap())
+            .lines()
+            .count()
+            < 4
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(server);
+        assert!(matches!(running.join().unwrap(), Err(Error::Disconnected)));
+        let printed = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let headers: Vec<&str> = printed
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .map(|line| &line[27..])
+            .collect();
+        assert_eq!(
+            headers,
+            vec![
+                "input (0x80000015), 2 bytes",
+                "unknown (0x800000ff), 2 bytes"
+            ]
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! A fake sway for testing code which talks to it, without a running compositor.
+//!
+//! ```
+//! use ksway::{ipc_command, testing::MockServer, Client, IpcEvent, SwayClient};
+//!
+//! let server = MockServer::start()?;
+//! server.reply(&ipc_command::get_version(), br#"{"major": 1, "minor": 9, "patch": 0}"#);
+//!
+//! let mut client = Client::connect_to_path(server.path())?;
+//! assert_eq!(client.get_version()?, br#"{"major": 1, "minor": 9, "patch": 0}"#);
+//! client.run("workspace 2")?;
+//! assert_eq!(server.run_commands(), vec!["workspace 2"]);
+//!
+//! let rx = client.subscribe(vec![IpcEvent::Tick])?;
+//! server.send_event(IpcEvent::Tick, br#"{"first": false, "payload": "hi"}"#)?;
+//! client.poll()?;
+//! assert_eq!(rx.try_recv().unwrap().0, IpcEvent::Tick);
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! For end to end tests against the real thing, `HeadlessSway` runs a sway without a display:
+//! ```no_run
+//! use ksway::{testing::HeadlessSway, SwayClient, SwayClientJson};
+//!
+//! let sway = HeadlessSway::start()?;
+//! let mut client = sway.connect()?;
+//! client.run("workspace 3")?;
+//! assert_eq!(client.focused_workspace()?.unwrap()["name"], "3");
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::core::{self, Header};
+use crate::{Client, EventCode, IpcCommand, IpcCommandCode, IpcEvent};
+
+/// A path in the temporary directory which is unique to this process and call.
+fn temp_path(prefix: &str, suffix: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "{}-{}-{}{}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        suffix
+    ))
+}
+
+/// A frame received by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub payload_type: u32,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Canned replies per command code, the last of which is repeated.
+    replies: Mutex<HashMap<u32, VecDeque<Vec<u8>>>>,
+    received: Mutex<Vec<Frame>>,
+    /// The write halves of the connections. Replies and events both go through the lock, so
+    /// that an event is never written in the middle of a reply.
+    clients: Mutex<Vec<Arc<Mutex<UnixStream>>>>,
+    stopped: AtomicBool,
+}
+
+impl State {
+    fn next_reply(&self, code: u32) -> Vec<u8> {
+        let mut replies = self.replies.lock().unwrap();
+        match replies.get_mut(&code) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) if !queue.is_empty() => queue[0].clone(),
+            _ if code == IpcCommandCode::RunCommand as u32 => b"[{\"success\":true}]".to_vec(),
+            _ if code == IpcCommandCode::Subscribe as u32
+                || code == IpcCommandCode::SendTick as u32 =>
+            {
+                b"{\"success\":true}".to_vec()
+            }
+            _ => b"null".to_vec(),
+        }
+    }
+}
+
+fn frame(payload_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(core::HEADER_LEN + payload.len());
+    Header {
+        payload_len: payload.len() as u32,
+        payload_type,
+    }
+    .write_to(&mut out);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Answer the commands read from `socket` through `writer` until the connection is closed.
+fn serve(mut socket: UnixStream, writer: &Mutex<UnixStream>, state: &State) -> io::Result<()> {
+    let mut header = [0u8; core::HEADER_LEN];
+    loop {
+        match socket.read_exact(&mut header) {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let header = Header::parse(&header)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid header"))?;
+        let mut payload = vec![0u8; header.payload_len as usize];
+        socket.read_exact(&mut payload)?;
+        let tick = match header.payload_type {
+            code if code == IpcCommandCode::SendTick as u32 => Some(serde_json::json!({
+                "first": false,
+                "payload": String::from_utf8_lossy(&payload),
+            })),
+            _ => None,
+        };
+        state.received.lock().unwrap().push(Frame {
+            payload_type: header.payload_type,
+            payload,
+        });
+        let reply = state.next_reply(header.payload_type);
+        let mut writer = writer.lock().unwrap();
+        writer.write_all(&frame(header.payload_type, &reply))?;
+        if let Some(tick) = tick {
+            writer.write_all(&frame(
+                IpcEvent::Tick.code().raw(),
+                tick.to_string().as_bytes(),
+            ))?;
+        }
+    }
+}
+
+/// Listens on a temporary socket and answers every command, with canned replies set with
+/// `reply()` or a plain success. Every frame received is recorded. The socket is removed when
+/// the server is dropped.
+pub struct MockServer {
+    path: PathBuf,
+    state: Arc<State>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<Self> {
+        let path = temp_path("ksway-mock", ".sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        let state = Arc::new(State::default());
+        let acceptor = {
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                for socket in listener.incoming() {
+                    if state.stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let socket = match socket {
+                        Ok(socket) => socket,
+                        Err(_) => continue,
+                    };
+                    let writer = match socket.try_clone() {
+                        Ok(writer) => Arc::new(Mutex::new(writer)),
+                        Err(_) => continue,
+                    };
+                    state.clients.lock().unwrap().push(Arc::clone(&writer));
+                    let state = Arc::clone(&state);
+                    thread::spawn(move || {
+                        let _ = serve(socket, &writer, &state);
+                    });
+                }
+            })
+        };
+        Ok(Self {
+            path,
+            state,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// The socket to connect to, e.g. with `Client::connect_to_path()`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue `payload` as the reply to the next command of the same type as `command`. Replies
+    /// are used in the order they were queued, and the last one keeps being used after that.
+    /// Commands without a canned reply get `[{"success":true}]` for RUN_COMMAND,
+    /// `{"success":true}` for SUBSCRIBE and SEND_TICK, and `null` for anything else. Like sway,
+    /// a SEND_TICK is followed by its tick event, though only on the connection which sent it.
+    pub fn reply<P: Into<Vec<u8>>>(&self, command: &IpcCommand, payload: P) {
+        self.state
+            .replies
+            .lock()
+            .unwrap()
+            .entry(command.code() as u32)
+            .or_default()
+            .push_back(payload.into());
+    }
+
+    /// Send an event to every client which is connected. Takes an `EventCode` for events which
+    /// `IpcEvent` doesn't know.
+    pub fn send_event<E: Into<EventCode>>(&self, event: E, payload: &[u8]) -> io::Result<()> {
+        let frame = frame(event.into().raw(), payload);
+        for client in self.state.clients.lock().unwrap().iter() {
+            client.lock().unwrap().write_all(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Write `bytes` as they are to every client which is connected, e.g. to test how a client
+    /// copes with a corrupt stream.
+    pub fn send_raw(&self, bytes: &[u8]) -> io::Result<()> {
+        for client in self.state.clients.lock().unwrap().iter() {
+            client.lock().unwrap().write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Close every connection, like sway does when it exits or restarts. New connections are
+    /// still accepted.
+    pub fn disconnect(&self) {
+        for client in self.state.clients.lock().unwrap().drain(..) {
+            let _ = client.lock().unwrap().shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Every frame received so far, from all connections.
+    pub fn received(&self) -> Vec<Frame> {
+        self.state.received.lock().unwrap().clone()
+    }
+
+    /// The payloads of the RUN_COMMAND frames received so far.
+    pub fn run_commands(&self) -> Vec<String> {
+        self.received()
+            .into_iter()
+            .filter(|frame| frame.payload_type == IpcCommandCode::RunCommand as u32)
+            .map(|frame| String::from_utf8_lossy(&frame.payload).into_owned())
+            .collect()
+    }
+
+    /// Assert that the RUN_COMMAND frames received so far are exactly `expected`.
+    pub fn assert_run_commands(&self, expected: &[&str]) {
+        assert_eq!(self.run_commands(), expected);
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // Wake up the acceptor so that it sees the flag.
+        let _ = UnixStream::connect(&self.path);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        for client in self.state.clients.lock().unwrap().iter() {
+            let _ = client.lock().unwrap().shutdown(std::net::Shutdown::Both);
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// What every `HeadlessSway` config starts with: no Xwayland, which needs an X server to start.
+const BASE_CONFIG: &str = "xwayland disable\n";
+
+/// How long `HeadlessSway` waits for sway's socket to accept connections.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A sway running with the headless wlroots backend, for end to end tests on machines without a
+/// display, e.g. in CI. It gets a private runtime directory, so it doesn't interfere with a sway
+/// the user is running, and it is killed when dropped. Its output is logged to `sway.log` in
+/// that directory, which is kept if it fails to start.
+pub struct HeadlessSway {
+    child: Child,
+    dir: PathBuf,
+    socket: PathBuf,
+    /// Set when sway failed to start, to keep its log.
+    keep_dir: bool,
+}
+
+impl HeadlessSway {
+    /// Run `sway` from the PATH with a minimal config.
+    pub fn start() -> io::Result<Self> {
+        Self::start_with("sway", "")
+    }
+
+    /// Run `binary` with a minimal config followed by `config`, e.g. for bindings or outputs.
+    pub fn start_with<S: AsRef<OsStr>>(binary: S, config: &str) -> io::Result<Self> {
+        let dir = temp_path("ksway-headless", "");
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let config_path = dir.join("config");
+        fs::write(&config_path, format!("{}{}", BASE_CONFIG, config))?;
+        let socket = dir.join("sway-ipc.sock");
+        let log = File::create(dir.join("sway.log"))?;
+        let child = Command::new(binary)
+            .arg("--config")
+            .arg(&config_path)
+            .arg("--unsupported-gpu")
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env("XDG_RUNTIME_DIR", &dir)
+            .env("SWAYSOCK", &socket)
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("DISPLAY")
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn();
+        let child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(err);
+            }
+        };
+        let mut sway = Self {
+            child,
+            dir,
+            socket,
+            keep_dir: false,
+        };
+        if let Err(err) = sway.wait_for_socket() {
+            sway.keep_dir = true;
+            return Err(err);
+        }
+        Ok(sway)
+    }
+
+    fn wait_for_socket(&mut self) -> io::Result<()> {
+        let log = self.dir.join("sway.log");
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Err(io::Error::other(format!(
+                    "sway exited with {}, see {}",
+                    status,
+                    log.display()
+                )));
+            }
+            if UnixStream::connect(&self.socket).is_ok() {
+                return Ok(());
+            }
+            if start.elapsed() > STARTUP_TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("sway didn't open its socket, see {}", log.display()),
+                ));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// The IPC socket.
+    pub fn path(&self) -> &Path {
+        &self.socket
+    }
+
+    pub fn connect(&self) -> crate::Result<Client> {
+        Client::connect_to_path(&self.socket)
+    }
+}
+
+impl Drop for HeadlessSway {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if !self.keep_dir {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ipc_command, Client, SwayClient};
+
+    #[test]
+    fn canned_replies() {
+        let server = MockServer::start().unwrap();
+        server.reply(&ipc_command::get_marks(), "[\"a\"]");
+        server.reply(&ipc_command::get_marks(), "[\"b\"]");
+
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert_eq!(client.get_marks().unwrap(), b"[\"a\"]");
+        assert_eq!(client.get_marks().unwrap(), b"[\"b\"]");
+        assert_eq!(client.get_marks().unwrap(), b"[\"b\"]");
+        assert_eq!(client.get_tree().unwrap(), b"null");
+        assert_eq!(client.run("nop").unwrap(), b"[{\"success\":true}]");
+        server.assert_run_commands(&["nop"]);
+        assert_eq!(server.received().len(), 5);
+    }
+
+    #[test]
+    fn headless_sway_missing_binary() {
+        let err = HeadlessSway::start_with("/nonexistent/sway", "")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
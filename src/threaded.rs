@@ -0,0 +1,70 @@
+//! A client whose events are read by a background thread, enabled with the `threaded` feature.
+//!
+//! `Client::spawn_event_loop()` splits the client and has a thread read the socket, handing
+//! replies to the commands waiting for them and events to subscribers, so nothing needs to call
+//! `poll()`. The `Handle` it returns can be shared between threads:
+//! ```no_run
+//! use std::{sync::Arc, thread};
+//! use ksway::{IpcEvent, SwayClient};
+//!
+//! let handle = Arc::new(ksway::Client::connect()?.spawn_event_loop()?);
+//! let rx = handle.subscribe(vec![IpcEvent::Window])?;
+//! let worker = Arc::clone(&handle);
+//! thread::spawn(move || {
+//!     for (event, _) in rx {
+//!         let _ = (&*worker).run("nop");
+//!     }
+//! });
+//! (&*handle).run("workspace 2")?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//! The `Handle` is a `SharedClient` built from the client, so it keeps the client's timeouts,
+//! and several threads can have commands in flight at once.
+use crate::{Client, Result, SharedClient};
+
+/// A client with a background thread reading its events, see `Client::spawn_event_loop()`.
+/// Dropping it closes the connection and waits for the thread to end.
+pub type Handle = SharedClient;
+
+impl Client {
+    /// Move the connection into a background thread which keeps reading it, see
+    /// `threaded::Handle`. Commands wait for their reply for the request timeout, or the read
+    /// timeout if there is none, see `Timeouts`.
+    pub fn spawn_event_loop(self) -> Result<Handle> {
+        SharedClient::from_client(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::{IpcEvent, SwayClient};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn events_without_polling() {
+        let server = MockServer::start().unwrap();
+        let handle = Arc::new(
+            Client::connect_to_path(server.path())
+                .unwrap()
+                .spawn_event_loop()
+                .unwrap(),
+        );
+        let rx = handle.subscribe(vec![IpcEvent::Tick]).unwrap();
+        let worker = Arc::clone(&handle);
+        thread::spawn(move || (&*worker).run("focus").unwrap())
+            .join()
+            .unwrap();
+        server.send_event(IpcEvent::Window, b"{}").unwrap();
+        server.send_event(IpcEvent::Tick, b"{}").unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(rx.recv_timeout(timeout).unwrap().0, IpcEvent::Tick);
+        assert_eq!(server.run_commands(), vec!["focus"]);
+
+        drop(handle);
+        assert!(rx.recv_timeout(timeout).is_err());
+    }
+}
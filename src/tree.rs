@@ -0,0 +1,1102 @@
+//! Walking the container tree from GET_TREE.
+//!
+//! Containers live under `nodes` or `floating_nodes`, and hidden scratchpad windows under the
+//! special `__i3_scratch` workspace. `nodes()` only follows those keys and tags every container
+//! with what kind it is, along with the output and workspace it is on:
+//! ```no_run
+//! use ksway::{tree::{self, NodeKind}, SwayClientJson};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let tree = client.get_tree_json()?;
+//! for node in tree::nodes(&tree).filter(|node| node.kind == NodeKind::Floating) {
+//!     println!("{} is floating on {:?}", node.node["id"], node.workspace);
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+//!
+//! `Tree` holds a typed tree with links to parents, for questions like which workspace the
+//! focused window is on.
+//!
+//! `visit_reader()` walks the tree as the reply is parsed, without building a `JsonValue` of it,
+//! and can stop early. `visit_tree()` does so straight from the socket, and stops reading the
+//! reply once the visitor is done:
+//! ```no_run
+//! use ksway::{tree::Visit, SwayClientJson};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let mut focused = None;
+//! client.visit_tree(|node| {
+//!     if node.focused {
+//!         focused = Some(node.id);
+//!         return Visit::Stop;
+//!     }
+//!     Visit::Continue
+//! })?;
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::collections::HashMap;
+
+use serde::de;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::{reply, JsonValue, Result};
+
+/// The name of the workspace which holds the scratchpad.
+pub const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Root,
+    Output,
+    Workspace,
+    /// A container in the tiling tree of a workspace, from `nodes`.
+    Tiling,
+    /// A container floating on a workspace, from `floating_nodes`, or anything nested in one.
+    Floating,
+    /// A container in the scratchpad, which is hidden unless it is shown on a workspace.
+    Scratchpad,
+}
+
+impl NodeKind {
+    /// Whether this is a container rather than part of the root/output/workspace structure.
+    pub fn is_container(self) -> bool {
+        match self {
+            NodeKind::Tiling | NodeKind::Floating | NodeKind::Scratchpad => true,
+            NodeKind::Root | NodeKind::Output | NodeKind::Workspace => false,
+        }
+    }
+}
+
+/// A node yielded by `nodes()`.
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    pub node: &'a JsonValue,
+    pub kind: NodeKind,
+    /// The output this node is on, `None` for the root.
+    pub output: Option<&'a str>,
+    /// The workspace this node is on, `None` above workspaces.
+    pub workspace: Option<&'a str>,
+    /// How many levels below the node `nodes()` was called on.
+    pub depth: usize,
+}
+
+impl<'a> Node<'a> {
+    pub fn id(&self) -> Option<u64> {
+        self.node["id"].as_u64()
+    }
+
+    /// See `app_identifier()`.
+    pub fn app_identifier(&self) -> Option<&'a str> {
+        app_identifier(self.node)
+    }
+
+    /// Whether this node has no children.
+    pub fn is_leaf(&self) -> bool {
+        self.child_count() == 0
+    }
+
+    /// Whether this is a window, see `is_window()`.
+    pub fn is_window(&self) -> bool {
+        is_window(self.kind, self.child_count())
+    }
+
+    fn child_count(&self) -> usize {
+        ["nodes", "floating_nodes"]
+            .iter()
+            .map(|key| self.node[*key].as_array().map_or(0, Vec::len))
+            .sum()
+    }
+}
+
+/// Whether a node is a window: a container without children. This is what every tree model
+/// here goes by.
+fn is_window(kind: NodeKind, child_count: usize) -> bool {
+    kind.is_container() && child_count == 0
+}
+
+/// What identifies the application of a window: the `app_id` of Wayland windows, or the class
+/// of XWayland windows, falling back to their instance.
+pub fn app_identifier(node: &JsonValue) -> Option<&str> {
+    let properties = &node["window_properties"];
+    pick_app_identifier(
+        node["app_id"].as_str(),
+        properties["class"].as_str(),
+        properties["instance"].as_str(),
+    )
+}
+
+/// `app_identifier()` for a typed node.
+pub(crate) fn typed_app_identifier<'a>(
+    app_id: Option<&'a str>,
+    properties: Option<&'a reply::WindowProperties>,
+) -> Option<&'a str> {
+    pick_app_identifier(
+        app_id,
+        properties.and_then(|p| p.class.as_deref()),
+        properties.and_then(|p| p.instance.as_deref()),
+    )
+}
+
+fn pick_app_identifier<'a>(
+    app_id: Option<&'a str>,
+    class: Option<&'a str>,
+    instance: Option<&'a str>,
+) -> Option<&'a str> {
+    app_id.or(class).or(instance)
+}
+
+/// Makes app identifiers comparable, since the same application can report different ones,
+/// e.g. `firefox` as a Wayland window and `Firefox` under XWayland, or `org.gnome.Nautilus`
+/// and `nautilus`.
+#[derive(Debug, Clone, Default)]
+pub struct AppIdNormalizer {
+    lowercase: bool,
+    aliases: HashMap<String, String>,
+}
+
+impl AppIdNormalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare identifiers case insensitively, by lowercasing them.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Replace the identifier `from` with `to`. Aliases are looked up after lowercasing, so
+    /// `from` should be lowercase when lowercasing is enabled.
+    pub fn alias<S: Into<String>, T: Into<String>>(mut self, from: S, to: T) -> Self {
+        self.aliases.insert(from.into(), to.into());
+        self
+    }
+
+    pub fn normalize(&self, id: &str) -> String {
+        let id = if self.lowercase {
+            id.to_lowercase()
+        } else {
+            id.to_owned()
+        };
+        match self.aliases.get(&id) {
+            Some(alias) => alias.clone(),
+            None => id,
+        }
+    }
+
+    /// The normalized `app_identifier()` of a node.
+    pub fn app_identifier(&self, node: &JsonValue) -> Option<String> {
+        app_identifier(node).map(|id| self.normalize(id))
+    }
+}
+
+/// Classify a node from its `type`, given the kind and name of its parent and whether it was
+/// found under `floating_nodes`. Without a parent, a container is assumed to be tiling.
+fn classify(
+    node_type: Option<reply::NodeType>,
+    parent: Option<(NodeKind, Option<&str>)>,
+    floating: bool,
+) -> NodeKind {
+    use reply::NodeType;
+    match (node_type, parent) {
+        (Some(NodeType::Root), _) => NodeKind::Root,
+        (Some(NodeType::Output), _) => NodeKind::Output,
+        (Some(NodeType::Workspace), _) => NodeKind::Workspace,
+        (_, Some((NodeKind::Workspace, Some(SCRATCHPAD_WORKSPACE))))
+        | (_, Some((NodeKind::Scratchpad, _))) => NodeKind::Scratchpad,
+        (Some(NodeType::FloatingCon), _) | (_, Some((NodeKind::Floating, _))) => NodeKind::Floating,
+        _ if floating => NodeKind::Floating,
+        _ => NodeKind::Tiling,
+    }
+}
+
+fn json_node_type(node: &JsonValue) -> Option<reply::NodeType> {
+    reply::NodeType::deserialize(&node["type"]).ok()
+}
+
+/// A preorder iterator over a tree, see `nodes()`.
+pub struct Nodes<'a> {
+    stack: Vec<Node<'a>>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        let current = self.stack.pop()?;
+        let parent = (current.kind, current.node["name"].as_str());
+        // Pushed in reverse so that `nodes` come out before `floating_nodes`, in order.
+        for (key, floating) in &[("floating_nodes", true), ("nodes", false)] {
+            for child in current.node[*key].as_array().into_iter().flatten().rev() {
+                let kind = classify(json_node_type(child), Some(parent), *floating);
+                let output = match kind {
+                    NodeKind::Output => child["name"].as_str(),
+                    _ => current.output,
+                };
+                let workspace = match kind {
+                    NodeKind::Workspace => child["name"].as_str(),
+                    _ => current.workspace,
+                };
+                self.stack.push(Node {
+                    node: child,
+                    kind,
+                    output,
+                    workspace,
+                    depth: current.depth + 1,
+                });
+            }
+        }
+        Some(current)
+    }
+}
+
+/// Walk every node of a GET_TREE reply in preorder, following `nodes` before `floating_nodes`.
+pub fn nodes(tree: &JsonValue) -> Nodes<'_> {
+    let kind = classify(json_node_type(tree), None, false);
+    Nodes {
+        stack: vec![Node {
+            node: tree,
+            kind,
+            output: None,
+            workspace: None,
+            depth: 0,
+        }],
+    }
+}
+
+/// Every window in a GET_TREE reply, i.e. the leaf containers, including those in the scratchpad.
+pub fn windows(tree: &JsonValue) -> impl Iterator<Item = Node<'_>> {
+    nodes(tree).filter(Node::is_window)
+}
+
+/// The con_ids of the windows in a GET_TREE reply by how recently they were focused, as far
+/// as the `focus` lists of the nodes tell, so the focused window comes first. Those only order
+/// siblings, e.g. every window of the focused workspace comes before those of other
+/// workspaces, which `focus_history::FocusHistory` doesn't have to guess.
+pub fn focus_order(tree: &JsonValue) -> Vec<u64> {
+    // For the nodes from the root down to the current one: the node, its position in its
+    // parent's `focus` list and among its siblings, and how many of its children were seen.
+    let mut path: Vec<(&JsonValue, (usize, usize), usize)> = Vec::new();
+    let mut windows: Vec<(Vec<(usize, usize)>, u64)> = Vec::new();
+    for node in nodes(tree) {
+        path.truncate(node.depth);
+        let position = match path.last_mut() {
+            Some((parent, _, seen)) => {
+                let focus = parent["focus"]
+                    .as_array()
+                    .and_then(|focus| focus.iter().position(|id| *id == node.node["id"]));
+                *seen += 1;
+                (focus.unwrap_or(usize::MAX), *seen)
+            }
+            None => (0, 0),
+        };
+        path.push((node.node, position, 0));
+        if let (true, Some(id)) = (node.is_window(), node.id()) {
+            windows.push((path.iter().map(|(_, position, _)| *position).collect(), id));
+        }
+    }
+    windows.sort_by(|(a, _), (b, _)| a.cmp(b));
+    windows.into_iter().map(|(_, id)| id).collect()
+}
+
+/// What `visit()` should do after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    /// Don't visit the children of this node, but carry on with its siblings.
+    SkipChildren,
+    /// Don't visit anything else.
+    Stop,
+}
+
+/// A node visited by `visit()`. Only the commonly used fields are parsed. The node is visited
+/// before its children are parsed, so fields which sway sends after them, like `focus`, are
+/// left out. Sway sends every field here before them.
+///
+/// Children which come before the `id` or `type` of their parent, e.g. with keys in
+/// alphabetical order, are kept as raw JSON and walked once their parent was visited.
+#[derive(Debug, Clone, Default)]
+pub struct RawNode {
+    pub id: u64,
+    pub node_type: String,
+    pub name: Option<String>,
+    pub focused: bool,
+    pub urgent: bool,
+    pub marks: Vec<String>,
+    pub app_id: Option<String>,
+    pub pid: Option<u32>,
+    pub window_properties: Option<reply::WindowProperties>,
+    /// How many levels below the root.
+    pub depth: usize,
+    leaf: bool,
+}
+
+impl RawNode {
+    /// See `app_identifier()`.
+    pub fn app_identifier(&self) -> Option<&str> {
+        typed_app_identifier(self.app_id.as_deref(), self.window_properties.as_ref())
+    }
+
+    /// Whether this node has no children.
+    pub fn is_leaf(&self) -> bool {
+        self.leaf
+    }
+}
+
+/// The fields of a node which `visit()` looks at.
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Id,
+    Type,
+    Name,
+    Focused,
+    Urgent,
+    Marks,
+    AppId,
+    Pid,
+    WindowProperties,
+    Nodes,
+    FloatingNodes,
+    #[serde(other)]
+    Other,
+}
+
+/// The visitor of a walk, and whether it asked to stop, which aborts the deserializer.
+struct Walk<'a, F> {
+    visitor: &'a mut F,
+    stopped: bool,
+}
+
+/// A node while its fields are being deserialized. It is visited when the first of its
+/// children is reached, or once it ends if it has none.
+struct PendingNode {
+    node: RawNode,
+    has_id: bool,
+    has_type: bool,
+    visited: Option<Visit>,
+}
+
+impl PendingNode {
+    fn visit<F: FnMut(&RawNode) -> Visit, E: de::Error>(
+        &mut self,
+        walk: &mut Walk<'_, F>,
+    ) -> std::result::Result<Visit, E> {
+        if let Some(visited) = self.visited {
+            return Ok(visited);
+        }
+        if !self.has_id {
+            return Err(E::missing_field("id"));
+        }
+        if !self.has_type {
+            return Err(E::missing_field("type"));
+        }
+        let visited = (walk.visitor)(&self.node);
+        self.visited = Some(visited);
+        if visited == Visit::Stop {
+            walk.stopped = true;
+            return Err(E::custom("the visitor stopped the walk"));
+        }
+        Ok(visited)
+    }
+}
+
+/// Deserializes a node, calling the visitor on it before deserializing its children.
+struct NodeVisitor<'w, 'a, F> {
+    walk: &'w mut Walk<'a, F>,
+    depth: usize,
+}
+
+impl<'de, F: FnMut(&RawNode) -> Visit> de::Visitor<'de> for NodeVisitor<'_, '_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a node")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        let mut pending = PendingNode {
+            node: RawNode {
+                depth: self.depth,
+                leaf: true,
+                ..RawNode::default()
+            },
+            has_id: false,
+            has_type: false,
+            visited: None,
+        };
+        let mut deferred: [Vec<Box<RawValue>>; 2] = Default::default();
+        while let Some(field) = map.next_key()? {
+            let streaming = pending.has_id && pending.has_type && deferred[0].is_empty();
+            let node = &mut pending.node;
+            match field {
+                Field::Id => {
+                    node.id = map.next_value()?;
+                    pending.has_id = true;
+                }
+                Field::Type => {
+                    node.node_type = map.next_value()?;
+                    pending.has_type = true;
+                }
+                Field::Name => node.name = map.next_value()?,
+                Field::Focused => node.focused = map.next_value()?,
+                Field::Urgent => node.urgent = map.next_value()?,
+                Field::Marks => node.marks = map.next_value()?,
+                Field::AppId => node.app_id = map.next_value()?,
+                Field::Pid => node.pid = map.next_value()?,
+                Field::WindowProperties => node.window_properties = map.next_value()?,
+                Field::Nodes | Field::FloatingNodes if streaming => {
+                    map.next_value_seed(Children {
+                        parent: &mut pending,
+                        walk: &mut *self.walk,
+                    })?
+                }
+                Field::Nodes => deferred[0] = map.next_value()?,
+                Field::FloatingNodes => deferred[1] = map.next_value()?,
+                Field::Other => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        if deferred.iter().any(|children| !children.is_empty()) {
+            pending.node.leaf = false;
+        }
+        if pending.visit(self.walk)? == Visit::SkipChildren {
+            return Ok(());
+        }
+        for child in deferred.iter().flatten() {
+            let mut deserializer = serde_json::Deserializer::from_str(child.get());
+            de::Deserializer::deserialize_map(
+                &mut deserializer,
+                NodeVisitor {
+                    walk: &mut *self.walk,
+                    depth: self.depth + 1,
+                },
+            )
+            .map_err(de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `nodes` or `floating_nodes` of a node.
+struct Children<'p, 'w, 'a, F> {
+    parent: &'p mut PendingNode,
+    walk: &'w mut Walk<'a, F>,
+}
+
+impl<'de, F: FnMut(&RawNode) -> Visit> de::DeserializeSeed<'de> for Children<'_, '_, '_, F> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(&RawNode) -> Visit> de::Visitor<'de> for Children<'_, '_, '_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a list of nodes")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while seq
+            .next_element_seed(Child {
+                parent: &mut *self.parent,
+                walk: &mut *self.walk,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// One of the children of a node, which is visited first, and skipped along with the others if
+/// the visitor says so.
+struct Child<'p, 'w, 'a, F> {
+    parent: &'p mut PendingNode,
+    walk: &'w mut Walk<'a, F>,
+}
+
+impl<'de, F: FnMut(&RawNode) -> Visit> de::DeserializeSeed<'de> for Child<'_, '_, '_, F> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        self.parent.node.leaf = false;
+        if self.parent.visit(self.walk)? == Visit::SkipChildren {
+            de::IgnoredAny::deserialize(deserializer)?;
+            return Ok(());
+        }
+        deserializer.deserialize_map(NodeVisitor {
+            walk: self.walk,
+            depth: self.parent.node.depth + 1,
+        })
+    }
+}
+
+/// Walk the tree `deserializer` is positioned on, see `visit_reader()`.
+fn walk<'de, R: serde_json::de::Read<'de>, F: FnMut(&RawNode) -> Visit>(
+    deserializer: &mut serde_json::Deserializer<R>,
+    mut visitor: F,
+) -> Result<()> {
+    let mut walk = Walk {
+        visitor: &mut visitor,
+        stopped: false,
+    };
+    let walked = de::Deserializer::deserialize_map(
+        &mut *deserializer,
+        NodeVisitor {
+            walk: &mut walk,
+            depth: 0,
+        },
+    );
+    match walked {
+        Err(_) if walk.stopped => Ok(()),
+        walked => {
+            walked?;
+            Ok(deserializer.end()?)
+        }
+    }
+}
+
+/// Visit the nodes of a raw GET_TREE reply in preorder, following `nodes` before
+/// `floating_nodes`, without building a `JsonValue` of the tree. Like `visit_reader()`, with
+/// the reply already in memory.
+pub fn visit<F: FnMut(&RawNode) -> Visit>(payload: &[u8], visitor: F) -> Result<()> {
+    walk(&mut serde_json::Deserializer::from_slice(payload), visitor)
+}
+
+/// Visit the nodes of a GET_TREE reply as it is read from `reader`, in preorder, following
+/// `nodes` before `floating_nodes`. Each node is parsed into a `RawNode` and visited as soon as
+/// its own fields are read, before its children, which are then parsed one at a time.
+/// Nothing else is kept, so memory doesn't grow with the size of the tree. Subtrees which are
+/// skipped are only scanned, and parsing stops as soon as the visitor returns `Visit::Stop`,
+/// leaving the rest of `reader` unread.
+///
+/// `reader` should be buffered, e.g. a `BufReader`, since it is read a byte at a time.
+/// `SwayClientJson::visit_tree()` drives this from the socket of a `Client`.
+pub fn visit_reader<R: std::io::Read, F: FnMut(&RawNode) -> Visit>(
+    reader: R,
+    visitor: F,
+) -> Result<()> {
+    walk(&mut serde_json::Deserializer::from_reader(reader), visitor)
+}
+
+/// A node of a `Tree` along with its position, e.g. to look at its parent. It dereferences to
+/// the node itself, whose `nodes` and `floating_nodes` are always empty since the children
+/// live in the tree, see `children()`.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a> {
+    tree: &'a Tree,
+    index: usize,
+}
+
+impl<'a> NodeRef<'a> {
+    fn entry(&self) -> &'a Entry {
+        &self.tree.entries[self.index]
+    }
+
+    pub fn node(&self) -> &'a reply::Node {
+        &self.entry().node
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        let index = self.entry().parent?;
+        Some(self.tree.get(index))
+    }
+
+    /// Whether this node is under its parent's `floating_nodes`.
+    pub fn is_floating(&self) -> bool {
+        self.entry().floating
+    }
+
+    /// The children, those from `nodes` before those from `floating_nodes`.
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let tree = self.tree;
+        self.entry()
+            .children
+            .iter()
+            .map(move |&index| tree.get(index))
+    }
+
+    /// The parent, its parent and so on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        std::iter::successors(self.parent(), |node| node.parent())
+    }
+
+    /// Everything below this node in preorder, not including itself.
+    pub fn descendants(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let tree = self.tree;
+        let mut stack: Vec<usize> = self.entry().children.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let index = stack.pop()?;
+            stack.extend(tree.entries[index].children.iter().rev());
+            Some(tree.get(index))
+        })
+    }
+
+    /// The workspace this node is on, which is itself for a workspace.
+    pub fn workspace(&self) -> Option<NodeRef<'a>> {
+        std::iter::once(*self)
+            .chain(self.ancestors())
+            .find(|node| node.node_type == reply::NodeType::Workspace)
+    }
+
+    /// The output this node is on, which is itself for an output.
+    pub fn output(&self) -> Option<NodeRef<'a>> {
+        std::iter::once(*self)
+            .chain(self.ancestors())
+            .find(|node| node.node_type == reply::NodeType::Output)
+    }
+
+    /// See `NodeKind`.
+    pub fn kind(&self) -> NodeKind {
+        self.entry().kind
+    }
+
+    /// Whether this is a container without children, i.e. a window.
+    pub fn is_window(&self) -> bool {
+        is_window(self.kind(), self.entry().children.len())
+    }
+
+    /// The only child, if there is exactly one.
+    pub fn only_child(&self) -> Option<NodeRef<'a>> {
+        match self.entry().children[..] {
+            [child] => Some(self.tree.get(child)),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a tiling container which only holds one child, which is what is left of a
+    /// split after the other windows in it closed or moved away. `layouts` are the layouts which
+    /// count, e.g. `splith` and `splitv`, since a tabbed container with one tab may be on purpose.
+    pub fn is_redundant(&self, layouts: &[&str]) -> bool {
+        self.node_type == reply::NodeType::Con
+            && !self.entry().floating
+            && self.only_child().is_some_and(|child| !child.is_floating())
+            && self
+                .layout
+                .as_deref()
+                .is_some_and(|layout| layouts.contains(&layout))
+    }
+}
+
+impl std::ops::Deref for NodeRef<'_> {
+    type Target = reply::Node;
+
+    fn deref(&self) -> &reply::Node {
+        self.node()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    node: reply::Node,
+    kind: NodeKind,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    floating: bool,
+}
+
+/// A GET_TREE reply which can be navigated in any direction, unlike `reply::Node` and
+/// `nodes()` which only go down from the root:
+/// ```no_run
+/// use ksway::{tree::Tree, SwayClientJson};
+///
+/// let mut client = ksway::Client::connect()?;
+/// let tree = Tree::from(client.get_tree_typed()?);
+/// if let Some(focused) = tree.find_focused() {
+///     let workspace = focused.workspace().and_then(|ws| ws.name.clone());
+///     println!("{:?} on {:?}, {} levels deep", focused.name, workspace, focused.ancestors().count());
+/// }
+/// # Ok::<(), ksway::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tree {
+    /// In preorder, so the root comes first.
+    entries: Vec<Entry>,
+}
+
+impl Tree {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        Ok(Self::from(serde_json::from_slice::<reply::Node>(payload)?))
+    }
+
+    fn add(&mut self, mut node: reply::Node, parent: Option<usize>, floating: bool) -> usize {
+        let index = self.entries.len();
+        let tiling = std::mem::take(&mut node.nodes);
+        let floating_nodes = std::mem::take(&mut node.floating_nodes);
+        let parent_entry = parent.map(|parent| &self.entries[parent]);
+        let kind = classify(
+            Some(node.node_type),
+            parent_entry.map(|entry| (entry.kind, entry.node.name.as_deref())),
+            floating,
+        );
+        self.entries.push(Entry {
+            node,
+            kind,
+            parent,
+            children: Vec::new(),
+            floating,
+        });
+        let children: Vec<usize> = tiling
+            .into_iter()
+            .map(|child| (child, false))
+            .chain(floating_nodes.into_iter().map(|child| (child, true)))
+            .map(|(child, floating)| self.add(child, Some(index), floating))
+            .collect();
+        self.entries[index].children = children;
+        index
+    }
+
+    fn get(&self, index: usize) -> NodeRef<'_> {
+        NodeRef { tree: self, index }
+    }
+
+    pub fn root(&self) -> NodeRef<'_> {
+        self.get(0)
+    }
+
+    /// Every node in preorder, starting with the root.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeRef<'_>> {
+        (0..self.entries.len()).map(move |index| self.get(index))
+    }
+
+    /// Every window, including those in the scratchpad, see `NodeRef::is_window()`.
+    pub fn windows(&self) -> impl Iterator<Item = NodeRef<'_>> {
+        self.nodes().filter(|node| node.is_window())
+    }
+
+    /// Every workspace, except the one holding the scratchpad.
+    pub fn workspaces(&self) -> impl Iterator<Item = NodeRef<'_>> {
+        self.nodes().filter(|node| {
+            node.node_type == reply::NodeType::Workspace
+                && node.name.as_deref() != Some(SCRATCHPAD_WORKSPACE)
+        })
+    }
+
+    pub fn find_focused(&self) -> Option<NodeRef<'_>> {
+        self.nodes().find(|node| node.focused)
+    }
+
+    /// The node with the con_id `id`.
+    pub fn find_by_id(&self, id: u64) -> Option<NodeRef<'_>> {
+        self.nodes().find(|node| node.id == id)
+    }
+
+    pub fn find_by_mark(&self, mark: &str) -> Option<NodeRef<'_>> {
+        self.nodes()
+            .find(|node| node.marks.iter().any(|m| m == mark))
+    }
+
+    /// Every container which `NodeRef::is_redundant()`, deepest first, along with the node which
+    /// ends up in its place once it and any redundant containers below it are flattened.
+    pub fn redundant_containers(&self, layouts: &[&str]) -> Vec<(NodeRef<'_>, NodeRef<'_>)> {
+        let mut redundant: Vec<_> = self
+            .nodes()
+            .filter(|node| node.is_redundant(layouts))
+            .map(|node| {
+                let mut survivor = node;
+                while let Some(child) = survivor
+                    .only_child()
+                    .filter(|_| survivor.is_redundant(layouts))
+                {
+                    survivor = child;
+                }
+                (node, survivor)
+            })
+            .collect();
+        // Preorder reversed puts descendants before their ancestors.
+        redundant.reverse();
+        redundant
+    }
+}
+
+impl From<reply::Node> for Tree {
+    fn from(root: reply::Node) -> Self {
+        let mut tree = Self {
+            entries: Vec::new(),
+        };
+        tree.add(root, None, false);
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn walk_kinds() {
+        let tree = json!({
+            "id": 1,
+            "type": "root",
+            "nodes": [
+                {
+                    "id": 2,
+                    "type": "output",
+                    "name": "__i3",
+                    "nodes": [{
+                        "id": 3,
+                        "type": "workspace",
+                        "name": "__i3_scratch",
+                        "floating_nodes": [{"id": 4, "type": "floating_con", "nodes": []}],
+                    }],
+                },
+                {
+                    "id": 5,
+                    "type": "output",
+                    "name": "DP-1",
+                    "nodes": [{
+                        "id": 6,
+                        "type": "workspace",
+                        "name": "1",
+                        "nodes": [
+                            {"id": 7, "type": "con", "nodes": [{"id": 8, "type": "con"}]},
+                        ],
+                        "floating_nodes": [{
+                            "id": 9,
+                            "type": "floating_con",
+                            "nodes": [{"id": 10, "type": "con", "nodes": []}],
+                        }],
+                    }],
+                },
+            ],
+        });
+        let walked: Vec<_> = nodes(&tree)
+            .map(|node| (node.id().unwrap(), node.kind, node.workspace, node.depth))
+            .collect();
+        assert_eq!(
+            walked,
+            vec![
+                (1, NodeKind::Root, None, 0),
+                (2, NodeKind::Output, None, 1),
+                (3, NodeKind::Workspace, Some("__i3_scratch"), 2),
+                (4, NodeKind::Scratchpad, Some("__i3_scratch"), 3),
+                (5, NodeKind::Output, None, 1),
+                (6, NodeKind::Workspace, Some("1"), 2),
+                (7, NodeKind::Tiling, Some("1"), 3),
+                (8, NodeKind::Tiling, Some("1"), 4),
+                (9, NodeKind::Floating, Some("1"), 3),
+                (10, NodeKind::Floating, Some("1"), 4),
+            ]
+        );
+        let windows: Vec<_> = windows(&tree).filter_map(|node| node.id()).collect();
+        assert_eq!(windows, vec![4, 8, 10]);
+    }
+
+    #[test]
+    fn navigate_arena() {
+        let tree = Tree::parse(
+            br#"{"id": 1, "type": "root", "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                "nodes": [{"id": 2, "type": "output", "name": "DP-1", "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                  "nodes": [{"id": 3, "type": "workspace", "name": "1", "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                    "nodes": [{"id": 4, "type": "con", "rect": {"x": 0, "y": 0, "width": 1, "height": 1},
+                               "nodes": [{"id": 5, "type": "con", "focused": true, "marks": ["a"],
+                                          "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}]}],
+                    "floating_nodes": [{"id": 6, "type": "floating_con",
+                                        "rect": {"x": 0, "y": 0, "width": 1, "height": 1}}]}]}]}"#,
+        )
+        .unwrap();
+        let ids = |nodes: &mut dyn Iterator<Item = NodeRef<'_>>| -> Vec<u64> {
+            nodes.map(|node| node.id).collect()
+        };
+        assert_eq!(ids(&mut tree.root().descendants()), vec![2, 3, 4, 5, 6]);
+        assert_eq!(ids(&mut tree.windows()), vec![5, 6]);
+        assert_eq!(ids(&mut tree.workspaces()), vec![3]);
+
+        let focused = tree.find_focused().unwrap();
+        assert_eq!(focused.id, 5);
+        assert_eq!(ids(&mut focused.ancestors()), vec![4, 3, 2, 1]);
+        assert_eq!(focused.workspace().unwrap().name.as_deref(), Some("1"));
+        assert_eq!(focused.output().unwrap().id, 2);
+        assert_eq!(tree.find_by_mark("a").unwrap().id, 5);
+        assert!(tree.find_by_id(6).unwrap().is_floating());
+        assert_eq!(ids(&mut tree.find_by_id(3).unwrap().children()), vec![4, 6]);
+        assert!(tree.find_by_id(7).is_none());
+    }
+
+    #[test]
+    fn redundant_splits() {
+        let rect = json!({"x": 0, "y": 0, "width": 1, "height": 1});
+        let con = |id: u64, layout: &str, nodes: JsonValue| json!({"id": id, "type": "con", "layout": layout, "rect": rect, "nodes": nodes});
+        let root: reply::Node = serde_json::from_value(json!({
+        "id": 1, "type": "workspace", "layout": "splith", "rect": rect, "nodes": [
+            con(2, "splitv", json!([con(3, "splith", json!([con(4, "none", json!([]))]))])),
+            con(5, "tabbed", json!([con(6, "none", json!([]))])),
+            con(7, "splitv", json!([con(8, "none", json!([])), con(9, "none", json!([]))])),
+        ]}))
+        .unwrap();
+        let tree = Tree::from(root);
+        let found: Vec<(u64, u64)> = tree
+            .redundant_containers(&["splith", "splitv"])
+            .iter()
+            .map(|(node, survivor)| (node.id, survivor.id))
+            .collect();
+        assert_eq!(found, vec![(3, 4), (2, 4)]);
+        assert_eq!(tree.redundant_containers(&["tabbed"]).len(), 1);
+        assert!(!tree.root().is_redundant(&["splith"]));
+    }
+
+    #[test]
+    fn visit_raw() {
+        let payload = br#"{"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "workspace", "name": "1", "nodes": [{"id": 3, "type": "con"}],
+             "floating_nodes": [{"id": 4, "type": "floating_con", "focused": true,
+                                 "window_properties": {"class": "Steam"}}]},
+            {"id": 5, "type": "workspace", "name": "2", "nodes": [{"id": 6, "type": "con"}]},
+            {"id": 7, "type": "workspace", "nodes": "never parsed"}]}"#;
+        let mut visited = Vec::new();
+        visit(payload, |node| {
+            visited.push((node.id, node.depth));
+            match node.id {
+                4 => Visit::Stop,
+                _ => Visit::Continue,
+            }
+        })
+        .unwrap();
+        assert_eq!(visited, vec![(1, 0), (2, 1), (3, 2), (4, 2)]);
+
+        let mut focused = None;
+        visit(payload, |node| {
+            if node.focused {
+                focused = node.app_identifier().map(ToOwned::to_owned);
+            }
+            match node.name.as_deref() {
+                Some("2") => Visit::SkipChildren,
+                _ => Visit::Continue,
+            }
+        })
+        // Only fails once it gets to the broken node.
+        .unwrap_err();
+        assert_eq!(focused.as_deref(), Some("Steam"));
+    }
+
+    #[test]
+    fn visit_streams() {
+        /// Counts how much of the reply was read.
+        struct Counting<'a>(&'a [u8], usize);
+
+        impl std::io::Read for Counting<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = (&self.0[self.1..]).read(buf)?;
+                self.1 += n;
+                Ok(n)
+            }
+        }
+
+        // Sway sends `focus` and the fields of views after the children.
+        let mut payload = br#"{"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "workspace", "name": "1", "nodes": [
+                {"id": 3, "type": "con", "nodes": [], "app_id": "foot", "focus": []}],
+             "focus": [3]}"#
+            .to_vec();
+        for id in 10..1000 {
+            payload.extend(format!(r#", {{"id": {}, "type": "workspace"}}"#, id).as_bytes());
+        }
+        payload.extend(b"]}");
+
+        let mut visited = Vec::new();
+        let mut reader = Counting(&payload, 0);
+        visit_reader(std::io::BufReader::with_capacity(64, &mut reader), |node| {
+            visited.push((
+                node.id,
+                node.is_leaf(),
+                node.app_identifier().map(String::from),
+            ));
+            match node.id {
+                3 => Visit::Stop,
+                _ => Visit::Continue,
+            }
+        })
+        .unwrap();
+        assert_eq!(
+            visited,
+            vec![
+                (1, false, None),
+                (2, false, None),
+                (3, true, Some("foot".into()))
+            ]
+        );
+        assert!(
+            reader.1 < 512,
+            "read {} of {} bytes",
+            reader.1,
+            payload.len()
+        );
+
+        let mut count = 0;
+        visit(&payload, |_| {
+            count += 1;
+            Visit::Continue
+        })
+        .unwrap();
+        assert_eq!(count, 993);
+
+        // With the keys in alphabetical order, the children are only walked at the end.
+        let sorted = serde_json::to_vec(&json!({"id": 1, "type": "root", "nodes": [
+            {"id": 2, "type": "workspace", "floating_nodes": [{"id": 4, "type": "floating_con"}],
+             "nodes": [{"id": 3, "type": "con"}]},
+        ]}))
+        .unwrap();
+        let mut visited = Vec::new();
+        visit(&sorted, |node| {
+            visited.push((node.id, node.depth, node.is_leaf()));
+            Visit::Continue
+        })
+        .unwrap();
+        assert_eq!(
+            visited,
+            vec![(1, 0, false), (2, 1, false), (3, 2, true), (4, 2, true)]
+        );
+    }
+
+    #[test]
+    fn focus_order_follows_focus_lists() {
+        let tree = json!({"id": 1, "type": "root", "focus": [2], "nodes": [
+            {"id": 2, "type": "workspace", "name": "1", "focus": [5, 3],
+                "nodes": [
+                    {"id": 3, "type": "con", "focus": [7], "nodes": [
+                        {"id": 6, "type": "con"},
+                        {"id": 7, "type": "con"},
+                    ]},
+                    {"id": 4, "type": "con"},
+                ],
+                "floating_nodes": [{"id": 5, "type": "floating_con"}]},
+        ]});
+        // Unknown to `focus` comes last, in tree order.
+        assert_eq!(focus_order(&tree), vec![5, 7, 6, 4]);
+    }
+
+    #[test]
+    fn app_identifiers() {
+        let wayland = json!({"app_id": "org.gnome.Nautilus"});
+        let xwayland = json!({"app_id": null, "window_properties": {"class": "Firefox"}});
+        let instance_only = json!({"window_properties": {"instance": "steam"}});
+        assert_eq!(app_identifier(&wayland), Some("org.gnome.Nautilus"));
+        assert_eq!(app_identifier(&xwayland), Some("Firefox"));
+        assert_eq!(app_identifier(&instance_only), Some("steam"));
+        assert_eq!(app_identifier(&json!({})), None);
+
+        let normalizer = AppIdNormalizer::new()
+            .lowercase(true)
+            .alias("org.gnome.nautilus", "nautilus");
+        assert_eq!(
+            normalizer.app_identifier(&wayland),
+            Some("nautilus".to_owned())
+        );
+        assert_eq!(
+            normalizer.app_identifier(&xwayland),
+            Some("firefox".to_owned())
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Which version of sway is running, and what it supports, for the few commands and requests
+//! which changed between versions.
+//!
+//! ```no_run
+//! use ksway::{command::output, version::Capability, SwayClient};
+//!
+//! let mut client = ksway::Client::connect()?;
+//! let version = client.server_version()?;
+//! println!("sway {}", version);
+//! // `output power` on sway 1.8 and later, `output dpms` before.
+//! client.run(output::power("*", false).for_version(version))?;
+//! if client.supports(Capability::BindingState)? {
+//!     // ...
+//! }
+//! # Ok::<(), ksway::Error>(())
+//! ```
+use std::convert::TryFrom;
+
+use crate::reply;
+
+/// A version of sway, ordered by release.
+#[derive(derive_more::Display, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[display(fmt = "{}.{}.{}", major, minor, patch)]
+pub struct SwayVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl SwayVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn supports(self, capability: Capability) -> bool {
+        self >= capability.since()
+    }
+}
+
+impl From<&reply::Version> for SwayVersion {
+    /// Negative numbers, which sway never sends, are taken as 0.
+    fn from(version: &reply::Version) -> Self {
+        let number = |n: i64| u32::try_from(n).unwrap_or(0);
+        Self::new(
+            number(version.major),
+            number(version.minor),
+            number(version.patch),
+        )
+    }
+}
+
+/// Something which only some versions of sway support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// `output <name> power on|off`, which superseded `output dpms`.
+    OutputPower,
+    /// The GET_BINDING_STATE request.
+    BindingState,
+}
+
+impl Capability {
+    /// The first version of sway which supports this.
+    pub fn since(self) -> SwayVersion {
+        match self {
+            Capability::OutputPower => SwayVersion::new(1, 8, 0),
+            Capability::BindingState => SwayVersion::new(1, 5, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::output;
+    use crate::testing::MockServer;
+    use crate::{ipc_command, Client, IpcCommandCode};
+
+    #[test]
+    fn capabilities_and_commands() {
+        let old = SwayVersion::new(1, 7, 0);
+        let new = SwayVersion::new(1, 10, 1);
+        assert!(old < new && new.to_string() == "1.10.1");
+        assert!(!old.supports(Capability::OutputPower) && old.supports(Capability::BindingState));
+        assert!(new.supports(Capability::OutputPower));
+
+        let power = output::power("*", false);
+        assert_eq!(
+            power.clone().for_version(old).to_string(),
+            r#"output "*" dpms off"#
+        );
+        assert_eq!(
+            power.for_version(new).to_string(),
+            r#"output "*" power off"#
+        );
+        let bind = crate::cmd!("bindsym XF86Display output * dpms on");
+        assert_eq!(bind.clone().for_version(new), bind);
+        let dpms = output::dpms("DP-1", true).for_version(new);
+        assert_eq!(dpms.to_string(), r#"output "DP-1" power on"#);
+    }
+
+    #[test]
+    fn cached_server_version() {
+        let server = MockServer::start().unwrap();
+        server.reply(
+            &ipc_command::get_version(),
+            r#"{"major": 1, "minor": 9, "patch": 0, "human_readable": "1.9", "loaded_config_file_name": null}"#,
+        );
+        let mut client = Client::connect_to_path(server.path()).unwrap();
+        assert_eq!(client.server_version().unwrap(), SwayVersion::new(1, 9, 0));
+        assert!(client.supports(Capability::OutputPower).unwrap());
+        let requests = server
+            .received()
+            .into_iter()
+            .filter(|frame| frame.payload_type == IpcCommandCode::GetVersion as u32)
+            .count();
+        assert_eq!(requests, 1);
+    }
+}
@@ -0,0 +1,468 @@
+//! Helpers for rearranging workspaces.
+//!
+//! Sway orders workspaces on an output by their number, so moving a workspace around means
+//! renaming it, and usually its neighbours too. Names keep whatever follows the number, e.g.
+//! moving `3:web` one slot to the left of `2:mail` results in `2:web` and `3:mail`.
+//!
+//! There are also bulk operations to merge workspaces or split windows out of them, and
+//! `renumber_workspaces()` to close the gaps between numbers.
+//!
+//! `workspace_display_name()` renders a workspace as its number and the icons of its
+//! applications, for renaming workspaces after what they hold or showing them in a bar.
+use std::collections::HashMap;
+
+use crate::tree::{self, AppIdNormalizer, Node, NodeKind};
+use crate::{cmd, command, JsonValue, Result, SwayClientJson, SwayJsonExt};
+
+/// Used to park a workspace while its new name is still taken.
+const TEMPORARY_NAME: &str = "__ksway_renaming";
+
+/// Everything in a workspace name after its number, e.g. `:web` for `3:web`.
+fn label(name: &str) -> &str {
+    name.trim_start_matches(|c: char| c.is_ascii_digit())
+}
+
+fn rename(from: &str, to: &str) -> String {
+    format!(
+        "rename workspace {} to {}",
+        command::quote(from),
+        command::quote(to)
+    )
+}
+
+/// The rename commands which swap the numbers of the workspaces `a` and `b` while keeping their
+/// labels.
+fn swap_numbers(a: (&str, i64), b: (&str, i64)) -> Vec<String> {
+    let (a_name, a_num) = a;
+    let (b_name, b_num) = b;
+    vec![
+        rename(a_name, TEMPORARY_NAME),
+        rename(b_name, &format!("{}{}", a_num, label(b_name))),
+        rename(TEMPORARY_NAME, &format!("{}{}", b_num, label(a_name))),
+    ]
+}
+
+/// Swap the focused workspace with its neighbour on the same output, `offset` slots away.
+fn move_focused_workspace<C: SwayClientJson>(client: &mut C, offset: isize) -> Result<bool> {
+    let workspaces = client.get_workspaces_json()?;
+    let workspaces = workspaces.as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let focused = match workspaces.iter().find(|ws| ws.is_focused()) {
+        Some(focused) => focused,
+        None => return Ok(false),
+    };
+
+    let mut siblings: Vec<&JsonValue> = workspaces
+        .iter()
+        .filter(|ws| ws["output"] == focused["output"])
+        .collect();
+    siblings.sort_by_key(|ws| ws["num"].as_i64().unwrap_or(-1));
+    let index = match siblings.iter().position(|ws| ws["id"] == focused["id"]) {
+        Some(index) => index as isize + offset,
+        None => return Ok(false),
+    };
+    if index < 0 || index as usize >= siblings.len() {
+        return Ok(false);
+    }
+    let neighbour = siblings[index as usize];
+
+    let as_numbered = |ws: &JsonValue| match (ws["name"].as_str(), ws["num"].as_i64()) {
+        // Workspaces without a number have a num of -1 and can't be reordered.
+        (Some(name), Some(num)) if num >= 0 => Some((name.to_owned(), num)),
+        _ => None,
+    };
+    let (a, b) = match (as_numbered(focused), as_numbered(neighbour)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Ok(false),
+    };
+    client.run(swap_numbers((&a.0, a.1), (&b.0, b.1)).join("; "))?;
+    Ok(true)
+}
+
+/// Move the focused workspace one slot to the left on its output by swapping numbers with the
+/// previous workspace. Returns `false` if there is nothing to swap with or either of the
+/// workspaces isn't numbered.
+pub fn move_workspace_left<C: SwayClientJson>(client: &mut C) -> Result<bool> {
+    move_focused_workspace(client, -1)
+}
+
+/// Move the focused workspace one slot to the right on its output by swapping numbers with the
+/// next workspace. Returns `false` if there is nothing to swap with or either of the workspaces
+/// isn't numbered.
+pub fn move_workspace_right<C: SwayClientJson>(client: &mut C) -> Result<bool> {
+    move_focused_workspace(client, 1)
+}
+
+/// The new names which number `workspaces`, given as name and number, from 1 in the order of
+/// their numbers. Workspaces which keep their name are left out.
+fn renumbered_names<'a>(workspaces: &[(&'a str, i64)]) -> Vec<(&'a str, String)> {
+    let mut sorted = workspaces.to_vec();
+    sorted.sort_by_key(|&(_, num)| num);
+    sorted
+        .iter()
+        .zip(1..)
+        .map(|(&(name, _), num)| (name, format!("{}{}", num, label(name))))
+        .filter(|(name, new_name)| name != new_name)
+        .collect()
+}
+
+/// The commands which apply `renames` at once. Workspaces whose name is the new name of another
+/// one are parked under a temporary name first, so that no rename hits a taken name.
+fn rename_commands(renames: &[(&str, String)]) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut sources = Vec::new();
+    for (i, (name, _)) in renames.iter().enumerate() {
+        if renames.iter().any(|(_, new_name)| new_name == name) {
+            let temporary = format!("{}{}", TEMPORARY_NAME, i);
+            commands.push(rename(name, &temporary));
+            sources.push(temporary);
+        } else {
+            sources.push(name.to_string());
+        }
+    }
+    for (from, (_, to)) in sources.iter().zip(renames) {
+        commands.push(rename(from, to));
+    }
+    commands
+}
+
+/// Renumber the numbered workspaces from 1 without gaps, e.g. `1`, `3:web` and `7` become `1`,
+/// `2:web` and `3`, so that bindings like `workspace number 2` reach them again. Workspaces
+/// without a number are left alone. Returns how many workspaces were renamed.
+pub fn renumber_workspaces<C: SwayClientJson>(client: &mut C) -> Result<usize> {
+    let workspaces = client.get_workspaces_typed()?;
+    let numbered: Vec<(&str, i64)> = workspaces
+        .iter()
+        .filter(|ws| ws.num >= 0)
+        .map(|ws| (ws.name.as_str(), ws.num))
+        .collect();
+    let renames = renumbered_names(&numbered);
+    if renames.is_empty() {
+        return Ok(0);
+    }
+    client.run(rename_commands(&renames).join("; "))?;
+    Ok(renames.len())
+}
+
+fn move_to_workspace(con_id: u64, workspace: &str) -> String {
+    cmd!([con_id=con_id] "move container to workspace {}", command::quote(workspace)).to_string()
+}
+
+/// The workspace node called `name` in a GET_TREE reply.
+fn find_workspace<'a>(tree: &'a JsonValue, name: &str) -> Option<Node<'a>> {
+    tree::nodes(tree).find(|node| node.kind == NodeKind::Workspace && node.workspace == Some(name))
+}
+
+/// Move the whole tiling and floating contents of `src` to `dst`.
+fn merge_commands(tree: &JsonValue, src: &str, dst: &str) -> Vec<String> {
+    let workspace = match find_workspace(tree, src) {
+        Some(workspace) => workspace,
+        None => return Vec::new(),
+    };
+    ["nodes", "floating_nodes"]
+        .iter()
+        .flat_map(|key| workspace.node[*key].as_array().into_iter().flatten())
+        .filter_map(|child| child.con_id())
+        .map(|id| move_to_workspace(id, dst))
+        .collect()
+}
+
+/// Move the windows on `workspace` for which `predicate` is true to `new_workspace`.
+fn split_commands<F: FnMut(&Node) -> bool>(
+    tree: &JsonValue,
+    workspace: &str,
+    mut predicate: F,
+    new_workspace: &str,
+) -> Vec<String> {
+    tree::windows(tree)
+        .filter(|window| window.workspace == Some(workspace) && predicate(window))
+        .filter_map(|window| window.id())
+        .map(|id| move_to_workspace(id, new_workspace))
+        .collect()
+}
+
+/// Run `commands` as one batch, then give focus back to the window which had it, wherever it
+/// ended up. Returns how many containers were moved.
+fn run_preserving_focus<C: SwayClientJson>(
+    client: &mut C,
+    tree: &JsonValue,
+    mut commands: Vec<String>,
+) -> Result<usize> {
+    let moved = commands.len();
+    if moved == 0 {
+        return Ok(0);
+    }
+    let focused = tree::windows(tree)
+        .find(|window| window.node.is_focused())
+        .and_then(|window| window.id());
+    if let Some(focused) = focused {
+        commands.push(cmd!([con_id=focused] "focus").to_string());
+    }
+    client.run(commands.join("; "))?;
+    Ok(moved)
+}
+
+/// Move everything on the workspace `src`, tiling and floating, to the workspace `dst`, which is
+/// created if needed. The focused window keeps focus. Returns how many containers were moved.
+pub fn merge_workspaces<C: SwayClientJson>(client: &mut C, src: &str, dst: &str) -> Result<usize> {
+    let tree = client.get_tree_json()?;
+    let commands = merge_commands(&tree, src, dst);
+    run_preserving_focus(client, &tree, commands)
+}
+
+/// Move the windows on `workspace` for which `predicate` is true to `new_workspace`, which is
+/// created if needed. The focused window keeps focus. Returns how many windows were moved.
+pub fn split_workspace<C: SwayClientJson, F: FnMut(&Node) -> bool>(
+    client: &mut C,
+    workspace: &str,
+    predicate: F,
+    new_workspace: &str,
+) -> Result<usize> {
+    let tree = client.get_tree_json()?;
+    let commands = split_commands(&tree, workspace, predicate, new_workspace);
+    run_preserving_focus(client, &tree, commands)
+}
+
+/// How `workspace_display_name()` renders a workspace.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    strip_number: bool,
+    separator: String,
+    icons: HashMap<String, String>,
+    default_icon: Option<String>,
+    max_len: Option<usize>,
+    normalizer: AppIdNormalizer,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            strip_number: false,
+            separator: ": ".into(),
+            icons: HashMap::new(),
+            default_icon: None,
+            max_len: None,
+            normalizer: AppIdNormalizer::new(),
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Keep the number, separated by `: `, with app identifiers for icons.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave the number out, e.g. for a bar which shows it separately.
+    pub fn strip_number(mut self, strip: bool) -> Self {
+        self.strip_number = strip;
+        self
+    }
+
+    /// What goes between the number and the rest.
+    pub fn separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Show the windows of `app_id` as `icon`, e.g. a Nerd Font glyph. The app identifier is
+    /// normalized first, see `normalizer()`.
+    pub fn icon<S: Into<String>, T: Into<String>>(mut self, app_id: S, icon: T) -> Self {
+        self.icons.insert(app_id.into(), icon.into());
+        self
+    }
+
+    /// Show applications without an icon as `icon` rather than their app identifier.
+    pub fn default_icon<S: Into<String>>(mut self, icon: S) -> Self {
+        self.default_icon = Some(icon.into());
+        self
+    }
+
+    /// Cut the name to `max_len` characters, ending it with `…` when it is cut.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Normalize app identifiers before looking up their icon.
+    pub fn normalizer(mut self, normalizer: AppIdNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    fn icon_for(&self, app_id: &str) -> String {
+        let app_id = self.normalizer.normalize(app_id);
+        match (self.icons.get(&app_id), &self.default_icon) {
+            (Some(icon), _) | (None, Some(icon)) => icon.clone(),
+            (None, None) => app_id,
+        }
+    }
+}
+
+/// The name to show for the workspace node `ws` from GET_TREE: its number, then the icons of
+/// the applications on it, each once in tree order. A workspace without windows keeps its label
+/// instead, i.e. what follows the number. Renaming workspaces with this and rendering them in a
+/// bar with the same options gives the same result.
+pub fn workspace_display_name(ws: &JsonValue, options: &FormatOptions) -> String {
+    let name = ws["name"].as_str().unwrap_or_default();
+    let number = &name[..name.len() - label(name).len()];
+    let mut icons: Vec<String> = Vec::new();
+    for window in tree::windows(ws) {
+        if let Some(app_id) = window.app_identifier() {
+            let icon = options.icon_for(app_id);
+            if !icons.contains(&icon) {
+                icons.push(icon);
+            }
+        }
+    }
+    let rest = if icons.is_empty() {
+        label(name).trim_start_matches(':').trim().to_owned()
+    } else {
+        icons.join(" ")
+    };
+    let display = if options.strip_number || number.is_empty() {
+        rest
+    } else if rest.is_empty() {
+        number.to_owned()
+    } else {
+        format!("{}{}{}", number, options.separator, rest)
+    };
+    match options.max_len {
+        Some(max_len) if display.chars().count() > max_len => {
+            let mut cut: String = display.chars().take(max_len.saturating_sub(1)).collect();
+            cut.push('…');
+            cut
+        }
+        _ => display,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_names() {
+        let ws = serde_json::json!({
+            "type": "workspace",
+            "name": "3:web",
+            "nodes": [
+                {"id": 10, "type": "con", "app_id": "firefox"},
+                {"id": 11, "type": "con", "nodes": [
+                    {"id": 12, "type": "con", "app_id": "Foot"},
+                    {"id": 13, "type": "con", "app_id": "firefox"},
+                ]},
+            ],
+            "floating_nodes": [{"id": 14, "type": "floating_con", "app_id": "mpv"}],
+        });
+        let options = FormatOptions::new()
+            .icon("firefox", "F")
+            .icon("foot", "T")
+            .normalizer(AppIdNormalizer::new().lowercase(true));
+        assert_eq!(workspace_display_name(&ws, &options), "3: F T mpv");
+        let options = options.default_icon("?").strip_number(true);
+        assert_eq!(workspace_display_name(&ws, &options), "F T ?");
+        assert_eq!(workspace_display_name(&ws, &options.max_len(3)), "F …");
+
+        let empty = serde_json::json!({"type": "workspace", "name": "4:mail", "nodes": []});
+        assert_eq!(
+            workspace_display_name(&empty, &FormatOptions::new()),
+            "4: mail"
+        );
+        let unnumbered = serde_json::json!({"type": "workspace", "name": "chat"});
+        assert_eq!(
+            workspace_display_name(&unnumbered, &FormatOptions::new()),
+            "chat"
+        );
+    }
+
+    #[test]
+    fn merge_and_split() {
+        let tree = serde_json::json!({
+            "type": "root",
+            "nodes": [{
+                "type": "output",
+                "name": "DP-1",
+                "nodes": [{
+                    "type": "workspace",
+                    "name": "1",
+                    "nodes": [
+                        {"id": 10, "type": "con", "nodes": [
+                            {"id": 11, "type": "con", "app_id": "foot"},
+                            {"id": 12, "type": "con", "app_id": "firefox"},
+                        ]},
+                        {"id": 13, "type": "con", "app_id": "foot"},
+                    ],
+                    "floating_nodes": [{"id": 14, "type": "floating_con", "app_id": "mpv"}],
+                }],
+            }],
+        });
+        assert_eq!(
+            merge_commands(&tree, "1", "2"),
+            vec![
+                r#"[con_id="10"] move container to workspace "2""#,
+                r#"[con_id="13"] move container to workspace "2""#,
+                r#"[con_id="14"] move container to workspace "2""#,
+            ]
+        );
+        assert!(merge_commands(&tree, "3", "2").is_empty());
+        assert_eq!(
+            split_commands(&tree, "1", |w| w.app_identifier() == Some("foot"), "term"),
+            vec![
+                r#"[con_id="11"] move container to workspace "term""#,
+                r#"[con_id="13"] move container to workspace "term""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn renumber() {
+        assert_eq!(
+            rename_commands(&renumbered_names(&[("7", 7), ("1", 1), ("3:web", 3)])),
+            vec![
+                r#"rename workspace "3:web" to "2:web""#,
+                r#"rename workspace "7" to "3""#,
+            ]
+        );
+        // 0 moves up to 1, which is still taken until 1 moves up to 2.
+        assert_eq!(
+            rename_commands(&renumbered_names(&[("0", 0), ("1", 1)])),
+            vec![
+                r#"rename workspace "1" to "__ksway_renaming1""#,
+                r#"rename workspace "0" to "1""#,
+                r#"rename workspace "__ksway_renaming1" to "2""#,
+            ]
+        );
+        assert!(renumbered_names(&[("1:a", 1), ("2", 2)]).is_empty());
+    }
+
+    #[test]
+    fn swap_numbers_keeps_labels() {
+        assert_eq!(
+            swap_numbers(("3:web", 3), ("2:mail", 2)),
+            vec![
+                r#"rename workspace "3:web" to "__ksway_renaming""#,
+                r#"rename workspace "2:mail" to "3:mail""#,
+                r#"rename workspace "__ksway_renaming" to "2:web""#,
+            ]
+        );
+        assert_eq!(
+            swap_numbers(("1", 1), ("10 code", 10))[1..],
+            [
+                r#"rename workspace "10 code" to "1 code""#,
+                r#"rename workspace "__ksway_renaming" to "10""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_quoted() {
+        assert_eq!(
+            rename(r#"2:"x""#, r"3:C:\"),
+            r#"rename workspace "2:\"x\"" to "3:C:\\""#
+        );
+        assert_eq!(
+            move_to_workspace(4, r#"a"; kill"#),
+            r#"[con_id="4"] move container to workspace "a\"; kill""#
+        );
+    }
+}